@@ -0,0 +1,677 @@
+//! An in-memory ledger following the rules of the program.
+//!
+//! Each transaction runs against a copy of the ledger, which replaces it only if every step
+//! succeeded, so a failed transaction changes nothing. Failures carry the same errors as the
+//! program: `MarketError` codes, and the token program's `InsufficientFunds` (custom error 1)
+//! when a balance is too low. Simulations run the same way and throw the copy away.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::SystemTime,
+};
+
+use anyhow::{Result, bail};
+use async_trait::async_trait;
+use blockchain_client::{
+    ClusterProfile, ComputeBudget, Credit, DynSigner, FaucetError, InMemoryProvider,
+    PackedEventOutcome, SentStatus, SignerProvider, Simulation, TransactionOutcome,
+    packing::ChunkOutcome, submit::before_send,
+};
+use blockchain_core::{
+    accounts::event::{Event, EventOption},
+    error::MarketError,
+    instructions::{
+        AddOptionArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs,
+    },
+    money::{MicroUsdc, Shares},
+};
+use solana_sdk::{
+    instruction::InstructionError,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    transaction::TransactionError,
+};
+use uuid::Uuid;
+
+use crate::chain::{ChainGateway, NewOption};
+
+/// `TokenError::InsufficientFunds` of the token program
+const INSUFFICIENT_FUNDS: InstructionError = InstructionError::Custom(1);
+/// `SystemError::AccountAlreadyInUse` of the system program
+const ACCOUNT_IN_USE: InstructionError = InstructionError::Custom(0);
+
+fn market_error(error: MarketError) -> InstructionError {
+    InstructionError::Custom(error as u32)
+}
+
+#[derive(Debug, Default, Clone)]
+struct MockEvent {
+    options: HashMap<Uuid, EventOption>,
+    /// USDC held by the event for open orders and rewards
+    treasury: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+struct Ledger {
+    usdc: HashMap<Pubkey, u64>,
+    /// Keyed by owner and mint
+    tokens: HashMap<(Pubkey, Pubkey), u64>,
+    events: HashMap<Uuid, MockEvent>,
+}
+
+impl Ledger {
+    fn event(&mut self, uuid: &Uuid) -> Result<&mut MockEvent, InstructionError> {
+        self.events
+            .get_mut(uuid)
+            .ok_or(market_error(MarketError::InvalidEvent))
+    }
+
+    fn debit_usdc(&mut self, wallet: &Pubkey, amount: u64) -> Result<(), InstructionError> {
+        let balance = self.usdc.entry(*wallet).or_default();
+        *balance = balance.checked_sub(amount).ok_or(INSUFFICIENT_FUNDS)?;
+        Ok(())
+    }
+
+    fn credit_usdc(&mut self, wallet: &Pubkey, amount: u64) -> Result<(), InstructionError> {
+        let balance = self.usdc.entry(*wallet).or_default();
+        *balance = balance
+            .checked_add(amount)
+            .ok_or(market_error(MarketError::Overflow))?;
+        Ok(())
+    }
+
+    fn create_event(&mut self, args: &CreateEmptyEventArgs) -> Result<(), InstructionError> {
+        if self.events.contains_key(&args.uuid) {
+            return Err(ACCOUNT_IN_USE);
+        }
+        self.events.insert(args.uuid, MockEvent::default());
+        Ok(())
+    }
+
+    fn add_option(&mut self, args: &AddOptionArgs) -> Result<(), InstructionError> {
+        self.event(&args.event_uuid)?
+            .options
+            .insert(args.option_uuid, args.option_info.clone());
+        Ok(())
+    }
+
+    fn create_order(
+        &mut self,
+        user: &Pubkey,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<(), InstructionError> {
+        let amount = args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(market_error(MarketError::Overflow))?
+            .0;
+
+        self.debit_usdc(user, amount)?;
+        let event = self.event(&args.event_uuid)?;
+        event.treasury = event
+            .treasury
+            .checked_add(amount)
+            .ok_or(market_error(MarketError::Overflow))?;
+        Ok(())
+    }
+
+    fn match_order(
+        &mut self,
+        yes_user: &Pubkey,
+        no_user: &Pubkey,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<(), InstructionError> {
+        let option = self
+            .event(&args.event_uuid)?
+            .options
+            .get(&args.option_uuid)
+            .ok_or(market_error(MarketError::OptionMissmatch))?;
+        if option.yes_mint != *yes_mint || option.no_mint != *no_mint {
+            return Err(market_error(MarketError::TokenMissmatch));
+        }
+
+        let amount = args
+            .num_shares
+            .to_token_amount()
+            .ok_or(market_error(MarketError::Overflow))?;
+        for key in [(*yes_user, *yes_mint), (*no_user, *no_mint)] {
+            let balance = self.tokens.entry(key).or_default();
+            *balance = balance
+                .checked_add(amount)
+                .ok_or(market_error(MarketError::Overflow))?;
+        }
+        Ok(())
+    }
+
+    fn cancel_order(
+        &mut self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<(), InstructionError> {
+        let amount = args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(market_error(MarketError::Overflow))?
+            .0;
+
+        let event = self.event(&args.event_uuid)?;
+        event.treasury = event
+            .treasury
+            .checked_sub(amount)
+            .ok_or(INSUFFICIENT_FUNDS)?;
+        self.credit_usdc(user, amount)
+    }
+
+    fn get_reward(
+        &mut self,
+        user: &Pubkey,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<(), InstructionError> {
+        let payout = args
+            .num_shares
+            .payout()
+            .ok_or(market_error(MarketError::Overflow))?
+            .0;
+        let burned = args
+            .num_shares
+            .to_token_amount()
+            .ok_or(market_error(MarketError::Overflow))?;
+
+        let event = self.event(&args.event_uuid)?;
+        event.treasury = event
+            .treasury
+            .checked_sub(payout)
+            .ok_or(INSUFFICIENT_FUNDS)?;
+        self.credit_usdc(user, payout)?;
+
+        let tokens = self.tokens.entry((*user, *mint)).or_default();
+        *tokens = tokens.checked_sub(burned).ok_or(INSUFFICIENT_FUNDS)?;
+        Ok(())
+    }
+
+    fn transfer_shares(
+        &mut self,
+        seller: &Pubkey,
+        buyer: &Pubkey,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<(), InstructionError> {
+        self.event(&args.event_uuid)?;
+
+        let amount = args
+            .num_shares
+            .to_token_amount()
+            .ok_or(market_error(MarketError::Overflow))?;
+        let price = args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(market_error(MarketError::Overflow))?
+            .0;
+
+        let tokens = self.tokens.entry((*seller, *mint)).or_default();
+        *tokens = tokens.checked_sub(amount).ok_or(INSUFFICIENT_FUNDS)?;
+        let tokens = self.tokens.entry((*buyer, *mint)).or_default();
+        *tokens = tokens
+            .checked_add(amount)
+            .ok_or(market_error(MarketError::Overflow))?;
+
+        self.debit_usdc(buyer, price)?;
+        self.credit_usdc(seller, price)
+    }
+}
+
+pub struct MockLedger {
+    ledger: Mutex<Ledger>,
+    profile: ClusterProfile,
+    signers: Arc<dyn SignerProvider>,
+    /// Transactions left to drop, see `drop_next`
+    dropping: AtomicUsize,
+    /// Transactions left to land without an answer, see `lose_next`
+    losing: AtomicUsize,
+    /// Every transaction sent, for `sent_status`
+    sent: Mutex<HashMap<Signature, SentStatus>>,
+}
+
+impl Default for MockLedger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MockLedger {
+    /// With the addresses and links of `ClusterProfile::localnet`, and an `InMemoryProvider`
+    pub fn new() -> Self {
+        Self {
+            ledger: Mutex::new(Ledger::default()),
+            profile: ClusterProfile::localnet(),
+            signers: Arc::new(InMemoryProvider),
+            dropping: AtomicUsize::new(0),
+            losing: AtomicUsize::new(0),
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// For the program id and the explorer links
+    pub fn with_profile(mut self, profile: ClusterProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    pub fn with_signer_provider(mut self, signers: Arc<dyn SignerProvider>) -> Self {
+        self.signers = signers;
+        self
+    }
+
+    pub fn usdc(&self, wallet: &Pubkey) -> MicroUsdc {
+        MicroUsdc(
+            self.ledger
+                .lock()
+                .unwrap()
+                .usdc
+                .get(wallet)
+                .copied()
+                .unwrap_or(0),
+        )
+    }
+
+    /// Whole shares of `mint` held by `wallet`
+    pub fn shares(&self, wallet: &Pubkey, mint: &Pubkey) -> Shares {
+        let tokens = self
+            .ledger
+            .lock()
+            .unwrap()
+            .tokens
+            .get(&(*wallet, *mint))
+            .copied()
+            .unwrap_or(0);
+        Shares::from_token_amount(tokens)
+    }
+
+    /// USDC held by the event, None if it was not created
+    pub fn treasury(&self, event: &Uuid) -> Option<MicroUsdc> {
+        self.ledger
+            .lock()
+            .unwrap()
+            .events
+            .get(event)
+            .map(|event| MicroUsdc(event.treasury))
+    }
+
+    /// Options of the event, None if it was not created
+    pub fn options(&self, event: &Uuid) -> Option<HashMap<Uuid, EventOption>> {
+        self.ledger
+            .lock()
+            .unwrap()
+            .events
+            .get(event)
+            .map(|event| event.options.clone())
+    }
+
+    /// The next `count` transactions never land, as if their blockhash expired
+    pub fn drop_next(&self, count: usize) {
+        self.dropping.store(count, Ordering::SeqCst);
+    }
+
+    /// The next `count` transactions land, but the answer is lost, as if the RPC failed right
+    /// after sending them
+    pub fn lose_next(&self, count: usize) {
+        self.losing.store(count, Ordering::SeqCst);
+    }
+
+    fn take(counter: &AtomicUsize) -> bool {
+        counter
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| {
+                left.checked_sub(1)
+            })
+            .is_ok()
+    }
+
+    /// Runs `transaction` on a copy of the ledger, and keeps the copy if it succeeded
+    async fn transact(
+        &self,
+        transaction: impl FnOnce(&mut Ledger) -> Result<(), InstructionError>,
+    ) -> Result<TransactionOutcome> {
+        let signature = Signature::new_unique();
+        // There are no blockhashes, a transaction that was not confirmed right away never lands
+        before_send(&signature, 0).await?;
+        if Self::take(&self.dropping) {
+            self.record(signature, SentStatus::Expired);
+            return Ok(TransactionOutcome::Dropped {
+                last_signature: signature,
+            });
+        }
+        let mut ledger = self.ledger.lock().unwrap();
+        let mut copy = ledger.clone();
+
+        let outcome = match transaction(&mut copy) {
+            Ok(()) => {
+                *ledger = copy;
+                TransactionOutcome::Confirmed {
+                    signature,
+                    budget: Self::budget(),
+                }
+            }
+            Err(error) => TransactionOutcome::Failed {
+                signature,
+                error: TransactionError::InstructionError(0, error),
+                budget: None,
+            },
+        };
+
+        drop(ledger);
+        self.record(
+            signature,
+            match &outcome {
+                TransactionOutcome::Failed { error, .. } => SentStatus::Failed(error.clone()),
+                _ => SentStatus::Confirmed,
+            },
+        );
+
+        if Self::take(&self.losing) {
+            bail!("Connection lost while waiting for {}", signature);
+        }
+        Ok(outcome)
+    }
+
+    fn record(&self, signature: Signature, status: SentStatus) {
+        self.sent.lock().unwrap().insert(signature, status);
+    }
+
+    fn simulate(
+        &self,
+        transaction: impl FnOnce(&mut Ledger) -> Result<(), InstructionError>,
+    ) -> Simulation {
+        let mut copy = self.ledger.lock().unwrap().clone();
+
+        Simulation {
+            error: transaction(&mut copy)
+                .err()
+                .map(|error| TransactionError::InstructionError(0, error)),
+            logs: Vec::new(),
+            budget: Self::budget(),
+            accounts: Vec::new(),
+        }
+    }
+
+    /// Nothing is metered
+    fn budget() -> ComputeBudget {
+        ComputeBudget {
+            units_consumed: 0,
+            compute_unit_limit: 0,
+            compute_unit_price: 0,
+            fee_lamports: 0,
+        }
+    }
+}
+
+/// None when it was not sent
+fn chunk(
+    creates_event: bool,
+    options: Vec<Uuid>,
+    sent: Option<Result<TransactionOutcome>>,
+) -> ChunkOutcome {
+    let (outcome, error) = match sent {
+        Some(Ok(outcome)) => (Some(outcome), None),
+        Some(Err(e)) => (None, Some(e.to_string())),
+        None => (None, None),
+    };
+    ChunkOutcome {
+        creates_event,
+        options,
+        outcome,
+        error,
+    }
+}
+
+#[async_trait]
+impl ChainGateway for MockLedger {
+    fn signers(&self) -> &dyn SignerProvider {
+        self.signers.as_ref()
+    }
+
+    fn event_pubkey(&self, event_id: &Uuid) -> Pubkey {
+        Event::find_program_address(event_id, &self.profile.deployment.program_id).0
+    }
+
+    fn account_url(&self, pubkey: &Pubkey) -> String {
+        self.profile.account_url(pubkey)
+    }
+
+    fn transaction_url(&self, signature: &Signature) -> String {
+        self.profile.transaction_url(signature)
+    }
+
+    async fn create_wallet(&self) -> Result<(String, Pubkey)> {
+        Ok(self.signers.create()?)
+    }
+
+    async fn usdc_balance(&self, wallet: &Pubkey) -> Result<MicroUsdc> {
+        Ok(self.usdc(wallet))
+    }
+
+    async fn share_balance(&self, wallet: &Pubkey, mint: &Pubkey) -> Result<Shares> {
+        Ok(self.shares(wallet, mint))
+    }
+
+    async fn treasury_balance(&self, event_id: &Uuid) -> Result<MicroUsdc> {
+        Ok(self.treasury(event_id).unwrap_or(MicroUsdc(0)))
+    }
+
+    async fn event_options(&self, event_id: &Uuid) -> Result<Option<Vec<Uuid>>> {
+        Ok(self
+            .options(event_id)
+            .map(|options| options.into_keys().collect()))
+    }
+
+    async fn sent_status(
+        &self,
+        signature: &Signature,
+        _last_valid_block_height: u64,
+    ) -> Result<SentStatus> {
+        Ok(self
+            .sent
+            .lock()
+            .unwrap()
+            .get(signature)
+            .cloned()
+            .unwrap_or(SentStatus::Expired))
+    }
+
+    async fn airdrop(&self, wallet: &Pubkey, amount: MicroUsdc) -> Result<Credit, FaucetError> {
+        let outcome = self
+            .transact(|ledger| ledger.credit_usdc(wallet, amount.0))
+            .await?;
+
+        Ok(Credit {
+            wallet: *wallet,
+            amount,
+            signature: Some(outcome.confirmed()?),
+            credited_at: SystemTime::now(),
+        })
+    }
+
+    /// One transaction for the event, and one for each option
+    async fn create_event(
+        &self,
+        event: &CreateEmptyEventArgs,
+        options: &[NewOption],
+    ) -> Result<PackedEventOutcome> {
+        let existing = self.options(&event.uuid);
+
+        let mut chunks = Vec::new();
+        if existing.is_none() {
+            let created = self.transact(|ledger| ledger.create_event(event)).await;
+            chunks.push(chunk(true, Vec::new(), Some(created)));
+        }
+        let event_exists = chunks.iter().all(ChunkOutcome::is_confirmed);
+
+        let missing = options.iter().filter(|option| {
+            existing
+                .as_ref()
+                .is_none_or(|existing| !existing.contains_key(&option.args.option_uuid))
+        });
+        for option in missing {
+            let sent = if event_exists {
+                Some(
+                    self.transact(|ledger| ledger.add_option(&option.args))
+                        .await,
+                )
+            } else {
+                None
+            };
+            chunks.push(chunk(false, vec![option.args.option_uuid], sent));
+        }
+
+        Ok(PackedEventOutcome { chunks })
+    }
+
+    async fn simulate_create_event(&self, args: &CreateEventArgs) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| {
+            ledger.create_event(&CreateEmptyEventArgs {
+                uuid: args.uuid,
+                description: args.description.clone(),
+            })?;
+            for (option_uuid, option_info) in &args.options {
+                ledger.add_option(&AddOptionArgs {
+                    event_uuid: args.uuid,
+                    option_uuid: *option_uuid,
+                    option_info: option_info.clone(),
+                })?;
+            }
+            Ok(())
+        }))
+    }
+
+    async fn add_option(
+        &self,
+        _yes_mint: &Keypair,
+        _no_mint: &Keypair,
+        args: &AddOptionArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| ledger.add_option(args)).await
+    }
+
+    async fn simulate_add_option(
+        &self,
+        _yes_mint: &Pubkey,
+        _no_mint: &Pubkey,
+        args: &AddOptionArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.add_option(args)))
+    }
+
+    async fn create_order(
+        &self,
+        user: &DynSigner,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| ledger.create_order(&user.pubkey(), args))
+            .await
+    }
+
+    async fn simulate_create_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.create_order(user, args)))
+    }
+
+    async fn match_order(
+        &self,
+        yes_user: &DynSigner,
+        no_user: &DynSigner,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| {
+            ledger.match_order(
+                &yes_user.pubkey(),
+                &no_user.pubkey(),
+                yes_mint,
+                no_mint,
+                args,
+            )
+        })
+        .await
+    }
+
+    async fn simulate_match_order(
+        &self,
+        yes_user: &Pubkey,
+        no_user: &Pubkey,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.match_order(yes_user, no_user, yes_mint, no_mint, args)))
+    }
+
+    async fn cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| ledger.cancel_order(user, args))
+            .await
+    }
+
+    async fn simulate_cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.cancel_order(user, args)))
+    }
+
+    async fn get_reward(
+        &self,
+        user: &DynSigner,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| ledger.get_reward(&user.pubkey(), mint, args))
+            .await
+    }
+
+    async fn simulate_get_reward(
+        &self,
+        user: &Pubkey,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.get_reward(user, mint, args)))
+    }
+
+    async fn transfer_shares(
+        &self,
+        seller: &DynSigner,
+        buyer: &DynSigner,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<TransactionOutcome> {
+        self.transact(|ledger| {
+            ledger.transfer_shares(&seller.pubkey(), &buyer.pubkey(), mint, args)
+        })
+        .await
+    }
+
+    async fn simulate_transfer_shares(
+        &self,
+        seller: &Pubkey,
+        buyer: &Pubkey,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<Simulation> {
+        Ok(self.simulate(|ledger| ledger.transfer_shares(seller, buyer, mint, args)))
+    }
+}
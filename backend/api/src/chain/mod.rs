@@ -0,0 +1,178 @@
+//! Everything the API does on chain, behind `ChainGateway`.
+//!
+//! `RpcGateway` talks to a cluster through `ProfeciaClient`. `MockLedger` keeps USDC and token
+//! balances in memory and follows the same rules as the program, so the API can run, and be
+//! tested, without a validator.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use blockchain_client::{
+    Credit, DynSigner, FaucetError, PackedEventOutcome, SentStatus, SignerProvider, Simulation,
+    TransactionOutcome,
+};
+use blockchain_core::{
+    instructions::{
+        AddOptionArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs,
+    },
+    money::{MicroUsdc, Shares},
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use uuid::Uuid;
+
+mod mock;
+mod rpc;
+
+pub use mock::MockLedger;
+pub use rpc::RpcGateway;
+
+/// An option to add to a new event, with the keypairs of its mints
+pub struct NewOption {
+    pub yes_mint: Keypair,
+    pub no_mint: Keypair,
+    pub args: AddOptionArgs,
+}
+
+#[async_trait]
+pub trait ChainGateway: Send + Sync {
+    /// Where user wallets are looked up and created
+    fn signers(&self) -> &dyn SignerProvider;
+
+    fn event_pubkey(&self, event_id: &Uuid) -> Pubkey;
+
+    fn account_url(&self, pubkey: &Pubkey) -> String;
+
+    fn transaction_url(&self, signature: &Signature) -> String;
+
+    /// Creates a wallet with `signers` and funds it for fees. Returns the key to store and its
+    /// address
+    async fn create_wallet(&self) -> Result<(String, Pubkey)>;
+
+    /// USDC held by `wallet`, 0 if it has no token account
+    async fn usdc_balance(&self, wallet: &Pubkey) -> Result<MicroUsdc>;
+
+    /// Whole shares of `mint` held by `wallet`, 0 if it has no token account
+    async fn share_balance(&self, wallet: &Pubkey, mint: &Pubkey) -> Result<Shares>;
+
+    /// USDC held by the event for open orders and rewards, 0 before its first order
+    async fn treasury_balance(&self, event_id: &Uuid) -> Result<MicroUsdc>;
+
+    /// Options the event holds on chain, None if it was not created
+    async fn event_options(&self, event_id: &Uuid) -> Result<Option<Vec<Uuid>>>;
+
+    /// Where a transaction sent earlier, with a blockhash valid up to `last_valid_block_height`,
+    /// stands. Every transaction below is handed to `blockchain_client::submit::before_send`
+    /// first
+    async fn sent_status(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> Result<SentStatus>;
+
+    /// Test USDC from the faucet
+    async fn airdrop(&self, wallet: &Pubkey, amount: MicroUsdc) -> Result<Credit, FaucetError>;
+
+    /// Creates the event and then its options, in as few transactions as possible. If the event
+    /// is already on chain, only the options it does not hold yet are sent
+    async fn create_event(
+        &self,
+        event: &CreateEmptyEventArgs,
+        options: &[NewOption],
+    ) -> Result<PackedEventOutcome>;
+
+    async fn simulate_create_event(&self, args: &CreateEventArgs) -> Result<Simulation>;
+
+    async fn add_option(
+        &self,
+        yes_mint: &Keypair,
+        no_mint: &Keypair,
+        args: &AddOptionArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_add_option(
+        &self,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &AddOptionArgs,
+    ) -> Result<Simulation>;
+
+    /// Moves the price of the order from the user to the event
+    async fn create_order(
+        &self,
+        user: &DynSigner,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_create_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<Simulation>;
+
+    /// Mints the shares of both sides of a match
+    async fn match_order(
+        &self,
+        yes_user: &DynSigner,
+        no_user: &DynSigner,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_match_order(
+        &self,
+        yes_user: &Pubkey,
+        no_user: &Pubkey,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<Simulation>;
+
+    /// Gives the price of the order back to the user
+    async fn cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<Simulation>;
+
+    /// Burns winning shares and pays them out
+    async fn get_reward(
+        &self,
+        user: &DynSigner,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_get_reward(
+        &self,
+        user: &Pubkey,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<Simulation>;
+
+    /// Sells shares of `mint` from `seller` to `buyer`, who pays for them in USDC
+    async fn transfer_shares(
+        &self,
+        seller: &DynSigner,
+        buyer: &DynSigner,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<TransactionOutcome>;
+
+    async fn simulate_transfer_shares(
+        &self,
+        seller: &Pubkey,
+        buyer: &Pubkey,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<Simulation>;
+}
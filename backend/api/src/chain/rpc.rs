@@ -0,0 +1,240 @@
+use anyhow::Result;
+use async_trait::async_trait;
+use blockchain_client::{
+    Credit, DynSigner, Faucet, FaucetError, PackedEventOutcome, ProfeciaClient, SentStatus,
+    SignerProvider, Simulation, TransactionOutcome, faucet::usdc_balance,
+};
+use blockchain_core::{
+    instructions::{
+        AddOptionArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs,
+    },
+    money::{MicroUsdc, Shares},
+};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use uuid::Uuid;
+
+use crate::chain::{ChainGateway, NewOption};
+
+/// A real cluster
+pub struct RpcGateway {
+    pub client: ProfeciaClient,
+    pub faucet: Faucet,
+}
+
+impl RpcGateway {
+    pub fn new(client: ProfeciaClient, faucet: Faucet) -> Self {
+        Self { client, faucet }
+    }
+}
+
+#[async_trait]
+impl ChainGateway for RpcGateway {
+    fn signers(&self) -> &dyn SignerProvider {
+        self.client.signers.as_ref()
+    }
+
+    fn event_pubkey(&self, event_id: &Uuid) -> Pubkey {
+        self.client.event_pubkey(event_id)
+    }
+
+    fn account_url(&self, pubkey: &Pubkey) -> String {
+        self.client.get_account_url(pubkey)
+    }
+
+    fn transaction_url(&self, signature: &Signature) -> String {
+        self.client.get_transaction_url(signature)
+    }
+
+    async fn create_wallet(&self) -> Result<(String, Pubkey)> {
+        self.client.init_new_wallet().await
+    }
+
+    async fn usdc_balance(&self, wallet: &Pubkey) -> Result<MicroUsdc> {
+        usdc_balance(&self.client, wallet).await
+    }
+
+    async fn share_balance(&self, wallet: &Pubkey, mint: &Pubkey) -> Result<Shares> {
+        let tokens = self.client.fetch_ata_if_exists(wallet, mint).await?;
+        Ok(Shares::from_token_amount(
+            tokens.map_or(0, |account| account.amount),
+        ))
+    }
+
+    async fn treasury_balance(&self, event_id: &Uuid) -> Result<MicroUsdc> {
+        // The treasury is only created with the first order
+        let treasury = self
+            .client
+            .fetch_ata_if_exists(
+                &self.client.event_pubkey(event_id),
+                &self.client.profile.deployment.collateral_mint,
+            )
+            .await?;
+        Ok(MicroUsdc(treasury.map_or(0, |account| account.amount)))
+    }
+
+    async fn event_options(&self, event_id: &Uuid) -> Result<Option<Vec<Uuid>>> {
+        Ok(self
+            .client
+            .fetch_event_if_exists(event_id)
+            .await?
+            .map(|event| event.options.into_keys().collect()))
+    }
+
+    async fn sent_status(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> Result<SentStatus> {
+        self.client
+            .sent_status(signature, last_valid_block_height)
+            .await
+    }
+
+    async fn airdrop(&self, wallet: &Pubkey, amount: MicroUsdc) -> Result<Credit, FaucetError> {
+        self.faucet.credit(&self.client, wallet, amount).await
+    }
+
+    async fn create_event(
+        &self,
+        event: &CreateEmptyEventArgs,
+        options: &[NewOption],
+    ) -> Result<PackedEventOutcome> {
+        let mut packer =
+            options
+                .iter()
+                .fold(self.client.event_packer(event.clone()), |packer, option| {
+                    packer.add_option(&option.yes_mint, &option.no_mint, option.args.clone())
+                });
+        if let Some(existing) = self.client.fetch_event_if_exists(&event.uuid).await? {
+            packer = packer.resuming(&existing);
+        }
+
+        self.client.create_packed_event(&packer).await
+    }
+
+    async fn simulate_create_event(&self, args: &CreateEventArgs) -> Result<Simulation> {
+        self.client.simulate_create_event(args).await
+    }
+
+    async fn add_option(
+        &self,
+        yes_mint: &Keypair,
+        no_mint: &Keypair,
+        args: &AddOptionArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client.add_option(yes_mint, no_mint, args).await
+    }
+
+    async fn simulate_add_option(
+        &self,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &AddOptionArgs,
+    ) -> Result<Simulation> {
+        self.client
+            .simulate_add_option(yes_mint, no_mint, args)
+            .await
+    }
+
+    async fn create_order(
+        &self,
+        user: &DynSigner,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client.create_order(user, args).await
+    }
+
+    async fn simulate_create_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<Simulation> {
+        self.client.simulate_create_order(user, args).await
+    }
+
+    async fn match_order(
+        &self,
+        yes_user: &DynSigner,
+        no_user: &DynSigner,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client
+            .match_order(yes_user, no_user, yes_mint, no_mint, args)
+            .await
+    }
+
+    async fn simulate_match_order(
+        &self,
+        yes_user: &Pubkey,
+        no_user: &Pubkey,
+        yes_mint: &Pubkey,
+        no_mint: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<Simulation> {
+        self.client
+            .simulate_match_order(yes_user, no_user, yes_mint, no_mint, args)
+            .await
+    }
+
+    async fn cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client.cancel_order(user, args).await
+    }
+
+    async fn simulate_cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<Simulation> {
+        self.client.simulate_cancel_order(user, args).await
+    }
+
+    async fn get_reward(
+        &self,
+        user: &DynSigner,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client.get_reward(user, mint, args).await
+    }
+
+    async fn simulate_get_reward(
+        &self,
+        user: &Pubkey,
+        mint: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<Simulation> {
+        self.client.simulate_get_reward(user, mint, args).await
+    }
+
+    async fn transfer_shares(
+        &self,
+        seller: &DynSigner,
+        buyer: &DynSigner,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<TransactionOutcome> {
+        self.client.trasfer_shares(seller, buyer, mint, args).await
+    }
+
+    async fn simulate_transfer_shares(
+        &self,
+        seller: &Pubkey,
+        buyer: &Pubkey,
+        mint: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<Simulation> {
+        self.client
+            .simulate_transfer_shares(seller, buyer, mint, args)
+            .await
+    }
+}
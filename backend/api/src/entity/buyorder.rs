@@ -1,7 +1,36 @@
 use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::entity::market::MarketOption;
 
+/// How long an order stays in the book. Only `Gtc` and `Gtd` orders ever rest
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum TimeInForce {
+    /// Good till cancelled
+    #[default]
+    #[sea_orm(string_value = "gtc")]
+    Gtc,
+    /// Immediate or cancel: what does not fill right away is dropped
+    #[sea_orm(string_value = "ioc")]
+    Ioc,
+    /// Fill or kill: fills entirely right away or not at all
+    #[sea_orm(string_value = "fok")]
+    Fok,
+    /// Good till date: cancelled at `expires_at`
+    #[sea_orm(string_value = "gtd")]
+    Gtd,
+}
+
+impl TimeInForce {
+    pub fn rests(&self) -> bool {
+        matches!(self, TimeInForce::Gtc | TimeInForce::Gtd)
+    }
+}
+
 #[sea_orm::model]
 #[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
 #[sea_orm(table_name = "buyorder")]
@@ -14,6 +43,9 @@ pub struct Model {
     pub shares: i64,
     pub price_per_share: i64,
     pub created_at: DateTimeWithTimeZone,
+    #[sea_orm(default_value = "gtc")]
+    pub time_in_force: TimeInForce,
+    pub expires_at: Option<DateTimeWithTimeZone>,
     #[sea_orm(belongs_to, from = "market_id", to = "id")]
     pub market: HasOne<super::market::Entity>,
     #[sea_orm(belongs_to, from = "user_id", to = "id")]
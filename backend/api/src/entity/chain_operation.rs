@@ -0,0 +1,53 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum OperationStatus {
+    /// Waiting for its turn in the saga, or for its next attempt
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    /// Claimed by a runner, which is sending it, or by a pass of `process_outbox` checking on a
+    /// runner that died
+    #[sea_orm(string_value = "submitted")]
+    Submitted,
+    #[sea_orm(string_value = "confirmed")]
+    Confirmed,
+    /// Gave up on, or skipped because an earlier step of the saga was. Its compensation was
+    /// applied
+    #[sea_orm(string_value = "failed")]
+    Failed,
+}
+
+/// A chain transaction that a committed change still has to land. The operations of a saga run
+/// one after the other, in the order of `step`
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_operation")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub saga_id: Uuid,
+    pub step: i32,
+    /// A `state::outbox::ChainOperation`
+    pub operation: Json,
+    /// `state::outbox::Compensation`s that undo the change if the operation fails for good
+    pub compensation: Json,
+    pub status: OperationStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    /// The transaction that confirmed it
+    pub signature: Option<String>,
+    /// The last transaction sent for it, looked up before it is sent again
+    pub sent_signature: Option<String>,
+    /// The last block height at which `sent_signature` can land
+    pub sent_valid_until: Option<i64>,
+    /// The fill it settles, which gets the signature
+    pub fill_id: Option<Uuid>,
+    pub next_attempt_at: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,25 @@
+use sea_orm::entity::prelude::*;
+
+use crate::entity::market::MarketOption;
+
+/// The redemption of a user's winning shares in a resolved market. Its progress is that of its
+/// saga in the outbox, which deletes the claim if it fails for good
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "claim")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    #[sea_orm(unique_key = "claimant")]
+    pub user_id: Uuid,
+    #[sea_orm(unique_key = "claimant")]
+    pub market_id: Uuid,
+    pub option: MarketOption,
+    pub shares: i64,
+    /// Micro USDC the user gets
+    pub amount: i64,
+    pub saga_id: Uuid,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
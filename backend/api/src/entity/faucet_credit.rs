@@ -0,0 +1,15 @@
+use sea_orm::entity::prelude::*;
+
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "faucet_credit")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub wallet: String,
+    pub micro_usdc: i64,
+    pub signature: Option<String>,
+    pub credited_at: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,52 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{entity::market::MarketOption, matching::Side};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum OrderSide {
+    #[sea_orm(string_value = "buy")]
+    Buy,
+    #[sea_orm(string_value = "sell")]
+    Sell,
+}
+
+impl From<Side> for OrderSide {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => OrderSide::Buy,
+            Side::Sell => OrderSide::Sell,
+        }
+    }
+}
+
+/// Shares exchanged between a new order (the taker) and one resting order (the maker)
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "fill")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub maker_user_id: Uuid,
+    pub taker_user_id: Uuid,
+    pub taker_side: OrderSide,
+    /// The option of the taker's order. A maker buying the opposite option got the other one
+    pub option: MarketOption,
+    pub shares: i64,
+    /// What the taker traded at
+    pub price_per_share: i64,
+    /// The maker's own price
+    pub maker_price_per_share: i64,
+    pub created_at: DateTimeWithTimeZone,
+    /// The transaction that settled the fill on chain, empty until it confirms
+    pub signature: String,
+    #[sea_orm(belongs_to, from = "market_id", to = "id")]
+    pub market: HasOne<super::market::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
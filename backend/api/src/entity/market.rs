@@ -36,6 +36,8 @@ pub struct Model {
     pub option_b_name: String,
     pub rules: String,
     pub resolved_option: Option<MarketOption>,
+    /// Price of option A in the latest fill, None before the first trade
+    pub last_trade_price: Option<i64>,
     #[sea_orm(belongs_to, from = "event_id", to = "id")]
     pub event: HasOne<super::event::Entity>,
     #[sea_orm(has_many)]
@@ -43,6 +45,10 @@ pub struct Model {
     #[sea_orm(has_many)]
     pub positions: HasMany<super::position::Entity>,
     #[sea_orm(has_many)]
+    pub fills: HasMany<super::fill::Entity>,
+    #[sea_orm(has_many)]
+    pub sell_orders: HasMany<super::sellorder::Entity>,
+    #[sea_orm(has_many)]
     pub snapshots: HasMany<super::market_snapshot::Entity>,
 }
 
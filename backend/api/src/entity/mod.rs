@@ -1,8 +1,17 @@
 pub mod buyorder;
+pub mod chain_operation;
+pub mod claim;
 pub mod event;
+pub mod faucet_credit;
+pub mod fill;
 pub mod identity;
 pub mod market;
 pub mod market_snapshot;
 pub mod position;
+pub mod reconciliation_discrepancy;
+pub mod reconciliation_run;
+pub mod resolution_job;
+pub mod resolution_refund;
+pub mod sellorder;
 pub mod session;
 pub mod user;
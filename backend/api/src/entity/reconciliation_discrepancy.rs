@@ -0,0 +1,69 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::market::MarketOption;
+
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    EnumIter,
+    DeriveActiveEnum,
+    Deserialize,
+    Serialize,
+)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Expected while chain operations of the market are in flight, or kept on purpose
+    #[sea_orm(string_value = "info")]
+    Info,
+    /// The chain holds more than the database accounts for
+    #[sea_orm(string_value = "warning")]
+    Warning,
+    /// The chain holds less than the database promises to users
+    #[sea_orm(string_value = "critical")]
+    Critical,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum DiscrepancyKind {
+    /// The positions of a user against their token balance, in whole shares
+    #[sea_orm(string_value = "position")]
+    Position,
+    /// Buy order escrow and matched collateral against the event's USDC, in micro USDC
+    #[sea_orm(string_value = "treasury")]
+    Treasury,
+}
+
+/// A balance on chain that does not match the database
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "reconciliation_discrepancy")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub run_id: Uuid,
+    pub kind: DiscrepancyKind,
+    pub severity: Severity,
+    pub event_id: Uuid,
+    /// None for the treasury, which is shared by the markets of the event
+    pub market_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub option: Option<MarketOption>,
+    /// What the database accounts for
+    pub expected: i64,
+    /// What the chain holds
+    pub actual: i64,
+    pub created_at: DateTimeWithTimeZone,
+    #[sea_orm(belongs_to, from = "run_id", to = "id")]
+    pub run: HasOne<super::reconciliation_run::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
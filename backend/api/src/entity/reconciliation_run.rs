@@ -0,0 +1,17 @@
+use sea_orm::entity::prelude::*;
+
+/// One pass of the reconciliation over every market
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "reconciliation_run")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub markets: i32,
+    pub started_at: DateTimeWithTimeZone,
+    pub finished_at: DateTimeWithTimeZone,
+    #[sea_orm(has_many)]
+    pub discrepancies: HasMany<super::reconciliation_discrepancy::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
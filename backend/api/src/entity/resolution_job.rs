@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::market::MarketOption;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum JobStatus {
+    /// Open orders are left to refund
+    #[sea_orm(string_value = "running")]
+    Running,
+    /// Every refund is in the outbox
+    #[sea_orm(string_value = "done")]
+    Done,
+}
+
+/// The refunds of the open orders of a resolved market, staged in batches by a worker
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "resolution_job")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    #[sea_orm(unique)]
+    pub market_id: Uuid,
+    pub option: MarketOption,
+    pub status: JobStatus,
+    /// Batches staged so far
+    pub batches: i32,
+    /// Until when a worker holds the job
+    pub locked_until: DateTimeWithTimeZone,
+    pub created_at: DateTimeWithTimeZone,
+    pub updated_at: DateTimeWithTimeZone,
+    pub finished_at: Option<DateTimeWithTimeZone>,
+    #[sea_orm(has_many)]
+    pub refunds: HasMany<super::resolution_refund::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
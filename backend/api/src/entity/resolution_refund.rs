@@ -0,0 +1,23 @@
+use sea_orm::entity::prelude::*;
+
+/// The refund of an open buy order, staged by a resolution job. Its progress is that of its saga
+/// in the outbox
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "resolution_refund")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub job_id: Uuid,
+    pub user_id: Uuid,
+    pub shares: i64,
+    /// Micro USDC the user gets back
+    pub amount: i64,
+    pub saga_id: Uuid,
+    pub batch: i32,
+    pub created_at: DateTimeWithTimeZone,
+    #[sea_orm(belongs_to, from = "job_id", to = "id")]
+    pub job: HasOne<super::resolution_job::Entity>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -19,6 +19,8 @@ pub struct Model {
     pub buy_orders: HasMany<super::buyorder::Entity>,
     #[sea_orm(has_many)]
     pub positions: HasMany<super::position::Entity>,
+    #[sea_orm(has_many)]
+    pub sell_orders: HasMany<super::sellorder::Entity>,
 }
 
 impl ActiveModelBehavior for ActiveModel {}
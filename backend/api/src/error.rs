@@ -4,6 +4,7 @@ use axum::{
     http::StatusCode,
     response::{IntoResponse, Response},
 };
+use blockchain_client::FaucetError;
 use serde::Serialize;
 
 pub type AppResult<T> = Result<T, AppError>;
@@ -32,14 +33,42 @@ pub enum AppError {
     UserNotFound,
     #[error("Market already resolved")]
     MarketAlreadyResolved,
+    #[error("Market not resolved yet")]
+    MarketNotResolved,
+    #[error("Reward already claimed")]
+    RewardAlreadyClaimed,
+    #[error("No winning shares to claim")]
+    NothingToClaim,
     #[error("Buy order not found")]
     BuyOrderNotFound,
     #[error("Not enough funds to place order")]
     InsufficientFunds,
+    #[error("Sell order not found")]
+    SellOrderNotFound,
+    #[error("No reconciliation has run yet")]
+    ReconciliationNotFound,
+    #[error("Resolution job not found")]
+    ResolutionJobNotFound,
+    #[error("Not enough shares to place order")]
+    InsufficientShares,
+    #[error("Invalid order: {0}")]
+    InvalidOrder(String),
+    #[error("Order could not be filled entirely")]
+    OrderNotFilled,
+    #[error("Invalid chart range: {0}")]
+    InvalidChartRange(String),
+    #[error("Invalid stream channel {0}")]
+    InvalidChannel(String),
     #[error("Airdrop cooldown active, try again later")]
     AirdropCooldown,
     #[error("Forbidden: {0}")]
     Forbidden(String),
+    #[error(transparent)]
+    InvalidAmount(#[from] blockchain_core::money::MoneyError),
+    #[error(transparent)]
+    Signer(#[from] blockchain_client::ProviderError),
+    #[error(transparent)]
+    Faucet(#[from] blockchain_client::FaucetError),
 }
 
 impl IntoResponse for AppError {
@@ -96,6 +125,17 @@ impl IntoResponse for AppError {
             AppError::MarketAlreadyResolved => {
                 (StatusCode::BAD_REQUEST, "Mercado já resolvido".to_string())
             }
+            AppError::MarketNotResolved => (
+                StatusCode::BAD_REQUEST,
+                "Mercado ainda não foi resolvido".to_string(),
+            ),
+            AppError::RewardAlreadyClaimed => {
+                (StatusCode::BAD_REQUEST, "Prémio já reclamado".to_string())
+            }
+            AppError::NothingToClaim => (
+                StatusCode::BAD_REQUEST,
+                "Não tens ações vencedoras neste mercado".to_string(),
+            ),
             AppError::BuyOrderNotFound => {
                 (StatusCode::NOT_FOUND, "Compra não encontrada".to_string())
             }
@@ -103,11 +143,52 @@ impl IntoResponse for AppError {
                 StatusCode::BAD_REQUEST,
                 "Fundos insuficientes para efetuar a compra".to_string(),
             ),
+            AppError::SellOrderNotFound => {
+                (StatusCode::NOT_FOUND, "Venda não encontrada".to_string())
+            }
+            AppError::ReconciliationNotFound => (
+                StatusCode::NOT_FOUND,
+                "Nenhuma reconciliação foi feita".to_string(),
+            ),
+            AppError::ResolutionJobNotFound => (
+                StatusCode::NOT_FOUND,
+                "Resolução não encontrada".to_string(),
+            ),
+            AppError::InsufficientShares => (
+                StatusCode::BAD_REQUEST,
+                "Ações insuficientes para efetuar a venda".to_string(),
+            ),
+            AppError::InvalidOrder(msg) => (StatusCode::BAD_REQUEST, msg.to_string()),
+            AppError::OrderNotFilled => (
+                StatusCode::BAD_REQUEST,
+                "A ordem não pode ser executada por completo".to_string(),
+            ),
+            AppError::InvalidChartRange(msg) => (StatusCode::BAD_REQUEST, msg.to_string()),
+            AppError::InvalidChannel(channel) => (
+                StatusCode::BAD_REQUEST,
+                format!("Canal inválido: {}", channel),
+            ),
             AppError::AirdropCooldown => (
                 StatusCode::TOO_MANY_REQUESTS,
                 "Airdrop em cooldown, tenta novamente mais tarde".to_string(),
             ),
             AppError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg.to_string()),
+            AppError::InvalidAmount(err) => {
+                tracing::error!("Invalid amount: {}", err);
+                (StatusCode::BAD_REQUEST, "Quantia inválida".to_string())
+            }
+            AppError::Signer(err) => {
+                tracing::error!("Signer error: {}", err);
+                internal_server_error
+            }
+            AppError::Faucet(FaucetError::CapExceeded { .. }) => (
+                StatusCode::BAD_REQUEST,
+                "Limite de airdrops atingido".to_string(),
+            ),
+            AppError::Faucet(err) => {
+                tracing::error!("Faucet error: {}", err);
+                internal_server_error
+            }
         };
 
         let body = ErrorBody { error };
@@ -0,0 +1,36 @@
+use std::sync::Arc;
+
+use chain::ChainGateway;
+use matching::PriceImprovement;
+use sea_orm::{DatabaseConnection, DbErr};
+use stream::Bus;
+
+pub mod chain;
+pub mod entity;
+pub mod error;
+pub mod matching;
+pub mod route;
+pub mod solana_integration;
+pub mod state;
+pub mod stream;
+pub mod utils;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub database: DatabaseConnection,
+    pub chain: Arc<dyn ChainGateway>,
+    pub price_improvement: PriceImprovement,
+    /// Where committed changes are published for the stream endpoint
+    pub bus: Bus,
+}
+
+/// Creates or updates every table of the entities
+pub async fn sync_schema(database: &DatabaseConnection) -> Result<(), DbErr> {
+    database
+        .get_schema_registry(module_path!().split("::").next().unwrap())
+        .sync(database)
+        .await?;
+
+    // The portfolio reads the tables of the indexer
+    indexer::sync_schema(database).await
+}
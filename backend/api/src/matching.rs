@@ -0,0 +1,200 @@
+//! Matching a new order against the resting orders of the book.
+//!
+//! A buy order can be filled two ways:
+//! - by a buy order of the opposite option, when both prices add up to at least the payout of a
+//!   share (`PAYOUT_CENTS`), since together they can then pay for a new YES and NO token;
+//! - by a sell order of the same option at or below its price, which hands over existing shares.
+//!
+//! A sell order is only filled by buy orders of the same option at or above its price.
+//!
+//! Resting orders are taken best price for the taker first, then oldest first, then by id so that
+//! the result never depends on the order the book was read in. The taker keeps matching, across as
+//! many price levels as needed, until it is filled or the best resting price no longer crosses.
+//!
+//! Each fill honours the maker's price. What the taker had offered beyond it (the price
+//! improvement) goes to the taker or is kept as fees, depending on `PriceImprovement`.
+
+use blockchain_core::money::{MicroUsdc, MoneyError, Price, Shares};
+use chrono::{DateTime, FixedOffset};
+use uuid::Uuid;
+
+/// Where the difference between the taker's limit and what it pays goes
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum PriceImprovement {
+    /// Refunded to the taker
+    #[default]
+    Taker,
+    /// Kept by the event
+    Fees,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// An order waiting in the book
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestingOrder {
+    pub id: Uuid,
+    pub side: Side,
+    pub user_id: Uuid,
+    pub price: Price,
+    pub shares: Shares,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+impl RestingOrder {
+    /// What a buyer filled by it pays per share. Buy orders are of the opposite option
+    pub fn price_for_buyer(&self) -> Price {
+        match self.side {
+            Side::Buy => self.price.complement(),
+            Side::Sell => self.price,
+        }
+    }
+}
+
+/// Shares exchanged between the taker and one resting order
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fill {
+    pub maker_id: Uuid,
+    pub maker_user_id: Uuid,
+    pub maker_side: Side,
+    pub shares: Shares,
+    /// Per share, the maker's own limit
+    pub maker_price: Price,
+    /// Per share, what the taker trades at given `maker_price`: its complement when the maker buys
+    /// the opposite option, the same price otherwise
+    pub taker_price: Price,
+    /// Per share, how much better `taker_price` is than the taker's limit
+    pub improvement: Price,
+}
+
+impl Fill {
+    /// Price improvement of the whole fill
+    pub fn improvement_total(&self) -> Result<MicroUsdc, MoneyError> {
+        self.improvement
+            .total(self.shares)
+            .ok_or(MoneyError::Overflow)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatchOutcome {
+    /// In the order they were matched
+    pub fills: Vec<Fill>,
+    /// Shares of the taker left to rest in the book
+    pub remaining: Shares,
+    /// Price improvement that goes to the taker
+    pub refund: MicroUsdc,
+    /// Price improvement kept as fees
+    pub fees: MicroUsdc,
+}
+
+/// Matches a taker buying `shares` at up to `price` against `book`, which must only hold buy
+/// orders of the opposite option and sell orders of the same option. `book` does not need to be
+/// sorted
+pub fn match_order(
+    price: Price,
+    shares: Shares,
+    book: &[RestingOrder],
+    rule: PriceImprovement,
+) -> Result<MatchOutcome, MoneyError> {
+    let mut makers: Vec<&RestingOrder> = book
+        .iter()
+        .filter(|maker| maker.price_for_buyer() <= price)
+        .collect();
+    makers.sort_by(|a, b| {
+        a.price_for_buyer()
+            .cmp(&b.price_for_buyer())
+            .then(a.created_at.cmp(&b.created_at))
+            .then(a.id.cmp(&b.id))
+    });
+
+    fill(shares, makers, rule, |maker| {
+        let taker_price = maker.price_for_buyer();
+        (taker_price, price.cents() - taker_price.cents())
+    })
+}
+
+/// Matches a taker selling `shares` at `price` or more against `book`, which must only hold buy
+/// orders of the same option. `book` does not need to be sorted
+pub fn match_sell(
+    price: Price,
+    shares: Shares,
+    book: &[RestingOrder],
+    rule: PriceImprovement,
+) -> Result<MatchOutcome, MoneyError> {
+    let mut makers: Vec<&RestingOrder> = book
+        .iter()
+        .filter(|maker| maker.side == Side::Buy && maker.price >= price)
+        .collect();
+    makers.sort_by(|a, b| {
+        b.price
+            .cmp(&a.price)
+            .then(a.created_at.cmp(&b.created_at))
+            .then(a.id.cmp(&b.id))
+    });
+
+    fill(shares, makers, rule, |maker| {
+        (maker.price, maker.price.cents() - price.cents())
+    })
+}
+
+/// Fills `shares` from `makers`, best first. `prices` gives the taker price and the improvement in
+/// cents of each maker
+fn fill(
+    shares: Shares,
+    makers: Vec<&RestingOrder>,
+    rule: PriceImprovement,
+    prices: impl Fn(&RestingOrder) -> (Price, u64),
+) -> Result<MatchOutcome, MoneyError> {
+    let mut outcome = MatchOutcome {
+        fills: Vec::new(),
+        remaining: shares,
+        refund: MicroUsdc(0),
+        fees: MicroUsdc(0),
+    };
+
+    for maker in makers {
+        if outcome.remaining == Shares(0) {
+            break;
+        }
+        if maker.shares == Shares(0) {
+            continue;
+        }
+
+        let (taker_price, improvement) = prices(maker);
+        let fill = Fill {
+            maker_id: maker.id,
+            maker_user_id: maker.user_id,
+            maker_side: maker.side,
+            shares: outcome.remaining.min(maker.shares),
+            maker_price: maker.price,
+            taker_price,
+            improvement: Price::new(improvement).ok_or(MoneyError::InvalidPrice)?,
+        };
+
+        let improvement = fill.improvement_total()?;
+        let collected = match rule {
+            PriceImprovement::Taker => &mut outcome.refund,
+            PriceImprovement::Fees => &mut outcome.fees,
+        };
+        *collected = collected
+            .checked_add(improvement)
+            .ok_or(MoneyError::Overflow)?;
+        outcome.remaining = outcome
+            .remaining
+            .checked_sub(fill.shares)
+            .ok_or(MoneyError::Overflow)?;
+        outcome.fills.push(fill);
+    }
+
+    Ok(outcome)
+}
+
+/// Whether bids of `a` and `b` on opposite options can pay for a share together
+pub fn crosses(a: Price, b: Price) -> bool {
+    a >= b.complement()
+}
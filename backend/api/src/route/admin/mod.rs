@@ -0,0 +1,14 @@
+use axum::{
+    Router,
+    routing::{get, post},
+};
+
+use crate::AppState;
+
+mod reconcile;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/reconcile", get(reconcile::latest))
+        .route("/reconcile", post(reconcile::run))
+}
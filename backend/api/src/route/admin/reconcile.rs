@@ -0,0 +1,23 @@
+use axum::{Json, debug_handler, extract::State};
+
+use crate::{
+    AppState, error::AppResult, route::extractors::AdminUser,
+    state::reconciliation::ReconciliationReportDto,
+};
+
+/// Compares the database to the chain now
+#[debug_handler]
+pub async fn run(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> AppResult<Json<ReconciliationReportDto>> {
+    Ok(Json(state.reconcile().await?))
+}
+
+#[debug_handler]
+pub async fn latest(
+    _admin: AdminUser,
+    State(state): State<AppState>,
+) -> AppResult<Json<ReconciliationReportDto>> {
+    Ok(Json(state.latest_reconciliation().await?))
+}
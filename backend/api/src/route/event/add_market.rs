@@ -1,6 +1,6 @@
 use axum::{
     Json, debug_handler,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
 use uuid::Uuid;
 
@@ -8,7 +8,10 @@ use crate::{
     AppState,
     error::AppResult,
     route::extractors::AdminUser,
-    state::event::{MarketDto, MarketRequest},
+    state::{
+        dry_run::{DryRunQuery, Outcome},
+        event::{MarketDto, MarketRequest},
+    },
 };
 
 #[debug_handler]
@@ -16,8 +19,11 @@ pub async fn handle(
     _admin: AdminUser,
     Path(event_id): Path<Uuid>,
     State(state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
     Json(request): Json<MarketRequest>,
-) -> AppResult<Json<MarketDto>> {
-    let market = state.add_market_to_event(event_id, request).await?;
+) -> AppResult<Json<Outcome<MarketDto>>> {
+    let market = state
+        .add_market_to_event(event_id, request, query.dry_run)
+        .await?;
     Ok(Json(market))
 }
@@ -0,0 +1,22 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, Query, State},
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::AppResult,
+    state::book::{BookQuery, OrderBookDto},
+};
+
+#[debug_handler]
+pub async fn handle(
+    Path(market_id): Path<Uuid>,
+    Query(query): Query<BookQuery>,
+    State(app_state): State<AppState>,
+) -> AppResult<Json<OrderBookDto>> {
+    let book = app_state.order_book(market_id, query.depth()).await?;
+
+    Ok(Json(book))
+}
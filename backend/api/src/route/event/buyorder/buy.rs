@@ -1,10 +1,23 @@
-use axum::{Json, debug_handler, extract::State};
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
+use blockchain_core::money::{Price, Shares};
+use chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 use crate::{
-    AppState, error::AppResult, route::extractors::ValidatedJson, state::event::MarketOptionDto,
+    AppState,
+    entity::buyorder::TimeInForce,
+    error::{AppError, AppResult},
+    route::extractors::ValidatedJson,
+    state::{
+        buyorder::{NewBuyOrder, OrderFillDto, OrderPrice},
+        dry_run::{DryRunQuery, Outcome},
+        event::MarketOptionDto,
+    },
 };
 
 #[derive(Serialize, Deserialize, Validate)]
@@ -12,39 +25,101 @@ use crate::{
 pub struct BuyOrderRequest {
     pub market_id: Uuid,
     pub user_id: Uuid,
-    #[validate(range(min = 1, max = 10000, message = "O número máximo de ações é de 10000"))]
-    pub shares: i64,
-    #[validate(range(
-        min = 1,
-        max = 99,
-        message = "Preço por ação deve estar compreendido entre 1 e 99."
-    ))]
-    pub price_per_share: i64,
+    #[validate(custom(function = "validate_shares"))]
+    pub shares: Shares,
+    #[serde(default)]
+    pub order_type: OrderType,
+    /// Required by limit orders
+    #[validate(custom(function = "validate_price"))]
+    pub price_per_share: Option<Price>,
+    /// Required by market orders, in cents above the best price of the book
+    #[validate(custom(function = "validate_slippage"))]
+    pub max_slippage: Option<Price>,
     pub option: MarketOptionDto,
+    #[serde(default)]
+    pub time_in_force: TimeInForce,
+    /// Required by good-till-date orders
+    pub expires_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OrderType {
+    #[default]
+    Limit,
+    Market,
+}
+
+impl BuyOrderRequest {
+    fn order_price(&self) -> AppResult<OrderPrice> {
+        match (self.order_type, self.price_per_share, self.max_slippage) {
+            (OrderType::Limit, Some(price), None) => Ok(OrderPrice::Limit(price)),
+            (OrderType::Market, None, Some(max_slippage)) => {
+                Ok(OrderPrice::Market { max_slippage })
+            }
+            (OrderType::Limit, _, _) => Err(AppError::InvalidOrder(
+                "Ordens limitadas precisam de um preço por ação".to_string(),
+            )),
+            (OrderType::Market, _, _) => Err(AppError::InvalidOrder(
+                "Ordens de mercado precisam de um desvio máximo de preço".to_string(),
+            )),
+        }
+    }
+}
+
+pub(crate) fn validate_shares(shares: &Shares) -> Result<(), ValidationError> {
+    if (1..=10000).contains(&shares.0) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("range")
+            .with_message("O número máximo de ações é de 10000".into()))
+    }
+}
+
+fn validate_slippage(slippage: &Price) -> Result<(), ValidationError> {
+    if slippage.cents() <= 99 {
+        Ok(())
+    } else {
+        Err(ValidationError::new("range")
+            .with_message("O desvio máximo de preço deve ser no máximo 99.".into()))
+    }
+}
+
+pub(crate) fn validate_price(price: &Price) -> Result<(), ValidationError> {
+    if (1..=99).contains(&price.cents()) {
+        Ok(())
+    } else {
+        Err(ValidationError::new("range")
+            .with_message("Preço por ação deve estar compreendido entre 1 e 99.".into()))
+    }
 }
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionResponse {
     pub transaction_urls: Vec<String>,
+    pub fill: OrderFillDto,
 }
 
 #[debug_handler]
 pub async fn handle(
     State(state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
     ValidatedJson(request): ValidatedJson<BuyOrderRequest>,
-) -> AppResult<Json<TransactionResponse>> {
-    let tx_urls = state
-        .create_buy_order(
-            request.market_id,
-            request.user_id,
-            request.shares,
-            request.price_per_share,
-            request.option,
-        )
-        .await?;
-
-    Ok(Json(TransactionResponse {
-        transaction_urls: tx_urls,
-    }))
+) -> AppResult<Json<Outcome<TransactionResponse>>> {
+    let order = NewBuyOrder {
+        market_id: request.market_id,
+        user_id: request.user_id,
+        shares: request.shares,
+        price: request.order_price()?,
+        option: request.option,
+        time_in_force: request.time_in_force,
+        expires_at: request.expires_at,
+    };
+    let outcome = state.create_buy_order(order, query.dry_run).await?;
+
+    Ok(Json(outcome.map(|placed| TransactionResponse {
+        transaction_urls: placed.tx_urls,
+        fill: placed.fill,
+    })))
 }
@@ -3,7 +3,6 @@ use axum::{
     extract::{Path, State},
 };
 use sea_orm::{EntityTrait, TransactionTrait};
-use solana_sdk::{signature::Keypair, signer::Signer};
 use uuid::Uuid;
 
 use serde::Serialize;
@@ -12,6 +11,7 @@ use crate::{
     AppState, entity,
     error::{AppError, AppResult},
     route::extractors::CurrentUser,
+    state::{dry_run::Execution, stream::MarketChange},
 };
 
 #[derive(Debug, Serialize)]
@@ -26,9 +26,13 @@ pub async fn handle(
     State(app_state): State<AppState>,
     CurrentUser(user): CurrentUser,
 ) -> AppResult<axum::Json<TransactionResponse>> {
+    let mut execution = Execution::new(false);
     let txn = app_state.database.begin().await?;
 
-    let Some(buy_order) = AppState::get_buy_order(&txn, buy_order_id).await? else {
+    let Some(buy_order) = entity::buyorder::Entity::find_by_id(buy_order_id)
+        .one(&txn)
+        .await?
+    else {
         return Err(AppError::BuyOrderNotFound);
     };
 
@@ -38,34 +42,22 @@ pub async fn handle(
         ));
     }
 
-    let market = entity::market::Entity::find_by_id(buy_order.market_id)
-        .one(&txn)
-        .await?
-        .ok_or(AppError::MarketNotFound)?;
-
-    let user_model = entity::user::Entity::find_by_id(user.id)
-        .one(&txn)
+    // Gone if it was filled or cancelled since it was read
+    app_state
+        .cancel_buy_order(&txn, buy_order.id, &mut execution)
         .await?
-        .ok_or(AppError::UserNotFound)?;
-
-    let user_wallet = Keypair::from_base58_string(&user_model.wallet);
-
-    let sig = AppState::cancel_buy_order(
-        &txn,
-        buy_order.id,
-        buy_order.shares,
-        buy_order.price_per_share,
-        market.event_id,
-        &user_wallet.pubkey(),
-        &app_state.solana,
-    )
-    .await?;
+        .ok_or(AppError::BuyOrderNotFound)?;
 
-    let tx_url = app_state.solana.get_transaction_url(&sig);
+    execution.commit(txn).await?;
+    let transaction_urls = app_state.settle(&execution).await;
 
-    txn.commit().await?;
+    app_state
+        .publish(MarketChange {
+            market_id: buy_order.market_id,
+            taker_order_id: None,
+            users: vec![user.id],
+        })
+        .await;
 
-    Ok(axum::Json(TransactionResponse {
-        transaction_urls: vec![tx_url],
-    }))
+    Ok(axum::Json(TransactionResponse { transaction_urls }))
 }
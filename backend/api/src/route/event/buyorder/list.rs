@@ -4,13 +4,16 @@ use axum::{
 };
 use uuid::Uuid;
 
-use crate::{AppState, error::AppResult, state::buyorder::BuyOrderDto};
+use crate::{
+    AppState, error::AppResult, route::extractors::CurrentUser, state::buyorder::BuyOrderDto,
+};
 
 #[debug_handler]
 pub async fn handle(
     Path(market_id): Path<Uuid>,
     State(app_state): State<AppState>,
+    CurrentUser(user): CurrentUser,
 ) -> AppResult<Json<Vec<BuyOrderDto>>> {
-    let buy_orders = app_state.list_buy_orders(market_id).await?;
+    let buy_orders = app_state.list_buy_orders(market_id, user.id).await?;
     Ok(Json(buy_orders))
 }
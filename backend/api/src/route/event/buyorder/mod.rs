@@ -5,7 +5,7 @@ use axum::{
 
 use crate::AppState;
 
-mod buy;
+pub(super) mod buy;
 mod cancel;
 mod list;
 
@@ -1,16 +1,24 @@
 use axum::{
     Json, debug_handler,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
 use uuid::Uuid;
 
-use crate::{AppState, error::AppResult, state::market_snapshot::EventChartDto};
+use crate::{
+    AppState,
+    error::AppResult,
+    state::chart::{ChartQuery, EventChartDto},
+};
 
 #[debug_handler]
 pub async fn handle(
     Path(event_id): Path<Uuid>,
+    Query(query): Query<ChartQuery>,
     State(app_state): State<AppState>,
 ) -> AppResult<Json<EventChartDto>> {
-    let chart = app_state.get_event_chart(event_id).await?;
+    let (from, to) = query.range()?;
+    let chart = app_state
+        .get_event_chart(event_id, query.interval, from, to)
+        .await?;
     Ok(Json(chart))
 }
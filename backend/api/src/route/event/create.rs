@@ -1,19 +1,26 @@
-use axum::{Json, debug_handler, extract::State};
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
 
 use crate::{
     AppState,
     error::AppResult,
     route::extractors::AdminUser,
-    state::event::{EventDto, EventRequest},
+    state::{
+        dry_run::{DryRunQuery, Outcome},
+        event::{EventDto, EventRequest},
+    },
 };
 
 #[debug_handler]
 pub async fn handle(
     _admin: AdminUser,
     State(state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
     Json(request): Json<EventRequest>,
-) -> AppResult<Json<EventDto>> {
-    let event = state.create_event(request).await?;
+) -> AppResult<Json<Outcome<EventDto>>> {
+    let event = state.create_event(request, query.dry_run).await?;
 
     Ok(Json(event))
 }
@@ -1,23 +1,30 @@
-use axum::{Json, debug_handler, extract::State};
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
 
 use crate::{
     AppState,
     error::AppResult,
     route::extractors::AdminUser,
-    state::event::{EventDto, EventRequest},
+    state::{
+        dry_run::{DryRunQuery, Outcome},
+        event::{EventDto, EventRequest},
+    },
 };
 
 #[debug_handler]
 pub async fn handle(
     _admin: AdminUser,
     State(state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
     Json(requests): Json<Vec<EventRequest>>,
-) -> AppResult<Json<Vec<EventDto>>> {
+) -> AppResult<Json<Vec<Outcome<EventDto>>>> {
     let mut events = Vec::with_capacity(requests.len());
 
     for request in requests {
         tracing::info!("Creating event: {}", request.display_name);
-        let event = state.create_event(request).await?;
+        let event = state.create_event(request, query.dry_run).await?;
         events.push(event);
     }
 
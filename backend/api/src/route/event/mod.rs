@@ -6,6 +6,7 @@ use axum::{
 use crate::AppState;
 
 mod add_market;
+mod book;
 mod buyorder;
 mod chart;
 mod create;
@@ -15,6 +16,8 @@ mod list;
 mod percentages;
 mod position;
 mod resolve;
+mod sellorder;
+mod trades;
 mod update_event;
 mod update_market;
 
@@ -27,10 +30,13 @@ pub fn router() -> Router<AppState> {
         .route("/{id}", patch(update_event::handle))
         .route("/{id}/market", post(add_market::handle))
         .route("/market/{market_id}", patch(update_market::handle))
-        .route("/resolve/{market_id}", post(resolve::handle))
+        .route("/market/{market_id}/book", get(book::handle))
+        .route("/resolve/{id}", post(resolve::handle).get(resolve::status))
         .route("/percentages", get(percentages::handle_all))
         .route("/percentages/{event_id}", get(percentages::handle))
         .route("/chart/{event_id}", get(chart::handle))
+        .route("/{id}/trades", get(trades::handle))
         .nest("/buyorder", buyorder::router())
+        .nest("/sellorder", sellorder::router())
         .nest("/position", position::router())
 }
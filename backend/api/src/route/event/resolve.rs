@@ -1,12 +1,19 @@
 use axum::{
     Json, debug_handler,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::{
-    AppState, error::AppResult, route::extractors::AdminUser, state::event::MarketOptionDto,
+    AppState,
+    error::AppResult,
+    route::extractors::AdminUser,
+    state::{
+        dry_run::{DryRunQuery, Outcome},
+        event::MarketOptionDto,
+        resolution::ResolutionJobDto,
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,22 +22,29 @@ pub struct ResolveMarketRequest {
     pub option: MarketOptionDto,
 }
 
-#[derive(Debug, Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct TransactionResponse {
-    pub transaction_urls: Vec<String>,
-}
-
+/// Records the outcome and returns the job that refunds and pays out in the background
 #[debug_handler]
 pub async fn handle(
     _admin: AdminUser,
     Path(market_id): Path<Uuid>,
     State(state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
     Json(request): Json<ResolveMarketRequest>,
-) -> AppResult<Json<TransactionResponse>> {
-    let tx_urls = state.resolve_market(market_id, request.option).await?;
+) -> AppResult<Json<Outcome<ResolutionJobDto>>> {
+    let outcome = state
+        .resolve_market(market_id, request.option, query.dry_run)
+        .await?;
+
+    Ok(Json(outcome))
+}
+
+#[debug_handler]
+pub async fn status(
+    _admin: AdminUser,
+    Path(job_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> AppResult<Json<ResolutionJobDto>> {
+    let job = state.resolution_job(job_id).await?;
 
-    Ok(Json(TransactionResponse {
-        transaction_urls: tx_urls,
-    }))
+    Ok(Json(job))
 }
@@ -0,0 +1,46 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, State},
+};
+use sea_orm::TransactionTrait;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::{AppError, AppResult},
+    route::extractors::CurrentUser,
+    state::{sellorder::SellOrderDto, stream::MarketChange},
+};
+
+#[debug_handler]
+pub async fn handle(
+    Path(sell_order_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> AppResult<Json<SellOrderDto>> {
+    let txn = app_state.database.begin().await?;
+
+    let Some(sell_order) = AppState::get_sell_order(&txn, sell_order_id).await? else {
+        return Err(AppError::SellOrderNotFound);
+    };
+
+    if sell_order.user_id != user.id {
+        return Err(AppError::Unauthorized(
+            "Não podes cancelar ordens de venda que não são tuas".to_string(),
+        ));
+    }
+
+    AppState::cancel_sell_order(&txn, &sell_order).await?;
+
+    txn.commit().await?;
+
+    app_state
+        .publish(MarketChange {
+            market_id: sell_order.market_id,
+            taker_order_id: None,
+            users: vec![user.id],
+        })
+        .await;
+
+    Ok(Json(sell_order))
+}
@@ -0,0 +1,19 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, State},
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState, error::AppResult, route::extractors::CurrentUser, state::sellorder::SellOrderDto,
+};
+
+#[debug_handler]
+pub async fn handle(
+    Path(market_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+) -> AppResult<Json<Vec<SellOrderDto>>> {
+    let sell_orders = app_state.list_sell_orders(market_id, user.id).await?;
+    Ok(Json(sell_orders))
+}
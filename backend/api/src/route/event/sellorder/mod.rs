@@ -0,0 +1,17 @@
+use axum::{
+    Router,
+    routing::{get, post},
+};
+
+use crate::AppState;
+
+mod cancel;
+mod list;
+mod sell;
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/", post(sell::handle))
+        .route("/{market_id}", get(list::handle))
+        .route("/cancel/{order_id}", post(cancel::handle))
+}
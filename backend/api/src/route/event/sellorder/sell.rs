@@ -0,0 +1,56 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
+use blockchain_core::money::{Price, Shares};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::{
+    AppState,
+    error::AppResult,
+    route::{
+        event::buyorder::buy::{TransactionResponse, validate_price, validate_shares},
+        extractors::{CurrentUser, ValidatedJson},
+    },
+    state::{
+        dry_run::{DryRunQuery, Outcome},
+        event::MarketOptionDto,
+    },
+};
+
+#[derive(Serialize, Deserialize, Validate)]
+#[serde(rename_all = "camelCase")]
+pub struct SellOrderRequest {
+    pub market_id: Uuid,
+    #[validate(custom(function = "validate_shares"))]
+    pub shares: Shares,
+    #[validate(custom(function = "validate_price"))]
+    pub price_per_share: Price,
+    pub option: MarketOptionDto,
+}
+
+#[debug_handler]
+pub async fn handle(
+    State(state): State<AppState>,
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<DryRunQuery>,
+    ValidatedJson(request): ValidatedJson<SellOrderRequest>,
+) -> AppResult<Json<Outcome<TransactionResponse>>> {
+    let outcome = state
+        .create_sell_order(
+            request.market_id,
+            user.id,
+            request.shares,
+            request.price_per_share,
+            request.option,
+            query.dry_run,
+        )
+        .await?;
+
+    Ok(Json(outcome.map(|placed| TransactionResponse {
+        transaction_urls: placed.tx_urls,
+        fill: placed.fill,
+    })))
+}
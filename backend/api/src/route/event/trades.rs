@@ -0,0 +1,24 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, Query, State},
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::AppResult,
+    state::fill::{PublicTradeDto, TradePage, TradesQuery},
+};
+
+#[debug_handler]
+pub async fn handle(
+    Path(event_id): Path<Uuid>,
+    Query(query): Query<TradesQuery>,
+    State(app_state): State<AppState>,
+) -> AppResult<Json<TradePage<PublicTradeDto>>> {
+    let trades = app_state
+        .list_event_trades(event_id, query.cursor, query.limit())
+        .await?;
+
+    Ok(Json(trades))
+}
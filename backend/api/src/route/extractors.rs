@@ -1,5 +1,8 @@
 use axum::{
-    extract::{FromRequest, FromRequestParts, Json, Request, rejection::JsonRejection},
+    extract::{
+        FromRequest, FromRequestParts, Json, OptionalFromRequestParts, Request,
+        rejection::JsonRejection,
+    },
     http::request::Parts,
 };
 use axum_extra::extract::CookieJar;
@@ -60,6 +63,25 @@ impl FromRequestParts<AppState> for CurrentUser {
     }
 }
 
+/// No session cookie is no user, while a bad one is still rejected
+impl OptionalFromRequestParts<AppState> for CurrentUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        if jar.get(AUTH_SESSION_COOKIE_NAME).is_none() {
+            return Ok(None);
+        }
+
+        <CurrentUser as FromRequestParts<AppState>>::from_request_parts(parts, state)
+            .await
+            .map(Some)
+    }
+}
+
 impl FromRequestParts<AppState> for AdminUser {
     type Rejection = AppError;
 
@@ -67,7 +89,8 @@ impl FromRequestParts<AppState> for AdminUser {
         parts: &mut Parts,
         state: &AppState,
     ) -> Result<Self, Self::Rejection> {
-        let CurrentUser(user) = CurrentUser::from_request_parts(parts, state).await?;
+        let CurrentUser(user) =
+            <CurrentUser as FromRequestParts<AppState>>::from_request_parts(parts, state).await?;
 
         if !user.is_admin() {
             return Err(AppError::Forbidden("admin access required".to_string()));
@@ -1,14 +1,18 @@
-use axum::Router;
+use axum::{Router, routing::get};
 
 use crate::AppState;
 
+mod admin;
 mod event;
 mod extractors;
+mod stream;
 mod user;
 
 pub fn router(state: AppState) -> Router {
     Router::new()
         .nest("/api/event", event::router())
         .nest("/api/user", user::router())
+        .nest("/api/admin", admin::router())
+        .route("/api/stream", get(stream::handle))
         .with_state(state)
 }
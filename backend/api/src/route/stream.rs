@@ -0,0 +1,70 @@
+use std::{collections::HashMap, sync::Arc};
+
+use axum::{
+    debug_handler,
+    extract::{Query, State},
+    response::sse::{Event, KeepAlive, Sse},
+};
+use serde::Deserialize;
+use tokio_stream::{Stream, StreamExt, wrappers::BroadcastStream};
+
+use crate::{
+    AppState,
+    error::{AppError, AppResult},
+    route::extractors::CurrentUser,
+    stream::Channel,
+};
+
+const MAX_CHANNELS: usize = 50;
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Comma separated, e.g. `market:{id}:book,event:{id}:percentages`
+    channels: String,
+}
+
+/// Server-sent events with a snapshot of every channel, then their deltas. A subscriber that
+/// falls too far behind is disconnected and reconnects for new snapshots
+#[debug_handler]
+pub async fn handle(
+    Query(query): Query<StreamQuery>,
+    State(app_state): State<AppState>,
+    user: Option<CurrentUser>,
+) -> AppResult<Sse<impl Stream<Item = Result<Event, axum::Error>>>> {
+    let user_id = user.map(|CurrentUser(user)| user.id);
+    let channels = query
+        .channels
+        .split(',')
+        .map(|name| Channel::parse(name.trim(), user_id))
+        .collect::<AppResult<Vec<_>>>()?;
+
+    if channels.len() > MAX_CHANNELS {
+        return Err(AppError::InvalidChannel(format!(
+            "no máximo {MAX_CHANNELS} canais"
+        )));
+    }
+
+    // Subscribed before the snapshots, so no message after them is missed
+    let receiver = app_state.bus.subscribe();
+
+    let mut snapshots = Vec::with_capacity(channels.len());
+    let mut seqs = HashMap::new();
+    for channel in channels {
+        let snapshot = app_state.stream_snapshot(channel).await?;
+        seqs.insert(channel, snapshot.seq);
+        snapshots.push(Arc::new(snapshot));
+    }
+
+    let deltas = BroadcastStream::new(receiver)
+        .map_while(Result::ok)
+        .filter(move |message| {
+            seqs.get(&message.channel)
+                .is_some_and(|seq| message.seq > *seq)
+        });
+
+    let events = tokio_stream::iter(snapshots)
+        .chain(deltas)
+        .map(|message| Event::default().json_data(&*message));
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
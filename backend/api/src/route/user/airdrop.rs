@@ -1,4 +1,5 @@
 use axum::{Json, debug_handler, extract::State};
+use blockchain_core::money::Cents;
 use serde::Serialize;
 
 use crate::{AppState, error::AppResult, route::extractors::CurrentUser};
@@ -30,6 +31,6 @@ pub async fn request(
     State(state): State<AppState>,
 ) -> AppResult<()> {
     // airdrop 10 USDC
-    state.request_airdrop(user.id, 10 * 100).await?;
+    state.request_airdrop(user.id, Cents(10 * 100)).await?;
     Ok(())
 }
@@ -1,4 +1,5 @@
 use axum::{Json, debug_handler, extract::State};
+use blockchain_core::money::Cents;
 use serde::Serialize;
 
 use crate::{AppState, error::AppResult, route::extractors::CurrentUser};
@@ -6,7 +7,7 @@ use crate::{AppState, error::AppResult, route::extractors::CurrentUser};
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceResponse {
-    pub balance_cents: u64,
+    pub balance_cents: Cents,
 }
 
 #[debug_handler]
@@ -14,15 +15,6 @@ pub async fn handle(
     CurrentUser(current_user): CurrentUser,
     State(state): State<AppState>,
 ) -> AppResult<Json<BalanceResponse>> {
-    let balance_cents = match state.get_balance_in_cents(current_user.id).await {
-        Ok(balance) => balance,
-        Err(e) => {
-            if e.to_string().contains("AccountNotFound") {
-                0
-            } else {
-                return Err(e);
-            }
-        }
-    };
+    let balance_cents = state.get_balance_in_cents(current_user.id).await?;
     Ok(Json(BalanceResponse { balance_cents }))
 }
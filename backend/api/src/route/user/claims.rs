@@ -0,0 +1,39 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Path, Query, State},
+};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    error::AppResult,
+    route::extractors::CurrentUser,
+    state::{
+        claim::{ClaimDto, UnclaimedRewardDto},
+        dry_run::{DryRunQuery, Outcome},
+    },
+};
+
+#[debug_handler]
+pub async fn list(
+    CurrentUser(user): CurrentUser,
+    State(app_state): State<AppState>,
+) -> AppResult<Json<Vec<UnclaimedRewardDto>>> {
+    let rewards = app_state.unclaimed_rewards(user.id).await?;
+
+    Ok(Json(rewards))
+}
+
+#[debug_handler]
+pub async fn claim(
+    CurrentUser(user): CurrentUser,
+    Path(market_id): Path<Uuid>,
+    State(app_state): State<AppState>,
+    Query(query): Query<DryRunQuery>,
+) -> AppResult<Json<Outcome<ClaimDto>>> {
+    let outcome = app_state
+        .claim_reward(user.id, market_id, query.dry_run)
+        .await?;
+
+    Ok(Json(outcome))
+}
@@ -5,6 +5,7 @@ use uuid::Uuid;
 use crate::{AppState, error::AppResult, route::extractors::AUTH_SESSION_COOKIE_NAME};
 
 #[debug_handler]
+#[allow(clippy::collapsible_if, clippy::needless_borrow)]
 pub async fn handle(jar: CookieJar, State(state): State<AppState>) -> AppResult<StatusCode> {
     if let Some(session_cookie) = jar.get(&AUTH_SESSION_COOKIE_NAME) {
         if let Ok(session_id) = Uuid::parse_str(session_cookie.value()) {
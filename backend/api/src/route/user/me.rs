@@ -18,7 +18,5 @@ pub async fn handle(
     CurrentUser(user): CurrentUser,
     _state: State<AppState>,
 ) -> AppResult<Json<MeResponse>> {
-    Ok(Json(MeResponse {
-        user,
-    }))
+    Ok(Json(MeResponse { user }))
 }
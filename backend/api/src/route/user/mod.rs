@@ -13,12 +13,15 @@ use crate::{AppState, state::user::UserDto};
 
 mod airdrop;
 mod balance;
+mod claims;
 mod leaderboard;
 mod login;
 mod logout;
 mod me;
+mod portfolio;
 mod positions;
 mod register;
+mod trades;
 
 pub static USERNAME_REGEX: LazyLock<Regex> =
     LazyLock::new(|| Regex::new(r"^[A-Za-z0-9_.]+$").expect("valid username regex"));
@@ -58,5 +61,9 @@ pub fn router() -> axum::Router<AppState> {
         .route("/airdrop", post(airdrop::request))
         .route("/balance", get(balance::handle))
         .route("/positions", get(positions::handle))
+        .route("/portfolio", get(portfolio::handle))
         .route("/leaderboard", get(leaderboard::handle))
+        .route("/trades", get(trades::handle))
+        .route("/claims", get(claims::list))
+        .route("/claims/{market_id}", post(claims::claim))
 }
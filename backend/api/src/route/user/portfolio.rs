@@ -0,0 +1,15 @@
+use axum::{Json, debug_handler, extract::State};
+
+use crate::{
+    AppState, error::AppResult, route::extractors::CurrentUser, state::portfolio::PortfolioEntryDto,
+};
+
+#[debug_handler]
+pub async fn handle(
+    CurrentUser(user): CurrentUser,
+    State(app_state): State<AppState>,
+) -> AppResult<Json<Vec<PortfolioEntryDto>>> {
+    let portfolio = app_state.user_portfolio(user.id).await?;
+
+    Ok(Json(portfolio))
+}
@@ -0,0 +1,24 @@
+use axum::{
+    Json, debug_handler,
+    extract::{Query, State},
+};
+
+use crate::{
+    AppState,
+    error::AppResult,
+    route::extractors::CurrentUser,
+    state::fill::{TradePage, TradesQuery},
+};
+
+#[debug_handler]
+pub async fn handle(
+    CurrentUser(user): CurrentUser,
+    Query(query): Query<TradesQuery>,
+    State(app_state): State<AppState>,
+) -> AppResult<Json<TradePage>> {
+    let trades = app_state
+        .list_user_trades(user.id, query.cursor, query.limit())
+        .await?;
+
+    Ok(Json(trades))
+}
@@ -1,5 +1,5 @@
+use blockchain_core::money::Cents;
 use sea_orm::EntityTrait;
-use solana_sdk::{signature::Keypair, signer::Signer};
 use uuid::Uuid;
 
 use crate::{
@@ -8,16 +8,16 @@ use crate::{
 };
 
 impl AppState {
-    pub async fn get_balance_in_cents(&self, user_id: Uuid) -> AppResult<u64> {
+    pub async fn get_balance_in_cents(&self, user_id: Uuid) -> AppResult<Cents> {
         let user = entity::user::Entity::find_by_id(user_id)
             .one(&self.database)
             .await?
             .ok_or(AppError::UserNotFound)?;
 
-        let wallet = Keypair::from_base58_string(&user.wallet);
+        let wallet = self.chain.signers().pubkey(&user.wallet)?;
 
-        let usdc = self.solana.fetch_usdc(&wallet.pubkey()).await?;
+        let usdc = self.chain.usdc_balance(&wallet).await?;
 
-        return Ok(usdc.amount / 10000);
+        Ok(usdc.to_cents())
     }
 }
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use blockchain_core::money::PAYOUT_CENTS;
+use chrono::Utc;
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self, market::MarketOption},
+    error::{AppError, AppResult},
+    state::buyorder::not_expired,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BookQuery {
+    /// Price levels per side
+    pub depth: Option<usize>,
+}
+
+impl BookQuery {
+    pub const DEFAULT_DEPTH: usize = 10;
+    pub const MAX_DEPTH: usize = 50;
+
+    pub fn depth(&self) -> usize {
+        self.depth
+            .unwrap_or(Self::DEFAULT_DEPTH)
+            .clamp(1, Self::MAX_DEPTH)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceLevelDto {
+    pub price: i64,
+    pub shares: i64,
+    pub orders: i64,
+}
+
+/// The orders of both options as a single book, in prices of option A. A bid for B at N cents
+/// is an ask for A at 100 - N, and an ask for B is a bid for A the same way
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderBookDto {
+    pub market_id: Uuid,
+    /// Best first, highest price
+    pub bids: Vec<PriceLevelDto>,
+    /// Best first, lowest price
+    pub asks: Vec<PriceLevelDto>,
+    pub best_bid: Option<i64>,
+    pub best_ask: Option<i64>,
+    /// `best_ask - best_bid`
+    pub spread: Option<i64>,
+}
+
+/// The price of the other option
+fn complement(cents: i64) -> i64 {
+    PAYOUT_CENTS as i64 - cents
+}
+
+/// A price of `option` in prices of option A
+pub(crate) fn price_of_a(option: &MarketOption, cents: i64) -> i64 {
+    match option {
+        MarketOption::A => cents,
+        MarketOption::B => complement(cents),
+    }
+}
+
+/// The best bid and ask of the orders, in prices of option A
+pub(crate) fn best_quotes(
+    buy_orders: &[entity::buyorder::Model],
+    sell_orders: &[entity::sellorder::Model],
+) -> (Option<i64>, Option<i64>) {
+    let (bids, asks) = levels(buy_orders, sell_orders);
+    (
+        bids.0.keys().next_back().copied(),
+        asks.0.keys().next().copied(),
+    )
+}
+
+#[derive(Default)]
+struct Levels(BTreeMap<i64, PriceLevelDto>);
+
+impl Levels {
+    fn add(&mut self, price: i64, shares: i64) {
+        let level = self.0.entry(price).or_insert(PriceLevelDto {
+            price,
+            shares: 0,
+            orders: 0,
+        });
+        level.shares += shares;
+        level.orders += 1;
+    }
+}
+
+/// Bids and asks of option A
+fn levels(
+    buy_orders: &[entity::buyorder::Model],
+    sell_orders: &[entity::sellorder::Model],
+) -> (Levels, Levels) {
+    let mut bids = Levels::default();
+    let mut asks = Levels::default();
+
+    for order in buy_orders.iter().filter(|order| order.shares > 0) {
+        let price = price_of_a(&order.option, order.price_per_share);
+        match order.option {
+            MarketOption::A => bids.add(price, order.shares),
+            MarketOption::B => asks.add(price, order.shares),
+        }
+    }
+    for order in sell_orders.iter().filter(|order| order.shares > 0) {
+        let price = price_of_a(&order.option, order.price_per_share);
+        match order.option {
+            MarketOption::A => asks.add(price, order.shares),
+            MarketOption::B => bids.add(price, order.shares),
+        }
+    }
+
+    (bids, asks)
+}
+
+impl AppState {
+    pub async fn order_book(&self, market_id: Uuid, depth: usize) -> AppResult<OrderBookDto> {
+        entity::market::Entity::find_by_id(market_id)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::MarketNotFound)?;
+
+        let buy_orders = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::MarketId.eq(market_id))
+            .filter(entity::buyorder::Column::Shares.gt(0))
+            .filter(not_expired(Utc::now()))
+            .all(&self.database)
+            .await?;
+        let sell_orders = entity::sellorder::Entity::find()
+            .filter(entity::sellorder::Column::MarketId.eq(market_id))
+            .filter(entity::sellorder::Column::Shares.gt(0))
+            .all(&self.database)
+            .await?;
+
+        let (bids, asks) = levels(&buy_orders, &sell_orders);
+        let bids: Vec<PriceLevelDto> = bids.0.into_values().rev().take(depth).collect();
+        let asks: Vec<PriceLevelDto> = asks.0.into_values().take(depth).collect();
+        let best_bid = bids.first().map(|level| level.price);
+        let best_ask = asks.first().map(|level| level.price);
+
+        Ok(OrderBookDto {
+            market_id,
+            bids,
+            asks,
+            best_bid,
+            best_ask,
+            spread: best_bid.zip(best_ask).map(|(bid, ask)| ask - bid),
+        })
+    }
+}
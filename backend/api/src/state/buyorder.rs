@@ -1,26 +1,28 @@
-use blockchain_client::ProfeciaClient;
-use blockchain_core::instructions::{FakeCancelOrderArgs, FakeCreateOrderArgs, FakeMatchOrderArgs};
-use chrono::Utc;
+use blockchain_core::money::{MoneyError, Price, Shares};
+use chrono::{DateTime, FixedOffset, Utc};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
-    TransactionTrait,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, EntityTrait, QueryFilter,
+    QuerySelect, TransactionTrait,
 };
 use serde::{Deserialize, Serialize};
-use solana_sdk::{
-    pubkey::Pubkey,
-    signature::{Keypair, Signature},
-    signer::Signer,
-};
 use uuid::Uuid;
 
 use crate::{
     AppState,
-    entity::{self, market::MarketOption},
+    entity::{self, buyorder::TimeInForce, fill::OrderSide, market::MarketOption},
     error::{AppError, AppResult},
-    state::event::MarketOptionDto,
+    matching::{MatchOutcome, PriceImprovement, RestingOrder, Side, match_order},
+    state::{
+        dry_run::{Execution, Outcome},
+        event::MarketOptionDto,
+        fill::Taker,
+        outbox::{ChainOperation, Compensation, Step},
+        position::Lot,
+        stream::MarketChange,
+    },
 };
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct BuyOrderDto {
     pub id: Uuid,
@@ -29,6 +31,8 @@ pub struct BuyOrderDto {
     pub shares: i64,
     pub price_per_share: i64,
     pub option: MarketOptionDto,
+    pub time_in_force: TimeInForce,
+    pub expires_at: Option<DateTime<FixedOffset>>,
 }
 
 impl From<entity::buyorder::Model> for BuyOrderDto {
@@ -40,21 +44,162 @@ impl From<entity::buyorder::Model> for BuyOrderDto {
             shares: model.shares,
             price_per_share: model.price_per_share,
             option: MarketOptionDto::from(model.option),
+            time_in_force: model.time_in_force,
+            expires_at: model.expires_at,
         }
     }
 }
 
+impl TryFrom<&entity::buyorder::Model> for RestingOrder {
+    type Error = MoneyError;
+
+    fn try_from(order: &entity::buyorder::Model) -> Result<Self, Self::Error> {
+        Ok(RestingOrder {
+            id: order.id,
+            side: Side::Buy,
+            user_id: order.user_id,
+            price: Price::try_from(order.price_per_share)?,
+            shares: Shares::try_from(order.shares)?,
+            created_at: order.created_at,
+        })
+    }
+}
+
+/// The price a new buy order is willing to pay
+#[derive(Debug, Clone, Copy)]
+pub enum OrderPrice {
+    Limit(Price),
+    /// Takes the best price in the book, and every level up to `max_slippage` above it
+    Market {
+        max_slippage: Price,
+    },
+}
+
+#[derive(Debug)]
+pub struct NewBuyOrder {
+    pub market_id: Uuid,
+    pub user_id: Uuid,
+    pub shares: Shares,
+    pub price: OrderPrice,
+    pub option: MarketOptionDto,
+    pub time_in_force: TimeInForce,
+    pub expires_at: Option<DateTime<FixedOffset>>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FillDto {
+    pub shares: Shares,
+    pub price_per_share: Price,
+}
+
+/// What happened to an order as it was placed
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OrderFillDto {
+    pub filled_shares: Shares,
+    pub resting_shares: Shares,
+    /// Dropped because of the time in force
+    pub cancelled_shares: Shares,
+    /// The order left in the book, if any
+    pub order_id: Option<Uuid>,
+    pub fills: Vec<FillDto>,
+}
+
+impl OrderFillDto {
+    pub fn new(
+        outcome: &MatchOutcome,
+        resting: Shares,
+        order_id: Option<Uuid>,
+    ) -> Result<Self, MoneyError> {
+        let filled = outcome
+            .fills
+            .iter()
+            .try_fold(Shares(0), |total, fill| total.checked_add(fill.shares))
+            .ok_or(MoneyError::Overflow)?;
+
+        Ok(Self {
+            filled_shares: filled,
+            resting_shares: resting,
+            cancelled_shares: outcome
+                .remaining
+                .checked_sub(resting)
+                .ok_or(MoneyError::Overflow)?,
+            order_id,
+            fills: outcome
+                .fills
+                .iter()
+                .map(|fill| FillDto {
+                    shares: fill.shares,
+                    price_per_share: fill.taker_price,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct PlacedOrder {
+    pub tx_urls: Vec<String>,
+    pub fill: OrderFillDto,
+}
+
+/// Buy orders that can still fill. Good-till-date ones stop at their expiry, even before
+/// `expire_buy_orders` gets to them
+pub(crate) fn not_expired(now: DateTime<Utc>) -> Condition {
+    Condition::any()
+        .add(entity::buyorder::Column::ExpiresAt.is_null())
+        .add(entity::buyorder::Column::ExpiresAt.gt(now))
+}
+
+/// Gives the user back what the order paid upfront for its shares
+pub(crate) fn refund_operation(order: &entity::buyorder::Model) -> AppResult<ChainOperation> {
+    Ok(ChainOperation::CancelOrder {
+        user_id: order.user_id,
+        market_id: order.market_id,
+        shares: order.shares.try_into()?,
+        price: Price::try_from(order.price_per_share)?,
+    })
+}
+
 impl AppState {
     pub async fn create_buy_order(
         &self,
-        market_id: Uuid,
-        user_id: Uuid,
-        shares: i64,
-        cents_per_share: i64,
-        option: MarketOptionDto,
-    ) -> AppResult<Vec<String>> {
+        order: NewBuyOrder,
+        dry_run: bool,
+    ) -> AppResult<Outcome<PlacedOrder>> {
+        let NewBuyOrder {
+            market_id,
+            user_id,
+            shares,
+            price: order_price,
+            option,
+            time_in_force,
+            expires_at,
+        } = order;
+
+        match (time_in_force, expires_at) {
+            (TimeInForce::Gtd, Some(expires_at)) if expires_at > Utc::now() => {}
+            (TimeInForce::Gtd, _) => {
+                return Err(AppError::InvalidOrder(
+                    "A data de expiração tem de ser no futuro".to_string(),
+                ));
+            }
+            (_, Some(_)) => {
+                return Err(AppError::InvalidOrder(
+                    "Só ordens com validade até uma data podem expirar".to_string(),
+                ));
+            }
+            (_, None) => {}
+        }
+        if matches!(order_price, OrderPrice::Market { .. }) && time_in_force.rests() {
+            return Err(AppError::InvalidOrder(
+                "Ordens de mercado não ficam no livro de ordens".to_string(),
+            ));
+        }
+
+        let mut execution = Execution::new(dry_run);
         let transaction = self.database.begin().await?;
-        let mut tx_urls: Vec<String> = Vec::new();
 
         let market = entity::market::Entity::find_by_id(market_id)
             .one(&transaction)
@@ -70,30 +215,7 @@ impl AppState {
             return Err(AppError::MarketAlreadyResolved);
         }
 
-        let event_id = market.event_id;
-        // let event_pda = blockchain_client::ProfeciaClient::derive_event_pubkey(&event_id);
-        // tracing::error!("event pda: {}", event_pda);
-
-        // check balance
-        let usdc_per_share = cents_per_share * 10000;
-        let user_wallet = Keypair::from_base58_string(user.wallet.as_str());
-
-        let user_ata_amount = match self.solana.fetch_usdc(&user_wallet.pubkey()).await {
-            Ok(user_ata) => user_ata.amount,
-            Err(e) => {
-                // Account doesn't exist => balance is 0
-                if e.to_string().contains("AccountNotFound") {
-                    0
-                } else {
-                    return Err(e.into());
-                }
-            }
-        };
-        let necessary_usdc: u64 = (usdc_per_share * shares).try_into().unwrap();
-
-        if necessary_usdc >= user_ata_amount {
-            return Err(AppError::InsufficientFunds);
-        }
+        let user_wallet = self.chain.signers().pubkey(&user.wallet)?;
 
         let option = match option {
             MarketOptionDto::OptionA => entity::market::MarketOption::A,
@@ -101,42 +223,118 @@ impl AppState {
         };
 
         let opposing_option = option.opposite();
-        let opposing_price = 100 - cents_per_share;
-
-        // blockchain tx to transfer funds
-        let create_order_args = FakeCreateOrderArgs {
-            event_uuid: event_id,
-            option_uuid: market_id,
-            num_shares: shares.try_into().unwrap(),
-            price_per_share: usdc_per_share.try_into().unwrap(),
-        };
-        let sig = self
-            .solana
-            .create_order(&user_wallet, &create_order_args)
-            .await?;
-        tx_urls.push(self.solana.get_transaction_url(&sig));
 
-        let token_yes = Keypair::from_base58_string(&market.yes_keypair).pubkey();
-        let token_no = Keypair::from_base58_string(&market.no_keypair).pubkey();
+        // Market orders look at the whole book
+        let ceiling = match order_price {
+            OrderPrice::Limit(price) => price,
+            OrderPrice::Market { .. } => Price::MAX,
+        };
 
-        // Find opposing buy orders that match (price_per_share == 100 - our price)
+        // Opposing buy orders whose price crosses ours
         let opposing_orders = entity::buyorder::Entity::find()
             .filter(entity::buyorder::Column::MarketId.eq(market.id))
             .filter(entity::buyorder::Column::Option.eq(opposing_option.clone()))
-            .filter(entity::buyorder::Column::PricePerShare.eq(opposing_price))
+            .filter(
+                entity::buyorder::Column::PricePerShare.gte(i64::try_from(ceiling.complement())?),
+            )
             .filter(entity::buyorder::Column::Shares.gt(0))
-            .order_by_asc(entity::buyorder::Column::CreatedAt)
+            .filter(not_expired(Utc::now()))
+            // a concurrent cancel must not refund what this fills
+            .lock_exclusive()
             .all(&transaction)
             .await?;
 
-        let mut my_remaining = shares;
+        // Sell orders of our option at or below our price
+        let asks = entity::sellorder::Entity::find()
+            .filter(entity::sellorder::Column::MarketId.eq(market.id))
+            .filter(entity::sellorder::Column::Option.eq(option.clone()))
+            .filter(entity::sellorder::Column::PricePerShare.lte(i64::try_from(ceiling)?))
+            .filter(entity::sellorder::Column::Shares.gt(0))
+            // two buys must not fill the same ask
+            .lock_exclusive()
+            .all(&transaction)
+            .await?;
+
+        let book = opposing_orders
+            .iter()
+            .map(RestingOrder::try_from)
+            .chain(asks.iter().map(RestingOrder::try_from))
+            .collect::<Result<Vec<_>, MoneyError>>()?;
+
+        let price = match order_price {
+            OrderPrice::Limit(price) => price,
+            OrderPrice::Market { max_slippage } => book
+                .iter()
+                .filter(|order| order.shares > Shares(0))
+                .map(RestingOrder::price_for_buyer)
+                .min()
+                .map(|best| Price::new(best.cents() + max_slippage.cents()).unwrap_or(Price::MAX))
+                .unwrap_or(Price::MIN),
+        };
+        let outcome = match_order(price, shares, &book, self.price_improvement)?;
+
+        if time_in_force == TimeInForce::Fok && outcome.remaining > Shares(0) {
+            return Err(AppError::OrderNotFilled);
+        }
+        let resting = if time_in_force.rests() {
+            outcome.remaining
+        } else {
+            Shares(0)
+        };
+
+        // Only what fills or rests is paid upfront
+        let committed = shares
+            .checked_sub(outcome.remaining)
+            .and_then(|filled| filled.checked_add(resting))
+            .ok_or(MoneyError::Overflow)?;
+
+        let taker = Taker {
+            order_id: Uuid::new_v4(),
+            user_id,
+            side: OrderSide::Buy,
+            option: option.clone(),
+        };
 
-        for opposing in opposing_orders {
-            if my_remaining == 0 {
-                break;
+        // The deposit comes first, refunding a fill only makes sense once it landed
+        let mut deposit = 0;
+        if committed > Shares(0) {
+            // check balance
+            let user_usdc = self.chain.usdc_balance(&user_wallet).await?;
+            let necessary_usdc = price.total(committed).ok_or(MoneyError::Overflow)?;
+
+            if necessary_usdc > user_usdc {
+                return Err(AppError::InsufficientFunds);
             }
 
-            let matched_qty = my_remaining.min(opposing.shares);
+            // Without the deposit, the order has nothing to rest on
+            let compensation = if resting > Shares(0) {
+                vec![Compensation::DeleteBuyOrder {
+                    order_id: taker.order_id,
+                }]
+            } else {
+                Vec::new()
+            };
+            let create_order = ChainOperation::CreateOrder {
+                user_id,
+                market_id,
+                shares: committed,
+                price,
+            };
+            deposit = self
+                .stage(
+                    &transaction,
+                    &mut execution,
+                    Step::new(create_order).with_compensation(compensation),
+                )
+                .await?;
+        }
+
+        for fill in &outcome.fills {
+            let matched_qty_db = i64::try_from(fill.shares)?;
+            let taker_lot = Lot {
+                shares: matched_qty_db,
+                price_per_share: i64::try_from(fill.taker_price)?,
+            };
 
             // Create position for the new order's user (gets shares of their chosen option)
             AppState::upsert_position(
@@ -144,28 +342,150 @@ impl AppState {
                 market.id,
                 user_id,
                 option.clone(),
-                matched_qty,
-                cents_per_share,
+                taker_lot.shares,
+                taker_lot.price_per_share,
             )
             .await?;
 
+            if fill.maker_side == Side::Sell {
+                let ask = asks
+                    .iter()
+                    .find(|order| order.id == fill.maker_id)
+                    .expect("Fills come from the book");
+                // What is left of it once the lock is ours
+                let ask = entity::sellorder::Entity::find_by_id(ask.id)
+                    .lock_exclusive()
+                    .one(&transaction)
+                    .await?
+                    .filter(|locked| locked.shares >= matched_qty_db)
+                    .ok_or(AppError::SellOrderNotFound)?;
+
+                let sold = AppState::reduce_position(
+                    &transaction,
+                    market.id,
+                    ask.user_id,
+                    option.clone(),
+                    matched_qty_db,
+                )
+                .await?;
+
+                // Update or delete the sell order
+                let new_ask_shares = ask.shares - matched_qty_db;
+
+                if new_ask_shares == 0 {
+                    entity::sellorder::Entity::delete_by_id(ask.id)
+                        .exec(&transaction)
+                        .await?;
+                } else {
+                    let mut ask_active: entity::sellorder::ActiveModel = ask.clone().into();
+                    ask_active.shares = Set(new_ask_shares);
+                    ask_active.update(&transaction).await?;
+                }
+
+                let fill_id = AppState::record_fill(&transaction, market.id, &taker, fill).await?;
+
+                // The order paid its limit upfront: give back what it owes the seller, and the
+                // improvement unless it is kept as fees
+                let released = match self.price_improvement {
+                    PriceImprovement::Taker => price,
+                    PriceImprovement::Fees => fill.taker_price,
+                };
+                let release = ChainOperation::CancelOrder {
+                    user_id,
+                    market_id,
+                    shares: fill.shares,
+                    price: released,
+                };
+                let refund_release = Compensation::Chain {
+                    operation: release.clone(),
+                    after: deposit,
+                };
+                self.stage(
+                    &transaction,
+                    &mut execution,
+                    Step::new(release).with_compensation(vec![refund_release]),
+                )
+                .await?;
+
+                let mut compensation = vec![
+                    Compensation::RemoveShares {
+                        market_id,
+                        user_id,
+                        option: option.clone(),
+                        lot: taker_lot,
+                    },
+                    Compensation::RestoreShares {
+                        market_id,
+                        user_id: ask.user_id,
+                        option: option.clone(),
+                        lots: sold,
+                    },
+                    Compensation::DeleteFill { fill_id },
+                    Compensation::RestoreSellOrder {
+                        order: ask.clone().into(),
+                        created_at: ask.created_at,
+                        shares: matched_qty_db,
+                    },
+                ];
+                // What the release left in the event, kept as fees
+                if let Some(kept) = price
+                    .cents()
+                    .checked_sub(released.cents())
+                    .and_then(Price::new)
+                    .filter(|kept| *kept > Price::MIN)
+                {
+                    compensation.push(Compensation::Chain {
+                        operation: ChainOperation::CancelOrder {
+                            user_id,
+                            market_id,
+                            shares: fill.shares,
+                            price: kept,
+                        },
+                        after: deposit,
+                    });
+                }
+
+                let transfer = ChainOperation::TransferShares {
+                    seller_id: ask.user_id,
+                    buyer_id: user_id,
+                    market_id,
+                    option: option.clone(),
+                    shares: fill.shares,
+                    price: fill.taker_price,
+                };
+                self.stage(
+                    &transaction,
+                    &mut execution,
+                    Step::new(transfer)
+                        .with_fill(fill_id)
+                        .with_compensation(compensation),
+                )
+                .await?;
+
+                continue;
+            }
+
+            let opposing = opposing_orders
+                .iter()
+                .find(|order| order.id == fill.maker_id)
+                .expect("Fills come from the book");
+
             // Create position for the opposing order's user (gets shares of opposing option)
             AppState::upsert_position(
                 &transaction,
                 market.id,
                 opposing.user_id,
                 opposing_option.clone(),
-                matched_qty,
-                opposing.price_per_share,
+                matched_qty_db,
+                i64::try_from(fill.maker_price)?,
             )
             .await?;
 
             // Update or delete the opposing order
-            let new_opposing_shares = opposing.shares - matched_qty;
-            let opposing_id = opposing.id;
+            let new_opposing_shares = opposing.shares - matched_qty_db;
 
             if new_opposing_shares == 0 {
-                entity::buyorder::Entity::delete_by_id(opposing_id)
+                entity::buyorder::Entity::delete_by_id(opposing.id)
                     .exec(&transaction)
                     .await?;
             } else {
@@ -174,101 +494,214 @@ impl AppState {
                 opposing_active.update(&transaction).await?;
             }
 
-            my_remaining -= matched_qty;
-
-            let opposing_user = entity::user::Entity::find_by_id(opposing.user_id)
-                .one(&transaction)
-                .await?
-                .ok_or(AppError::UserNotFound)?;
+            let fill_id = AppState::record_fill(&transaction, market.id, &taker, fill).await?;
 
-            // blockchain tx to match order
-            let match_order_args = FakeMatchOrderArgs {
-                event_uuid: event_id,
-                option_uuid: market_id,
-                num_shares: matched_qty.try_into().unwrap(),
+            let (yes_user_id, no_user_id) = match option {
+                // the market/user we received is the YES, and the opposing is the NO
+                MarketOption::A => (user_id, opposing.user_id),
+                // the market/user we received is the NO, and the opposing is the YES
+                MarketOption::B => (opposing.user_id, user_id),
             };
+            let match_order = ChainOperation::MatchOrder {
+                yes_user_id,
+                no_user_id,
+                market_id,
+                shares: fill.shares,
+            };
+            let compensation = vec![
+                Compensation::RemoveShares {
+                    market_id,
+                    user_id,
+                    option: option.clone(),
+                    lot: taker_lot,
+                },
+                Compensation::RemoveShares {
+                    market_id,
+                    user_id: opposing.user_id,
+                    option: opposing_option.clone(),
+                    lot: Lot {
+                        shares: matched_qty_db,
+                        price_per_share: i64::try_from(fill.maker_price)?,
+                    },
+                },
+                Compensation::DeleteFill { fill_id },
+                Compensation::RestoreBuyOrder {
+                    order: opposing.clone().into(),
+                    created_at: opposing.created_at,
+                    shares: matched_qty_db,
+                },
+                // The whole limit is still in the event
+                Compensation::Chain {
+                    operation: ChainOperation::CancelOrder {
+                        user_id,
+                        market_id,
+                        shares: fill.shares,
+                        price,
+                    },
+                    after: deposit,
+                },
+            ];
+            self.stage(
+                &transaction,
+                &mut execution,
+                Step::new(match_order)
+                    .with_fill(fill_id)
+                    .with_compensation(compensation),
+            )
+            .await?;
 
-            match option {
-                MarketOption::A => {
-                    // the market/user we received is the YES, and the opposing is the NO
-                    let user_yes_wallet = &user_wallet;
-                    let user_no_wallet = Keypair::from_base58_string(&opposing_user.wallet);
-
-                    let sig = self
-                        .solana
-                        .match_order(
-                            user_yes_wallet,
-                            &user_no_wallet,
-                            &token_yes,
-                            &token_no,
-                            &match_order_args,
-                        )
-                        .await?;
-                    tx_urls.push(self.solana.get_transaction_url(&sig));
-                }
-                MarketOption::B => {
-                    // the market/user we received is the NO, and the opposing is the YES
-                    let user_no_wallet = &user_wallet;
-                    let user_yes_wallet = Keypair::from_base58_string(&opposing_user.wallet);
-
-                    let sig = self
-                        .solana
-                        .match_order(
-                            &user_yes_wallet,
-                            user_no_wallet,
-                            &token_yes,
-                            &token_no,
-                            &match_order_args,
-                        )
-                        .await?;
-                    tx_urls.push(self.solana.get_transaction_url(&sig));
-                }
+            // The order paid its limit upfront, give back what it did not need
+            if self.price_improvement == PriceImprovement::Taker && fill.improvement > Price::MIN {
+                let refund = ChainOperation::CancelOrder {
+                    user_id,
+                    market_id,
+                    shares: fill.shares,
+                    price: fill.improvement,
+                };
+                self.stage(&transaction, &mut execution, Step::new(refund))
+                    .await?;
             }
         }
 
-        if my_remaining > 0 {
+        let mut order_id = None;
+        if resting > Shares(0) {
+            let id = taker.order_id;
             entity::buyorder::ActiveModel {
-                id: Set(Uuid::new_v4()),
+                id: Set(id),
                 market_id: Set(market.id),
                 user_id: Set(user_id),
                 option: Set(option),
-                shares: Set(my_remaining),
-                price_per_share: Set(cents_per_share),
+                shares: Set(i64::try_from(resting)?),
+                price_per_share: Set(i64::try_from(price)?),
                 created_at: Set(Utc::now().into()),
+                time_in_force: Set(time_in_force),
+                expires_at: Set(expires_at),
             }
             .insert(&transaction)
             .await?;
+            order_id = Some(id);
         }
 
-        transaction.commit().await?;
+        execution.commit(transaction).await?;
+        let tx_urls = self.settle(&execution).await;
+
+        if !execution.is_dry_run() {
+            self.publish(MarketChange {
+                market_id: market.id,
+                taker_order_id: Some(taker.order_id),
+                users: outcome
+                    .fills
+                    .iter()
+                    .map(|fill| fill.maker_user_id)
+                    .chain([user_id])
+                    .collect(),
+            })
+            .await;
+        }
 
-        Ok(tx_urls)
+        let fill = OrderFillDto::new(&outcome, resting, order_id)?;
+        Ok(execution.finish(PlacedOrder { tx_urls, fill }))
     }
 
-    pub async fn cancel_buy_order(
+    /// Deletes the order, read again under a lock so that a concurrent cancel, expiry or fill
+    /// cannot refund the same shares. Returns it as it was deleted, None if it was already gone
+    pub(crate) async fn take_buy_order(
         txn: &impl sea_orm::ConnectionTrait,
         order_id: Uuid,
-        num_shares: i64,
-        cents_per_share: i64,
-        event_id: Uuid,
-        user_pubkey: &Pubkey,
-        solana: &ProfeciaClient,
-    ) -> AppResult<Signature> {
-        entity::buyorder::Entity::delete_by_id(order_id)
+    ) -> AppResult<Option<entity::buyorder::Model>> {
+        let Some(order) = entity::buyorder::Entity::find_by_id(order_id)
+            .lock_exclusive()
+            .one(txn)
+            .await?
+        else {
+            return Ok(None);
+        };
+
+        let deleted = entity::buyorder::Entity::delete_by_id(order.id)
             .exec(txn)
             .await?;
+        if deleted.rows_affected == 0 {
+            return Ok(None);
+        }
+
+        Ok(Some(order))
+    }
+
+    /// Deletes the order and stages the refund of what it paid upfront. If the refund fails for
+    /// good the order is put back, for its owner to cancel again. Returns it as it was deleted,
+    /// None if it was already gone
+    pub async fn cancel_buy_order(
+        &self,
+        txn: &impl sea_orm::ConnectionTrait,
+        order_id: Uuid,
+        execution: &mut Execution,
+    ) -> AppResult<Option<entity::buyorder::Model>> {
+        let Some(order) = AppState::take_buy_order(txn, order_id).await? else {
+            return Ok(None);
+        };
+
+        let refund = refund_operation(&order)?;
+        let restore = Compensation::RestoreBuyOrder {
+            order: order.clone().into(),
+            created_at: order.created_at,
+            shares: order.shares,
+        };
+        self.stage(
+            txn,
+            execution,
+            Step::new(refund).with_compensation(vec![restore]),
+        )
+        .await?;
+
+        Ok(Some(order))
+    }
+
+    /// Cancels the good-till-date orders past their expiry and refunds them. Returns how many
+    /// were cancelled
+    pub async fn expire_buy_orders(&self) -> AppResult<usize> {
+        let expired: Vec<Uuid> = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::ExpiresAt.lte(Utc::now()))
+            .select_only()
+            .column(entity::buyorder::Column::Id)
+            .into_tuple()
+            .all(&self.database)
+            .await?;
+
+        let mut cancelled = 0;
+        for order_id in expired {
+            match self.expire_buy_order(order_id).await {
+                Ok(true) => cancelled += 1,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Failed to expire buy order {}: {}", order_id, e),
+            }
+        }
+
+        Ok(cancelled)
+    }
 
-        let cents_per_share: u64 = cents_per_share.try_into().unwrap();
+    /// Returns false if the order was cancelled or filled in the meantime
+    async fn expire_buy_order(&self, order_id: Uuid) -> AppResult<bool> {
+        let mut execution = Execution::new(false);
+        let transaction = self.database.begin().await?;
 
-        let cancel_order_args = FakeCancelOrderArgs {
-            event_uuid: event_id,
-            option_uuid: order_id,
-            num_shares: num_shares.try_into().unwrap(),
-            price_per_share: cents_per_share * 10000,
+        let Some(order) = self
+            .cancel_buy_order(&transaction, order_id, &mut execution)
+            .await?
+        else {
+            return Ok(false);
         };
-        let sig = solana.cancel_order(user_pubkey, &cancel_order_args).await?;
 
-        Ok(sig)
+        execution.commit(transaction).await?;
+        self.settle(&execution).await;
+
+        self.publish(MarketChange {
+            market_id: order.market_id,
+            taker_order_id: None,
+            users: vec![order.user_id],
+        })
+        .await;
+
+        Ok(true)
     }
 
     pub async fn get_buy_order(
@@ -282,9 +715,15 @@ impl AppState {
             .map(Into::into))
     }
 
-    pub async fn list_buy_orders(&self, market_id: Uuid) -> AppResult<Vec<BuyOrderDto>> {
+    /// The user's own orders in the market
+    pub async fn list_buy_orders(
+        &self,
+        market_id: Uuid,
+        user_id: Uuid,
+    ) -> AppResult<Vec<BuyOrderDto>> {
         Ok(entity::buyorder::Entity::find()
             .filter(entity::buyorder::Column::MarketId.eq(market_id))
+            .filter(entity::buyorder::Column::UserId.eq(user_id))
             .all(&self.database)
             .await
             .map_err(Into::<AppError>::into)?
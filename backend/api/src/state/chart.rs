@@ -0,0 +1,207 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AppState, entity,
+    error::{AppError, AppResult},
+    state::book::price_of_a,
+};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CandleInterval {
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[default]
+    #[serde(rename = "1h")]
+    Hour,
+    #[serde(rename = "1d")]
+    Day,
+}
+
+impl CandleInterval {
+    pub fn seconds(self) -> i64 {
+        match self {
+            CandleInterval::Minute => 60,
+            CandleInterval::FiveMinutes => 5 * 60,
+            CandleInterval::Hour => 60 * 60,
+            CandleInterval::Day => 24 * 60 * 60,
+        }
+    }
+
+    /// The start of the candle `time` falls in. Days start at midnight UTC
+    fn bucket(self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let start = time.timestamp().div_euclid(self.seconds()) * self.seconds();
+        DateTime::from_timestamp(start, 0).expect("Floored timestamps are in range")
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartQuery {
+    #[serde(default)]
+    pub interval: CandleInterval,
+    /// Defaults to as far back as `MAX_CANDLES` candles go
+    pub from: Option<DateTime<Utc>>,
+    /// Defaults to now
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl ChartQuery {
+    pub const MAX_CANDLES: i64 = 1000;
+
+    /// The `[from, to)` range of the chart
+    pub fn range(&self) -> AppResult<(DateTime<Utc>, DateTime<Utc>)> {
+        let span = Duration::seconds(self.interval.seconds() * Self::MAX_CANDLES);
+        let to = self.to.unwrap_or_else(Utc::now);
+        let from = self.from.unwrap_or(to - span);
+
+        if from >= to {
+            return Err(AppError::InvalidChartRange(
+                "O início do gráfico tem de ser anterior ao fim".to_string(),
+            ));
+        }
+        if to - from > span {
+            return Err(AppError::InvalidChartRange(format!(
+                "O gráfico não pode ter mais de {} velas",
+                Self::MAX_CANDLES
+            )));
+        }
+
+        Ok((from, to))
+    }
+}
+
+/// The fills of one interval, in prices of option A
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct CandleDto {
+    pub start: DateTime<Utc>,
+    pub open: i64,
+    pub high: i64,
+    pub low: i64,
+    pub close: i64,
+    /// Shares traded
+    pub volume: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChartPointDto {
+    pub time: DateTime<Utc>,
+    /// The last traded percentage of each market, None before its first trade
+    pub percentages: HashMap<Uuid, Option<i64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventChartDto {
+    pub interval: CandleInterval,
+    /// Only intervals with fills have a candle
+    pub candles: HashMap<Uuid, Vec<CandleDto>>,
+    /// The closes of all markets at every interval that traded
+    pub points: Vec<ChartPointDto>,
+}
+
+impl AppState {
+    /// OHLCV candles of the executed fills of each market in the event
+    pub async fn get_event_chart(
+        &self,
+        event_id: Uuid,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> AppResult<EventChartDto> {
+        let markets = entity::market::Entity::find()
+            .filter(entity::market::Column::EventId.eq(event_id))
+            .all(&self.database)
+            .await?;
+
+        let market_ids: Vec<Uuid> = markets.iter().map(|m| m.id).collect();
+
+        let fills = entity::fill::Entity::find()
+            .filter(entity::fill::Column::MarketId.is_in(market_ids.clone()))
+            .filter(entity::fill::Column::CreatedAt.gte(from.fixed_offset()))
+            .filter(entity::fill::Column::CreatedAt.lt(to.fixed_offset()))
+            .order_by_asc(entity::fill::Column::CreatedAt)
+            .order_by_asc(entity::fill::Column::Id)
+            .all(&self.database)
+            .await?;
+
+        // Where the line of each market starts, before its first fill in range
+        let mut last_prices = HashMap::new();
+        for market_id in &market_ids {
+            let before = entity::fill::Entity::find()
+                .filter(entity::fill::Column::MarketId.eq(*market_id))
+                .filter(entity::fill::Column::CreatedAt.lt(from.fixed_offset()))
+                .order_by_desc(entity::fill::Column::CreatedAt)
+                .order_by_desc(entity::fill::Column::Id)
+                .one(&self.database)
+                .await?;
+            last_prices.insert(
+                *market_id,
+                before.map(|fill| price_of_a(&fill.option, fill.price_per_share)),
+            );
+        }
+
+        let mut candles: HashMap<Uuid, Vec<CandleDto>> = market_ids
+            .iter()
+            .map(|market_id| (*market_id, Vec::new()))
+            .collect();
+        let mut closes: BTreeMap<DateTime<Utc>, HashMap<Uuid, i64>> = BTreeMap::new();
+
+        for fill in &fills {
+            let price = price_of_a(&fill.option, fill.price_per_share);
+            let start = interval.bucket(fill.created_at.with_timezone(&Utc));
+            let market_candles = candles.entry(fill.market_id).or_default();
+
+            match market_candles.last_mut() {
+                Some(candle) if candle.start == start => {
+                    candle.high = candle.high.max(price);
+                    candle.low = candle.low.min(price);
+                    candle.close = price;
+                    candle.volume += fill.shares;
+                }
+                _ => market_candles.push(CandleDto {
+                    start,
+                    open: price,
+                    high: price,
+                    low: price,
+                    close: price,
+                    volume: fill.shares,
+                }),
+            }
+
+            closes
+                .entry(start)
+                .or_default()
+                .insert(fill.market_id, price);
+        }
+
+        let points = closes
+            .into_iter()
+            .map(|(time, market_closes)| {
+                last_prices.extend(
+                    market_closes
+                        .into_iter()
+                        .map(|(market_id, close)| (market_id, Some(close))),
+                );
+                ChartPointDto {
+                    time,
+                    percentages: last_prices.clone(),
+                }
+            })
+            .collect();
+
+        Ok(EventChartDto {
+            interval,
+            candles,
+            points,
+        })
+    }
+}
@@ -0,0 +1,246 @@
+//! Rewards of resolved markets, claimed by each winner for themselves.
+//!
+//! A claim redeems every winning share the user holds in the market, with their own custodial
+//! keypair, in a saga of its own, and takes them out of the user's positions. If the redemption
+//! fails for good the claim is deleted, the positions are given back and the winnings show up as
+//! unclaimed again.
+
+use std::collections::{BTreeMap, HashMap};
+
+use blockchain_core::money::Shares;
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QuerySelect, SqlErr,
+    TransactionTrait,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self, market::MarketOption},
+    error::{AppError, AppResult},
+    state::{
+        dry_run::{Execution, Outcome},
+        event::MarketOptionDto,
+        outbox::{ChainOperation, Compensation, SagaStatus, Step, saga_progress},
+    },
+};
+
+/// Winning shares that have not been claimed yet
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnclaimedRewardDto {
+    pub market_id: Uuid,
+    pub event_id: Uuid,
+    pub option: MarketOptionDto,
+    pub shares: i64,
+    /// Micro USDC the shares pay out
+    pub amount: i64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClaimDto {
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub event_id: Uuid,
+    pub option: MarketOptionDto,
+    pub shares: i64,
+    /// Micro USDC the user gets
+    pub amount: i64,
+    pub status: SagaStatus,
+    pub transaction_urls: Vec<String>,
+    pub created_at: DateTime<FixedOffset>,
+}
+
+/// The shares of `option` the user holds in the market, over every price they bought at, locked
+/// until the claim commits
+async fn winning_shares(
+    conn: &impl sea_orm::ConnectionTrait,
+    user_id: Uuid,
+    market_id: Uuid,
+    option: MarketOption,
+) -> AppResult<i64> {
+    Ok(entity::position::Entity::find()
+        .filter(entity::position::Column::UserId.eq(user_id))
+        .filter(entity::position::Column::MarketId.eq(market_id))
+        .filter(entity::position::Column::Option.eq(option))
+        .lock_exclusive()
+        .all(conn)
+        .await?
+        .iter()
+        .map(|position| position.shares)
+        .sum())
+}
+
+fn payout(shares: i64) -> AppResult<i64> {
+    let payout = Shares::try_from(shares)?
+        .payout()
+        .ok_or_else(|| anyhow::anyhow!("Payout of {} shares overflows", shares))?;
+    Ok(i64::try_from(payout)?)
+}
+
+impl AppState {
+    /// The winnings of the user in every resolved market they have not claimed yet
+    pub async fn unclaimed_rewards(&self, user_id: Uuid) -> AppResult<Vec<UnclaimedRewardDto>> {
+        let positions = entity::position::Entity::find()
+            .filter(entity::position::Column::UserId.eq(user_id))
+            .all(&self.database)
+            .await?;
+        let markets: HashMap<Uuid, entity::market::Model> = entity::market::Entity::find()
+            .filter(
+                entity::market::Column::Id
+                    .is_in(positions.iter().map(|position| position.market_id)),
+            )
+            .filter(entity::market::Column::ResolvedOption.is_not_null())
+            .all(&self.database)
+            .await?
+            .into_iter()
+            .map(|market| (market.id, market))
+            .collect();
+        let claimed: Vec<Uuid> = entity::claim::Entity::find()
+            .filter(entity::claim::Column::UserId.eq(user_id))
+            .all(&self.database)
+            .await?
+            .into_iter()
+            .map(|claim| claim.market_id)
+            .collect();
+
+        let mut winnings: BTreeMap<Uuid, i64> = BTreeMap::new();
+        for position in positions {
+            let Some(market) = markets.get(&position.market_id) else {
+                continue;
+            };
+            if market.resolved_option.as_ref() == Some(&position.option)
+                && !claimed.contains(&market.id)
+            {
+                *winnings.entry(market.id).or_default() += position.shares;
+            }
+        }
+
+        let mut rewards = Vec::new();
+        for (market_id, shares) in winnings {
+            let market = &markets[&market_id];
+            let option = market
+                .resolved_option
+                .clone()
+                .expect("Only resolved markets are kept");
+            if shares > 0 {
+                rewards.push(UnclaimedRewardDto {
+                    market_id,
+                    event_id: market.event_id,
+                    option: option.into(),
+                    shares,
+                    amount: payout(shares)?,
+                });
+            }
+        }
+        Ok(rewards)
+    }
+
+    /// Redeems the user's winning shares in the resolved market
+    pub async fn claim_reward(
+        &self,
+        user_id: Uuid,
+        market_id: Uuid,
+        dry_run: bool,
+    ) -> AppResult<Outcome<ClaimDto>> {
+        let mut execution = Execution::new(dry_run);
+        let transaction = self.database.begin().await?;
+
+        let market = entity::market::Entity::find_by_id(market_id)
+            .one(&transaction)
+            .await?
+            .ok_or(AppError::MarketNotFound)?;
+        let option = market
+            .resolved_option
+            .clone()
+            .ok_or(AppError::MarketNotResolved)?;
+
+        // Locked first, so a concurrent claim waits here and then sees this one
+        let shares = winning_shares(&transaction, user_id, market_id, option.clone()).await?;
+
+        let claimed = entity::claim::Entity::find()
+            .filter(entity::claim::Column::UserId.eq(user_id))
+            .filter(entity::claim::Column::MarketId.eq(market_id))
+            .one(&transaction)
+            .await?;
+        if claimed.is_some() {
+            return Err(AppError::RewardAlreadyClaimed);
+        }
+
+        if shares <= 0 {
+            return Err(AppError::NothingToClaim);
+        }
+
+        // The redemption burns the shares
+        let lots =
+            AppState::reduce_position(&transaction, market_id, user_id, option.clone(), shares)
+                .await?;
+
+        let claim_id = Uuid::new_v4();
+        let get_reward = ChainOperation::GetReward {
+            user_id,
+            market_id,
+            option: option.clone(),
+            shares: Shares::try_from(shares)?,
+        };
+        execution.new_saga();
+        self.stage(
+            &transaction,
+            &mut execution,
+            Step::new(get_reward).with_compensation(vec![
+                Compensation::RestoreShares {
+                    market_id,
+                    user_id,
+                    option: option.clone(),
+                    lots,
+                },
+                Compensation::DeleteClaim { claim_id },
+            ]),
+        )
+        .await?;
+
+        let claim = entity::claim::ActiveModel {
+            id: Set(claim_id),
+            user_id: Set(user_id),
+            market_id: Set(market_id),
+            option: Set(option),
+            shares: Set(shares),
+            amount: Set(payout(shares)?),
+            saga_id: Set(execution
+                .current_saga()
+                .ok_or_else(|| anyhow::anyhow!("Claim {} has no saga", claim_id))?),
+            created_at: Set(Utc::now().into()),
+        }
+        .insert(&transaction)
+        .await
+        // A concurrent claim got in between the check above and here
+        .map_err(|e| match e.sql_err() {
+            Some(SqlErr::UniqueConstraintViolation(_)) => AppError::RewardAlreadyClaimed,
+            _ => e.into(),
+        })?;
+
+        execution.commit(transaction).await?;
+        let transaction_urls = self.settle(&execution).await;
+
+        // What does not confirm now is retried by the outbox
+        let status = saga_progress(&self.database, [claim.saga_id])
+            .await?
+            .get(&claim.saga_id)
+            .map_or(SagaStatus::Pending, |saga| saga.status);
+
+        Ok(execution.finish(ClaimDto {
+            id: claim.id,
+            market_id,
+            event_id: market.event_id,
+            option: claim.option.into(),
+            shares: claim.shares,
+            amount: claim.amount,
+            status,
+            transaction_urls,
+            created_at: claim.created_at,
+        }))
+    }
+}
@@ -0,0 +1,201 @@
+//! Dry runs: requests that go through every validation and simulate their chain transactions,
+//! but commit neither the database transaction nor anything on chain.
+
+use blockchain_client::{AccountDiff, Simulation};
+use sea_orm::DatabaseTransaction;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    error::AppResult,
+    state::outbox::{self, Saga, Step},
+};
+
+/// `?dryRun=true`
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Collects the chain transactions of a request for the outbox, or their simulations in a dry
+/// run
+#[derive(Debug, Default)]
+pub struct Execution {
+    dry_run: bool,
+    simulations: Vec<Simulation>,
+    sagas: Vec<Saga>,
+}
+
+impl Execution {
+    pub fn new(dry_run: bool) -> Self {
+        Self {
+            dry_run,
+            simulations: Vec::new(),
+            sagas: Vec::new(),
+        }
+    }
+
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    pub fn record(&mut self, simulation: Simulation) {
+        self.simulations.push(simulation);
+    }
+
+    /// Adds `step` to the current saga and returns its number. See `AppState::stage`
+    pub fn push(&mut self, step: Step) -> i32 {
+        if self.sagas.is_empty() {
+            self.sagas.push(Saga::new());
+        }
+        let saga = self.sagas.last_mut().expect("A saga was just added");
+        saga.steps.push(step);
+        saga.steps.len() as i32 - 1
+    }
+
+    /// The steps pushed from now on go to a saga of their own, which does not wait for the ones
+    /// before it nor fails with them
+    pub fn new_saga(&mut self) {
+        if self.sagas.last().is_none_or(|saga| !saga.steps.is_empty()) {
+            self.sagas.push(Saga::new());
+        }
+    }
+
+    /// The saga the steps are pushed to
+    pub fn current_saga(&self) -> Option<Uuid> {
+        self.sagas.last().map(|saga| saga.id)
+    }
+
+    pub fn saga_ids(&self) -> Vec<Uuid> {
+        self.sagas
+            .iter()
+            .filter(|saga| !saga.steps.is_empty())
+            .map(|saga| saga.id)
+            .collect()
+    }
+
+    /// Queues the sagas in the outbox and commits, or rolls `transaction` back in a dry run
+    pub async fn commit(&self, transaction: DatabaseTransaction) -> AppResult<()> {
+        if self.dry_run {
+            transaction.rollback().await?;
+        } else {
+            outbox::enqueue(&transaction, &self.sagas).await?;
+            transaction.commit().await?;
+        }
+        Ok(())
+    }
+
+    pub fn finish<T>(self, value: T) -> Outcome<T> {
+        if self.dry_run {
+            Outcome::DryRun(DryRunDto::new(value, self.simulations))
+        } else {
+            Outcome::Done(value)
+        }
+    }
+}
+
+/// The usual response, or what it would have been along with the simulations
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum Outcome<T> {
+    Done(T),
+    DryRun(DryRunDto<T>),
+}
+
+impl<T> Outcome<T> {
+    pub fn map<U>(self, f: impl FnOnce(T) -> U) -> Outcome<U> {
+        match self {
+            Outcome::Done(value) => Outcome::Done(f(value)),
+            Outcome::DryRun(dry_run) => Outcome::DryRun(DryRunDto {
+                dry_run: dry_run.dry_run,
+                success: dry_run.success,
+                lamport_cost: dry_run.lamport_cost,
+                simulations: dry_run.simulations,
+                result: f(dry_run.result),
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DryRunDto<T> {
+    /// Always true, so that clients can tell it apart from the usual response
+    pub dry_run: bool,
+    /// Whether every simulated transaction succeeded
+    pub success: bool,
+    /// Sum of the cost of every simulated transaction
+    pub lamport_cost: u64,
+    pub simulations: Vec<SimulationDto>,
+    /// Nothing in it was saved
+    pub result: T,
+}
+
+impl<T> DryRunDto<T> {
+    fn new(result: T, simulations: Vec<Simulation>) -> Self {
+        Self {
+            dry_run: true,
+            success: simulations.iter().all(Simulation::succeeded),
+            lamport_cost: simulations.iter().map(Simulation::lamport_cost).sum(),
+            simulations: simulations.into_iter().map(Into::into).collect(),
+            result,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationDto {
+    pub success: bool,
+    pub error: Option<String>,
+    pub logs: Vec<String>,
+    pub units_consumed: u64,
+    pub compute_unit_limit: u32,
+    pub fee_lamports: u64,
+    pub rent_lamports: u64,
+    pub lamport_cost: u64,
+    pub accounts: Vec<AccountDiffDto>,
+}
+
+impl From<Simulation> for SimulationDto {
+    fn from(simulation: Simulation) -> Self {
+        SimulationDto {
+            success: simulation.succeeded(),
+            error: simulation.error.as_ref().map(ToString::to_string),
+            units_consumed: simulation.budget.units_consumed,
+            compute_unit_limit: simulation.budget.compute_unit_limit,
+            fee_lamports: simulation.budget.fee_lamports,
+            rent_lamports: simulation.rent_lamports(),
+            lamport_cost: simulation.lamport_cost(),
+            accounts: simulation.accounts.iter().map(Into::into).collect(),
+            logs: simulation.logs,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDiffDto {
+    pub pubkey: String,
+    pub created: bool,
+    pub lamports_before: u64,
+    pub lamports_after: u64,
+    /// None when the account does not exist
+    pub data_len_before: Option<usize>,
+    pub data_len_after: Option<usize>,
+}
+
+impl From<&AccountDiff> for AccountDiffDto {
+    fn from(diff: &AccountDiff) -> Self {
+        AccountDiffDto {
+            pubkey: diff.pubkey.to_string(),
+            created: diff.is_created(),
+            lamports_before: diff.lamports_before(),
+            lamports_after: diff.lamports_after(),
+            data_len_before: diff.before.map(|account| account.data_len),
+            data_len_after: diff.after.map(|account| account.data_len),
+        }
+    }
+}
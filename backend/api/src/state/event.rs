@@ -1,23 +1,22 @@
 use std::collections::HashMap;
 
-use blockchain_client::ProfeciaClient;
-use blockchain_core::{
-    accounts::event::EventOption,
-    instructions::{AddOptionArgs, CreateEmptyEventArgs, FakeGetRewardArgs},
-};
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, ExprTrait, ModelTrait, QueryFilter,
-    QuerySelect, TransactionTrait,
-    sea_query::{Expr, Alias},
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, EntityTrait, ExprTrait, QueryFilter, QuerySelect, TransactionTrait,
+    sea_query::{Alias, Expr},
 };
 use serde::{Deserialize, Serialize};
-use solana_sdk::{signature::Keypair, signer::Signer};
+use solana_sdk::signature::Keypair;
 use uuid::Uuid;
 
 use crate::{
-    AppState,
-    entity::{self, market::MarketOption},
+    AppState, entity,
     error::{AppError, AppResult},
+    state::{
+        dry_run::{Execution, Outcome},
+        outbox::{ChainOperation, Step},
+    },
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,7 +44,7 @@ pub struct MarketDto {
     pub resolved_option: Option<MarketOptionDto>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum MarketOptionDto {
     OptionA,
@@ -168,7 +167,9 @@ impl AppState {
                 .select_only()
                 .column(entity::position::Column::MarketId)
                 .column_as(
-                    Expr::col(entity::position::Column::Shares).sum().cast_as(Alias::new("BIGINT")),
+                    Expr::col(entity::position::Column::Shares)
+                        .sum()
+                        .cast_as(Alias::new("BIGINT")),
                     "total_shares",
                 )
                 .group_by(entity::position::Column::MarketId)
@@ -183,7 +184,7 @@ impl AppState {
         Ok(rows
             .into_iter()
             .map(|(event, markets)| {
-                let event_pda = ProfeciaClient::derive_event_pubkey(&event.id);
+                let event_pda = self.chain.event_pubkey(&event.id);
                 let pending: i64 = markets
                     .iter()
                     .map(|m| market_order_counts.get(&m.id).copied().unwrap_or(0))
@@ -196,7 +197,7 @@ impl AppState {
                     id: event.id,
                     display_name: event.display_name,
                     image_url: event.image_url,
-                    solana_url: self.solana.get_account_url(&event_pda),
+                    solana_url: self.chain.account_url(&event_pda),
                     pubkey: event_pda.to_string(),
                     markets: markets.into_iter().map(Into::into).collect(),
                     pending_buy_orders: pending,
@@ -240,7 +241,9 @@ impl AppState {
                 .filter(entity::position::Column::MarketId.is_in(market_ids))
                 .select_only()
                 .column_as(
-                    Expr::col(entity::position::Column::Shares).sum().cast_as(Alias::new("BIGINT")),
+                    Expr::col(entity::position::Column::Shares)
+                        .sum()
+                        .cast_as(Alias::new("BIGINT")),
                     "total_shares",
                 )
                 .into_tuple::<Option<i64>>()
@@ -250,12 +253,12 @@ impl AppState {
                 .unwrap_or(0)
         };
 
-        let event_pda = ProfeciaClient::derive_event_pubkey(&event.id);
+        let event_pda = self.chain.event_pubkey(&event.id);
         Ok(Some(EventDto {
             id: event.id,
             display_name: event.display_name,
             image_url: event.image_url,
-            solana_url: self.solana.get_account_url(&event_pda),
+            solana_url: self.chain.account_url(&event_pda),
             pubkey: event_pda.to_string(),
             markets: markets.into_iter().map(Into::into).collect(),
             pending_buy_orders: pending,
@@ -263,7 +266,12 @@ impl AppState {
         }))
     }
 
-    pub async fn create_event(&self, event: EventRequest) -> AppResult<EventDto> {
+    pub async fn create_event(
+        &self,
+        event: EventRequest,
+        dry_run: bool,
+    ) -> AppResult<Outcome<EventDto>> {
+        let mut execution = Execution::new(dry_run);
         let transaction = self.database.begin().await?;
         let event_id = Uuid::new_v4();
         let display_name = event.display_name.trim().to_string();
@@ -281,9 +289,6 @@ impl AppState {
         .insert(&transaction)
         .await?;
 
-        let mut token_keypairs: Vec<(Keypair, Keypair)> = Vec::new();
-        let mut markets_map: HashMap<Uuid, EventOption> = HashMap::new();
-
         let mut markets = Vec::with_capacity(event.markets.len());
 
         for market in event.markets {
@@ -301,17 +306,6 @@ impl AppState {
             let yes_keypair_string = yes_keypair.to_base58_string();
             let no_keypair_string = no_keypair.to_base58_string();
 
-            markets_map.insert(
-                market_id,
-                EventOption {
-                    option_desc: "".into(),
-                    yes_mint: yes_keypair.pubkey(),
-                    no_mint: no_keypair.pubkey(),
-                },
-            );
-
-            token_keypairs.push((yes_keypair, no_keypair));
-
             let market = entity::market::ActiveModel {
                 id: Set(market_id),
                 yes_keypair: Set(yes_keypair_string),
@@ -323,6 +317,7 @@ impl AppState {
                 option_b_name: Set(market.option_b_name),
                 rules: Set(market.rules),
                 resolved_option: Set(None),
+                last_trade_price: Set(None),
             };
 
             let market = market.insert(&transaction).await?;
@@ -337,46 +332,22 @@ impl AppState {
             });
         }
 
-        transaction.commit().await?;
-
-        // let create_event_args = CreateEventArgs {
-        //     uuid: event_id,
-        //     description: "".into(),
-        //     options: markets_map,
-        // };
-        // let _sig = self
-        //     .solana
-        //     .create_event(&token_keypairs, &create_event_args)
-        //     .await?;
-
-        let create_empty_event_args = CreateEmptyEventArgs {
-            uuid: event_id,
-            description: "".into(),
+        // The markets that do not make it with the event are added on their own afterwards
+        let create_event = ChainOperation::CreateEvent {
+            event_id,
+            market_ids: markets.iter().map(|market| market.id).collect(),
         };
-        let _sig = self
-            .solana
-            .create_empty_event(&create_empty_event_args)
+        self.stage(&transaction, &mut execution, Step::new(create_event))
             .await?;
 
-        for ((option_id, option_info), (yes_token, no_token)) in
-            markets_map.iter().zip(token_keypairs.iter())
-        {
-            let add_option_args = AddOptionArgs {
-                event_uuid: event_id,
-                option_uuid: *option_id,
-                option_info: option_info.clone(),
-            };
-            let _sig = self
-                .solana
-                .add_option(yes_token, no_token, &add_option_args)
-                .await?;
-        }
+        execution.commit(transaction).await?;
+        self.settle(&execution).await;
 
-        let event_pda = ProfeciaClient::derive_event_pubkey(&event_id);
+        let event_pda = self.chain.event_pubkey(&event_id);
 
         let event_dto = EventDto {
             id: event_id,
-            solana_url: self.solana.get_account_url(&event_pda),
+            solana_url: self.chain.account_url(&event_pda),
             display_name: event.display_name,
             image_url: event_image_url,
             pubkey: event_pda.to_string(),
@@ -385,96 +356,14 @@ impl AppState {
             volume: 0,
         };
 
-        Ok(event_dto)
+        Ok(execution.finish(event_dto))
     }
 
-    pub async fn resolve_market(&self, market_id: Uuid, option: MarketOptionDto) -> AppResult<Vec<String>> {
-        let transaction = self.database.begin().await?;
-
-        let market = entity::market::Entity::find_by_id(market_id)
-            .one(&transaction)
-            .await?
-            .ok_or(AppError::MarketNotFound)?;
-
-        if market.resolved_option.is_some() {
-            return Err(AppError::MarketAlreadyResolved);
-        }
-
-        let winning_option: entity::market::MarketOption = option.into();
-        let mut tx_urls: Vec<String> = Vec::new();
-
-        // Cancel all remaining buy orders for this market
-        let buy_orders = market
-            .find_related(entity::buyorder::Entity)
-            .all(&transaction)
-            .await?;
-
-        for order in &buy_orders {
-            let user = entity::user::Entity::find_by_id(order.user_id)
-                .one(&transaction)
-                .await?
-                .ok_or(AppError::UserNotFound)?;
-
-            let user_wallet = Keypair::from_base58_string(&user.wallet);
-
-            let sig = AppState::cancel_buy_order(
-                &transaction,
-                order.id,
-                order.shares,
-                order.price_per_share,
-                market.event_id,
-                &user_wallet.pubkey(),
-                &self.solana,
-            )
-            .await?;
-            tx_urls.push(self.solana.get_transaction_url(&sig));
-        }
-
-        // Pay out positions that hold the winning option
-        let winning_positions = entity::position::Entity::find()
-            .filter(entity::position::Column::MarketId.eq(market_id))
-            .filter(entity::position::Column::Option.eq(winning_option.clone()))
-            .all(&transaction)
-            .await?;
-
-        let winning_mint = match winning_option {
-            MarketOption::A => Keypair::from_base58_string(market.yes_keypair.as_str()).pubkey(),
-            MarketOption::B => Keypair::from_base58_string(market.no_keypair.as_str()).pubkey(),
-        };
-
-        for position in &winning_positions {
-            // blockchain tx to get rewards for this position
-            let fake_get_reward_args = FakeGetRewardArgs {
-                event_uuid: market.event_id,
-                option_uuid: market_id,
-                num_shares: position.shares.try_into().unwrap(),
-            };
-
-            let user = entity::user::Entity::find_by_id(position.user_id)
-                .one(&transaction)
-                .await?
-                .ok_or(AppError::UserNotFound)?;
-
-            let user_wallet = Keypair::from_base58_string(&user.wallet);
-
-            let sig = self
-                .solana
-                .get_reward(&user_wallet, &winning_mint, &fake_get_reward_args)
-                .await?;
-            tx_urls.push(self.solana.get_transaction_url(&sig));
-        }
-
-        // Mark the market as resolved
-        let mut active_market: entity::market::ActiveModel = market.into();
-        active_market.resolved_option = Set(Some(winning_option));
-        active_market.update(&transaction).await?;
-
-        transaction.commit().await?;
-
-        Ok(tx_urls)
-    }
-
-    pub async fn update_event(&self, event_id: Uuid, request: UpdateEventRequest) -> AppResult<EventDto> {
+    pub async fn update_event(
+        &self,
+        event_id: Uuid,
+        request: UpdateEventRequest,
+    ) -> AppResult<EventDto> {
         let event = entity::event::Entity::find_by_id(event_id)
             .one(&self.database)
             .await?
@@ -496,7 +385,11 @@ impl AppState {
             .ok_or(AppError::EventNotFound)
     }
 
-    pub async fn update_market(&self, market_id: Uuid, request: UpdateMarketRequest) -> AppResult<MarketDto> {
+    pub async fn update_market(
+        &self,
+        market_id: Uuid,
+        request: UpdateMarketRequest,
+    ) -> AppResult<MarketDto> {
         let market = entity::market::Entity::find_by_id(market_id)
             .one(&self.database)
             .await?
@@ -524,10 +417,18 @@ impl AppState {
         Ok(updated.into())
     }
 
-    pub async fn add_market_to_event(&self, event_id: Uuid, request: MarketRequest) -> AppResult<MarketDto> {
+    pub async fn add_market_to_event(
+        &self,
+        event_id: Uuid,
+        request: MarketRequest,
+        dry_run: bool,
+    ) -> AppResult<Outcome<MarketDto>> {
+        let mut execution = Execution::new(dry_run);
+        let transaction = self.database.begin().await?;
+
         // Verify event exists
         entity::event::Entity::find_by_id(event_id)
-            .one(&self.database)
+            .one(&transaction)
             .await?
             .ok_or(AppError::EventNotFound)?;
 
@@ -545,12 +446,6 @@ impl AppState {
         let yes_keypair_string = yes_keypair.to_base58_string();
         let no_keypair_string = no_keypair.to_base58_string();
 
-        let option_info = EventOption {
-            option_desc: "".into(),
-            yes_mint: yes_keypair.pubkey(),
-            no_mint: no_keypair.pubkey(),
-        };
-
         let market = entity::market::ActiveModel {
             id: Set(market_id),
             yes_keypair: Set(yes_keypair_string),
@@ -562,22 +457,19 @@ impl AppState {
             option_b_name: Set(request.option_b_name),
             rules: Set(request.rules),
             resolved_option: Set(None),
+            last_trade_price: Set(None),
         };
 
-        let market = market.insert(&self.database).await?;
+        let market = market.insert(&transaction).await?;
 
-        // Register on blockchain
-        let add_option_args = AddOptionArgs {
-            event_uuid: event_id,
-            option_uuid: market_id,
-            option_info,
-        };
-        let _sig = self
-            .solana
-            .add_option(&yes_keypair, &no_keypair, &add_option_args)
+        let add_option = ChainOperation::AddOption { market_id };
+        self.stage(&transaction, &mut execution, Step::new(add_option))
             .await?;
 
-        Ok(MarketDto {
+        execution.commit(transaction).await?;
+        self.settle(&execution).await;
+
+        Ok(execution.finish(MarketDto {
             id: market.id,
             display_name: market.display_name,
             image_url: market_image_url,
@@ -585,6 +477,6 @@ impl AppState {
             option_b_name: market.option_b_name,
             rules: market.rules,
             resolved_option: None,
-        })
+        }))
     }
 }
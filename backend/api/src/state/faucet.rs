@@ -0,0 +1,47 @@
+use async_trait::async_trait;
+use blockchain_client::faucet::{Credit, CreditLedger};
+use blockchain_core::money::MicroUsdc;
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+};
+use solana_sdk::pubkey::Pubkey;
+use uuid::Uuid;
+
+use crate::entity;
+
+/// Keeps faucet credits in the `faucet_credit` table, so caps hold across restarts
+pub struct DatabaseLedger {
+    pub database: DatabaseConnection,
+}
+
+#[async_trait]
+impl CreditLedger for DatabaseLedger {
+    async fn credited(&self, wallet: &Pubkey) -> anyhow::Result<MicroUsdc> {
+        let credits = entity::faucet_credit::Entity::find()
+            .filter(entity::faucet_credit::Column::Wallet.eq(wallet.to_string()))
+            .all(&self.database)
+            .await?;
+
+        credits.into_iter().try_fold(MicroUsdc(0), |total, credit| {
+            let amount = MicroUsdc::try_from(credit.micro_usdc)?;
+            total
+                .checked_add(amount)
+                .ok_or_else(|| anyhow::anyhow!("Faucet credits of {} overflow", wallet))
+        })
+    }
+
+    async fn record(&self, credit: &Credit) -> anyhow::Result<()> {
+        entity::faucet_credit::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            wallet: Set(credit.wallet.to_string()),
+            micro_usdc: Set(i64::try_from(credit.amount)?),
+            signature: Set(credit.signature.map(|signature| signature.to_string())),
+            credited_at: Set(DateTime::<Utc>::from(credit.credited_at).into()),
+        }
+        .insert(&self.database)
+        .await?;
+
+        Ok(())
+    }
+}
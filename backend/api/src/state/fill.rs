@@ -0,0 +1,282 @@
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{
+    ActiveModelTrait,
+    ActiveValue::Set,
+    ColumnTrait, Condition, EntityTrait, QueryFilter, QueryOrder, QuerySelect, RelationTrait,
+    sea_query::{Expr, JoinType},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self, fill::OrderSide, market::MarketOption},
+    error::AppResult,
+    matching::Fill,
+    state::{book::price_of_a, event::MarketOptionDto},
+};
+
+/// The new order of a match
+#[derive(Debug, Clone)]
+pub struct Taker {
+    pub order_id: Uuid,
+    pub user_id: Uuid,
+    pub side: OrderSide,
+    pub option: MarketOption,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradeDto {
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub maker_user_id: Uuid,
+    pub taker_user_id: Uuid,
+    pub taker_side: OrderSide,
+    pub option: MarketOptionDto,
+    pub shares: i64,
+    pub price_per_share: i64,
+    pub maker_price_per_share: i64,
+    pub created_at: DateTime<FixedOffset>,
+    pub signature: String,
+}
+
+impl From<entity::fill::Model> for TradeDto {
+    fn from(model: entity::fill::Model) -> Self {
+        Self {
+            id: model.id,
+            market_id: model.market_id,
+            maker_order_id: model.maker_order_id,
+            taker_order_id: model.taker_order_id,
+            maker_user_id: model.maker_user_id,
+            taker_user_id: model.taker_user_id,
+            taker_side: model.taker_side,
+            option: MarketOptionDto::from(model.option),
+            shares: model.shares,
+            price_per_share: model.price_per_share,
+            maker_price_per_share: model.maker_price_per_share,
+            created_at: model.created_at,
+            signature: model.signature,
+        }
+    }
+}
+
+/// A trade as anyone may see it, without who made it
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PublicTradeDto {
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub maker_order_id: Uuid,
+    pub taker_order_id: Uuid,
+    pub taker_side: OrderSide,
+    pub option: MarketOptionDto,
+    pub shares: i64,
+    pub price_per_share: i64,
+    pub maker_price_per_share: i64,
+    pub created_at: DateTime<FixedOffset>,
+    pub signature: String,
+}
+
+impl From<entity::fill::Model> for PublicTradeDto {
+    fn from(model: entity::fill::Model) -> Self {
+        Self {
+            id: model.id,
+            market_id: model.market_id,
+            maker_order_id: model.maker_order_id,
+            taker_order_id: model.taker_order_id,
+            taker_side: model.taker_side,
+            option: MarketOptionDto::from(model.option),
+            shares: model.shares,
+            price_per_share: model.price_per_share,
+            maker_price_per_share: model.maker_price_per_share,
+            created_at: model.created_at,
+            signature: model.signature,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradesQuery {
+    pub cursor: Option<Uuid>,
+    pub limit: Option<u64>,
+}
+
+impl TradesQuery {
+    pub const DEFAULT_LIMIT: u64 = 50;
+    pub const MAX_LIMIT: u64 = 100;
+
+    pub fn limit(&self) -> u64 {
+        self.limit
+            .unwrap_or(Self::DEFAULT_LIMIT)
+            .clamp(1, Self::MAX_LIMIT)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TradePage<T = TradeDto> {
+    /// Newest first
+    pub trades: Vec<T>,
+    /// Pass as `cursor` to get the next page, None on the last one
+    pub next_cursor: Option<Uuid>,
+}
+
+impl AppState {
+    /// Records a fill of `taker`, in the transaction of the match. Its signature is set once
+    /// the transaction settling it confirms, and stays empty in a dry run. Returns its id
+    pub async fn record_fill(
+        txn: &impl sea_orm::ConnectionTrait,
+        market_id: Uuid,
+        taker: &Taker,
+        fill: &Fill,
+    ) -> AppResult<Uuid> {
+        let id = Uuid::new_v4();
+        let price_per_share = i64::try_from(fill.taker_price)?;
+
+        entity::fill::ActiveModel {
+            id: Set(id),
+            market_id: Set(market_id),
+            maker_order_id: Set(fill.maker_id),
+            taker_order_id: Set(taker.order_id),
+            maker_user_id: Set(fill.maker_user_id),
+            taker_user_id: Set(taker.user_id),
+            taker_side: Set(taker.side),
+            option: Set(taker.option.clone()),
+            shares: Set(i64::try_from(fill.shares)?),
+            price_per_share: Set(price_per_share),
+            maker_price_per_share: Set(i64::try_from(fill.maker_price)?),
+            created_at: Set(Utc::now().into()),
+            signature: Set(String::new()),
+        }
+        .insert(txn)
+        .await?;
+
+        entity::market::Entity::update_many()
+            .col_expr(
+                entity::market::Column::LastTradePrice,
+                Expr::value(price_of_a(&taker.option, price_per_share)),
+            )
+            .filter(entity::market::Column::Id.eq(market_id))
+            .exec(txn)
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Sets the last trade price of the market back to its newest fill, or to none, once a fill
+    /// is deleted
+    pub(crate) async fn restore_last_trade_price(
+        txn: &impl sea_orm::ConnectionTrait,
+        market_id: Uuid,
+    ) -> AppResult<()> {
+        let last = entity::fill::Entity::find()
+            .filter(entity::fill::Column::MarketId.eq(market_id))
+            .order_by_desc(entity::fill::Column::CreatedAt)
+            .order_by_desc(entity::fill::Column::Id)
+            .one(txn)
+            .await?;
+
+        entity::market::Entity::update_many()
+            .col_expr(
+                entity::market::Column::LastTradePrice,
+                Expr::value(last.map(|fill| price_of_a(&fill.option, fill.price_per_share))),
+            )
+            .filter(entity::market::Column::Id.eq(market_id))
+            .exec(txn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn list_event_trades(
+        &self,
+        event_id: Uuid,
+        cursor: Option<Uuid>,
+        limit: u64,
+    ) -> AppResult<TradePage<PublicTradeDto>> {
+        let filter = Condition::all().add(entity::market::Column::EventId.eq(event_id));
+        self.list_trades(filter, cursor, limit).await
+    }
+
+    pub async fn list_market_trades(
+        &self,
+        market_id: Uuid,
+        cursor: Option<Uuid>,
+        limit: u64,
+    ) -> AppResult<TradePage<PublicTradeDto>> {
+        let filter = Condition::all().add(entity::fill::Column::MarketId.eq(market_id));
+        self.list_trades(filter, cursor, limit).await
+    }
+
+    /// Trades where the user was either side
+    pub async fn list_user_trades(
+        &self,
+        user_id: Uuid,
+        cursor: Option<Uuid>,
+        limit: u64,
+    ) -> AppResult<TradePage> {
+        let filter = Condition::any()
+            .add(entity::fill::Column::MakerUserId.eq(user_id))
+            .add(entity::fill::Column::TakerUserId.eq(user_id));
+        self.list_trades(filter, cursor, limit).await
+    }
+
+    /// Newest first. `cursor` is the last trade of the previous page, an unknown one gives an
+    /// empty page
+    async fn list_trades<T: From<entity::fill::Model>>(
+        &self,
+        filter: Condition,
+        cursor: Option<Uuid>,
+        limit: u64,
+    ) -> AppResult<TradePage<T>> {
+        let mut query = entity::fill::Entity::find()
+            .join(JoinType::InnerJoin, entity::fill::Relation::Market.def())
+            .filter(filter);
+
+        if let Some(cursor) = cursor {
+            let Some(last) = entity::fill::Entity::find_by_id(cursor)
+                .one(&self.database)
+                .await?
+            else {
+                return Ok(TradePage {
+                    trades: Vec::new(),
+                    next_cursor: None,
+                });
+            };
+
+            query = query.filter(
+                Condition::any()
+                    .add(entity::fill::Column::CreatedAt.lt(last.created_at))
+                    .add(
+                        Condition::all()
+                            .add(entity::fill::Column::CreatedAt.eq(last.created_at))
+                            .add(entity::fill::Column::Id.lt(last.id)),
+                    ),
+            );
+        }
+
+        // One more than asked tells whether there is a next page
+        let mut trades = query
+            .order_by_desc(entity::fill::Column::CreatedAt)
+            .order_by_desc(entity::fill::Column::Id)
+            .limit(limit + 1)
+            .all(&self.database)
+            .await?;
+
+        let next_cursor = if trades.len() as u64 > limit {
+            trades.truncate(limit as usize);
+            trades.last().map(|trade| trade.id)
+        } else {
+            None
+        };
+
+        Ok(TradePage {
+            trades: trades.into_iter().map(Into::into).collect(),
+            next_cursor,
+        })
+    }
+}
@@ -26,9 +26,7 @@ pub struct LeaderboardResponse {
 
 impl AppState {
     pub async fn get_leaderboard(&self) -> AppResult<LeaderboardResponse> {
-        let users = entity::user::Entity::find()
-            .all(&self.database)
-            .await?;
+        let users = entity::user::Entity::find().all(&self.database).await?;
 
         let positions = entity::position::Entity::find()
             .find_also_related(entity::market::Entity)
@@ -36,18 +34,18 @@ impl AppState {
             .await?;
 
         // Start with every user at zero profit (includes users with no shares)
-        let mut user_profits: HashMap<Uuid, i64> =
-            users.iter().map(|u| (u.id, 0)).collect();
+        let mut user_profits: HashMap<Uuid, i64> = users.iter().map(|u| (u.id, 0)).collect();
 
         // Add realized profit from resolved positions
         for (position, market) in &positions {
             let Some(market) = market else { continue };
-            let Some(resolved_option) = &market.resolved_option else { continue };
-            let won = match (&position.option, resolved_option) {
-                (MarketOption::A, MarketOption::A) => true,
-                (MarketOption::B, MarketOption::B) => true,
-                _ => false,
+            let Some(resolved_option) = &market.resolved_option else {
+                continue;
             };
+            let won = matches!(
+                (&position.option, resolved_option),
+                (MarketOption::A, MarketOption::A) | (MarketOption::B, MarketOption::B)
+            );
             let payout = if won { position.shares * 100 } else { 0 };
             let cost = position.shares * position.price_per_share;
             if let Some(profit) = user_profits.get_mut(&position.user_id) {
@@ -64,7 +62,7 @@ impl AppState {
             })
             .collect();
 
-        entries.sort_by(|a, b| b.realized_profit_cents.cmp(&a.realized_profit_cents));
+        entries.sort_by_key(|entry| std::cmp::Reverse(entry.realized_profit_cents));
 
         Ok(LeaderboardResponse { entries })
     }
@@ -1,19 +1,24 @@
 use std::collections::HashMap;
 
+use chrono::Utc;
 use sea_orm::{ColumnTrait, EntityTrait, QueryFilter};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
-use crate::{AppState, entity, error::AppResult};
+use crate::{
+    AppState, entity,
+    error::AppResult,
+    state::{book::best_quotes, buyorder::not_expired},
+};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct MarketPercentagesDto {
     pub option_a_percentage: Option<i64>,
     pub option_b_percentage: Option<i64>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EventPercentagesDto {
     pub percentages: HashMap<Uuid, MarketPercentagesDto>,
@@ -25,18 +30,23 @@ pub struct AllPercentagesDto {
     pub percentages: HashMap<Uuid, EventPercentagesDto>,
 }
 
+/// Beyond this spread the mid-price says little, and the last trade is shown instead
+pub const MAX_MID_SPREAD: i64 = 10;
+
 impl AppState {
-    /// Compute implied probabilities for all markets across all events.
+    /// Compute the headline percentages for all markets across all events.
     pub async fn get_all_percentages(&self) -> AppResult<AllPercentagesDto> {
         let rows = entity::market::Entity::find()
             .find_with_related(entity::buyorder::Entity)
+            .filter(not_expired(Utc::now()))
             .all(&self.database)
             .await?;
+        let sell_orders = self.sell_orders_by_market(None).await?;
 
         let percentages = rows
             .iter()
             .fold(HashMap::new(), |mut acc, (market, buy_orders)| {
-                let (id, dto) = market_percentages(market, buy_orders);
+                let (id, dto) = market_percentages(market, buy_orders, &sell_orders);
                 acc.entry(market.event_id)
                     .or_insert_with(|| EventPercentagesDto {
                         percentages: HashMap::new(),
@@ -49,28 +59,68 @@ impl AppState {
         Ok(AllPercentagesDto { percentages })
     }
 
-    /// Compute implied probabilities for all markets in an event.
+    /// Compute the headline percentages for all markets in an event.
     pub async fn get_event_percentages(&self, event_id: Uuid) -> AppResult<EventPercentagesDto> {
         let rows = entity::market::Entity::find()
             .filter(entity::market::Column::EventId.eq(event_id))
             .find_with_related(entity::buyorder::Entity)
+            .filter(not_expired(Utc::now()))
             .all(&self.database)
             .await?;
+        let sell_orders = self
+            .sell_orders_by_market(Some(rows.iter().map(|(market, _)| market.id).collect()))
+            .await?;
 
         let percentages = rows
             .iter()
-            .map(|(market, buy_orders)| market_percentages(market, buy_orders))
+            .map(|(market, buy_orders)| market_percentages(market, buy_orders, &sell_orders))
             .collect();
 
         Ok(EventPercentagesDto { percentages })
     }
+
+    /// Sell orders of the given markets, or of all of them
+    async fn sell_orders_by_market(
+        &self,
+        market_ids: Option<Vec<Uuid>>,
+    ) -> AppResult<HashMap<Uuid, Vec<entity::sellorder::Model>>> {
+        let mut query = entity::sellorder::Entity::find();
+        if let Some(market_ids) = market_ids {
+            query = query.filter(entity::sellorder::Column::MarketId.is_in(market_ids));
+        }
+
+        Ok(query
+            .all(&self.database)
+            .await?
+            .into_iter()
+            .fold(HashMap::new(), |mut acc, order| {
+                acc.entry(order.market_id)
+                    .or_insert_with(Vec::new)
+                    .push(order);
+                acc
+            }))
+    }
 }
 
+/// Markets that traded show the mid-price of their book, or the last trade when the book is
+/// one-sided or too wide. The others fall back to the implied probability of their bids.
+/// `buy_orders` are the ones that can still fill, without those past their expiry
 fn market_percentages(
     market: &entity::market::Model,
     buy_orders: &[entity::buyorder::Model],
+    sell_orders: &HashMap<Uuid, Vec<entity::sellorder::Model>>,
 ) -> (Uuid, MarketPercentagesDto) {
-    let (option_a_percentage, option_b_percentage) = implied_probability(buy_orders);
+    let (option_a_percentage, option_b_percentage) = match market.last_trade_price {
+        Some(last_trade) => {
+            let sell_orders = sell_orders.get(&market.id).map_or(&[][..], Vec::as_slice);
+            let pct_a = match best_quotes(buy_orders, sell_orders) {
+                (Some(bid), Some(ask)) if ask - bid <= MAX_MID_SPREAD => (bid + ask + 1) / 2,
+                _ => last_trade,
+            };
+            (Some(pct_a), Some(100 - pct_a))
+        }
+        None => implied_probability(buy_orders),
+    };
     (
         market.id,
         MarketPercentagesDto {
@@ -1,8 +1,20 @@
+pub mod book;
 pub mod buyorder;
+pub mod chart;
+pub mod claim;
+pub mod dry_run;
 pub mod event;
+pub mod faucet;
+pub mod fill;
 pub mod leaderboard;
 pub mod market;
 pub mod market_snapshot;
+pub mod outbox;
+pub mod portfolio;
 pub mod position;
+pub mod reconciliation;
+pub mod resolution;
+pub mod sellorder;
 pub mod session;
+pub mod stream;
 pub mod user;
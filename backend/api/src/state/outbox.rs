@@ -0,0 +1,1468 @@
+//! The outbox of chain transactions.
+//!
+//! Requests never send a transaction while their database transaction is open. They stage
+//! `ChainOperation`s instead, which commit along with the rest of the change as rows of
+//! `chain_operation`, and run right after the commit. Whatever does not confirm then is retried
+//! by `process_outbox` with a backoff. An operation that runs out of attempts fails its saga: the
+//! steps after it are skipped, and the compensations of the failed and skipped steps undo their
+//! part of the change, in the database and with refunds on chain, so that both agree again.
+//!
+//! Failed and dropped transactions never landed and are safe to send again. A gateway error may
+//! come after the transaction was sent, so each signature is saved on its operation right before
+//! it goes out, and looked up before the operation is sent again or given up on: one that landed
+//! confirms the operation, and one that may still land is waited for. Operations left submitted by
+//! a runner that died are taken over by `process_outbox`, which goes through the same lookup, and
+//! the runner can no longer save a signature, so it sends nothing else. Creating an event and
+//! adding options look at what is on chain instead. Either way an operation lands once.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+};
+
+use async_trait::async_trait;
+use blockchain_client::{
+    SendHook, SentStatus, Simulation, signer::keypair_from_base58, submit::with_send_hook,
+};
+use blockchain_core::{
+    accounts::event::EventOption,
+    instructions::{
+        AddOptionArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs,
+    },
+    money::{Price, Shares},
+};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use solana_sdk::{pubkey::Pubkey, signature::Signature, signer::Signer};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    chain::NewOption,
+    entity::{self, chain_operation::OperationStatus, market::MarketOption},
+    error::{AppError, AppResult},
+    state::{
+        buyorder::BuyOrderDto,
+        dry_run::Execution,
+        position::Lot,
+        sellorder::{SellOrderDto, option_token},
+        stream::MarketChange,
+    },
+};
+
+/// Attempts before an operation fails its saga
+pub const MAX_ATTEMPTS: i32 = 5;
+
+/// How long an operation can stay submitted before a runner is assumed to have died with it
+const SUBMIT_TIMEOUT: Duration = Duration::minutes(5);
+
+/// A chain transaction, with its signers kept as users and looked up when it runs
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum ChainOperation {
+    /// Creates the event along with its first markets
+    CreateEvent {
+        event_id: Uuid,
+        market_ids: Vec<Uuid>,
+    },
+    AddOption {
+        market_id: Uuid,
+    },
+    /// Moves `price` per share from the user to the event
+    CreateOrder {
+        user_id: Uuid,
+        market_id: Uuid,
+        shares: Shares,
+        price: Price,
+    },
+    MatchOrder {
+        yes_user_id: Uuid,
+        no_user_id: Uuid,
+        market_id: Uuid,
+        shares: Shares,
+    },
+    /// Gives `price` per share back to the user
+    CancelOrder {
+        user_id: Uuid,
+        market_id: Uuid,
+        shares: Shares,
+        price: Price,
+    },
+    TransferShares {
+        seller_id: Uuid,
+        buyer_id: Uuid,
+        market_id: Uuid,
+        option: MarketOption,
+        shares: Shares,
+        price: Price,
+    },
+    GetReward {
+        user_id: Uuid,
+        market_id: Uuid,
+        option: MarketOption,
+        shares: Shares,
+    },
+}
+
+impl ChainOperation {
+    fn market_id(&self) -> Option<Uuid> {
+        match self {
+            ChainOperation::CreateEvent { .. } => None,
+            ChainOperation::AddOption { market_id }
+            | ChainOperation::CreateOrder { market_id, .. }
+            | ChainOperation::MatchOrder { market_id, .. }
+            | ChainOperation::CancelOrder { market_id, .. }
+            | ChainOperation::TransferShares { market_id, .. }
+            | ChainOperation::GetReward { market_id, .. } => Some(*market_id),
+        }
+    }
+
+    /// Looks at what is on chain before sending anything, so it is always safe to send again
+    fn resumes(&self) -> bool {
+        matches!(
+            self,
+            ChainOperation::CreateEvent { .. } | ChainOperation::AddOption { .. }
+        )
+    }
+}
+
+/// Undoes part of a change whose chain operation failed for good
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(
+    tag = "type",
+    rename_all = "camelCase",
+    rename_all_fields = "camelCase"
+)]
+pub enum Compensation {
+    /// Takes back shares given to the user
+    RemoveShares {
+        market_id: Uuid,
+        user_id: Uuid,
+        option: MarketOption,
+        lot: Lot,
+    },
+    /// Gives back the lots taken from the user's positions
+    RestoreShares {
+        market_id: Uuid,
+        user_id: Uuid,
+        option: MarketOption,
+        lots: Vec<Lot>,
+    },
+    /// Deletes the fill, and puts the last trade price of its market back to the trade before
+    DeleteFill {
+        fill_id: Uuid,
+    },
+    /// Puts back shares a fill took from a buy order, recreating the order if it was emptied
+    RestoreBuyOrder {
+        order: BuyOrderDto,
+        created_at: DateTime<FixedOffset>,
+        shares: i64,
+    },
+    RestoreSellOrder {
+        order: SellOrderDto,
+        created_at: DateTime<FixedOffset>,
+        shares: i64,
+    },
+    DeleteBuyOrder {
+        order_id: Uuid,
+    },
+    /// Lets the user claim their winnings again
+    DeleteClaim {
+        claim_id: Uuid,
+    },
+    /// Queues the refund of a resolved market again in a saga of its own, as its order can't be
+    /// put back
+    RequeueRefund {
+        refund_id: Uuid,
+        operation: ChainOperation,
+    },
+    /// Queues `operation` in a saga of its own, if step `after` of the saga confirmed
+    Chain {
+        operation: ChainOperation,
+        after: i32,
+    },
+}
+
+/// One operation of a saga, with what undoes it
+#[derive(Debug)]
+pub struct Step {
+    operation: ChainOperation,
+    compensation: Vec<Compensation>,
+    fill_id: Option<Uuid>,
+}
+
+impl Step {
+    pub fn new(operation: ChainOperation) -> Self {
+        Self {
+            operation,
+            compensation: Vec::new(),
+            fill_id: None,
+        }
+    }
+
+    /// Applied in order if the step fails, or is skipped because an earlier one failed
+    pub fn with_compensation(mut self, compensation: Vec<Compensation>) -> Self {
+        self.compensation = compensation;
+        self
+    }
+
+    /// The fill that gets the signature of the transaction
+    pub fn with_fill(mut self, fill_id: Uuid) -> Self {
+        self.fill_id = Some(fill_id);
+        self
+    }
+}
+
+/// Steps that run one after the other
+#[derive(Debug)]
+pub struct Saga {
+    pub id: Uuid,
+    pub steps: Vec<Step>,
+}
+
+impl Saga {
+    pub fn new() -> Self {
+        Self {
+            id: Uuid::new_v4(),
+            steps: Vec::new(),
+        }
+    }
+}
+
+impl Default for Saga {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How far a saga got
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SagaStatus {
+    Pending,
+    Confirmed,
+    Failed,
+}
+
+/// Where a saga stands, for requests that report on the transactions they queued
+#[derive(Debug, Clone)]
+pub struct SagaProgress {
+    pub status: SagaStatus,
+    /// Why it failed for good
+    pub error: Option<String>,
+    /// Of the steps that confirmed, in order
+    pub signatures: Vec<Signature>,
+}
+
+/// A confirmed operation
+struct Submitted {
+    /// None when everything had already landed in an earlier attempt whose answer was lost
+    signature: Option<Signature>,
+    /// What is still missing on chain, when it only partly landed
+    follow_up: Vec<ChainOperation>,
+}
+
+/// Where the last transaction sent for an operation stands
+enum Previous {
+    /// Nothing was sent, or it can no longer land
+    NotLanded,
+    Landed(Signature),
+    /// Not seen yet, but its blockhash is still valid
+    InFlight,
+}
+
+/// The operation, as long as the runner that claimed it with `attempts` still holds it
+fn held(operation_id: Uuid, attempts: i32) -> Condition {
+    Condition::all()
+        .add(entity::chain_operation::Column::Id.eq(operation_id))
+        .add(entity::chain_operation::Column::Status.eq(OperationStatus::Submitted))
+        .add(entity::chain_operation::Column::Attempts.eq(attempts))
+}
+
+/// Saves every signature on the operation before it is sent, and stops the send once another
+/// runner took the operation over
+struct SaveSignature {
+    database: DatabaseConnection,
+    operation_id: Uuid,
+    attempts: i32,
+}
+
+#[async_trait]
+impl SendHook for SaveSignature {
+    async fn before_send(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> anyhow::Result<()> {
+        let now = Utc::now();
+        let saved = entity::chain_operation::Entity::update_many()
+            .set(entity::chain_operation::ActiveModel {
+                sent_signature: Set(Some(signature.to_string())),
+                sent_valid_until: Set(Some(i64::try_from(last_valid_block_height)?)),
+                updated_at: Set(now.into()),
+                ..Default::default()
+            })
+            .filter(held(self.operation_id, self.attempts))
+            .exec(&self.database)
+            .await?;
+        if saved.rows_affected == 0 {
+            anyhow::bail!(
+                "Chain operation {} was taken over by another runner",
+                self.operation_id
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Adds the steps of `sagas` to the outbox, in the transaction of the change they belong to
+pub(crate) async fn enqueue(txn: &impl ConnectionTrait, sagas: &[Saga]) -> AppResult<()> {
+    let now = Utc::now();
+    for saga in sagas {
+        for (step, operation) in saga.steps.iter().enumerate() {
+            insert_operation(
+                txn,
+                saga.id,
+                i32::try_from(step).map_err(anyhow::Error::from)?,
+                &operation.operation,
+                &operation.compensation,
+                operation.fill_id,
+                now,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn insert_operation(
+    txn: &impl ConnectionTrait,
+    saga_id: Uuid,
+    step: i32,
+    operation: &ChainOperation,
+    compensation: &[Compensation],
+    fill_id: Option<Uuid>,
+    now: DateTime<Utc>,
+) -> AppResult<()> {
+    entity::chain_operation::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        saga_id: Set(saga_id),
+        step: Set(step),
+        operation: Set(to_json(operation)?),
+        compensation: Set(to_json(compensation)?),
+        status: Set(OperationStatus::Pending),
+        attempts: Set(0),
+        last_error: Set(None),
+        signature: Set(None),
+        sent_signature: Set(None),
+        sent_valid_until: Set(None),
+        fill_id: Set(fill_id),
+        next_attempt_at: Set(now.into()),
+        created_at: Set(now.into()),
+        updated_at: Set(now.into()),
+    }
+    .insert(txn)
+    .await?;
+    Ok(())
+}
+
+/// Markets with operations not yet confirmed or given up on, whose balances on chain may still
+/// lag behind the database
+pub(crate) async fn unsettled_markets(conn: &impl ConnectionTrait) -> AppResult<BTreeSet<Uuid>> {
+    let operations = entity::chain_operation::Entity::find()
+        .filter(
+            entity::chain_operation::Column::Status
+                .is_in([OperationStatus::Pending, OperationStatus::Submitted]),
+        )
+        .all(conn)
+        .await?;
+
+    let mut markets = BTreeSet::new();
+    for operation in &operations {
+        match from_json(&operation.operation)? {
+            ChainOperation::CreateEvent { market_ids, .. } => markets.extend(market_ids),
+            operation => markets.extend(operation.market_id()),
+        }
+    }
+    Ok(markets)
+}
+
+/// The progress of each saga. Sagas that were never queued, as in a dry run, are left out
+pub(crate) async fn saga_progress(
+    conn: &impl ConnectionTrait,
+    saga_ids: impl IntoIterator<Item = Uuid>,
+) -> AppResult<HashMap<Uuid, SagaProgress>> {
+    let operations = entity::chain_operation::Entity::find()
+        .filter(entity::chain_operation::Column::SagaId.is_in(saga_ids))
+        .order_by_asc(entity::chain_operation::Column::Step)
+        .all(conn)
+        .await?;
+
+    let mut sagas: HashMap<Uuid, Vec<entity::chain_operation::Model>> = HashMap::new();
+    for operation in operations {
+        sagas.entry(operation.saga_id).or_default().push(operation);
+    }
+
+    Ok(sagas
+        .into_iter()
+        .map(|(saga_id, operations)| {
+            let failed = operations
+                .iter()
+                .find(|operation| operation.status == OperationStatus::Failed);
+            let status = if failed.is_some() {
+                SagaStatus::Failed
+            } else if operations
+                .iter()
+                .all(|operation| operation.status == OperationStatus::Confirmed)
+            {
+                SagaStatus::Confirmed
+            } else {
+                SagaStatus::Pending
+            };
+            let progress = SagaProgress {
+                status,
+                error: failed.and_then(|operation| operation.last_error.clone()),
+                signatures: operations
+                    .iter()
+                    .filter_map(|operation| operation.signature.as_deref())
+                    .filter_map(|sig| sig.parse().ok())
+                    .collect(),
+            };
+            (saga_id, progress)
+        })
+        .collect())
+}
+
+fn to_json(value: &(impl Serialize + ?Sized)) -> AppResult<Value> {
+    Ok(serde_json::to_value(value).map_err(anyhow::Error::from)?)
+}
+
+fn from_json<T: for<'de> Deserialize<'de>>(value: &Value) -> AppResult<T> {
+    Ok(serde_json::from_value(value.clone()).map_err(anyhow::Error::from)?)
+}
+
+/// Waits twice as long after every attempt
+fn backoff(attempts: i32) -> Duration {
+    Duration::seconds(1 << attempts.clamp(0, 10))
+}
+
+async fn find_market(
+    conn: &impl ConnectionTrait,
+    market_id: Uuid,
+) -> AppResult<entity::market::Model> {
+    entity::market::Entity::find_by_id(market_id)
+        .one(conn)
+        .await?
+        .ok_or(AppError::MarketNotFound)
+}
+
+/// The key of the user's wallet in the signer provider
+async fn find_wallet(conn: &impl ConnectionTrait, user_id: Uuid) -> AppResult<String> {
+    Ok(entity::user::Entity::find_by_id(user_id)
+        .one(conn)
+        .await?
+        .ok_or(AppError::UserNotFound)?
+        .wallet)
+}
+
+fn new_option(market: &entity::market::Model) -> AppResult<NewOption> {
+    let yes_mint = keypair_from_base58(&market.yes_keypair)?;
+    let no_mint = keypair_from_base58(&market.no_keypair)?;
+    Ok(NewOption {
+        args: AddOptionArgs {
+            event_uuid: market.event_id,
+            option_uuid: market.id,
+            option_info: EventOption {
+                option_desc: "".into(),
+                yes_mint: yes_mint.pubkey(),
+                no_mint: no_mint.pubkey(),
+            },
+        },
+        yes_mint,
+        no_mint,
+    })
+}
+
+impl AppState {
+    /// Adds `step` to the current saga of the request and returns its number. In a dry run it is
+    /// simulated right away instead
+    pub async fn stage(
+        &self,
+        txn: &impl ConnectionTrait,
+        execution: &mut Execution,
+        step: Step,
+    ) -> AppResult<i32> {
+        if execution.is_dry_run() {
+            let simulation = self.simulate_operation(txn, &step.operation).await?;
+            execution.record(simulation);
+        }
+        Ok(execution.push(step))
+    }
+
+    /// Runs the sagas a request just committed, and returns the links of the transactions that
+    /// confirmed. What does not confirm now is left to `process_outbox`
+    pub async fn settle(&self, execution: &Execution) -> Vec<String> {
+        let mut tx_urls = Vec::new();
+        if execution.is_dry_run() {
+            return tx_urls;
+        }
+
+        for saga_id in execution.saga_ids() {
+            match self.run_saga(saga_id).await {
+                Ok(signatures) => {
+                    tx_urls.extend(signatures.iter().map(|sig| self.chain.transaction_url(sig)))
+                }
+                Err(e) => tracing::error!("Failed to run saga {}: {}", saga_id, e),
+            }
+        }
+        tx_urls
+    }
+
+    /// Takes over the operations left submitted by a runner that died, then retries the
+    /// operations that are due. Returns how many sagas were run
+    pub async fn process_outbox(&self) -> AppResult<usize> {
+        let now = Utc::now();
+
+        let abandoned = entity::chain_operation::Entity::find()
+            .filter(entity::chain_operation::Column::Status.eq(OperationStatus::Submitted))
+            .filter(entity::chain_operation::Column::UpdatedAt.lt(now - SUBMIT_TIMEOUT))
+            .all(&self.database)
+            .await?;
+        for operation in abandoned {
+            if let Err(e) = self.take_over(operation.clone()).await {
+                tracing::error!(
+                    "Failed to take over chain operation {}: {}",
+                    operation.id,
+                    e
+                );
+            }
+        }
+
+        let sagas: Vec<Uuid> = entity::chain_operation::Entity::find()
+            .filter(entity::chain_operation::Column::Status.eq(OperationStatus::Pending))
+            .filter(entity::chain_operation::Column::NextAttemptAt.lte(now))
+            .select_only()
+            .column(entity::chain_operation::Column::SagaId)
+            .distinct()
+            .into_tuple()
+            .all(&self.database)
+            .await?;
+
+        for saga_id in &sagas {
+            if let Err(e) = self.run_saga(*saga_id).await {
+                tracing::error!("Failed to run saga {}: {}", saga_id, e);
+            }
+        }
+
+        Ok(sagas.len())
+    }
+
+    /// Runs the steps of the saga in order, up to the first one that does not confirm. Returns
+    /// the signatures of the confirmed steps
+    pub async fn run_saga(&self, saga_id: Uuid) -> AppResult<Vec<Signature>> {
+        loop {
+            let operations = entity::chain_operation::Entity::find()
+                .filter(entity::chain_operation::Column::SagaId.eq(saga_id))
+                .order_by_asc(entity::chain_operation::Column::Step)
+                .all(&self.database)
+                .await?;
+
+            let next = operations
+                .iter()
+                .find(|operation| operation.status != OperationStatus::Confirmed);
+            let runnable = next.is_some_and(|operation| {
+                operation.status == OperationStatus::Pending
+                    && operation.next_attempt_at <= Utc::now()
+            });
+
+            if !runnable || !self.attempt(next.expect("Runnable").clone()).await? {
+                return Ok(operations
+                    .iter()
+                    .filter_map(|operation| operation.signature.as_deref())
+                    .filter_map(|sig| sig.parse().ok())
+                    .collect());
+            }
+        }
+    }
+
+    /// Claims an operation left submitted by a runner that died, counting what it did as an
+    /// attempt, and goes on where it stopped
+    async fn take_over(&self, operation: entity::chain_operation::Model) -> AppResult<()> {
+        let attempts = operation.attempts + 1;
+        let taken = entity::chain_operation::Entity::update_many()
+            .set(entity::chain_operation::ActiveModel {
+                attempts: Set(attempts),
+                updated_at: Set(Utc::now().into()),
+                ..Default::default()
+            })
+            .filter(held(operation.id, operation.attempts))
+            .filter(entity::chain_operation::Column::UpdatedAt.eq(operation.updated_at))
+            .exec(&self.database)
+            .await?;
+        if taken.rows_affected == 0 {
+            return Ok(());
+        }
+
+        tracing::warn!(
+            "Taking over chain operation {}, left submitted since {}",
+            operation.id,
+            operation.updated_at
+        );
+        self.run_claimed(entity::chain_operation::Model {
+            attempts,
+            ..operation
+        })
+        .await?;
+        Ok(())
+    }
+
+    /// Sends the operation if no other runner claimed it. Returns whether it confirmed
+    async fn attempt(&self, operation: entity::chain_operation::Model) -> AppResult<bool> {
+        let claimed = entity::chain_operation::Entity::update_many()
+            .set(entity::chain_operation::ActiveModel {
+                status: Set(OperationStatus::Submitted),
+                updated_at: Set(Utc::now().into()),
+                ..Default::default()
+            })
+            .filter(entity::chain_operation::Column::Id.eq(operation.id))
+            .filter(entity::chain_operation::Column::Status.eq(OperationStatus::Pending))
+            .exec(&self.database)
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        self.run_claimed(operation).await
+    }
+
+    /// Sends an operation this runner holds, unless the transaction of an earlier attempt landed
+    /// or may still land. Returns whether it confirmed
+    async fn run_claimed(&self, operation: entity::chain_operation::Model) -> AppResult<bool> {
+        let chain_operation: ChainOperation = match from_json(&operation.operation) {
+            Ok(chain_operation) => chain_operation,
+            Err(e) => {
+                self.retry_or_fail(operation, e.to_string(), Previous::NotLanded)
+                    .await?;
+                return Ok(false);
+            }
+        };
+
+        match self.previous_attempt(&operation, &chain_operation).await {
+            Ok(Previous::Landed(signature)) => {
+                tracing::info!(
+                    "Chain operation {} landed in {} after all",
+                    operation.id,
+                    signature
+                );
+                return self
+                    .confirm_operation(
+                        operation,
+                        Submitted {
+                            signature: Some(signature),
+                            follow_up: Vec::new(),
+                        },
+                    )
+                    .await;
+            }
+            Ok(Previous::InFlight) => {
+                self.retry_later(&operation, operation.attempts, None)
+                    .await?;
+                return Ok(false);
+            }
+            Ok(Previous::NotLanded) => {}
+            // Sending again could land it twice
+            Err(e) => {
+                self.retry_later(&operation, operation.attempts, Some(e.to_string()))
+                    .await?;
+                return Ok(false);
+            }
+        }
+
+        // Out of attempts, but the last one was not settled when it ended
+        if operation.attempts >= MAX_ATTEMPTS {
+            let error = operation
+                .last_error
+                .clone()
+                .unwrap_or_else(|| "Out of attempts".to_string());
+            self.fail_saga(operation, error).await?;
+            return Ok(false);
+        }
+
+        let hook = Arc::new(SaveSignature {
+            database: self.database.clone(),
+            operation_id: operation.id,
+            attempts: operation.attempts,
+        });
+        match with_send_hook(hook, self.submit_operation(&chain_operation)).await {
+            Ok(submitted) => self.confirm_operation(operation, submitted).await,
+            Err(e) => {
+                // The transaction may have been sent before the error, with its signature saved
+                let sent = entity::chain_operation::Entity::find_by_id(operation.id)
+                    .one(&self.database)
+                    .await?
+                    .ok_or_else(|| anyhow::anyhow!("Chain operation {} is gone", operation.id))?;
+                let previous = self.previous_attempt(&sent, &chain_operation).await;
+                self.retry_or_fail(
+                    operation,
+                    e.to_string(),
+                    previous.unwrap_or(Previous::InFlight),
+                )
+                .await?;
+                Ok(false)
+            }
+        }
+    }
+
+    /// Looks up the last transaction sent for the operation
+    async fn previous_attempt(
+        &self,
+        operation: &entity::chain_operation::Model,
+        chain_operation: &ChainOperation,
+    ) -> AppResult<Previous> {
+        let (Some(signature), Some(valid_until)) =
+            (&operation.sent_signature, operation.sent_valid_until)
+        else {
+            return Ok(Previous::NotLanded);
+        };
+        if chain_operation.resumes() {
+            return Ok(Previous::NotLanded);
+        }
+
+        let signature: Signature = signature.parse().map_err(anyhow::Error::from)?;
+        let valid_until = u64::try_from(valid_until).map_err(anyhow::Error::from)?;
+        Ok(
+            match self.chain.sent_status(&signature, valid_until).await? {
+                SentStatus::Confirmed => Previous::Landed(signature),
+                SentStatus::InFlight => Previous::InFlight,
+                SentStatus::Failed(_) | SentStatus::Expired => Previous::NotLanded,
+            },
+        )
+    }
+
+    /// Counts a failed attempt. Gives up on the operation once it is out of attempts, unless the
+    /// transaction it sent last may still land
+    async fn retry_or_fail(
+        &self,
+        operation: entity::chain_operation::Model,
+        error: String,
+        previous: Previous,
+    ) -> AppResult<()> {
+        let attempts = operation.attempts + 1;
+        if attempts >= MAX_ATTEMPTS && matches!(previous, Previous::NotLanded) {
+            return self.fail_saga(operation, error).await;
+        }
+
+        tracing::warn!(
+            "Chain operation {} failed, attempt {} of {}: {}",
+            operation.id,
+            attempts,
+            MAX_ATTEMPTS,
+            error
+        );
+        self.retry_later(&operation, attempts, Some(error)).await
+    }
+
+    /// Puts the operation back for its next attempt, if this runner still holds it
+    async fn retry_later(
+        &self,
+        operation: &entity::chain_operation::Model,
+        attempts: i32,
+        error: Option<String>,
+    ) -> AppResult<()> {
+        let now = Utc::now();
+        let mut update = entity::chain_operation::ActiveModel {
+            status: Set(OperationStatus::Pending),
+            attempts: Set(attempts),
+            next_attempt_at: Set((now + backoff(attempts)).into()),
+            updated_at: Set(now.into()),
+            ..Default::default()
+        };
+        if let Some(error) = error {
+            update.last_error = Set(Some(error));
+        }
+        entity::chain_operation::Entity::update_many()
+            .set(update)
+            .filter(held(operation.id, operation.attempts))
+            .exec(&self.database)
+            .await?;
+        Ok(())
+    }
+
+    /// Records the operation as confirmed, with its follow-ups. Returns false if another runner
+    /// took it over in the meantime
+    async fn confirm_operation(
+        &self,
+        operation: entity::chain_operation::Model,
+        submitted: Submitted,
+    ) -> AppResult<bool> {
+        let transaction = self.database.begin().await?;
+        let now = Utc::now();
+
+        let holding = entity::chain_operation::Entity::find()
+            .filter(held(operation.id, operation.attempts))
+            .lock_exclusive()
+            .one(&transaction)
+            .await?;
+        if holding.is_none() {
+            return Ok(false);
+        }
+        let signature = submitted.signature.map(|signature| signature.to_string());
+
+        if let (Some(fill_id), Some(signature)) = (operation.fill_id, &signature) {
+            entity::fill::Entity::update_many()
+                .set(entity::fill::ActiveModel {
+                    signature: Set(signature.clone()),
+                    ..Default::default()
+                })
+                .filter(entity::fill::Column::Id.eq(fill_id))
+                .exec(&transaction)
+                .await?;
+        }
+
+        // Follow-ups go at the end of the saga
+        let last_step: Option<i32> = entity::chain_operation::Entity::find()
+            .filter(entity::chain_operation::Column::SagaId.eq(operation.saga_id))
+            .select_only()
+            .column_as(entity::chain_operation::Column::Step.max(), "last_step")
+            .into_tuple()
+            .one(&transaction)
+            .await?
+            .flatten();
+        for (i, follow_up) in submitted.follow_up.iter().enumerate() {
+            let step = last_step.unwrap_or(operation.step)
+                + 1
+                + i32::try_from(i).map_err(anyhow::Error::from)?;
+            insert_operation(
+                &transaction,
+                operation.saga_id,
+                step,
+                follow_up,
+                &[],
+                None,
+                now,
+            )
+            .await?;
+        }
+
+        let attempts = operation.attempts + 1;
+        let mut active: entity::chain_operation::ActiveModel = operation.into();
+        active.status = Set(OperationStatus::Confirmed);
+        active.attempts = Set(attempts);
+        active.signature = Set(signature);
+        active.updated_at = Set(now.into());
+        active.update(&transaction).await?;
+
+        transaction.commit().await?;
+        Ok(true)
+    }
+
+    /// Gives up on `failed`, skips the rest of its saga and applies their compensations, last
+    /// step first. Refunds are queued for the outbox to run
+    async fn fail_saga(
+        &self,
+        failed: entity::chain_operation::Model,
+        error: String,
+    ) -> AppResult<()> {
+        tracing::error!(
+            "Chain operation {} failed for good, compensating saga {}: {}",
+            failed.id,
+            failed.saga_id,
+            error
+        );
+
+        let transaction = self.database.begin().await?;
+        let now = Utc::now();
+
+        let holding = entity::chain_operation::Entity::find()
+            .filter(held(failed.id, failed.attempts))
+            .lock_exclusive()
+            .one(&transaction)
+            .await?;
+        if holding.is_none() {
+            return Ok(());
+        }
+
+        let operations = entity::chain_operation::Entity::find()
+            .filter(entity::chain_operation::Column::SagaId.eq(failed.saga_id))
+            .order_by_desc(entity::chain_operation::Column::Step)
+            .all(&transaction)
+            .await?;
+        let confirmed: BTreeSet<i32> = operations
+            .iter()
+            .filter(|operation| operation.status == OperationStatus::Confirmed)
+            .map(|operation| operation.step)
+            .collect();
+
+        let mut touched: HashMap<Uuid, BTreeSet<Uuid>> = HashMap::new();
+        if let Some(market_id) = from_json::<ChainOperation>(&failed.operation)?.market_id() {
+            touched.entry(market_id).or_default();
+        }
+        let mut refunds = Vec::new();
+
+        for operation in operations {
+            let last_error = if operation.id == failed.id {
+                error.clone()
+            } else if operation.step > failed.step && operation.status == OperationStatus::Pending {
+                format!("Skipped, step {} failed", failed.step)
+            } else {
+                continue;
+            };
+
+            for compensation in from_json::<Vec<Compensation>>(&operation.compensation)? {
+                match compensation {
+                    Compensation::Chain { operation, after } => {
+                        if confirmed.contains(&after) {
+                            refunds.push(Saga {
+                                id: Uuid::new_v4(),
+                                steps: vec![Step::new(operation)],
+                            });
+                        }
+                    }
+                    Compensation::RequeueRefund {
+                        refund_id,
+                        operation,
+                    } => {
+                        let saga = Saga {
+                            id: Uuid::new_v4(),
+                            steps: vec![Step::new(operation.clone()).with_compensation(vec![
+                                Compensation::RequeueRefund {
+                                    refund_id,
+                                    operation,
+                                },
+                            ])],
+                        };
+                        entity::resolution_refund::Entity::update_many()
+                            .set(entity::resolution_refund::ActiveModel {
+                                saga_id: Set(saga.id),
+                                ..Default::default()
+                            })
+                            .filter(entity::resolution_refund::Column::Id.eq(refund_id))
+                            .exec(&transaction)
+                            .await?;
+                        refunds.push(saga);
+                    }
+                    compensation => {
+                        if let Some((market_id, user_id)) =
+                            AppState::compensate(&transaction, compensation).await?
+                        {
+                            touched.entry(market_id).or_default().insert(user_id);
+                        }
+                    }
+                }
+            }
+
+            let attempts = operation.attempts + i32::from(operation.id == failed.id);
+            let mut active: entity::chain_operation::ActiveModel = operation.into();
+            active.status = Set(OperationStatus::Failed);
+            active.attempts = Set(attempts);
+            active.last_error = Set(Some(last_error));
+            active.updated_at = Set(now.into());
+            active.update(&transaction).await?;
+        }
+
+        enqueue(&transaction, &refunds).await?;
+        transaction.commit().await?;
+
+        for (market_id, users) in touched {
+            self.publish(MarketChange {
+                market_id,
+                taker_order_id: None,
+                users: users.into_iter().collect(),
+            })
+            .await;
+        }
+
+        Ok(())
+    }
+
+    /// Applies a database compensation. Returns the market and user it touched, if any
+    async fn compensate(
+        txn: &impl ConnectionTrait,
+        compensation: Compensation,
+    ) -> AppResult<Option<(Uuid, Uuid)>> {
+        Ok(match compensation {
+            Compensation::RemoveShares {
+                market_id,
+                user_id,
+                option,
+                lot,
+            } => {
+                let position = entity::position::Entity::find()
+                    .filter(entity::position::Column::MarketId.eq(market_id))
+                    .filter(entity::position::Column::UserId.eq(user_id))
+                    .filter(entity::position::Column::Option.eq(option))
+                    .filter(entity::position::Column::PricePerShare.eq(lot.price_per_share))
+                    .one(txn)
+                    .await?;
+                if let Some(position) = position {
+                    let shares = position.shares - lot.shares;
+                    if shares > 0 {
+                        let mut active: entity::position::ActiveModel = position.into();
+                        active.shares = Set(shares);
+                        active.update(txn).await?;
+                    } else {
+                        entity::position::Entity::delete_by_id(position.id)
+                            .exec(txn)
+                            .await?;
+                    }
+                }
+                Some((market_id, user_id))
+            }
+            Compensation::RestoreShares {
+                market_id,
+                user_id,
+                option,
+                lots,
+            } => {
+                for lot in lots {
+                    AppState::upsert_position(
+                        txn,
+                        market_id,
+                        user_id,
+                        option.clone(),
+                        lot.shares,
+                        lot.price_per_share,
+                    )
+                    .await?;
+                }
+                Some((market_id, user_id))
+            }
+            Compensation::DeleteFill { fill_id } => {
+                let fill = entity::fill::Entity::find_by_id(fill_id).one(txn).await?;
+                entity::fill::Entity::delete_by_id(fill_id)
+                    .exec(txn)
+                    .await?;
+                // The fill may have been the last trade of its market
+                if let Some(fill) = fill {
+                    AppState::restore_last_trade_price(txn, fill.market_id).await?;
+                }
+                None
+            }
+            Compensation::RestoreBuyOrder {
+                order,
+                created_at,
+                shares,
+            } => {
+                match entity::buyorder::Entity::find_by_id(order.id)
+                    .one(txn)
+                    .await?
+                {
+                    Some(existing) => {
+                        let new_shares = existing.shares + shares;
+                        let mut active: entity::buyorder::ActiveModel = existing.into();
+                        active.shares = Set(new_shares);
+                        active.update(txn).await?;
+                    }
+                    None => {
+                        entity::buyorder::ActiveModel {
+                            id: Set(order.id),
+                            market_id: Set(order.market_id),
+                            user_id: Set(order.user_id),
+                            option: Set(order.option.into()),
+                            shares: Set(shares),
+                            price_per_share: Set(order.price_per_share),
+                            created_at: Set(created_at),
+                            time_in_force: Set(order.time_in_force),
+                            expires_at: Set(order.expires_at),
+                        }
+                        .insert(txn)
+                        .await?;
+                    }
+                }
+                Some((order.market_id, order.user_id))
+            }
+            Compensation::RestoreSellOrder {
+                order,
+                created_at,
+                shares,
+            } => {
+                match entity::sellorder::Entity::find_by_id(order.id)
+                    .one(txn)
+                    .await?
+                {
+                    Some(existing) => {
+                        let new_shares = existing.shares + shares;
+                        let mut active: entity::sellorder::ActiveModel = existing.into();
+                        active.shares = Set(new_shares);
+                        active.update(txn).await?;
+                    }
+                    None => {
+                        entity::sellorder::ActiveModel {
+                            id: Set(order.id),
+                            market_id: Set(order.market_id),
+                            user_id: Set(order.user_id),
+                            option: Set(order.option.into()),
+                            shares: Set(shares),
+                            price_per_share: Set(order.price_per_share),
+                            created_at: Set(created_at),
+                        }
+                        .insert(txn)
+                        .await?;
+                    }
+                }
+                Some((order.market_id, order.user_id))
+            }
+            Compensation::DeleteBuyOrder { order_id } => {
+                let order = entity::buyorder::Entity::find_by_id(order_id)
+                    .one(txn)
+                    .await?;
+                match order {
+                    Some(order) => {
+                        entity::buyorder::Entity::delete_by_id(order.id)
+                            .exec(txn)
+                            .await?;
+                        Some((order.market_id, order.user_id))
+                    }
+                    None => None,
+                }
+            }
+            Compensation::DeleteClaim { claim_id } => {
+                entity::claim::Entity::delete_by_id(claim_id)
+                    .exec(txn)
+                    .await?;
+                None
+            }
+            Compensation::Chain { .. } | Compensation::RequeueRefund { .. } => {
+                unreachable!("Chain compensations are queued")
+            }
+        })
+    }
+
+    async fn simulate_operation(
+        &self,
+        conn: &impl ConnectionTrait,
+        operation: &ChainOperation,
+    ) -> AppResult<Simulation> {
+        let signers = self.chain.signers();
+
+        Ok(match operation {
+            ChainOperation::CreateEvent {
+                event_id,
+                market_ids,
+            } => {
+                let mut options = Vec::with_capacity(market_ids.len());
+                for market_id in market_ids {
+                    let option = new_option(&find_market(conn, *market_id).await?)?;
+                    options.push((option.args.option_uuid, option.args.option_info));
+                }
+                let args = CreateEventArgs {
+                    uuid: *event_id,
+                    description: "".into(),
+                    options: options.into_iter().collect(),
+                };
+                self.chain.simulate_create_event(&args).await?
+            }
+            ChainOperation::AddOption { market_id } => {
+                let option = new_option(&find_market(conn, *market_id).await?)?;
+                self.chain
+                    .simulate_add_option(
+                        &option.yes_mint.pubkey(),
+                        &option.no_mint.pubkey(),
+                        &option.args,
+                    )
+                    .await?
+            }
+            ChainOperation::CreateOrder {
+                user_id,
+                market_id,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.pubkey(&find_wallet(conn, *user_id).await?)?;
+                self.chain
+                    .simulate_create_order(&user, &create_order_args(&market, *shares, *price))
+                    .await?
+            }
+            ChainOperation::MatchOrder {
+                yes_user_id,
+                no_user_id,
+                market_id,
+                shares,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let yes_user = signers.pubkey(&find_wallet(conn, *yes_user_id).await?)?;
+                let no_user = signers.pubkey(&find_wallet(conn, *no_user_id).await?)?;
+                let (yes_mint, _) = option_token(&market, &MarketOption::A)?;
+                let (no_mint, _) = option_token(&market, &MarketOption::B)?;
+                self.chain
+                    .simulate_match_order(
+                        &yes_user,
+                        &no_user,
+                        &yes_mint,
+                        &no_mint,
+                        &match_order_args(&market, *shares),
+                    )
+                    .await?
+            }
+            ChainOperation::CancelOrder {
+                user_id,
+                market_id,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.pubkey(&find_wallet(conn, *user_id).await?)?;
+                self.chain
+                    .simulate_cancel_order(&user, &cancel_order_args(&market, *shares, *price))
+                    .await?
+            }
+            ChainOperation::TransferShares {
+                seller_id,
+                buyer_id,
+                market_id,
+                option,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let seller = signers.pubkey(&find_wallet(conn, *seller_id).await?)?;
+                let buyer = signers.pubkey(&find_wallet(conn, *buyer_id).await?)?;
+                let (mint, args) = transfer_shares_args(&market, option, *shares, *price)?;
+                self.chain
+                    .simulate_transfer_shares(&seller, &buyer, &mint, &args)
+                    .await?
+            }
+            ChainOperation::GetReward {
+                user_id,
+                market_id,
+                option,
+                shares,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.pubkey(&find_wallet(conn, *user_id).await?)?;
+                let (mint, _) = option_token(&market, option)?;
+                self.chain
+                    .simulate_get_reward(&user, &mint, &get_reward_args(&market, *shares))
+                    .await?
+            }
+        })
+    }
+
+    async fn submit_operation(&self, operation: &ChainOperation) -> AppResult<Submitted> {
+        let conn = &self.database;
+        let signers = self.chain.signers();
+
+        let outcome = match operation {
+            ChainOperation::CreateEvent {
+                event_id,
+                market_ids,
+            } => {
+                let mut options = Vec::with_capacity(market_ids.len());
+                for market_id in market_ids {
+                    options.push(new_option(&find_market(conn, *market_id).await?)?);
+                }
+                let event = CreateEmptyEventArgs {
+                    uuid: *event_id,
+                    description: "".into(),
+                };
+                // Resumes after the chunks that landed in an earlier attempt
+                let outcome = self.chain.create_event(&event, &options).await?;
+
+                let signature = match outcome.event_chunk() {
+                    // Without the event nothing else was sent
+                    Some(chunk) => {
+                        let created = chunk.outcome.clone().ok_or_else(|| {
+                            anyhow::anyhow!(
+                                "Event {} was not confirmed: {}",
+                                event_id,
+                                chunk.error.as_deref().unwrap_or("not sent")
+                            )
+                        })?;
+                        Some(created.confirmed()?)
+                    }
+                    // Created by an earlier attempt
+                    None => outcome.landed().next().map(|(_, signature)| signature),
+                };
+
+                for (market_id, sig) in outcome.landed() {
+                    tracing::info!(
+                        "Market {} of event {} added in {}",
+                        market_id,
+                        event_id,
+                        sig
+                    );
+                }
+                return Ok(Submitted {
+                    signature,
+                    follow_up: outcome
+                        .missing()
+                        .map(|market_id| ChainOperation::AddOption { market_id })
+                        .collect(),
+                });
+            }
+            ChainOperation::AddOption { market_id } => {
+                let market = find_market(conn, *market_id).await?;
+                // An earlier attempt, or a chunk of the event whose answer was lost, may have
+                // added it already
+                let added = self
+                    .chain
+                    .event_options(&market.event_id)
+                    .await?
+                    .is_some_and(|options| options.contains(market_id));
+                if added {
+                    return Ok(Submitted {
+                        signature: None,
+                        follow_up: Vec::new(),
+                    });
+                }
+
+                let option = new_option(&market)?;
+                self.chain
+                    .add_option(&option.yes_mint, &option.no_mint, &option.args)
+                    .await?
+            }
+            ChainOperation::CreateOrder {
+                user_id,
+                market_id,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.signer(&find_wallet(conn, *user_id).await?)?;
+                self.chain
+                    .create_order(user.as_ref(), &create_order_args(&market, *shares, *price))
+                    .await?
+            }
+            ChainOperation::MatchOrder {
+                yes_user_id,
+                no_user_id,
+                market_id,
+                shares,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let yes_user = signers.signer(&find_wallet(conn, *yes_user_id).await?)?;
+                let no_user = signers.signer(&find_wallet(conn, *no_user_id).await?)?;
+                let (yes_mint, _) = option_token(&market, &MarketOption::A)?;
+                let (no_mint, _) = option_token(&market, &MarketOption::B)?;
+                self.chain
+                    .match_order(
+                        yes_user.as_ref(),
+                        no_user.as_ref(),
+                        &yes_mint,
+                        &no_mint,
+                        &match_order_args(&market, *shares),
+                    )
+                    .await?
+            }
+            ChainOperation::CancelOrder {
+                user_id,
+                market_id,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.pubkey(&find_wallet(conn, *user_id).await?)?;
+                self.chain
+                    .cancel_order(&user, &cancel_order_args(&market, *shares, *price))
+                    .await?
+            }
+            ChainOperation::TransferShares {
+                seller_id,
+                buyer_id,
+                market_id,
+                option,
+                shares,
+                price,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let seller = signers.signer(&find_wallet(conn, *seller_id).await?)?;
+                let buyer = signers.signer(&find_wallet(conn, *buyer_id).await?)?;
+                let (mint, args) = transfer_shares_args(&market, option, *shares, *price)?;
+                self.chain
+                    .transfer_shares(seller.as_ref(), buyer.as_ref(), &mint, &args)
+                    .await?
+            }
+            ChainOperation::GetReward {
+                user_id,
+                market_id,
+                option,
+                shares,
+            } => {
+                let market = find_market(conn, *market_id).await?;
+                let user = signers.signer(&find_wallet(conn, *user_id).await?)?;
+                let (mint, _) = option_token(&market, option)?;
+                self.chain
+                    .get_reward(user.as_ref(), &mint, &get_reward_args(&market, *shares))
+                    .await?
+            }
+        };
+
+        Ok(Submitted {
+            signature: Some(outcome.confirmed()?),
+            follow_up: Vec::new(),
+        })
+    }
+}
+
+fn create_order_args(
+    market: &entity::market::Model,
+    shares: Shares,
+    price: Price,
+) -> FakeCreateOrderArgs {
+    FakeCreateOrderArgs {
+        event_uuid: market.event_id,
+        option_uuid: market.id,
+        num_shares: shares,
+        price_per_share: price.per_share(),
+    }
+}
+
+fn match_order_args(market: &entity::market::Model, shares: Shares) -> FakeMatchOrderArgs {
+    FakeMatchOrderArgs {
+        event_uuid: market.event_id,
+        option_uuid: market.id,
+        num_shares: shares,
+    }
+}
+
+fn cancel_order_args(
+    market: &entity::market::Model,
+    shares: Shares,
+    price: Price,
+) -> FakeCancelOrderArgs {
+    FakeCancelOrderArgs {
+        event_uuid: market.event_id,
+        option_uuid: market.id,
+        num_shares: shares,
+        price_per_share: price.per_share(),
+    }
+}
+
+/// The mint of the shares, and the args to sell them at `price`
+fn transfer_shares_args(
+    market: &entity::market::Model,
+    option: &MarketOption,
+    shares: Shares,
+    price: Price,
+) -> AppResult<(Pubkey, TransferSharesArgs)> {
+    let (mint, token_option) = option_token(market, option)?;
+    Ok((
+        mint,
+        TransferSharesArgs {
+            event_uuid: market.event_id,
+            option_uuid: market.id,
+            token_option,
+            num_shares: shares,
+            price_per_share: price.per_share(),
+        },
+    ))
+}
+
+fn get_reward_args(market: &entity::market::Model, shares: Shares) -> FakeGetRewardArgs {
+    FakeGetRewardArgs {
+        event_uuid: market.event_id,
+        option_uuid: market.id,
+        num_shares: shares,
+    }
+}
@@ -0,0 +1,65 @@
+use indexer::entity::{self as chain, market::Token};
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self},
+    error::{AppError, AppResult},
+    state::event::MarketOptionDto,
+};
+
+/// Shares the user's wallet holds on chain, as rebuilt by the indexer
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortfolioEntryDto {
+    pub market_id: Uuid,
+    pub event_id: Uuid,
+    pub option: MarketOptionDto,
+    pub shares: i64,
+    /// Slot of the last transaction that changed the holding
+    pub slot: i64,
+}
+
+impl From<chain::position::Model> for PortfolioEntryDto {
+    fn from(model: chain::position::Model) -> Self {
+        // Option A is the yes token of the market
+        let option = match model.token {
+            Token::Yes => MarketOptionDto::OptionA,
+            Token::No => MarketOptionDto::OptionB,
+        };
+        Self {
+            market_id: model.market_id,
+            event_id: model.event_id,
+            option,
+            shares: model.shares,
+            slot: model.slot,
+        }
+    }
+}
+
+impl AppState {
+    /// The holdings of the user's wallet that are not empty
+    pub async fn user_portfolio(&self, user_id: Uuid) -> AppResult<Vec<PortfolioEntryDto>> {
+        let user = entity::user::Entity::find_by_id(user_id)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::UserNotFound)?;
+        let wallet = self.chain.signers().pubkey(&user.wallet)?;
+
+        let portfolio = chain::position::Entity::find()
+            .filter(chain::position::Column::Wallet.eq(wallet.to_string()))
+            .filter(chain::position::Column::Shares.gt(0))
+            .order_by_asc(chain::position::Column::EventId)
+            .order_by_asc(chain::position::Column::MarketId)
+            .order_by_asc(chain::position::Column::Token)
+            .all(&self.database)
+            .await?
+            .into_iter()
+            .map(Into::into)
+            .collect();
+
+        Ok(portfolio)
+    }
+}
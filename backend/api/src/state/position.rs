@@ -1,6 +1,6 @@
 use sea_orm::{
-    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QuerySelect,
-    RelationTrait, sea_query::JoinType,
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, RelationTrait, sea_query::JoinType,
 };
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -8,7 +8,7 @@ use uuid::Uuid;
 use crate::{
     AppState,
     entity::{self},
-    error::AppResult,
+    error::{AppError, AppResult},
     state::event::MarketOptionDto,
 };
 
@@ -37,6 +37,14 @@ impl From<entity::position::Model> for PositionDto {
     }
 }
 
+/// Shares taken out of one position by `reduce_position`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Lot {
+    pub shares: i64,
+    pub price_per_share: i64,
+}
+
 impl AppState {
     pub async fn list_user_positions_in_event(
         &self,
@@ -112,4 +120,89 @@ impl AppState {
 
         Ok(())
     }
+
+    /// Shares the user holds in the market option that are not already offered in a sell order.
+    /// The positions stay locked until `txn` ends, so that a concurrent sell of the same shares
+    /// waits for this one and then sees its order
+    pub async fn available_shares(
+        txn: &impl sea_orm::ConnectionTrait,
+        market_id: Uuid,
+        user_id: Uuid,
+        option: entity::market::MarketOption,
+    ) -> AppResult<i64> {
+        let held: i64 = entity::position::Entity::find()
+            .filter(entity::position::Column::MarketId.eq(market_id))
+            .filter(entity::position::Column::UserId.eq(user_id))
+            .filter(entity::position::Column::Option.eq(option.clone()))
+            .lock_exclusive()
+            .all(txn)
+            .await?
+            .iter()
+            .map(|position| position.shares)
+            .sum();
+
+        let offered: i64 = entity::sellorder::Entity::find()
+            .filter(entity::sellorder::Column::MarketId.eq(market_id))
+            .filter(entity::sellorder::Column::UserId.eq(user_id))
+            .filter(entity::sellorder::Column::Option.eq(option))
+            .all(txn)
+            .await?
+            .iter()
+            .map(|order| order.shares)
+            .sum();
+
+        Ok(held - offered)
+    }
+
+    /// Takes `shares` out of the user's positions in the market option, cheapest first, and
+    /// deletes the positions left empty. Returns what was taken from each
+    pub async fn reduce_position(
+        txn: &impl sea_orm::ConnectionTrait,
+        market_id: Uuid,
+        user_id: Uuid,
+        option: entity::market::MarketOption,
+        shares: i64,
+    ) -> AppResult<Vec<Lot>> {
+        let positions = entity::position::Entity::find()
+            .filter(entity::position::Column::MarketId.eq(market_id))
+            .filter(entity::position::Column::UserId.eq(user_id))
+            .filter(entity::position::Column::Option.eq(option))
+            .order_by_asc(entity::position::Column::PricePerShare)
+            .order_by_asc(entity::position::Column::Id)
+            .lock_exclusive()
+            .all(txn)
+            .await?;
+
+        let mut left = shares;
+        let mut lots = Vec::new();
+        for position in positions {
+            if left == 0 {
+                break;
+            }
+
+            let taken = left.min(position.shares);
+            left -= taken;
+            lots.push(Lot {
+                shares: taken,
+                price_per_share: position.price_per_share,
+            });
+
+            if taken == position.shares {
+                entity::position::Entity::delete_by_id(position.id)
+                    .exec(txn)
+                    .await?;
+            } else {
+                let new_shares = position.shares - taken;
+                let mut active: entity::position::ActiveModel = position.into();
+                active.shares = Set(new_shares);
+                active.update(txn).await?;
+            }
+        }
+
+        if left > 0 {
+            return Err(AppError::InsufficientShares);
+        }
+
+        Ok(lots)
+    }
 }
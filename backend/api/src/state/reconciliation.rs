@@ -0,0 +1,330 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use blockchain_core::money::{MicroUsdc, Price, Shares};
+use chrono::{DateTime, FixedOffset, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+use serde::Serialize;
+use solana_sdk::pubkey::Pubkey;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{
+        self,
+        market::MarketOption,
+        reconciliation_discrepancy::{DiscrepancyKind, Severity},
+    },
+    error::{AppError, AppResult},
+    matching::PriceImprovement,
+    state::{
+        event::MarketOptionDto, outbox::unsettled_markets, resolution::resolving_markets,
+        sellorder::option_token,
+    },
+};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscrepancyDto {
+    pub kind: DiscrepancyKind,
+    pub severity: Severity,
+    pub event_id: Uuid,
+    pub market_id: Option<Uuid>,
+    pub user_id: Option<Uuid>,
+    pub option: Option<MarketOptionDto>,
+    pub expected: i64,
+    pub actual: i64,
+}
+
+impl From<entity::reconciliation_discrepancy::Model> for DiscrepancyDto {
+    fn from(model: entity::reconciliation_discrepancy::Model) -> Self {
+        Self {
+            kind: model.kind,
+            severity: model.severity,
+            event_id: model.event_id,
+            market_id: model.market_id,
+            user_id: model.user_id,
+            option: model.option.map(MarketOptionDto::from),
+            expected: model.expected,
+            actual: model.actual,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReconciliationReportDto {
+    pub run_id: Uuid,
+    /// Markets that were checked
+    pub markets: i32,
+    pub started_at: DateTime<FixedOffset>,
+    pub finished_at: DateTime<FixedOffset>,
+    /// Most severe first
+    pub discrepancies: Vec<DiscrepancyDto>,
+}
+
+impl ReconciliationReportDto {
+    pub fn count(&self, severity: Severity) -> usize {
+        self.discrepancies
+            .iter()
+            .filter(|discrepancy| discrepancy.severity == severity)
+            .count()
+    }
+}
+
+/// How bad it is that the chain holds `actual` where the database accounts for `expected`
+fn severity(expected: i64, actual: i64, in_flight: bool, surplus: Severity) -> Severity {
+    if in_flight {
+        Severity::Info
+    } else if actual < expected {
+        Severity::Critical
+    } else {
+        surplus
+    }
+}
+
+/// Users who claimed their winnings in the market
+async fn claimants(conn: &impl ConnectionTrait, market_id: Uuid) -> AppResult<BTreeSet<Uuid>> {
+    Ok(entity::claim::Entity::find()
+        .filter(entity::claim::Column::MarketId.eq(market_id))
+        .all(conn)
+        .await?
+        .into_iter()
+        .map(|claim| claim.user_id)
+        .collect())
+}
+
+impl AppState {
+    /// Compares every market to the chain and records what does not match: the positions of each
+    /// user against their token balances, and the open buy orders and matched shares of an event
+    /// against its treasury
+    pub async fn reconcile(&self) -> AppResult<ReconciliationReportDto> {
+        let started_at = Utc::now();
+        let run_id = Uuid::new_v4();
+
+        let markets = entity::market::Entity::find()
+            .order_by_asc(entity::market::Column::EventId)
+            .all(&self.database)
+            .await?;
+        let mut unsettled = unsettled_markets(&self.database).await?;
+        unsettled.extend(resolving_markets(&self.database).await?);
+
+        let mut events: BTreeMap<Uuid, Vec<&entity::market::Model>> = BTreeMap::new();
+        for market in &markets {
+            events.entry(market.event_id).or_default().push(market);
+        }
+
+        let mut wallets = HashMap::new();
+        let mut discrepancies = Vec::new();
+        for (event_id, markets) in events {
+            let mut expected = MicroUsdc(0);
+            for market in &markets {
+                let in_flight = unsettled.contains(&market.id);
+                discrepancies.extend(
+                    self.reconcile_positions(market, in_flight, &mut wallets)
+                        .await?,
+                );
+                expected = expected
+                    .checked_add(self.market_collateral(market).await?)
+                    .ok_or_else(|| anyhow::anyhow!("Collateral of event {} overflows", event_id))?;
+            }
+
+            let expected = i64::try_from(expected)?;
+            let actual = i64::try_from(self.chain.treasury_balance(&event_id).await?)?;
+            if actual != expected {
+                // Improvements kept as fees stay in the treasury
+                let surplus = match self.price_improvement {
+                    PriceImprovement::Taker => Severity::Warning,
+                    PriceImprovement::Fees => Severity::Info,
+                };
+                let in_flight = markets.iter().any(|market| unsettled.contains(&market.id));
+                discrepancies.push(entity::reconciliation_discrepancy::ActiveModel {
+                    kind: Set(DiscrepancyKind::Treasury),
+                    severity: Set(severity(expected, actual, in_flight, surplus)),
+                    event_id: Set(event_id),
+                    market_id: Set(None),
+                    user_id: Set(None),
+                    option: Set(None),
+                    expected: Set(expected),
+                    actual: Set(actual),
+                    ..Default::default()
+                });
+            }
+        }
+
+        let finished_at = Utc::now();
+        let transaction = self.database.begin().await?;
+        entity::reconciliation_run::ActiveModel {
+            id: Set(run_id),
+            markets: Set(i32::try_from(markets.len()).map_err(anyhow::Error::from)?),
+            started_at: Set(started_at.into()),
+            finished_at: Set(finished_at.into()),
+        }
+        .insert(&transaction)
+        .await?;
+        for mut discrepancy in discrepancies {
+            discrepancy.id = Set(Uuid::new_v4());
+            discrepancy.run_id = Set(run_id);
+            discrepancy.created_at = Set(finished_at.into());
+            discrepancy.insert(&transaction).await?;
+        }
+        transaction.commit().await?;
+
+        self.reconciliation_report(run_id).await
+    }
+
+    /// The report of the last reconciliation
+    pub async fn latest_reconciliation(&self) -> AppResult<ReconciliationReportDto> {
+        let run = entity::reconciliation_run::Entity::find()
+            .order_by_desc(entity::reconciliation_run::Column::StartedAt)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::ReconciliationNotFound)?;
+
+        self.reconciliation_report(run.id).await
+    }
+
+    async fn reconciliation_report(&self, run_id: Uuid) -> AppResult<ReconciliationReportDto> {
+        let run = entity::reconciliation_run::Entity::find_by_id(run_id)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::ReconciliationNotFound)?;
+
+        let mut discrepancies: Vec<DiscrepancyDto> =
+            entity::reconciliation_discrepancy::Entity::find()
+                .filter(entity::reconciliation_discrepancy::Column::RunId.eq(run_id))
+                .order_by_asc(entity::reconciliation_discrepancy::Column::EventId)
+                .order_by_asc(entity::reconciliation_discrepancy::Column::MarketId)
+                .all(&self.database)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect();
+        discrepancies.sort_by_key(|discrepancy| std::cmp::Reverse(discrepancy.severity));
+
+        Ok(ReconciliationReportDto {
+            run_id,
+            markets: run.markets,
+            started_at: run.started_at,
+            finished_at: run.finished_at,
+            discrepancies,
+        })
+    }
+
+    /// The shares of every user who holds or traded in the market, against their token balances
+    async fn reconcile_positions(
+        &self,
+        market: &entity::market::Model,
+        in_flight: bool,
+        wallets: &mut HashMap<Uuid, Pubkey>,
+    ) -> AppResult<Vec<entity::reconciliation_discrepancy::ActiveModel>> {
+        let positions = entity::position::Entity::find()
+            .filter(entity::position::Column::MarketId.eq(market.id))
+            .all(&self.database)
+            .await?;
+        let fills = entity::fill::Entity::find()
+            .filter(entity::fill::Column::MarketId.eq(market.id))
+            .all(&self.database)
+            .await?;
+        let claimed = claimants(&self.database, market.id).await?;
+
+        // Someone who sold everything has no position left, but may still hold the tokens
+        let users: BTreeSet<Uuid> = positions
+            .iter()
+            .map(|position| position.user_id)
+            .chain(
+                fills
+                    .iter()
+                    .flat_map(|fill| [fill.maker_user_id, fill.taker_user_id]),
+            )
+            .collect();
+
+        let mut discrepancies = Vec::new();
+        for user_id in users {
+            let wallet = match wallets.get(&user_id) {
+                Some(wallet) => *wallet,
+                None => {
+                    let user = entity::user::Entity::find_by_id(user_id)
+                        .one(&self.database)
+                        .await?
+                        .ok_or(AppError::UserNotFound)?;
+                    let wallet = self.chain.signers().pubkey(&user.wallet)?;
+                    *wallets.entry(user_id).or_insert(wallet)
+                }
+            };
+
+            for option in [MarketOption::A, MarketOption::B] {
+                // Winning shares are burned when they are claimed
+                let expected = if market.resolved_option.as_ref() == Some(&option)
+                    && claimed.contains(&user_id)
+                {
+                    0
+                } else {
+                    positions
+                        .iter()
+                        .filter(|position| position.user_id == user_id && position.option == option)
+                        .map(|position| position.shares)
+                        .sum()
+                };
+
+                let (mint, _) = option_token(market, &option)?;
+                let actual = i64::try_from(self.chain.share_balance(&wallet, &mint).await?)?;
+                if actual != expected {
+                    discrepancies.push(entity::reconciliation_discrepancy::ActiveModel {
+                        kind: Set(DiscrepancyKind::Position),
+                        severity: Set(severity(expected, actual, in_flight, Severity::Warning)),
+                        event_id: Set(market.event_id),
+                        market_id: Set(Some(market.id)),
+                        user_id: Set(Some(user_id)),
+                        option: Set(Some(option)),
+                        expected: Set(expected),
+                        actual: Set(actual),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+
+        Ok(discrepancies)
+    }
+
+    /// What the treasury holds for the market: the price of its open buy orders, and the payout
+    /// of every matched pair of shares until the market is resolved, then of the winning shares
+    /// that are not claimed yet
+    async fn market_collateral(&self, market: &entity::market::Model) -> AppResult<MicroUsdc> {
+        let overflow = || anyhow::anyhow!("Collateral of market {} overflows", market.id);
+
+        let buy_orders = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::MarketId.eq(market.id))
+            .all(&self.database)
+            .await?;
+        let mut collateral = MicroUsdc(0);
+        for order in &buy_orders {
+            let escrow = Price::try_from(order.price_per_share)?
+                .total(Shares::try_from(order.shares)?)
+                .ok_or_else(overflow)?;
+            collateral = collateral.checked_add(escrow).ok_or_else(overflow)?;
+        }
+
+        // Every match mints one share of each option, so the shares of A count the pairs
+        let (option, claimed) = match &market.resolved_option {
+            Some(option) => (option.clone(), claimants(&self.database, market.id).await?),
+            None => (MarketOption::A, BTreeSet::new()),
+        };
+        let shares: i64 = entity::position::Entity::find()
+            .filter(entity::position::Column::MarketId.eq(market.id))
+            .filter(entity::position::Column::Option.eq(option))
+            .all(&self.database)
+            .await?
+            .iter()
+            .filter(|position| !claimed.contains(&position.user_id))
+            .map(|position| position.shares)
+            .sum();
+        let payout = Shares::try_from(shares)?.payout().ok_or_else(overflow)?;
+        collateral = collateral.checked_add(payout).ok_or_else(overflow)?;
+
+        Ok(collateral)
+    }
+}
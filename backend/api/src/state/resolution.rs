@@ -0,0 +1,438 @@
+//! Resolution of markets.
+//!
+//! Resolving a market only records the winning option, which closes it to new orders, and
+//! creates a `resolution_job`. A worker then refunds the open buy orders in batches, each
+//! committed in a transaction of its own with its sagas in the outbox. A batch that fails halfway
+//! leaves nothing behind, and the next pass picks up whatever is left. A refund that fails for
+//! good is queued again in a new saga, its order is never put back in the closed market. Winners claim their
+//! rewards themselves, see `state::claim`.
+
+use std::collections::BTreeSet;
+
+use blockchain_core::money::{Price, Shares};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectionTrait, EntityTrait, PaginatorTrait,
+    QueryFilter, QueryOrder, QuerySelect, TransactionTrait,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self, market::MarketOption, resolution_job::JobStatus},
+    error::{AppError, AppResult},
+    state::{
+        buyorder::refund_operation,
+        dry_run::{Execution, Outcome},
+        event::MarketOptionDto,
+        outbox::{Compensation, SagaStatus, Step, saga_progress},
+        stream::MarketChange,
+    },
+};
+
+/// Refunds staged in one transaction
+pub const RESOLUTION_BATCH: usize = 25;
+
+/// How long a worker holds a job before another one can take it over
+const LEASE: Duration = Duration::minutes(5);
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionRefundDto {
+    pub user_id: Uuid,
+    pub shares: i64,
+    /// Micro USDC the user gets back
+    pub amount: i64,
+    pub batch: i32,
+    pub status: SagaStatus,
+    /// Why it failed for good
+    pub error: Option<String>,
+    pub transaction_urls: Vec<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionProgressDto {
+    pub total: usize,
+    /// Open orders not in a batch yet
+    pub waiting: usize,
+    pub pending: usize,
+    pub confirmed: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResolutionJobDto {
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub option: MarketOptionDto,
+    pub status: JobStatus,
+    pub batches: i32,
+    pub progress: ResolutionProgressDto,
+    /// In the order they were staged
+    pub refunds: Vec<ResolutionRefundDto>,
+    pub created_at: DateTime<FixedOffset>,
+    pub finished_at: Option<DateTime<FixedOffset>>,
+}
+
+/// Markets whose resolution is still being staged, and whose balances on chain lag behind
+pub(crate) async fn resolving_markets(conn: &impl ConnectionTrait) -> AppResult<BTreeSet<Uuid>> {
+    Ok(entity::resolution_job::Entity::find()
+        .filter(entity::resolution_job::Column::Status.eq(JobStatus::Running))
+        .select_only()
+        .column(entity::resolution_job::Column::MarketId)
+        .into_tuple()
+        .all(conn)
+        .await?
+        .into_iter()
+        .collect())
+}
+
+async fn insert_refund(
+    txn: &impl ConnectionTrait,
+    job: &entity::resolution_job::Model,
+    execution: &Execution,
+    refund_id: Uuid,
+    order: &entity::buyorder::Model,
+) -> AppResult<()> {
+    let amount = Price::try_from(order.price_per_share)?
+        .total(Shares::try_from(order.shares)?)
+        .ok_or_else(|| anyhow::anyhow!("Refund of order {} overflows", order.id))?;
+
+    entity::resolution_refund::ActiveModel {
+        id: Set(refund_id),
+        job_id: Set(job.id),
+        user_id: Set(order.user_id),
+        shares: Set(order.shares),
+        amount: Set(i64::try_from(amount)?),
+        saga_id: Set(execution
+            .current_saga()
+            .ok_or_else(|| anyhow::anyhow!("Refund of job {} has no saga", job.id))?),
+        batch: Set(job.batches + 1),
+        created_at: Set(Utc::now().into()),
+    }
+    .insert(txn)
+    .await?;
+    Ok(())
+}
+
+impl AppState {
+    /// Records the winning option and creates the job that refunds the open orders. In a dry run
+    /// every batch is staged and simulated right away
+    pub async fn resolve_market(
+        &self,
+        market_id: Uuid,
+        option: MarketOptionDto,
+        dry_run: bool,
+    ) -> AppResult<Outcome<ResolutionJobDto>> {
+        let mut execution = Execution::new(dry_run);
+        let transaction = self.database.begin().await?;
+
+        let market = entity::market::Entity::find_by_id(market_id)
+            .one(&transaction)
+            .await?
+            .ok_or(AppError::MarketNotFound)?;
+
+        if market.resolved_option.is_some() {
+            return Err(AppError::MarketAlreadyResolved);
+        }
+
+        let winning_option: MarketOption = option.into();
+
+        // Orders are refused from now on, so nothing new comes in while the job runs
+        let mut active_market: entity::market::ActiveModel = market.into();
+        active_market.resolved_option = Set(Some(winning_option.clone()));
+        active_market.update(&transaction).await?;
+
+        let now = Utc::now();
+        let mut job = entity::resolution_job::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            market_id: Set(market_id),
+            option: Set(winning_option),
+            status: Set(JobStatus::Running),
+            batches: Set(0),
+            locked_until: Set(now.into()),
+            created_at: Set(now.into()),
+            updated_at: Set(now.into()),
+            finished_at: Set(None),
+        }
+        .insert(&transaction)
+        .await?;
+
+        if execution.is_dry_run() {
+            loop {
+                let (done, _) = self
+                    .stage_resolution_batch(&transaction, &job, &mut execution)
+                    .await?;
+                job = self.finish_batch(&transaction, job, done).await?;
+                if done {
+                    break;
+                }
+            }
+        }
+
+        let job = self.resolution_job_dto(&transaction, job).await?;
+        execution.commit(transaction).await?;
+
+        if !execution.is_dry_run() {
+            self.publish(MarketChange {
+                market_id,
+                taker_order_id: None,
+                users: Vec::new(),
+            })
+            .await;
+        }
+
+        Ok(execution.finish(job))
+    }
+
+    pub async fn resolution_job(&self, job_id: Uuid) -> AppResult<ResolutionJobDto> {
+        let job = entity::resolution_job::Entity::find_by_id(job_id)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::ResolutionJobNotFound)?;
+
+        self.resolution_job_dto(&self.database, job).await
+    }
+
+    /// Stages the batches of every running job that no other worker holds. Returns how many jobs
+    /// were worked on
+    pub async fn process_resolutions(&self) -> AppResult<usize> {
+        let jobs: Vec<Uuid> = entity::resolution_job::Entity::find()
+            .filter(entity::resolution_job::Column::Status.eq(JobStatus::Running))
+            .filter(entity::resolution_job::Column::LockedUntil.lte(Utc::now()))
+            .order_by_asc(entity::resolution_job::Column::CreatedAt)
+            .select_only()
+            .column(entity::resolution_job::Column::Id)
+            .into_tuple()
+            .all(&self.database)
+            .await?;
+
+        let mut processed = 0;
+        for job_id in jobs {
+            match self.run_resolution(job_id).await {
+                Ok(true) => processed += 1,
+                Ok(false) => {}
+                Err(e) => tracing::error!("Failed to run resolution job {}: {}", job_id, e),
+            }
+        }
+        Ok(processed)
+    }
+
+    /// Stages the batches of the job one after the other and runs their sagas. Returns false if
+    /// another worker holds it
+    async fn run_resolution(&self, job_id: Uuid) -> AppResult<bool> {
+        let now = Utc::now();
+        let claimed = entity::resolution_job::Entity::update_many()
+            .set(entity::resolution_job::ActiveModel {
+                locked_until: Set((now + LEASE).into()),
+                ..Default::default()
+            })
+            .filter(entity::resolution_job::Column::Id.eq(job_id))
+            .filter(entity::resolution_job::Column::Status.eq(JobStatus::Running))
+            .filter(entity::resolution_job::Column::LockedUntil.lte(now))
+            .exec(&self.database)
+            .await?;
+        if claimed.rows_affected == 0 {
+            return Ok(false);
+        }
+
+        let result = self.stage_resolution_batches(job_id).await;
+        if result.is_err() {
+            // The next pass picks it up again rather than wait out the lease
+            entity::resolution_job::Entity::update_many()
+                .set(entity::resolution_job::ActiveModel {
+                    locked_until: Set(Utc::now().into()),
+                    ..Default::default()
+                })
+                .filter(entity::resolution_job::Column::Id.eq(job_id))
+                .exec(&self.database)
+                .await?;
+        }
+        result.map(|()| true)
+    }
+
+    /// Stages the batches of a job this worker holds until none is left
+    async fn stage_resolution_batches(&self, job_id: Uuid) -> AppResult<()> {
+        loop {
+            let mut execution = Execution::new(false);
+            let transaction = self.database.begin().await?;
+            let job = entity::resolution_job::Entity::find_by_id(job_id)
+                .one(&transaction)
+                .await?
+                .ok_or(AppError::ResolutionJobNotFound)?;
+
+            let (done, users) = self
+                .stage_resolution_batch(&transaction, &job, &mut execution)
+                .await?;
+            let job = self.finish_batch(&transaction, job, done).await?;
+            execution.commit(transaction).await?;
+
+            tracing::info!(
+                "Staged batch {} of resolution job {} with {} sagas",
+                job.batches,
+                job.id,
+                execution.saga_ids().len()
+            );
+            self.settle(&execution).await;
+            self.publish(MarketChange {
+                market_id: job.market_id,
+                taker_order_id: None,
+                users,
+            })
+            .await;
+
+            if done {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Stages up to `RESOLUTION_BATCH` refunds of open orders. Returns whether none is left after
+    /// them, and the users whose orders were removed
+    async fn stage_resolution_batch(
+        &self,
+        txn: &impl ConnectionTrait,
+        job: &entity::resolution_job::Model,
+        execution: &mut Execution,
+    ) -> AppResult<(bool, Vec<Uuid>)> {
+        let mut users = Vec::new();
+
+        // Sell orders hold nothing on chain, drop them with the first batch
+        if job.batches == 0 {
+            let sell_orders = entity::sellorder::Entity::find()
+                .filter(entity::sellorder::Column::MarketId.eq(job.market_id))
+                .all(txn)
+                .await?;
+            entity::sellorder::Entity::delete_many()
+                .filter(entity::sellorder::Column::MarketId.eq(job.market_id))
+                .exec(txn)
+                .await?;
+            users.extend(sell_orders.iter().map(|order| order.user_id));
+        }
+
+        // Each refund is a saga of its own, one failing does not hold back the others
+        let buy_orders: Vec<Uuid> = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::MarketId.eq(job.market_id))
+            .order_by_asc(entity::buyorder::Column::CreatedAt)
+            .limit(RESOLUTION_BATCH as u64)
+            .select_only()
+            .column(entity::buyorder::Column::Id)
+            .into_tuple()
+            .all(txn)
+            .await?;
+        for order_id in &buy_orders {
+            let Some(order) = AppState::take_buy_order(txn, *order_id).await? else {
+                continue;
+            };
+
+            // The market is closed, so a refund that fails is sent again rather than the order
+            // put back
+            let refund_id = Uuid::new_v4();
+            let operation = refund_operation(&order)?;
+            let requeue = Compensation::RequeueRefund {
+                refund_id,
+                operation: operation.clone(),
+            };
+            execution.new_saga();
+            self.stage(
+                txn,
+                execution,
+                Step::new(operation).with_compensation(vec![requeue]),
+            )
+            .await?;
+            insert_refund(txn, job, execution, refund_id, &order).await?;
+            users.push(order.user_id);
+        }
+
+        Ok((buy_orders.len() < RESOLUTION_BATCH, users))
+    }
+
+    /// Counts the batch and renews the lease, or lets go of the job once it is done
+    async fn finish_batch(
+        &self,
+        txn: &impl ConnectionTrait,
+        job: entity::resolution_job::Model,
+        done: bool,
+    ) -> AppResult<entity::resolution_job::Model> {
+        let now = Utc::now();
+        let batches = job.batches + 1;
+        let mut active: entity::resolution_job::ActiveModel = job.into();
+        active.batches = Set(batches);
+        active.updated_at = Set(now.into());
+        if done {
+            active.status = Set(JobStatus::Done);
+            active.locked_until = Set(now.into());
+            active.finished_at = Set(Some(now.into()));
+        } else {
+            active.locked_until = Set((now + LEASE).into());
+        }
+        Ok(active.update(txn).await?)
+    }
+
+    async fn resolution_job_dto(
+        &self,
+        conn: &impl ConnectionTrait,
+        job: entity::resolution_job::Model,
+    ) -> AppResult<ResolutionJobDto> {
+        let refunds = entity::resolution_refund::Entity::find()
+            .filter(entity::resolution_refund::Column::JobId.eq(job.id))
+            .order_by_asc(entity::resolution_refund::Column::Batch)
+            .order_by_asc(entity::resolution_refund::Column::CreatedAt)
+            .all(conn)
+            .await?;
+        let sagas = saga_progress(conn, refunds.iter().map(|refund| refund.saga_id)).await?;
+
+        let mut progress = ResolutionProgressDto::default();
+        let mut refund_dtos = Vec::with_capacity(refunds.len());
+        for refund in refunds {
+            let saga = sagas.get(&refund.saga_id);
+            let status = saga.map_or(SagaStatus::Pending, |saga| saga.status);
+            match status {
+                SagaStatus::Pending => progress.pending += 1,
+                SagaStatus::Confirmed => progress.confirmed += 1,
+                SagaStatus::Failed => progress.failed += 1,
+            }
+
+            refund_dtos.push(ResolutionRefundDto {
+                user_id: refund.user_id,
+                shares: refund.shares,
+                amount: refund.amount,
+                batch: refund.batch,
+                status,
+                error: saga.and_then(|saga| saga.error.clone()),
+                transaction_urls: saga
+                    .map(|saga| {
+                        saga.signatures
+                            .iter()
+                            .map(|sig| self.chain.transaction_url(sig))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            });
+        }
+
+        if job.status == JobStatus::Running {
+            let orders = entity::buyorder::Entity::find()
+                .filter(entity::buyorder::Column::MarketId.eq(job.market_id))
+                .count(conn)
+                .await?;
+            progress.waiting = usize::try_from(orders).map_err(anyhow::Error::from)?;
+        }
+        progress.total = progress.waiting + refund_dtos.len();
+
+        Ok(ResolutionJobDto {
+            id: job.id,
+            market_id: job.market_id,
+            option: job.option.into(),
+            status: job.status,
+            batches: job.batches,
+            progress,
+            refunds: refund_dtos,
+            created_at: job.created_at,
+            finished_at: job.finished_at,
+        })
+    }
+}
@@ -0,0 +1,335 @@
+use blockchain_client::signer::keypair_from_base58;
+use blockchain_core::{
+    accounts::order::TokenOption,
+    money::{MoneyError, Price, Shares},
+};
+use chrono::Utc;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter, QuerySelect,
+    TransactionTrait,
+};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{pubkey::Pubkey, signer::Signer};
+use uuid::Uuid;
+
+use crate::{
+    AppState,
+    entity::{self, fill::OrderSide, market::MarketOption},
+    error::{AppError, AppResult},
+    matching::{PriceImprovement, RestingOrder, Side, match_sell},
+    state::{
+        buyorder::{OrderFillDto, PlacedOrder, not_expired},
+        dry_run::{Execution, Outcome},
+        event::MarketOptionDto,
+        fill::Taker,
+        outbox::{ChainOperation, Compensation, Step},
+        position::Lot,
+        stream::MarketChange,
+    },
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SellOrderDto {
+    pub id: Uuid,
+    pub market_id: Uuid,
+    pub user_id: Uuid,
+    pub shares: i64,
+    pub price_per_share: i64,
+    pub option: MarketOptionDto,
+}
+
+impl From<entity::sellorder::Model> for SellOrderDto {
+    fn from(model: entity::sellorder::Model) -> Self {
+        SellOrderDto {
+            id: model.id,
+            market_id: model.market_id,
+            user_id: model.user_id,
+            shares: model.shares,
+            price_per_share: model.price_per_share,
+            option: MarketOptionDto::from(model.option),
+        }
+    }
+}
+
+impl TryFrom<&entity::sellorder::Model> for RestingOrder {
+    type Error = MoneyError;
+
+    fn try_from(order: &entity::sellorder::Model) -> Result<Self, Self::Error> {
+        Ok(RestingOrder {
+            id: order.id,
+            side: Side::Sell,
+            user_id: order.user_id,
+            price: Price::try_from(order.price_per_share)?,
+            shares: Shares::try_from(order.shares)?,
+            created_at: order.created_at,
+        })
+    }
+}
+
+/// The mint of the option's shares, and which of the market's tokens it is
+pub(crate) fn option_token(
+    market: &entity::market::Model,
+    option: &MarketOption,
+) -> AppResult<(Pubkey, TokenOption)> {
+    Ok(match option {
+        MarketOption::A => (
+            keypair_from_base58(&market.yes_keypair)?.pubkey(),
+            TokenOption::Yes,
+        ),
+        MarketOption::B => (
+            keypair_from_base58(&market.no_keypair)?.pubkey(),
+            TokenOption::No,
+        ),
+    })
+}
+
+impl AppState {
+    pub async fn create_sell_order(
+        &self,
+        market_id: Uuid,
+        user_id: Uuid,
+        shares: Shares,
+        price: Price,
+        option: MarketOptionDto,
+        dry_run: bool,
+    ) -> AppResult<Outcome<PlacedOrder>> {
+        let mut execution = Execution::new(dry_run);
+        let transaction = self.database.begin().await?;
+
+        let market = entity::market::Entity::find_by_id(market_id)
+            .one(&transaction)
+            .await?
+            .ok_or(AppError::MarketNotFound)?;
+
+        entity::user::Entity::find_by_id(user_id)
+            .one(&transaction)
+            .await?
+            .ok_or(AppError::UserNotFound)?;
+
+        if market.resolved_option.is_some() {
+            return Err(AppError::MarketAlreadyResolved);
+        }
+
+        let option: MarketOption = option.into();
+        let shares_db = i64::try_from(shares)?;
+
+        // Shares already offered in other sell orders can't be sold again
+        let available =
+            AppState::available_shares(&transaction, market.id, user_id, option.clone()).await?;
+        if shares_db > available {
+            return Err(AppError::InsufficientShares);
+        }
+
+        // Buy orders of the same option at or above our price
+        let bids = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::MarketId.eq(market.id))
+            .filter(entity::buyorder::Column::Option.eq(option.clone()))
+            .filter(entity::buyorder::Column::PricePerShare.gte(i64::try_from(price)?))
+            .filter(entity::buyorder::Column::Shares.gt(0))
+            .filter(not_expired(Utc::now()))
+            // a concurrent cancel must not refund what this fills
+            .lock_exclusive()
+            .all(&transaction)
+            .await?;
+
+        let book = bids
+            .iter()
+            .map(RestingOrder::try_from)
+            .collect::<Result<Vec<_>, MoneyError>>()?;
+        let outcome = match_sell(price, shares, &book, self.price_improvement)?;
+
+        let taker = Taker {
+            order_id: Uuid::new_v4(),
+            user_id,
+            side: OrderSide::Sell,
+            option: option.clone(),
+        };
+
+        for fill in &outcome.fills {
+            let bid = bids
+                .iter()
+                .find(|order| order.id == fill.maker_id)
+                .expect("Fills come from the book");
+            let matched_qty_db = i64::try_from(fill.shares)?;
+
+            let sold = AppState::reduce_position(
+                &transaction,
+                market.id,
+                user_id,
+                option.clone(),
+                matched_qty_db,
+            )
+            .await?;
+
+            AppState::upsert_position(
+                &transaction,
+                market.id,
+                bid.user_id,
+                option.clone(),
+                matched_qty_db,
+                i64::try_from(fill.maker_price)?,
+            )
+            .await?;
+
+            // Update or delete the buy order
+            let new_bid_shares = bid.shares - matched_qty_db;
+
+            if new_bid_shares == 0 {
+                entity::buyorder::Entity::delete_by_id(bid.id)
+                    .exec(&transaction)
+                    .await?;
+            } else {
+                let mut bid_active: entity::buyorder::ActiveModel = bid.clone().into();
+                bid_active.shares = Set(new_bid_shares);
+                bid_active.update(&transaction).await?;
+            }
+
+            let fill_id = AppState::record_fill(&transaction, market.id, &taker, fill).await?;
+
+            // The seller gets the bid, or only its own price when the improvement is kept as fees
+            let transfer_price = match self.price_improvement {
+                PriceImprovement::Taker => fill.taker_price,
+                PriceImprovement::Fees => price,
+            };
+
+            // The bid paid its price upfront, give the buyer what it owes the seller
+            let release = ChainOperation::CancelOrder {
+                user_id: bid.user_id,
+                market_id,
+                shares: fill.shares,
+                price: transfer_price,
+            };
+            let released = self
+                .stage(&transaction, &mut execution, Step::new(release))
+                .await?;
+
+            let compensation = vec![
+                Compensation::RestoreShares {
+                    market_id,
+                    user_id,
+                    option: option.clone(),
+                    lots: sold,
+                },
+                Compensation::RemoveShares {
+                    market_id,
+                    user_id: bid.user_id,
+                    option: option.clone(),
+                    lot: Lot {
+                        shares: matched_qty_db,
+                        price_per_share: i64::try_from(fill.maker_price)?,
+                    },
+                },
+                Compensation::DeleteFill { fill_id },
+                Compensation::RestoreBuyOrder {
+                    order: bid.clone().into(),
+                    created_at: bid.created_at,
+                    shares: matched_qty_db,
+                },
+                // The bid holds its price on chain again
+                Compensation::Chain {
+                    operation: ChainOperation::CreateOrder {
+                        user_id: bid.user_id,
+                        market_id,
+                        shares: fill.shares,
+                        price: transfer_price,
+                    },
+                    after: released,
+                },
+            ];
+            let transfer = ChainOperation::TransferShares {
+                seller_id: user_id,
+                buyer_id: bid.user_id,
+                market_id,
+                option: option.clone(),
+                shares: fill.shares,
+                price: transfer_price,
+            };
+            self.stage(
+                &transaction,
+                &mut execution,
+                Step::new(transfer)
+                    .with_fill(fill_id)
+                    .with_compensation(compensation),
+            )
+            .await?;
+        }
+
+        let mut order_id = None;
+        if outcome.remaining > Shares(0) {
+            let id = taker.order_id;
+            entity::sellorder::ActiveModel {
+                id: Set(id),
+                market_id: Set(market.id),
+                user_id: Set(user_id),
+                option: Set(option),
+                shares: Set(i64::try_from(outcome.remaining)?),
+                price_per_share: Set(i64::try_from(price)?),
+                created_at: Set(Utc::now().into()),
+            }
+            .insert(&transaction)
+            .await?;
+            order_id = Some(id);
+        }
+
+        execution.commit(transaction).await?;
+        let tx_urls = self.settle(&execution).await;
+
+        if !execution.is_dry_run() {
+            self.publish(MarketChange {
+                market_id: market.id,
+                taker_order_id: Some(taker.order_id),
+                users: outcome
+                    .fills
+                    .iter()
+                    .map(|fill| fill.maker_user_id)
+                    .chain([user_id])
+                    .collect(),
+            })
+            .await;
+        }
+
+        let fill = OrderFillDto::new(&outcome, outcome.remaining, order_id)?;
+        Ok(execution.finish(PlacedOrder { tx_urls, fill }))
+    }
+
+    /// Nothing is held on chain for a sell order, so this only frees its shares
+    pub async fn cancel_sell_order(
+        txn: &impl sea_orm::ConnectionTrait,
+        order: &SellOrderDto,
+    ) -> AppResult<()> {
+        entity::sellorder::Entity::delete_by_id(order.id)
+            .exec(txn)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_sell_order(
+        txn: &impl sea_orm::ConnectionTrait,
+        id: Uuid,
+    ) -> AppResult<Option<SellOrderDto>> {
+        Ok(entity::sellorder::Entity::find_by_id(id)
+            .one(txn)
+            .await
+            .map_err(Into::<AppError>::into)?
+            .map(Into::into))
+    }
+
+    /// The user's own orders in the market
+    pub async fn list_sell_orders(
+        &self,
+        market_id: Uuid,
+        user_id: Uuid,
+    ) -> AppResult<Vec<SellOrderDto>> {
+        Ok(entity::sellorder::Entity::find()
+            .filter(entity::sellorder::Column::MarketId.eq(market_id))
+            .filter(entity::sellorder::Column::UserId.eq(user_id))
+            .all(&self.database)
+            .await
+            .map_err(Into::<AppError>::into)?
+            .into_iter()
+            .map(Into::into)
+            .collect())
+    }
+}
@@ -43,6 +43,6 @@ impl AppState {
             return Ok(None);
         }
 
-        Ok(self.get_user_by_id(session.user_id).await?)
+        self.get_user_by_id(session.user_id).await
     }
 }
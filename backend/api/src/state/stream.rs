@@ -0,0 +1,302 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use sea_orm::{ColumnTrait, EntityTrait, QueryFilter, QueryOrder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+
+use crate::{
+    AppState, entity,
+    error::{AppError, AppResult},
+    state::{
+        book::{BookQuery, PriceLevelDto},
+        buyorder::BuyOrderDto,
+        fill::{PublicTradeDto, TradesQuery},
+        market::EventPercentagesDto,
+        sellorder::SellOrderDto,
+    },
+    stream::{Channel, ChannelState, MessageKind, Published, StreamMessage},
+};
+
+/// The open orders of a user in every market
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOrdersDto {
+    pub buy_orders: Vec<BuyOrderDto>,
+    pub sell_orders: Vec<SellOrderDto>,
+    /// Orders filled or cancelled since the previous message, only in deltas
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<Uuid>,
+}
+
+/// The levels that changed, a level with no shares left the book
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct BookDeltaDto {
+    bids: Vec<PriceLevelDto>,
+    asks: Vec<PriceLevelDto>,
+}
+
+/// What a committed change touched
+#[derive(Debug)]
+pub struct MarketChange {
+    pub market_id: Uuid,
+    /// The new order, when it may have traded
+    pub taker_order_id: Option<Uuid>,
+    /// Users whose orders were placed, filled or cancelled
+    pub users: Vec<Uuid>,
+}
+
+impl AppState {
+    /// Publishes the new state of everything `change` touched. The change is already committed,
+    /// so a failure here is only logged
+    pub async fn publish(&self, change: MarketChange) {
+        let market_id = change.market_id;
+        if let Err(e) = self.try_publish(change).await {
+            tracing::error!(
+                "Failed to publish the change of market {}: {}",
+                market_id,
+                e
+            );
+        }
+    }
+
+    async fn try_publish(&self, change: MarketChange) -> AppResult<()> {
+        if !self.bus.has_subscribers() {
+            for state in self.bus.lock().await.values_mut() {
+                state.last = None;
+            }
+            return Ok(());
+        }
+
+        // Everything is read before the bus is locked, so a slow query doesn't hold up other
+        // publishers and subscribers
+        let market = entity::market::Entity::find_by_id(change.market_id)
+            .one(&self.database)
+            .await?
+            .ok_or(AppError::MarketNotFound)?;
+
+        let trades: Vec<PublicTradeDto> = match change.taker_order_id {
+            Some(order_id) => entity::fill::Entity::find()
+                .filter(entity::fill::Column::TakerOrderId.eq(order_id))
+                .order_by_asc(entity::fill::Column::CreatedAt)
+                .all(&self.database)
+                .await?
+                .into_iter()
+                .map(Into::into)
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let users: BTreeSet<Uuid> = change.users.into_iter().collect();
+        let touched = [
+            Channel::Book(market.id),
+            Channel::Percentages(market.event_id),
+        ]
+        .into_iter()
+        .chain(users.into_iter().map(Channel::Orders));
+        let mut states = Vec::new();
+        for channel in touched {
+            if let Some(new) = self.channel_state(channel).await? {
+                states.push((channel, new));
+            }
+        }
+
+        let mut channels = self.bus.lock().await;
+        if !trades.is_empty() {
+            self.bus.send(
+                &mut channels,
+                Channel::Trades(market.id),
+                MessageKind::Delta,
+                to_value(&trades)?,
+            );
+        }
+        for (channel, new) in states {
+            self.publish_state(&mut channels, channel, new)?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends what changed since the last state published on the channel, or all of it when
+    /// there is none
+    fn publish_state(
+        &self,
+        channels: &mut HashMap<Channel, ChannelState>,
+        channel: Channel,
+        new: Published,
+    ) -> AppResult<()> {
+        let last = channels
+            .get_mut(&channel)
+            .and_then(|state| state.last.take());
+
+        let message = match (last, &new) {
+            (Some(Published::Book(last)), Published::Book(new)) => {
+                let delta = BookDeltaDto {
+                    bids: level_changes(&last.bids, &new.bids),
+                    asks: level_changes(&last.asks, &new.asks),
+                };
+                delta_message(!delta.bids.is_empty() || !delta.asks.is_empty(), &delta)?
+            }
+            (Some(Published::Percentages(last)), Published::Percentages(new)) => {
+                let delta = EventPercentagesDto {
+                    percentages: new
+                        .percentages
+                        .iter()
+                        .filter(|(id, pct)| last.percentages.get(id) != Some(pct))
+                        .map(|(id, pct)| (*id, pct.clone()))
+                        .collect(),
+                };
+                delta_message(!delta.percentages.is_empty(), &delta)?
+            }
+            (Some(Published::Orders(last)), Published::Orders(new)) => {
+                let delta = order_changes(&last, new);
+                delta_message(delta != UserOrdersDto::default(), &delta)?
+            }
+            _ => Some((MessageKind::Snapshot, to_value(&new)?)),
+        };
+
+        if let Some((kind, data)) = message {
+            self.bus.send(channels, channel, kind, data);
+        }
+        channels.entry(channel).or_default().last = Some(new);
+
+        Ok(())
+    }
+
+    /// The current state of the channel, numbered after the last message sent on it
+    pub async fn stream_snapshot(&self, channel: Channel) -> AppResult<StreamMessage> {
+        let mut channels = self.bus.lock().await;
+
+        let data = match self.channel_state(channel).await? {
+            Some(current) => {
+                let data = to_value(&current)?;
+                // Without an earlier state, the next delta is computed from this one
+                channels
+                    .entry(channel)
+                    .or_default()
+                    .last
+                    .get_or_insert(current);
+                data
+            }
+            None => {
+                let Channel::Trades(market_id) = channel else {
+                    unreachable!("Only trades have no state")
+                };
+                let page = self
+                    .list_market_trades(market_id, None, TradesQuery::DEFAULT_LIMIT)
+                    .await?;
+                to_value(&page.trades)?
+            }
+        };
+
+        Ok(StreamMessage {
+            channel,
+            seq: channels.get(&channel).map_or(0, |state| state.seq),
+            kind: MessageKind::Snapshot,
+            data,
+        })
+    }
+
+    /// What deltas of the channel are computed from, None for trades which are only appended
+    async fn channel_state(&self, channel: Channel) -> AppResult<Option<Published>> {
+        Ok(Some(match channel {
+            Channel::Book(market_id) => {
+                Published::Book(self.order_book(market_id, BookQuery::MAX_DEPTH).await?)
+            }
+            Channel::Trades(_) => return Ok(None),
+            Channel::Percentages(event_id) => {
+                Published::Percentages(self.get_event_percentages(event_id).await?)
+            }
+            Channel::Orders(user_id) => Published::Orders(self.user_orders(user_id).await?),
+        }))
+    }
+
+    async fn user_orders(&self, user_id: Uuid) -> AppResult<UserOrdersDto> {
+        let buy_orders = entity::buyorder::Entity::find()
+            .filter(entity::buyorder::Column::UserId.eq(user_id))
+            .order_by_asc(entity::buyorder::Column::CreatedAt)
+            .all(&self.database)
+            .await?;
+        let sell_orders = entity::sellorder::Entity::find()
+            .filter(entity::sellorder::Column::UserId.eq(user_id))
+            .order_by_asc(entity::sellorder::Column::CreatedAt)
+            .all(&self.database)
+            .await?;
+
+        Ok(UserOrdersDto {
+            buy_orders: buy_orders.into_iter().map(Into::into).collect(),
+            sell_orders: sell_orders.into_iter().map(Into::into).collect(),
+            removed: Vec::new(),
+        })
+    }
+}
+
+fn to_value(value: &impl Serialize) -> AppResult<Value> {
+    Ok(serde_json::to_value(value).map_err(anyhow::Error::from)?)
+}
+
+/// A delta when anything changed
+fn delta_message(changed: bool, delta: &impl Serialize) -> AppResult<Option<(MessageKind, Value)>> {
+    if !changed {
+        return Ok(None);
+    }
+    Ok(Some((MessageKind::Delta, to_value(delta)?)))
+}
+
+fn level_changes(last: &[PriceLevelDto], new: &[PriceLevelDto]) -> Vec<PriceLevelDto> {
+    let mut levels: BTreeMap<i64, PriceLevelDto> = last
+        .iter()
+        .map(|level| {
+            let gone = PriceLevelDto {
+                price: level.price,
+                shares: 0,
+                orders: 0,
+            };
+            (level.price, gone)
+        })
+        .collect();
+    for level in new {
+        levels.insert(level.price, level.clone());
+    }
+
+    levels
+        .into_values()
+        .filter(|level| !last.contains(level))
+        .collect()
+}
+
+fn order_changes(last: &UserOrdersDto, new: &UserOrdersDto) -> UserOrdersDto {
+    let buy_orders: Vec<BuyOrderDto> = new
+        .buy_orders
+        .iter()
+        .filter(|order| !last.buy_orders.contains(order))
+        .cloned()
+        .collect();
+    let sell_orders: Vec<SellOrderDto> = new
+        .sell_orders
+        .iter()
+        .filter(|order| !last.sell_orders.contains(order))
+        .cloned()
+        .collect();
+
+    let open: BTreeSet<Uuid> = new
+        .buy_orders
+        .iter()
+        .map(|order| order.id)
+        .chain(new.sell_orders.iter().map(|order| order.id))
+        .collect();
+    let removed = last
+        .buy_orders
+        .iter()
+        .map(|order| order.id)
+        .chain(last.sell_orders.iter().map(|order| order.id))
+        .filter(|id| !open.contains(id))
+        .collect();
+
+    UserOrdersDto {
+        buy_orders,
+        sell_orders,
+        removed,
+    }
+}
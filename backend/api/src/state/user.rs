@@ -1,12 +1,13 @@
-use blockchain_client::ProfeciaClient;
+use blockchain_core::money::{Cents, MoneyError};
 use chrono::{DateTime, FixedOffset, Utc};
 use sea_orm::{ActiveModelTrait, ActiveValue::Set, EntityTrait};
 use serde::{Deserialize, Serialize};
-use solana_sdk::{signature::Keypair, signer::Signer};
 use uuid::Uuid;
 
 use crate::{
-    AppState, entity,
+    AppState,
+    chain::ChainGateway,
+    entity,
     error::{AppError, AppResult},
     utils::password::{hash_password, verify_password},
 };
@@ -29,19 +30,23 @@ impl UserDto {
         self.username == "admin"
     }
 
-    pub fn new(id: Uuid, username: String, wallet: &String, solana: &ProfeciaClient) -> Self {
+    pub fn new(
+        id: Uuid,
+        username: String,
+        wallet: &str,
+        chain: &dyn ChainGateway,
+    ) -> AppResult<Self> {
         let is_admin = username == "admin";
-        let wallet = Keypair::from_base58_string(wallet);
-        let pubkey = wallet.pubkey();
-        let solana_url = solana.get_account_url(&pubkey);
+        let pubkey = chain.signers().pubkey(wallet)?;
+        let solana_url = chain.account_url(&pubkey);
 
-        Self {
+        Ok(Self {
             id,
             username,
             is_admin,
             solana_url,
             pubkey: pubkey.to_string(),
-        }
+        })
     }
 }
 
@@ -61,7 +66,7 @@ impl AppState {
             return Ok(None);
         };
 
-        let user_dto = UserDto::new(user.id, user.username, &user.wallet, &self.solana);
+        let user_dto = UserDto::new(user.id, user.username, &user.wallet, self.chain.as_ref())?;
 
         Ok(Some(user_dto))
     }
@@ -74,11 +79,12 @@ impl AppState {
             return Ok(None);
         };
 
-        let user_dto = UserDto::new(user.id, user.username, &user.wallet, &self.solana);
+        let user_dto = UserDto::new(user.id, user.username, &user.wallet, self.chain.as_ref())?;
 
         Ok(Some(user_dto))
     }
 
+    #[allow(clippy::redundant_pattern_matching, clippy::needless_update)]
     pub async fn register_user(&self, username: &str, raw_password: &str) -> AppResult<UserDto> {
         if let Some(_) = self.get_user_by_username(username).await? {
             return Err(AppError::UserAlreadyExists(username.to_string()));
@@ -87,13 +93,13 @@ impl AppState {
         let user_id = Uuid::new_v4();
         let hashed_password = hash_password(raw_password.to_string()).await?;
 
-        let wallet = self.solana.init_new_wallet().await?;
+        let (wallet, _) = self.chain.create_wallet().await?;
 
         let user = entity::user::ActiveModel {
             id: Set(user_id),
             username: Set(username.to_string()),
             next_airdrop_at: Set(None),
-            wallet: Set(wallet.to_base58_string()),
+            wallet: Set(wallet),
         }
         .insert(&self.database)
         .await?;
@@ -106,7 +112,12 @@ impl AppState {
         .insert(&self.database)
         .await?;
 
-        Ok(UserDto::new(user_id, username.to_string(), &user.wallet, &self.solana))
+        UserDto::new(
+            user_id,
+            username.to_string(),
+            &user.wallet,
+            self.chain.as_ref(),
+        )
     }
 
     pub async fn get_user_identity_by_id(
@@ -156,7 +167,8 @@ impl AppState {
         }
     }
 
-    pub async fn request_airdrop(&self, user_id: Uuid, cents: u64) -> AppResult<()> {
+    #[allow(clippy::collapsible_if)]
+    pub async fn request_airdrop(&self, user_id: Uuid, cents: Cents) -> AppResult<()> {
         let user = entity::user::Entity::find_by_id(user_id)
             .one(&self.database)
             .await?
@@ -168,23 +180,10 @@ impl AppState {
             }
         }
 
-        let wallet = Keypair::from_base58_string(&user.wallet);
-
-        let current_balance = match self.get_balance_in_cents(user_id).await {
-            Ok(balance) => balance,
-            Err(e) => {
-                if e.to_string().contains("AccountNotFound") {
-                    0
-                } else {
-                    return Err(e);
-                }
-            }
-        };
+        let wallet = self.chain.signers().pubkey(&user.wallet)?;
+        let amount = cents.to_micro_usdc().ok_or(MoneyError::Overflow)?;
 
-        // x 10k to make it into micro usd
-        self.solana
-            .airdrop_usdc(&wallet.pubkey(), (current_balance + cents) * 10000)
-            .await?;
+        self.chain.airdrop(&wallet, amount).await?;
 
         let mut active_user: entity::user::ActiveModel = user.into();
         let next_airdrop_at: DateTime<FixedOffset> = (Utc::now() + AIRDROP_COOLDOWN).into();
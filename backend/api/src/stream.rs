@@ -0,0 +1,157 @@
+//! Live updates: every committed change publishes to a broadcast bus, and each subscriber of the
+//! stream endpoint gets a snapshot of its channels followed by the deltas of the bus.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use tokio::sync::{Mutex, MutexGuard, broadcast};
+use uuid::Uuid;
+
+use crate::{
+    error::{AppError, AppResult},
+    state::{book::OrderBookDto, market::EventPercentagesDto, stream::UserOrdersDto},
+};
+
+/// Messages a slow subscriber can fall behind by before it is dropped
+const BUS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    /// `market:{id}:book`
+    Book(Uuid),
+    /// `market:{id}:trades`
+    Trades(Uuid),
+    /// `event:{id}:percentages`
+    Percentages(Uuid),
+    /// `user:me:orders`, of the user in the key
+    Orders(Uuid),
+}
+
+impl Channel {
+    /// `user` is who `user:me` refers to, None for anonymous subscribers
+    pub fn parse(name: &str, user: Option<Uuid>) -> AppResult<Self> {
+        let invalid = || AppError::InvalidChannel(name.to_string());
+
+        match name.split(':').collect::<Vec<_>>()[..] {
+            ["user", "me", "orders"] => user.map(Channel::Orders).ok_or(AppError::Unauthorized(
+                "subscribing to user:me requires a session".to_string(),
+            )),
+            [kind, id, topic] => {
+                let id = Uuid::try_parse(id).map_err(|_| invalid())?;
+                match (kind, topic) {
+                    ("market", "book") => Ok(Channel::Book(id)),
+                    ("market", "trades") => Ok(Channel::Trades(id)),
+                    ("event", "percentages") => Ok(Channel::Percentages(id)),
+                    _ => Err(invalid()),
+                }
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Channel::Book(id) => write!(f, "market:{id}:book"),
+            Channel::Trades(id) => write!(f, "market:{id}:trades"),
+            Channel::Percentages(id) => write!(f, "event:{id}:percentages"),
+            Channel::Orders(_) => write!(f, "user:me:orders"),
+        }
+    }
+}
+
+impl Serialize for Channel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    /// The whole state of the channel, replaces anything received before
+    Snapshot,
+    /// What changed since the message before it
+    Delta,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamMessage {
+    pub channel: Channel,
+    /// Increases by one with every message of the channel. A subscriber gets the deltas numbered
+    /// after its snapshot, each applying to the message before it
+    pub seq: u64,
+    pub kind: MessageKind,
+    pub data: Value,
+}
+
+/// The last state published on a channel, to compute the next delta from
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum Published {
+    Book(OrderBookDto),
+    Percentages(EventPercentagesDto),
+    Orders(UserOrdersDto),
+}
+
+#[derive(Debug, Default)]
+pub struct ChannelState {
+    pub seq: u64,
+    /// None when nothing was published since the last subscriber left, the next message is then
+    /// a snapshot
+    pub last: Option<Published>,
+}
+
+#[derive(Clone)]
+pub struct Bus {
+    sender: broadcast::Sender<Arc<StreamMessage>>,
+    channels: Arc<Mutex<HashMap<Channel, ChannelState>>>,
+}
+
+impl Default for Bus {
+    fn default() -> Self {
+        Self {
+            sender: broadcast::channel(BUS_CAPACITY).0,
+            channels: Arc::default(),
+        }
+    }
+}
+
+impl Bus {
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<StreamMessage>> {
+        self.sender.subscribe()
+    }
+
+    pub fn has_subscribers(&self) -> bool {
+        self.sender.receiver_count() > 0
+    }
+
+    /// Held while a change is published or a snapshot taken, so a snapshot sits exactly between
+    /// two messages of its channel
+    pub async fn lock(&self) -> MutexGuard<'_, HashMap<Channel, ChannelState>> {
+        self.channels.lock().await
+    }
+
+    /// Numbers the message in its channel and sends it to every subscriber
+    pub fn send(
+        &self,
+        channels: &mut HashMap<Channel, ChannelState>,
+        channel: Channel,
+        kind: MessageKind,
+        data: Value,
+    ) {
+        let state = channels.entry(channel).or_default();
+        state.seq += 1;
+
+        // Nobody listening is not an error, the next subscriber starts from a snapshot
+        let _ = self.sender.send(Arc::new(StreamMessage {
+            channel,
+            seq: state.seq,
+            kind,
+            data,
+        }));
+    }
+}
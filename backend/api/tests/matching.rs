@@ -0,0 +1,209 @@
+//! The matching engine, on books built by hand.
+
+use api::matching::{PriceImprovement, RestingOrder, Side, crosses, match_order, match_sell};
+use blockchain_core::money::{MicroUsdc, Price, Shares};
+use chrono::{DateTime, Duration, FixedOffset};
+use uuid::Uuid;
+
+fn price(cents: u64) -> Price {
+    Price::new(cents).unwrap()
+}
+
+fn at(seconds: i64) -> DateTime<FixedOffset> {
+    DateTime::UNIX_EPOCH.fixed_offset() + Duration::seconds(seconds)
+}
+
+/// A buy order of the opposite option
+fn order(cents: u64, shares: u64, created: i64) -> RestingOrder {
+    RestingOrder {
+        id: Uuid::new_v4(),
+        side: Side::Buy,
+        user_id: Uuid::new_v4(),
+        price: price(cents),
+        shares: Shares(shares),
+        created_at: at(created),
+    }
+}
+
+#[test]
+fn prices_cross_when_they_cover_the_payout() {
+    assert!(crosses(price(60), price(40)));
+    assert!(crosses(price(70), price(40)));
+    assert!(!crosses(price(50), price(40)));
+}
+
+#[test]
+fn crossing_order_fills_at_the_maker_price() {
+    let maker = order(40, 10, 0);
+
+    let outcome = match_order(
+        price(70),
+        Shares(10),
+        std::slice::from_ref(&maker),
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    assert_eq!(outcome.fills.len(), 1);
+    let fill = outcome.fills[0];
+    assert_eq!(fill.maker_id, maker.id);
+    assert_eq!(fill.maker_price, price(40));
+    assert_eq!(fill.taker_price, price(60));
+    assert_eq!(fill.improvement, price(10));
+    assert_eq!(outcome.remaining, Shares(0));
+    // 10 cents on 10 shares
+    assert_eq!(outcome.refund, MicroUsdc(1_000_000));
+    assert_eq!(outcome.fees, MicroUsdc(0));
+}
+
+#[test]
+fn improvement_can_go_to_fees() {
+    let outcome = match_order(
+        price(70),
+        Shares(10),
+        &[order(40, 10, 0)],
+        PriceImprovement::Fees,
+    )
+    .unwrap();
+
+    assert_eq!(outcome.refund, MicroUsdc(0));
+    assert_eq!(outcome.fees, MicroUsdc(1_000_000));
+}
+
+#[test]
+fn best_price_then_oldest_first() {
+    let late_high = order(45, 5, 10);
+    let early_high = order(45, 5, 5);
+    let low = order(35, 5, 0);
+
+    let outcome = match_order(
+        price(70),
+        Shares(12),
+        &[low.clone(), late_high.clone(), early_high.clone()],
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    let makers: Vec<Uuid> = outcome.fills.iter().map(|fill| fill.maker_id).collect();
+    assert_eq!(makers, vec![early_high.id, late_high.id, low.id]);
+    assert_eq!(outcome.fills[2].shares, Shares(2));
+    assert_eq!(outcome.fills[2].taker_price, price(65));
+    assert_eq!(outcome.remaining, Shares(0));
+}
+
+#[test]
+fn stops_at_the_first_level_that_does_not_cross() {
+    let outcome = match_order(
+        price(60),
+        Shares(20),
+        &[order(45, 5, 0), order(40, 5, 1), order(30, 5, 2)],
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    assert_eq!(outcome.fills.len(), 2);
+    assert_eq!(outcome.remaining, Shares(10));
+    // 5 cents on the first 5 shares, none on the exact match
+    assert_eq!(outcome.refund, MicroUsdc(250_000));
+}
+
+#[test]
+fn same_book_in_any_order_gives_the_same_fills() {
+    let book = vec![
+        order(40, 3, 1),
+        order(40, 3, 1),
+        order(45, 3, 2),
+        order(50, 3, 0),
+    ];
+    let mut reversed = book.clone();
+    reversed.reverse();
+
+    let a = match_order(price(70), Shares(10), &book, PriceImprovement::Taker).unwrap();
+    let b = match_order(price(70), Shares(10), &reversed, PriceImprovement::Taker).unwrap();
+
+    assert_eq!(a, b);
+}
+
+#[test]
+fn empty_orders_are_skipped() {
+    let outcome = match_order(
+        price(70),
+        Shares(5),
+        &[order(40, 0, 0)],
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    assert!(outcome.fills.is_empty());
+    assert_eq!(outcome.remaining, Shares(5));
+}
+
+fn ask(cents: u64, shares: u64, created: i64) -> RestingOrder {
+    RestingOrder {
+        side: Side::Sell,
+        ..order(cents, shares, created)
+    }
+}
+
+#[test]
+fn buy_takes_the_cheapest_of_asks_and_opposite_bids() {
+    // costs 65 to the buyer
+    let bid = order(35, 5, 0);
+    // costs 55
+    let cheap_ask = ask(55, 5, 1);
+    // costs 75, above the limit
+    let dear_ask = ask(75, 5, 2);
+
+    let outcome = match_order(
+        price(70),
+        Shares(10),
+        &[bid.clone(), dear_ask, cheap_ask.clone()],
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    let makers: Vec<Uuid> = outcome.fills.iter().map(|fill| fill.maker_id).collect();
+    assert_eq!(makers, vec![cheap_ask.id, bid.id]);
+    assert_eq!(outcome.fills[0].maker_side, Side::Sell);
+    assert_eq!(outcome.fills[0].taker_price, price(55));
+    assert_eq!(outcome.fills[0].improvement, price(15));
+    assert_eq!(outcome.fills[1].taker_price, price(65));
+    // 15 cents on 5 shares, then 5 cents on 5 shares
+    assert_eq!(outcome.refund, MicroUsdc(1_000_000));
+}
+
+#[test]
+fn sell_takes_the_highest_bids_of_the_same_option() {
+    let low = order(50, 5, 0);
+    let high = order(65, 5, 1);
+    let below = order(40, 5, 2);
+
+    let outcome = match_sell(
+        price(50),
+        Shares(8),
+        &[low.clone(), below, high.clone()],
+        PriceImprovement::Fees,
+    )
+    .unwrap();
+
+    let makers: Vec<Uuid> = outcome.fills.iter().map(|fill| fill.maker_id).collect();
+    assert_eq!(makers, vec![high.id, low.id]);
+    assert_eq!(outcome.fills[0].taker_price, price(65));
+    assert_eq!(outcome.fills[0].improvement, price(15));
+    assert_eq!(outcome.fills[1].shares, Shares(3));
+    assert_eq!(outcome.remaining, Shares(0));
+    assert_eq!(outcome.fees, MicroUsdc(750_000));
+}
+
+#[test]
+fn sell_ignores_asks() {
+    let outcome = match_sell(
+        price(50),
+        Shares(5),
+        &[ask(60, 5, 0)],
+        PriceImprovement::Taker,
+    )
+    .unwrap();
+
+    assert!(outcome.fills.is_empty());
+}
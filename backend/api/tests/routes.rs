@@ -0,0 +1,1848 @@
+//! Drives the router against an in-memory SQLite database and a `MockLedger`, with no validator.
+
+use std::{sync::Arc, time::Duration};
+
+use api::{
+    AppState,
+    chain::MockLedger,
+    entity::{self, chain_operation::OperationStatus, market::MarketOption},
+    matching::PriceImprovement,
+    route,
+    state::{outbox::MAX_ATTEMPTS, resolution::RESOLUTION_BATCH},
+    stream::Bus,
+    sync_schema,
+};
+use axum::{
+    Router,
+    body::{Body, BodyDataStream, to_bytes},
+    http::{Method, Request, StatusCode, header},
+};
+use blockchain_core::money::MicroUsdc;
+use indexer::entity::market::Token;
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, ConnectOptions, ConnectionTrait, Database,
+    DatabaseConnection, DatabaseTransaction, EntityTrait, QueryFilter, QueryOrder, QuerySelect,
+    TransactionTrait,
+};
+use serde_json::{Value, json};
+use solana_sdk::pubkey::Pubkey;
+use tokio_stream::StreamExt;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+struct TestApp {
+    router: Router,
+    state: AppState,
+    ledger: Arc<MockLedger>,
+}
+
+struct TestUser {
+    id: Uuid,
+    session: String,
+    pubkey: Pubkey,
+}
+
+impl TestApp {
+    async fn new() -> Self {
+        Self::with_database(Database::connect("sqlite::memory:").await.unwrap()).await
+    }
+
+    /// On a new database of the Postgres server at `DATABASE_URL`, for tests of concurrent
+    /// requests: SQLite runs one transaction at a time and has no row locks. None when it is
+    /// not set
+    async fn postgres() -> Option<Self> {
+        let Ok(url) = std::env::var("DATABASE_URL") else {
+            eprintln!("DATABASE_URL is not set, skipping");
+            return None;
+        };
+        let name = format!("test_{}", Uuid::new_v4().simple());
+        Database::connect(&url)
+            .await
+            .unwrap()
+            .execute_unprepared(&format!("CREATE DATABASE {name}"))
+            .await
+            .unwrap();
+        let (server, _) = url.rsplit_once('/').expect("DATABASE_URL names a database");
+        // Connections opened up front, or the first request is done before the next one has one
+        let options = ConnectOptions::new(format!("{server}/{name}"))
+            .min_connections(4)
+            .to_owned();
+        let database = Database::connect(options).await.unwrap();
+        Some(Self::with_database(database).await)
+    }
+
+    async fn with_database(database: DatabaseConnection) -> Self {
+        sync_schema(&database).await.unwrap();
+
+        let ledger = Arc::new(MockLedger::new());
+        let state = AppState {
+            database,
+            chain: ledger.clone(),
+            price_improvement: PriceImprovement::Taker,
+            bus: Bus::default(),
+        };
+        let router = route::router(state.clone());
+
+        Self {
+            router,
+            state,
+            ledger,
+        }
+    }
+
+    async fn request(
+        &self,
+        method: Method,
+        uri: &str,
+        user: Option<&TestUser>,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(user) = user {
+            request = request.header(header::COOKIE, format!("sessionId={}", user.session));
+        }
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+        (status, body)
+    }
+
+    async fn register(&self, username: &str) -> TestUser {
+        let (status, body) = self
+            .request(
+                Method::POST,
+                "/api/user/register",
+                None,
+                Some(json!({ "username": username, "password": "password" })),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        TestUser {
+            id: body["user"]["id"].as_str().unwrap().parse().unwrap(),
+            session: body["sessionId"].as_str().unwrap().to_string(),
+            pubkey: body["user"]["pubkey"].as_str().unwrap().parse().unwrap(),
+        }
+    }
+
+    async fn airdrop(&self, user: &TestUser) {
+        let (status, body) = self
+            .request(Method::POST, "/api/user/airdrop", Some(user), None)
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+
+    /// Returns the event and its only market
+    async fn create_event(&self, admin: &TestUser) -> (Uuid, Uuid) {
+        let (status, body) = self
+            .request(
+                Method::POST,
+                "/api/event",
+                Some(admin),
+                Some(event_request()),
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+
+        (
+            body["id"].as_str().unwrap().parse().unwrap(),
+            body["markets"][0]["id"].as_str().unwrap().parse().unwrap(),
+        )
+    }
+
+    async fn buy(
+        &self,
+        user: &TestUser,
+        market: Uuid,
+        option: &str,
+        shares: u64,
+        price: u64,
+    ) -> (StatusCode, Value) {
+        self.request(
+            Method::POST,
+            "/api/event/buyorder",
+            Some(user),
+            Some(json!({
+                "marketId": market,
+                "userId": user.id,
+                "shares": shares,
+                "pricePerShare": price,
+                "option": option,
+            })),
+        )
+        .await
+    }
+
+    /// A buy order with more than a limit price, `order` is merged into the request
+    async fn place(
+        &self,
+        user: &TestUser,
+        market: Uuid,
+        option: &str,
+        shares: u64,
+        order: Value,
+    ) -> (StatusCode, Value) {
+        let mut request = json!({
+            "marketId": market,
+            "userId": user.id,
+            "shares": shares,
+            "option": option,
+        });
+        request
+            .as_object_mut()
+            .unwrap()
+            .extend(order.as_object().unwrap().clone());
+
+        self.request(
+            Method::POST,
+            "/api/event/buyorder",
+            Some(user),
+            Some(request),
+        )
+        .await
+    }
+
+    /// The user's own buy orders
+    async fn buy_orders(&self, user: &TestUser, market: Uuid) -> Value {
+        let (status, body) = self
+            .request(
+                Method::GET,
+                &format!("/api/event/buyorder/{market}"),
+                Some(user),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    async fn sell(
+        &self,
+        user: &TestUser,
+        market: Uuid,
+        option: &str,
+        shares: u64,
+        price: u64,
+    ) -> (StatusCode, Value) {
+        self.request(
+            Method::POST,
+            "/api/event/sellorder",
+            Some(user),
+            Some(json!({
+                "marketId": market,
+                "shares": shares,
+                "pricePerShare": price,
+                "option": option,
+            })),
+        )
+        .await
+    }
+
+    /// The user's own sell orders
+    async fn sell_orders(&self, user: &TestUser, market: Uuid) -> Value {
+        let (status, body) = self
+            .request(
+                Method::GET,
+                &format!("/api/event/sellorder/{market}"),
+                Some(user),
+                None,
+            )
+            .await;
+        assert_eq!(status, StatusCode::OK);
+        body
+    }
+
+    /// Runs the outbox as if every backoff had passed
+    async fn retry_outbox(&self) -> usize {
+        entity::chain_operation::Entity::update_many()
+            .set(entity::chain_operation::ActiveModel {
+                next_attempt_at: Set(chrono::Utc::now().into()),
+                ..Default::default()
+            })
+            .filter(entity::chain_operation::Column::Status.eq(OperationStatus::Pending))
+            .exec(&self.state.database)
+            .await
+            .unwrap();
+        self.state.process_outbox().await.unwrap()
+    }
+
+    /// Statuses of the outbox, oldest first
+    async fn outbox(&self) -> Vec<OperationStatus> {
+        entity::chain_operation::Entity::find()
+            .order_by_asc(entity::chain_operation::Column::CreatedAt)
+            .order_by_asc(entity::chain_operation::Column::Step)
+            .all(&self.state.database)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|operation| operation.status)
+            .collect()
+    }
+
+    /// Subscribes to the stream endpoint
+    async fn stream(&self, channels: &str, user: Option<&TestUser>) -> Stream {
+        let mut request = Request::builder().uri(format!("/api/stream?channels={channels}"));
+        if let Some(user) = user {
+            request = request.header(header::COOKIE, format!("sessionId={}", user.session));
+        }
+        let request = request.body(Body::empty()).unwrap();
+
+        let response = self.router.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        Stream(response.into_body().into_data_stream())
+    }
+}
+
+/// Runs `request` while `other`, a transaction standing for a concurrent request, is still open,
+/// and commits it once the request has had the time to run into its locks
+async fn race<T>(other: DatabaseTransaction, request: impl Future<Output = T>) -> T {
+    let (output, ()) = tokio::join!(request, async {
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        other.commit().await.unwrap();
+    });
+    output
+}
+
+struct Stream(BodyDataStream);
+
+impl Stream {
+    /// The data of the next server-sent event
+    async fn next(&mut self) -> Value {
+        let frame = tokio::time::timeout(Duration::from_secs(5), self.0.next())
+            .await
+            .expect("No message in time")
+            .unwrap()
+            .unwrap();
+        let event = std::str::from_utf8(&frame).unwrap();
+        serde_json::from_str(event.trim().strip_prefix("data: ").unwrap()).unwrap()
+    }
+}
+
+fn event_request() -> Value {
+    json!({
+        "displayName": "Event",
+        "markets": [{
+            "displayName": "Market",
+            "optionAName": "Yes",
+            "optionBName": "No",
+            "rules": "Rules",
+        }],
+    })
+}
+
+#[tokio::test]
+async fn airdrop_credits_the_balance() {
+    let app = TestApp::new().await;
+    let user = app.register("alice").await;
+
+    app.airdrop(&user).await;
+
+    let (status, body) = app
+        .request(Method::GET, "/api/user/balance", Some(&user), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["balanceCents"], 1000);
+    assert_eq!(app.ledger.usdc(&user.pubkey), MicroUsdc(10_000_000));
+}
+
+#[tokio::test]
+async fn created_event_has_its_markets_on_chain() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+
+    let (event, market) = app.create_event(&admin).await;
+
+    let options = app.ledger.options(&event).unwrap();
+    assert_eq!(options.len(), 1);
+    assert!(options.contains_key(&market));
+}
+
+#[tokio::test]
+async fn event_creation_resumes_after_a_lost_answer() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+
+    // The event lands but the answer is lost, so its market is not sent
+    app.ledger.lose_next(1);
+    let (event, market) = app.create_event(&admin).await;
+    assert!(app.ledger.options(&event).unwrap().is_empty());
+    assert_eq!(app.outbox().await, vec![OperationStatus::Pending]);
+
+    // The retry does not create the event again, which would fail, and only adds the market
+    assert_eq!(app.retry_outbox().await, 1);
+    assert!(app.ledger.options(&event).unwrap().contains_key(&market));
+    assert_eq!(app.outbox().await, vec![OperationStatus::Confirmed]);
+}
+
+#[tokio::test]
+async fn dry_run_leaves_the_ledger_untouched() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            "/api/event?dryRun=true",
+            Some(&admin),
+            Some(event_request()),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["dryRun"], true);
+    assert_eq!(body["success"], true);
+
+    let event: Uuid = body["result"]["id"].as_str().unwrap().parse().unwrap();
+    assert!(app.ledger.options(&event).is_none());
+
+    let (status, body) = app
+        .request(Method::GET, &format!("/api/event/{event}"), None, None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["event"].is_null());
+}
+
+#[tokio::test]
+async fn matched_orders_mint_shares_and_the_winner_claims_the_reward() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+
+    let (status, body) = app.buy(&alice, market, "optionA", 5, 60).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, body) = app.buy(&bob, market, "optionB", 5, 40).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 5);
+    assert_eq!(app.ledger.shares(&bob.pubkey, &option.no_mint).0, 5);
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.usdc(&bob.pubkey), MicroUsdc(8_000_000));
+
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/resolve/{market}"),
+            Some(&admin),
+            Some(json!({ "option": "optionA" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["status"], "running");
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+
+    // Resolution only refunds open orders, the winnings wait for their owner
+    assert_eq!(app.state.process_resolutions().await.unwrap(), 1);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+
+    let (status, body) = app
+        .request(Method::GET, "/api/user/claims", Some(&alice), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(
+        body,
+        json!([{
+            "marketId": market,
+            "eventId": event,
+            "option": "optionA",
+            "shares": 5,
+            "amount": 5_000_000,
+        }])
+    );
+    let (_, body) = app
+        .request(Method::GET, "/api/user/claims", Some(&bob), None)
+        .await;
+    assert_eq!(body, json!([]));
+
+    let claim = format!("/api/user/claims/{market}");
+    let (status, _) = app.request(Method::POST, &claim, Some(&bob), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let (status, body) = app.request(Method::POST, &claim, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["status"], "confirmed");
+    assert_eq!(body["amount"], 5_000_000);
+    assert_eq!(body["transactionUrls"].as_array().unwrap().len(), 1);
+
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(12_000_000));
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 0);
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(0)));
+    // The redeemed shares are gone from the positions too
+    let (_, body) = app
+        .request(Method::GET, "/api/user/positions", Some(&alice), None)
+        .await;
+    assert_eq!(body, json!([]));
+
+    let (_, body) = app
+        .request(Method::GET, "/api/user/claims", Some(&alice), None)
+        .await;
+    assert_eq!(body, json!([]));
+    let (status, _) = app.request(Method::POST, &claim, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn claim_racing_another_claim_is_already_claimed() {
+    let Some(app) = TestApp::postgres().await else {
+        return;
+    };
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/resolve/{market}"),
+            Some(&admin),
+            Some(json!({ "option": "optionA" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // Another claim got past the check, and has not committed yet
+    let other = app.state.database.begin().await.unwrap();
+    entity::claim::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        user_id: Set(alice.id),
+        market_id: Set(market),
+        option: Set(MarketOption::A),
+        shares: Set(5),
+        amount: Set(5_000_000),
+        saga_id: Set(Uuid::new_v4()),
+        created_at: Set(chrono::Utc::now().into()),
+    }
+    .insert(&other)
+    .await
+    .unwrap();
+
+    let claim = format!("/api/user/claims/{market}");
+    let (status, body) = race(other, app.request(Method::POST, &claim, Some(&alice), None)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+}
+
+#[tokio::test]
+async fn claim_that_never_lands_can_be_made_again() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+
+    let claim = format!("/api/user/claims/{market}");
+    let (status, _) = app.request(Method::POST, &claim, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    app.request(
+        Method::POST,
+        &format!("/api/event/resolve/{market}"),
+        Some(&admin),
+        Some(json!({ "option": "optionA" })),
+    )
+    .await;
+
+    app.ledger.drop_next(MAX_ATTEMPTS as usize);
+    let (status, body) = app.request(Method::POST, &claim, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["status"], "pending");
+    let (_, body) = app
+        .request(Method::GET, "/api/user/positions", Some(&alice), None)
+        .await;
+    assert_eq!(body, json!([]));
+    for _ in 1..MAX_ATTEMPTS {
+        assert_eq!(app.retry_outbox().await, 1);
+    }
+
+    // Nothing was paid, and the shares and winnings are back
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+    let (_, body) = app
+        .request(Method::GET, "/api/user/positions", Some(&alice), None)
+        .await;
+    assert_eq!(body[0]["shares"], 5);
+    let (_, body) = app
+        .request(Method::GET, "/api/user/claims", Some(&alice), None)
+        .await;
+    assert_eq!(body[0]["shares"], 5);
+
+    let (status, body) = app.request(Method::POST, &claim, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["status"], "confirmed");
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(12_000_000));
+}
+
+#[tokio::test]
+async fn order_without_funds_is_rejected() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let (event, market) = app.create_event(&admin).await;
+
+    let (status, _) = app.buy(&alice, market, "optionA", 5, 60).await;
+
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(0)));
+}
+
+#[tokio::test]
+async fn order_can_spend_the_whole_balance() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (event, market) = app.create_event(&admin).await;
+
+    let (status, body) = app.buy(&alice, market, "optionA", 20, 50).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(0));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(10_000_000)));
+
+    let (status, _) = app.buy(&alice, market, "optionA", 1, 1).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn crossing_orders_match_at_the_maker_price() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+
+    let (status, body) = app.buy(&bob, market, "optionB", 5, 40).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, body) = app.buy(&alice, market, "optionA", 5, 70).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 5);
+    assert_eq!(app.ledger.shares(&bob.pubkey, &option.no_mint).0, 5);
+    // alice pays 60 per share, the 10 above it are refunded
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+
+    assert_eq!(app.buy_orders(&bob, market).await, json!([]));
+}
+
+#[tokio::test]
+async fn resting_sell_order_fills_a_later_buy() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+
+    let (status, body) = app.sell(&alice, market, "optionA", 5, 70).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(app.sell_orders(&alice, market).await[0]["shares"], 5);
+
+    let (status, body) = app.buy(&carol, market, "optionA", 5, 75).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    assert_eq!(app.ledger.shares(&carol.pubkey, &option.yes_mint).0, 5);
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 0);
+    // carol pays the ask of 70, the 5 above it are refunded
+    assert_eq!(app.ledger.usdc(&carol.pubkey), MicroUsdc(6_500_000));
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(10_500_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+    assert_eq!(app.sell_orders(&alice, market).await, json!([]));
+}
+
+#[tokio::test]
+async fn sell_order_fills_resting_bids_and_rests_the_rest() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    app.buy(&carol, market, "optionA", 3, 65).await;
+
+    let (status, body) = app.sell(&alice, market, "optionA", 5, 60).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    assert_eq!(app.ledger.shares(&carol.pubkey, &option.yes_mint).0, 3);
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 2);
+    // alice gets carol's bid of 65
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(8_950_000));
+    assert_eq!(app.ledger.usdc(&carol.pubkey), MicroUsdc(8_050_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+
+    let asks = app.sell_orders(&alice, market).await;
+    assert_eq!(asks.as_array().unwrap().len(), 1);
+    assert_eq!(asks[0]["shares"], 2);
+    assert_eq!(asks[0]["pricePerShare"], 60);
+}
+
+#[tokio::test]
+async fn offered_shares_cannot_be_sold_twice() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (_, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+
+    let (status, body) = app.sell(&alice, market, "optionA", 4, 70).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let (status, _) = app.sell(&alice, market, "optionA", 2, 80).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (status, _) = app.sell(&alice, market, "optionB", 1, 80).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let order = app.sell_orders(&alice, market).await[0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/sellorder/cancel/{order}"),
+            Some(&alice),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    let (status, body) = app.sell(&alice, market, "optionA", 5, 80).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+}
+
+#[tokio::test]
+async fn sell_waits_for_a_concurrent_sell_of_the_same_position() {
+    let Some(app) = TestApp::postgres().await else {
+        return;
+    };
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (_, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+
+    // Another sell of 3 holds the position and has not committed its order yet
+    let other = app.state.database.begin().await.unwrap();
+    entity::position::Entity::find()
+        .filter(entity::position::Column::UserId.eq(alice.id))
+        .lock_exclusive()
+        .all(&other)
+        .await
+        .unwrap();
+    entity::sellorder::ActiveModel {
+        id: Set(Uuid::new_v4()),
+        market_id: Set(market),
+        user_id: Set(alice.id),
+        option: Set(MarketOption::A),
+        shares: Set(3),
+        price_per_share: Set(70),
+        created_at: Set(chrono::Utc::now().into()),
+    }
+    .insert(&other)
+    .await
+    .unwrap();
+
+    let (status, _) = race(other, app.sell(&alice, market, "optionA", 3, 80)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let asks = app.sell_orders(&alice, market).await;
+    assert_eq!(asks.as_array().unwrap().len(), 1);
+    assert_eq!(asks[0]["shares"], 3);
+}
+
+#[tokio::test]
+async fn buy_waits_for_a_concurrent_buy_of_the_same_ask() {
+    let Some(app) = TestApp::postgres().await else {
+        return;
+    };
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    let (status, body) = app.sell(&alice, market, "optionA", 5, 50).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+
+    // Another buy holds the ask, and takes all of it along with the seller's shares
+    let other = app.state.database.begin().await.unwrap();
+    let ask = entity::sellorder::Entity::find()
+        .filter(entity::sellorder::Column::MarketId.eq(market))
+        .lock_exclusive()
+        .one(&other)
+        .await
+        .unwrap()
+        .unwrap();
+    entity::sellorder::Entity::delete_by_id(ask.id)
+        .exec(&other)
+        .await
+        .unwrap();
+    entity::position::Entity::delete_many()
+        .filter(entity::position::Column::UserId.eq(alice.id))
+        .exec(&other)
+        .await
+        .unwrap();
+
+    let (status, body) = race(other, app.buy(&carol, market, "optionA", 5, 50)).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["fill"]["filledShares"], 0);
+    assert_eq!(body["fill"]["restingShares"], 5);
+    assert_eq!(app.ledger.shares(&carol.pubkey, &option.yes_mint).0, 0);
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 5);
+}
+
+#[tokio::test]
+async fn immediate_or_cancel_drops_the_remainder() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&bob, market, "optionB", 3, 40).await;
+
+    let (status, body) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "pricePerShare": 60, "timeInForce": "ioc" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["fill"]["filledShares"], 3);
+    assert_eq!(body["fill"]["cancelledShares"], 2);
+    assert_eq!(body["fill"]["restingShares"], 0);
+    assert!(body["fill"]["orderId"].is_null());
+
+    // only the 3 filled shares were paid for
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(8_200_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+    assert_eq!(app.buy_orders(&alice, market).await, json!([]));
+}
+
+#[tokio::test]
+async fn fill_or_kill_leaves_everything_untouched_when_it_cannot_fill() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&bob, market, "optionB", 3, 40).await;
+
+    let (status, _) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "pricePerShare": 60, "timeInForce": "fok" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(10_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(1_200_000)));
+    assert_eq!(app.buy_orders(&bob, market).await[0]["shares"], 3);
+
+    let (status, body) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            3,
+            json!({ "pricePerShare": 60, "timeInForce": "fok" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["fill"]["filledShares"], 3);
+}
+
+#[tokio::test]
+async fn market_order_stops_at_its_slippage() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (_, market) = app.create_event(&admin).await;
+    // YES at 60, then at 65
+    app.buy(&bob, market, "optionB", 3, 40).await;
+    app.buy(&carol, market, "optionB", 3, 35).await;
+
+    let (status, body) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "orderType": "market", "maxSlippage": 3, "timeInForce": "ioc" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(
+        body["fill"]["fills"],
+        json!([{ "shares": 3, "pricePerShare": 60 }])
+    );
+    assert_eq!(body["fill"]["cancelledShares"], 2);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(8_200_000));
+
+    let (status, _) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "orderType": "market", "maxSlippage": 3 }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn good_till_date_order_is_refunded_once_expired() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (event, market) = app.create_event(&admin).await;
+
+    let (status, _) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "pricePerShare": 60, "timeInForce": "gtd" }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::milliseconds(500);
+    let (status, body) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "pricePerShare": 60, "timeInForce": "gtd", "expiresAt": expires_at }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["fill"]["restingShares"], 5);
+    assert_eq!(app.state.expire_buy_orders().await.unwrap(), 0);
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+
+    tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+
+    assert_eq!(app.state.expire_buy_orders().await.unwrap(), 1);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(10_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(0)));
+    assert_eq!(app.buy_orders(&alice, market).await, json!([]));
+}
+
+#[tokio::test]
+async fn expired_order_does_not_match_before_it_is_swept() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    let option = app.ledger.options(&event).unwrap()[&market].clone();
+
+    let expires_at = chrono::Utc::now() + chrono::Duration::milliseconds(200);
+    let (status, body) = app
+        .place(
+            &alice,
+            market,
+            "optionA",
+            5,
+            json!({ "pricePerShare": 60, "timeInForce": "gtd", "expiresAt": expires_at }),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+
+    // Past its expiry it is out of the book and the percentages
+    let (_, book) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/market/{market}/book"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(book["bids"], json!([]));
+    let (_, pct) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/percentages/{event}"),
+            None,
+            None,
+        )
+        .await;
+    assert!(pct["percentages"][market.to_string()]["optionAPercentage"].is_null());
+
+    let (status, body) = app.buy(&bob, market, "optionB", 5, 40).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(app.ledger.shares(&alice.pubkey, &option.yes_mint).0, 0);
+    assert_eq!(app.ledger.shares(&bob.pubkey, &option.no_mint).0, 0);
+
+    assert_eq!(app.state.expire_buy_orders().await.unwrap(), 1);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(10_000_000));
+}
+
+#[tokio::test]
+async fn cancel_waits_for_a_concurrent_cancel_of_the_same_order() {
+    let Some(app) = TestApp::postgres().await else {
+        return;
+    };
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (event, market) = app.create_event(&admin).await;
+    let (status, body) = app.buy(&alice, market, "optionA", 5, 60).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let order_id: Uuid = app.buy_orders(&alice, market).await[0]["id"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+
+    // Another cancel, or the sweeper, holds the order and has removed it, refund still to come
+    let other = app.state.database.begin().await.unwrap();
+    entity::buyorder::Entity::find_by_id(order_id)
+        .lock_exclusive()
+        .one(&other)
+        .await
+        .unwrap()
+        .unwrap();
+    entity::buyorder::Entity::delete_by_id(order_id)
+        .exec(&other)
+        .await
+        .unwrap();
+
+    let cancel = format!("/api/event/buyorder/cancel/{order_id}");
+    let (status, _) = race(
+        other,
+        app.request(Method::POST, &cancel, Some(&alice), None),
+    )
+    .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Only the other one refunds
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+}
+
+#[tokio::test]
+async fn fills_are_listed_as_trades_newest_first() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 2, 40).await;
+    app.buy(&bob, market, "optionB", 3, 40).await;
+    app.buy(&carol, market, "optionA", 2, 65).await;
+    app.sell(&alice, market, "optionA", 2, 60).await;
+
+    let (status, page) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/{event}/trades?limit=2"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    let trades = page["trades"].as_array().unwrap();
+    assert_eq!(trades.len(), 2);
+    assert_eq!(trades[0]["takerSide"], "sell");
+    assert_eq!(trades[0]["pricePerShare"], 65);
+    // Who traded is only shown to the traders themselves
+    assert!(trades[0].get("takerUserId").is_none());
+    assert!(trades[0].get("makerUserId").is_none());
+    assert_eq!(trades[1]["shares"], 3);
+    assert_eq!(trades[1]["pricePerShare"], 40);
+    assert_eq!(trades[1]["makerPricePerShare"], 60);
+    assert!(!trades[1]["signature"].as_str().unwrap().is_empty());
+
+    let cursor = page["nextCursor"].as_str().unwrap();
+    let (status, page) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/{event}/trades?limit=2&cursor={cursor}"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["trades"].as_array().unwrap().len(), 1);
+    assert_eq!(page["trades"][0]["shares"], 2);
+    assert!(page["nextCursor"].is_null());
+
+    let (status, page) = app
+        .request(Method::GET, "/api/user/trades", Some(&bob), None)
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(page["trades"].as_array().unwrap().len(), 2);
+    let (_, page) = app
+        .request(Method::GET, "/api/user/trades", Some(&carol), None)
+        .await;
+    assert_eq!(page["trades"].as_array().unwrap().len(), 1);
+    assert_eq!(page["trades"][0]["takerUserId"], json!(alice.id));
+    assert_eq!(page["trades"][0]["makerUserId"], json!(carol.id));
+}
+
+#[tokio::test]
+async fn chart_candles_and_percentages_follow_the_trades() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    app.buy(&carol, market, "optionA", 2, 65).await;
+    app.sell(&alice, market, "optionA", 2, 60).await;
+
+    let (status, chart) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/chart/{event}?interval=1d"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(chart["interval"], "1d");
+    let candles = chart["candles"][market.to_string()].as_array().unwrap();
+    assert_eq!(candles.len(), 1);
+    assert_eq!(candles[0]["open"], 60);
+    assert_eq!(candles[0]["high"], 65);
+    assert_eq!(candles[0]["low"], 60);
+    assert_eq!(candles[0]["close"], 65);
+    assert_eq!(candles[0]["volume"], 7);
+    assert_eq!(chart["points"][0]["percentages"][market.to_string()], 65);
+
+    // The book is empty, so the last trade is the headline
+    let percentage =
+        |body: &Value| body["percentages"][market.to_string()]["optionAPercentage"].clone();
+    let (_, pct) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/percentages/{event}"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(percentage(&pct), 65);
+
+    // A tight book takes over with its mid-price
+    app.buy(&alice, market, "optionA", 1, 62).await;
+    app.buy(&bob, market, "optionB", 1, 30).await;
+    let (_, pct) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/percentages/{event}"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(percentage(&pct), 66);
+
+    let (status, _) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/chart/{event}?from=2026-01-02T00:00:00Z&to=2026-01-01T00:00:00Z"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn stream_sends_snapshots_then_numbered_deltas() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+
+    let mut stream = app
+        .stream(
+            &format!("market:{market}:book,market:{market}:trades,event:{event}:percentages,user:me:orders"),
+            Some(&alice),
+        )
+        .await;
+
+    let book = stream.next().await;
+    assert_eq!(book["channel"], format!("market:{market}:book"));
+    assert_eq!(book["kind"], "snapshot");
+    assert_eq!(book["seq"], 0);
+    assert_eq!(
+        book["data"]["bids"],
+        json!([{ "price": 60, "shares": 5, "orders": 1 }])
+    );
+    assert_eq!(stream.next().await["data"], json!([]));
+    assert_eq!(
+        stream.next().await["data"]["percentages"][market.to_string()]["optionAPercentage"],
+        60
+    );
+    let orders = stream.next().await;
+    assert_eq!(orders["channel"], "user:me:orders");
+    assert_eq!(orders["data"]["buyOrders"][0]["shares"], 5);
+
+    app.buy(&bob, market, "optionB", 2, 40).await;
+
+    let trades = stream.next().await;
+    assert_eq!(trades["channel"], format!("market:{market}:trades"));
+    assert_eq!(trades["kind"], "delta");
+    assert_eq!(trades["seq"], 1);
+    assert_eq!(trades["data"][0]["shares"], 2);
+    assert!(trades["data"][0].get("takerUserId").is_none());
+    let book = stream.next().await;
+    assert_eq!(book["kind"], "delta");
+    assert_eq!(book["seq"], 1);
+    assert_eq!(
+        book["data"]["bids"],
+        json!([{ "price": 60, "shares": 3, "orders": 1 }])
+    );
+    assert_eq!(book["data"]["asks"], json!([]));
+    // The last trade is where the bid already put the percentages, so they send nothing, and
+    let orders = stream.next().await;
+    assert_eq!(orders["channel"], "user:me:orders");
+    assert_eq!(orders["kind"], "delta");
+    assert_eq!(orders["data"]["buyOrders"][0]["shares"], 3);
+
+    let order_id = orders["data"]["buyOrders"][0]["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let (status, _) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/buyorder/cancel/{order_id}"),
+            Some(&alice),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK);
+
+    let book = stream.next().await;
+    assert_eq!(book["seq"], 2);
+    assert_eq!(
+        book["data"]["bids"],
+        json!([{ "price": 60, "shares": 0, "orders": 0 }])
+    );
+    let orders = stream.next().await;
+    assert_eq!(orders["data"]["removed"], json!([order_id]));
+
+    let (status, _) = app
+        .request(
+            Method::GET,
+            "/api/stream?channels=user:me:orders",
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn book_aggregates_both_options_in_prices_of_option_a() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (_, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 2, 40).await;
+    app.buy(&bob, market, "optionA", 3, 40).await;
+    app.buy(&alice, market, "optionA", 1, 30).await;
+    app.buy(&bob, market, "optionB", 4, 45).await;
+    app.buy(&bob, market, "optionB", 1, 20).await;
+
+    let (status, book) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/market/{market}/book?depth=1"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{book}");
+    assert_eq!(
+        book["bids"],
+        json!([{ "price": 40, "shares": 5, "orders": 2 }])
+    );
+    // a NO bid at 45 is a YES ask at 55
+    assert_eq!(
+        book["asks"],
+        json!([{ "price": 55, "shares": 4, "orders": 1 }])
+    );
+    assert_eq!(book["bestBid"], 40);
+    assert_eq!(book["bestAsk"], 55);
+    assert_eq!(book["spread"], 15);
+
+    let (_, book) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/market/{market}/book"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(book["bids"].as_array().unwrap().len(), 2);
+    assert_eq!(book["asks"][1]["price"], 80);
+
+    // the raw orders are only shown to their owner
+    let orders = app.buy_orders(&alice, market).await;
+    assert_eq!(orders.as_array().unwrap().len(), 2);
+    assert!(
+        orders
+            .as_array()
+            .unwrap()
+            .iter()
+            .all(|order| order["userId"] == json!(alice.id))
+    );
+    let (status, _) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/buyorder/{market}"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn dropped_transactions_are_retried_by_the_outbox() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+
+    // The deposit is dropped, and the match waits for it
+    app.ledger.drop_next(1);
+    let (status, body) = app.buy(&bob, market, "optionB", 5, 40).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["transactionUrls"], json!([]));
+    assert_eq!(body["fill"]["filledShares"], 5);
+    assert_eq!(app.ledger.usdc(&bob.pubkey), MicroUsdc(10_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+
+    let (_, page) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/{event}/trades"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(page["trades"][0]["signature"], "");
+
+    assert_eq!(app.retry_outbox().await, 1);
+    assert_eq!(app.ledger.usdc(&bob.pubkey), MicroUsdc(8_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+    assert!(
+        app.outbox()
+            .await
+            .iter()
+            .all(|status| *status == OperationStatus::Confirmed)
+    );
+
+    let (_, page) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/{event}/trades"),
+            None,
+            None,
+        )
+        .await;
+    assert!(!page["trades"][0]["signature"].as_str().unwrap().is_empty());
+    assert_eq!(app.retry_outbox().await, 0);
+}
+
+#[tokio::test]
+async fn transaction_whose_answer_was_lost_is_not_sent_again() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (event, market) = app.create_event(&admin).await;
+
+    // The deposit lands, but the runner only sees an error
+    app.ledger.lose_next(1);
+    let (status, body) = app.buy(&alice, market, "optionA", 5, 60).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["transactionUrls"], json!([]));
+    assert_eq!(app.outbox().await[1], OperationStatus::Pending);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+
+    // Its signature was saved, and found on chain
+    assert_eq!(app.retry_outbox().await, 1);
+    assert_eq!(app.outbox().await[1], OperationStatus::Confirmed);
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+}
+
+#[tokio::test]
+async fn operation_left_submitted_is_taken_over_without_sending_it_twice() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+
+    app.ledger.lose_next(1);
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.ledger.drop_next(1);
+    app.buy(&bob, market, "optionA", 5, 40).await;
+
+    // As if both runners died while sending, one after the deposit landed and one before it
+    entity::chain_operation::Entity::update_many()
+        .set(entity::chain_operation::ActiveModel {
+            status: Set(OperationStatus::Submitted),
+            updated_at: Set((chrono::Utc::now() - chrono::Duration::hours(1)).into()),
+            ..Default::default()
+        })
+        .filter(entity::chain_operation::Column::Status.eq(OperationStatus::Pending))
+        .exec(&app.state.database)
+        .await
+        .unwrap();
+
+    app.state.process_outbox().await.unwrap();
+    assert!(
+        app.outbox()
+            .await
+            .iter()
+            .all(|status| *status == OperationStatus::Confirmed)
+    );
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.ledger.usdc(&bob.pubkey), MicroUsdc(8_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+}
+
+#[tokio::test]
+async fn refund_that_never_lands_puts_the_order_back() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    let order_id = app.buy_orders(&alice, market).await[0]["id"].clone();
+
+    app.ledger.drop_next(MAX_ATTEMPTS as usize);
+    let (status, body) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/buyorder/cancel/{}", order_id.as_str().unwrap()),
+            Some(&alice),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(app.buy_orders(&alice, market).await, json!([]));
+
+    for _ in 1..MAX_ATTEMPTS {
+        assert_eq!(app.retry_outbox().await, 1);
+    }
+    assert_eq!(app.outbox().await.last(), Some(&OperationStatus::Failed));
+
+    // Nothing left the event, so the order still holds its deposit
+    let orders = app.buy_orders(&alice, market).await;
+    assert_eq!(orders[0]["id"], order_id);
+    assert_eq!(orders[0]["shares"], 5);
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(3_000_000)));
+    assert_eq!(app.ledger.usdc(&alice.pubkey), MicroUsdc(7_000_000));
+    assert_eq!(app.retry_outbox().await, 0);
+}
+
+#[tokio::test]
+async fn match_that_never_lands_undoes_its_trade() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    app.buy(&alice, market, "optionA", 2, 70).await;
+
+    app.ledger.drop_next(MAX_ATTEMPTS as usize);
+    let (status, body) = app.buy(&bob, market, "optionB", 2, 30).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    for _ in 1..MAX_ATTEMPTS {
+        app.retry_outbox().await;
+    }
+    assert_eq!(app.outbox().await.last(), Some(&OperationStatus::Failed));
+
+    // The trade is gone, and the headline is the one before it again
+    let (_, page) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/{event}/trades"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(page["trades"].as_array().unwrap().len(), 1);
+    assert_eq!(app.buy_orders(&alice, market).await[0]["shares"], 2);
+    let (_, pct) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/percentages/{event}"),
+            None,
+            None,
+        )
+        .await;
+    assert_eq!(
+        pct["percentages"][market.to_string()]["optionAPercentage"],
+        60
+    );
+}
+
+#[tokio::test]
+async fn reconciliation_reports_positions_the_chain_does_not_hold() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    let (event, market) = app.create_event(&admin).await;
+
+    let (status, _) = app
+        .request(Method::GET, "/api/admin/reconcile", Some(&admin), None)
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    app.buy(&alice, market, "optionA", 2, 50).await;
+
+    // Matched collateral and the resting order's escrow add up to the treasury
+    let (status, body) = app
+        .request(Method::POST, "/api/admin/reconcile", Some(&admin), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["markets"], 1);
+    assert_eq!(body["discrepancies"], json!([]));
+
+    entity::position::Entity::update_many()
+        .set(entity::position::ActiveModel {
+            shares: Set(7),
+            ..Default::default()
+        })
+        .filter(entity::position::Column::UserId.eq(bob.id))
+        .exec(&app.state.database)
+        .await
+        .unwrap();
+
+    let (status, body) = app
+        .request(Method::POST, "/api/admin/reconcile", Some(&admin), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(
+        body["discrepancies"],
+        json!([{
+            "kind": "position",
+            "severity": "critical",
+            "eventId": event,
+            "marketId": market,
+            "userId": bob.id,
+            "option": "optionB",
+            "expected": 7,
+            "actual": 5,
+        }])
+    );
+
+    let (status, latest) = app
+        .request(Method::GET, "/api/admin/reconcile", Some(&admin), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{latest}");
+    assert_eq!(latest["runId"], body["runId"]);
+
+    let (status, _) = app
+        .request(Method::POST, "/api/admin/reconcile", Some(&alice), None)
+        .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+}
+
+#[tokio::test]
+async fn portfolio_lists_the_holdings_indexed_from_the_chain() {
+    let app = TestApp::new().await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let (event, market) = (Uuid::new_v4(), Uuid::new_v4());
+
+    for (wallet, token, shares) in [
+        (alice.pubkey, Token::Yes, 3),
+        (alice.pubkey, Token::No, 0),
+        (bob.pubkey, Token::No, 5),
+    ] {
+        indexer::entity::position::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            wallet: Set(wallet.to_string()),
+            mint: Set(Pubkey::new_unique().to_string()),
+            event_id: Set(event),
+            market_id: Set(market),
+            token: Set(token),
+            shares: Set(shares),
+            slot: Set(42),
+        }
+        .insert(&app.state.database)
+        .await
+        .unwrap();
+    }
+
+    let (status, body) = app
+        .request(Method::GET, "/api/user/portfolio", Some(&alice), None)
+        .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(
+        body,
+        json!([{
+            "marketId": market,
+            "eventId": event,
+            "option": "optionA",
+            "shares": 3,
+            "slot": 42,
+        }])
+    );
+
+    let (status, _) = app
+        .request(Method::GET, "/api/user/portfolio", None, None)
+        .await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn resolution_job_reports_each_refund() {
+    let app = TestApp::new().await;
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    let bob = app.register("bob").await;
+    let carol = app.register("carol").await;
+    app.airdrop(&alice).await;
+    app.airdrop(&bob).await;
+    app.airdrop(&carol).await;
+    let (event, market) = app.create_event(&admin).await;
+    app.buy(&alice, market, "optionA", 5, 60).await;
+    app.buy(&bob, market, "optionB", 5, 40).await;
+    app.buy(&carol, market, "optionA", 2, 50).await;
+    app.buy(&bob, market, "optionB", 1, 30).await;
+
+    let (status, job) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/resolve/{market}"),
+            Some(&admin),
+            Some(json!({ "option": "optionA" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{job}");
+    assert_eq!(job["status"], "running");
+    assert_eq!(job["progress"]["waiting"], 2);
+    assert_eq!(job["refunds"], json!([]));
+
+    // The market takes no orders while its job runs
+    let (status, _) = app.buy(&bob, market, "optionB", 1, 40).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // The oldest order is refunded first, and its refund is dropped
+    app.ledger.drop_next(1);
+    assert_eq!(app.state.process_resolutions().await.unwrap(), 1);
+    assert_eq!(app.state.process_resolutions().await.unwrap(), 0);
+
+    let uri = format!("/api/event/resolve/{}", job["id"].as_str().unwrap());
+    let (status, job) = app.request(Method::GET, &uri, Some(&admin), None).await;
+    assert_eq!(status, StatusCode::OK, "{job}");
+    assert_eq!(
+        job["progress"],
+        json!({ "total": 2, "waiting": 0, "pending": 1, "confirmed": 1, "failed": 0 })
+    );
+
+    // And never lands
+    app.ledger.drop_next(MAX_ATTEMPTS as usize - 1);
+    for _ in 1..MAX_ATTEMPTS {
+        assert_eq!(app.retry_outbox().await, 1);
+    }
+
+    let (status, job) = app.request(Method::GET, &uri, Some(&admin), None).await;
+    assert_eq!(status, StatusCode::OK, "{job}");
+    assert_eq!(job["status"], "done");
+    assert_eq!(job["batches"], 1);
+    // So it is queued again, and the order stays out of the resolved market
+    assert_eq!(
+        job["progress"],
+        json!({ "total": 2, "waiting": 0, "pending": 1, "confirmed": 1, "failed": 0 })
+    );
+    assert_eq!(app.buy_orders(&carol, market).await, json!([]));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(6_000_000)));
+
+    let refunded = &job["refunds"][1];
+    assert_eq!(refunded["userId"], json!(bob.id));
+    assert_eq!(refunded["shares"], 1);
+    assert_eq!(refunded["amount"], 300_000);
+    assert_eq!(refunded["status"], "confirmed");
+    assert_eq!(refunded["transactionUrls"].as_array().unwrap().len(), 1);
+    assert_eq!(app.ledger.usdc(&bob.pubkey), MicroUsdc(8_000_000));
+
+    assert_eq!(app.retry_outbox().await, 1);
+    let (_, job) = app.request(Method::GET, &uri, Some(&admin), None).await;
+    assert_eq!(
+        job["progress"],
+        json!({ "total": 2, "waiting": 0, "pending": 0, "confirmed": 2, "failed": 0 })
+    );
+    let requeued = &job["refunds"][0];
+    assert_eq!(requeued["userId"], json!(carol.id));
+    assert_eq!(requeued["amount"], 1_000_000);
+    assert_eq!(requeued["status"], "confirmed");
+    assert_eq!(requeued["transactionUrls"].as_array().unwrap().len(), 1);
+    assert_eq!(app.ledger.usdc(&carol.pubkey), MicroUsdc(10_000_000));
+    assert_eq!(app.ledger.treasury(&event), Some(MicroUsdc(5_000_000)));
+
+    let (status, _) = app.request(Method::GET, &uri, Some(&alice), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = app
+        .request(
+            Method::GET,
+            &format!("/api/event/resolve/{}", Uuid::new_v4()),
+            Some(&admin),
+            None,
+        )
+        .await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn resolution_job_holds_its_lease_between_batches() {
+    let Some(app) = TestApp::postgres().await else {
+        return;
+    };
+    let admin = app.register("admin").await;
+    let alice = app.register("alice").await;
+    app.airdrop(&alice).await;
+    let (_, market) = app.create_event(&admin).await;
+    for _ in 0..=RESOLUTION_BATCH {
+        let (status, body) = app.buy(&alice, market, "optionA", 1, 10).await;
+        assert_eq!(status, StatusCode::OK, "{body}");
+    }
+
+    let (status, job) = app
+        .request(
+            Method::POST,
+            &format!("/api/event/resolve/{market}"),
+            Some(&admin),
+            Some(json!({ "option": "optionA" })),
+        )
+        .await;
+    assert_eq!(status, StatusCode::OK, "{job}");
+    let job_id: Uuid = job["id"].as_str().unwrap().parse().unwrap();
+
+    let state = app.state.clone();
+    let worker = tokio::spawn(async move { state.process_resolutions().await.unwrap() });
+    // No other pass may take the job while its batches are staged
+    loop {
+        let job = entity::resolution_job::Entity::find_by_id(job_id)
+            .one(&app.state.database)
+            .await
+            .unwrap()
+            .unwrap();
+        if job.status == entity::resolution_job::JobStatus::Done {
+            assert!(job.locked_until <= chrono::Utc::now());
+            break;
+        }
+        if job.batches > 0 {
+            assert!(job.locked_until > chrono::Utc::now());
+            assert_eq!(app.state.process_resolutions().await.unwrap(), 0);
+        }
+        tokio::task::yield_now().await;
+    }
+    assert_eq!(worker.await.unwrap(), 1);
+    assert_eq!(app.buy_orders(&alice, market).await, json!([]));
+}
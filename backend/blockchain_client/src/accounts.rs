@@ -0,0 +1,152 @@
+//! Reading the program state back: events, orders and the outcome tokens held by users.
+
+use anyhow::{Result, anyhow, bail};
+use blockchain_core::{
+    accounts::{event::Event, order::Order},
+    money::Shares,
+};
+use solana_client::{
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig, UiAccountEncoding},
+    rpc_filter::{Memcmp, RpcFilterType},
+};
+use solana_sdk::{account::Account, program_pack::Pack, pubkey::Pubkey};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::state::Account as TokenAccount;
+use uuid::Uuid;
+
+use crate::{ProfeciaClient, is_account_not_found};
+
+/// Shares a user holds of both outcomes of one option
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OptionBalance {
+    pub option_uuid: Uuid,
+    pub yes: Shares,
+    pub no: Shares,
+}
+
+fn token_amount(account: Option<&Account>) -> Result<u64> {
+    match account {
+        Some(account) if !account.data.is_empty() => {
+            Ok(TokenAccount::unpack(&account.data)?.amount)
+        }
+        _ => Ok(0),
+    }
+}
+
+impl ProfeciaClient {
+    fn check_owner(&self, pubkey: &Pubkey, account: &Account) -> Result<()> {
+        if account.owner != self.profile.deployment.program_id {
+            bail!("Account {} is not owned by the marketplace program", pubkey);
+        }
+        Ok(())
+    }
+
+    pub async fn fetch_event(&self, uuid: &Uuid) -> Result<Event> {
+        self.fetch_event_by_pubkey(&self.event_pubkey(uuid)).await
+    }
+
+    /// Like `fetch_event`, but None when the event was not created yet
+    pub async fn fetch_event_if_exists(&self, uuid: &Uuid) -> Result<Option<Event>> {
+        let pubkey = self.event_pubkey(uuid);
+        match self.fetch_event_by_pubkey(&pubkey).await {
+            Ok(event) => Ok(Some(event)),
+            Err(e) if is_account_not_found(&e, &pubkey) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn fetch_event_by_pubkey(&self, pubkey: &Pubkey) -> Result<Event> {
+        let account = self.rpc_client.get_account(pubkey).await?;
+        self.check_owner(pubkey, &account)?;
+
+        Event::from_bytes(&account.data)
+            .map_err(|e| anyhow!("Error reading event {}: {:?}", pubkey, e))
+    }
+
+    /// Every event of the program, with the address of its account
+    pub async fn list_events(&self) -> Result<Vec<(Pubkey, Event)>> {
+        self.program_accounts(Event::DISCRIMINATOR)
+            .await?
+            .into_iter()
+            .map(|(pubkey, data)| {
+                let event = Event::from_bytes(&data)
+                    .map_err(|e| anyhow!("Error reading event {}: {:?}", pubkey, e))?;
+                Ok((pubkey, event))
+            })
+            .collect()
+    }
+
+    /// The PDA can be derived with `Order::find_program_address`
+    pub async fn fetch_order(&self, pubkey: &Pubkey) -> Result<Order> {
+        let account = self.rpc_client.get_account(pubkey).await?;
+        self.check_owner(pubkey, &account)?;
+
+        Order::from_bytes(&account.data)
+            .map_err(|e| anyhow!("Error reading order {}: {:?}", pubkey, e))
+    }
+
+    /// YES and NO shares held by `wallet` for every option of the event.
+    /// Missing token accounts count as 0 shares
+    pub async fn fetch_option_balances(
+        &self,
+        wallet: &Pubkey,
+        event_uuid: &Uuid,
+    ) -> Result<Vec<OptionBalance>> {
+        let event = self.fetch_event(event_uuid).await?;
+
+        let options: Vec<_> = event.options.into_iter().collect();
+        let atas: Vec<Pubkey> = options
+            .iter()
+            .flat_map(|(_, option)| {
+                [
+                    get_associated_token_address(wallet, &option.yes_mint),
+                    get_associated_token_address(wallet, &option.no_mint),
+                ]
+            })
+            .collect();
+
+        let accounts = self.rpc_client.get_multiple_accounts(&atas).await?;
+
+        options
+            .iter()
+            .zip(accounts.chunks_exact(2))
+            .map(|((option_uuid, _), accounts)| {
+                Ok(OptionBalance {
+                    option_uuid: *option_uuid,
+                    yes: Shares::from_token_amount(token_amount(accounts[0].as_ref())?),
+                    no: Shares::from_token_amount(token_amount(accounts[1].as_ref())?),
+                })
+            })
+            .collect()
+    }
+
+    /// Data of every account of the program that starts with `discriminator`
+    async fn program_accounts(&self, discriminator: u8) -> Result<Vec<(Pubkey, Vec<u8>)>> {
+        let config = RpcProgramAccountsConfig {
+            filters: Some(vec![RpcFilterType::Memcmp(Memcmp::new_raw_bytes(
+                0,
+                vec![discriminator],
+            ))]),
+            account_config: RpcAccountInfoConfig {
+                encoding: Some(UiAccountEncoding::Base64),
+                commitment: Some(self.submit_config.commitment),
+                ..Default::default()
+            },
+            with_context: None,
+            sort_results: None,
+        };
+
+        self.rpc_client
+            .get_program_ui_accounts_with_config(&self.profile.deployment.program_id, config)
+            .await?
+            .into_iter()
+            .map(|(pubkey, account)| {
+                let data = account
+                    .data
+                    .decode()
+                    .ok_or_else(|| anyhow!("Could not decode data of account {}", pubkey))?;
+                Ok((pubkey, data))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,257 @@
+//! Which cluster the client talks to, and where the marketplace is deployed on it.
+//!
+//! A `ClusterProfile` starts from a preset (`surfpool` or `localnet`) or a TOML file, and any
+//! field can then be overridden from the environment. Every field of the TOML file is optional:
+//!
+//! ```toml
+//! name = "staging"
+//! rpc_url = "https://rpc.staging.example"
+//! ws_url = "wss://rpc.staging.example"
+//! program_id = "ProirMXDTFF4AEqGyZVKPhWte4chANDd1c4Y8w7Nsd4"
+//! collateral_mint = "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v"
+//! commitment = "finalized"
+//! explorer = "solana_explorer"
+//! ```
+//!
+//! The program only accepts the collateral mint it was built with, so `collateral_mint` must
+//! match the deployed binary.
+
+use std::{env, fs, path::Path};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::{Deserialize, Deserializer};
+use solana_client::rpc_config::CommitmentConfig;
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+use crate::{DEFAULT_RPC_HTTP, DEFAULT_RPC_WS, MARKETPLACE_PROGRAM, USDC_MINT};
+
+/// Addresses the instructions are built with
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Deployment {
+    #[serde(deserialize_with = "pubkey_from_str")]
+    pub program_id: Pubkey,
+    /// Token the orders are paid in, and the rewards paid out in
+    #[serde(deserialize_with = "pubkey_from_str")]
+    pub collateral_mint: Pubkey,
+}
+
+impl Deployment {
+    pub const DEFAULT: Self = Self {
+        program_id: MARKETPLACE_PROGRAM,
+        collateral_mint: USDC_MINT,
+    };
+}
+
+impl Default for Deployment {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<Commitment> for CommitmentConfig {
+    fn from(commitment: Commitment) -> Self {
+        match commitment {
+            Commitment::Processed => CommitmentConfig::processed(),
+            Commitment::Confirmed => CommitmentConfig::confirmed(),
+            Commitment::Finalized => CommitmentConfig::finalized(),
+        }
+    }
+}
+
+/// Where account and transaction links point to
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Explorer {
+    Solscan,
+    SolanaExplorer,
+    /// `{address}`, `{signature}` and `{rpc_url}` are replaced in the templates
+    Custom {
+        account: String,
+        transaction: String,
+    },
+    /// Links are just the address or signature
+    None,
+}
+
+impl Explorer {
+    fn link(&self, kind: &str, id: &str, rpc_url: &str) -> String {
+        match self {
+            Explorer::Solscan => {
+                let kind = if kind == "address" { "account" } else { kind };
+                format!(
+                    "https://solscan.io/{}/{}?cluster=custom&customUrl={}",
+                    kind, id, rpc_url
+                )
+            }
+            Explorer::SolanaExplorer => format!(
+                "https://explorer.solana.com/{}/{}?cluster=custom&customUrl={}",
+                kind, id, rpc_url
+            ),
+            Explorer::Custom {
+                account,
+                transaction,
+            } => {
+                let (template, placeholder) = if kind == "address" {
+                    (account, "{address}")
+                } else {
+                    (transaction, "{signature}")
+                };
+                template
+                    .replace(placeholder, id)
+                    .replace("{rpc_url}", rpc_url)
+            }
+            Explorer::None => id.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ClusterProfile {
+    pub name: String,
+    pub rpc_url: String,
+    pub ws_url: String,
+    /// How the RPC is reached from outside, which is what explorer links need
+    pub external_rpc_url: String,
+    #[serde(flatten)]
+    pub deployment: Deployment,
+    pub commitment: Commitment,
+    pub explorer: Explorer,
+}
+
+impl Default for ClusterProfile {
+    fn default() -> Self {
+        Self::surfpool()
+    }
+}
+
+impl ClusterProfile {
+    pub fn surfpool() -> Self {
+        Self {
+            name: "surfpool".into(),
+            rpc_url: DEFAULT_RPC_HTTP.into(),
+            ws_url: DEFAULT_RPC_WS.into(),
+            external_rpc_url: DEFAULT_RPC_HTTP.into(),
+            deployment: Deployment::DEFAULT,
+            commitment: Commitment::Confirmed,
+            explorer: Explorer::Solscan,
+        }
+    }
+
+    /// `solana-test-validator` with its default ports
+    pub fn localnet() -> Self {
+        Self {
+            name: "localnet".into(),
+            explorer: Explorer::SolanaExplorer,
+            ..Self::surfpool()
+        }
+    }
+
+    pub fn preset(name: &str) -> Option<Self> {
+        match name {
+            "surfpool" => Some(Self::surfpool()),
+            "localnet" => Some(Self::localnet()),
+            _ => None,
+        }
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    pub fn from_toml_file(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Reading cluster profile {}", path.display()))?;
+        Self::from_toml(&content)
+            .with_context(|| format!("Parsing cluster profile {}", path.display()))
+    }
+
+    /// `CLUSTER_PROFILE` is a preset name or a TOML file, surfpool by default. Then `RPC_URL`,
+    /// `WS_URL`, `EXTERNAL_RPC_URL`, `PROGRAM_ID`, `COLLATERAL_MINT`, `COMMITMENT` and `EXPLORER`
+    /// override the fields of the same name
+    pub fn from_env() -> Result<Self> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    /// Same as `from_env`, reading the variables with `var`
+    pub fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut profile = match var("CLUSTER_PROFILE") {
+            None => Self::surfpool(),
+            Some(name) => match Self::preset(&name) {
+                Some(profile) => profile,
+                None => Self::from_toml_file(Path::new(&name))?,
+            },
+        };
+
+        if let Some(rpc_url) = var("RPC_URL") {
+            profile.rpc_url = rpc_url;
+        }
+        if let Some(ws_url) = var("WS_URL") {
+            profile.ws_url = ws_url;
+        }
+        if let Some(external_rpc_url) = var("EXTERNAL_RPC_URL") {
+            profile.external_rpc_url = external_rpc_url;
+        }
+        if let Some(program_id) = var("PROGRAM_ID") {
+            profile.deployment.program_id = parse_pubkey("PROGRAM_ID", &program_id)?;
+        }
+        if let Some(mint) = var("COLLATERAL_MINT") {
+            profile.deployment.collateral_mint = parse_pubkey("COLLATERAL_MINT", &mint)?;
+        }
+        if let Some(commitment) = var("COMMITMENT") {
+            profile.commitment = parse_value("COMMITMENT", &commitment)?;
+        }
+        if let Some(explorer) = var("EXPLORER") {
+            profile.explorer = parse_value("EXPLORER", &explorer)?;
+        }
+
+        Ok(profile)
+    }
+
+    pub fn commitment_config(&self) -> CommitmentConfig {
+        self.commitment.into()
+    }
+
+    pub fn account_url(&self, pubkey: &Pubkey) -> String {
+        self.explorer
+            .link("address", &pubkey.to_string(), &self.external_rpc_url)
+    }
+
+    pub fn transaction_url(&self, signature: &Signature) -> String {
+        self.explorer
+            .link("tx", &signature.to_string(), &self.external_rpc_url)
+    }
+}
+
+fn parse_pubkey(name: &str, value: &str) -> Result<Pubkey> {
+    value
+        .parse()
+        .map_err(|_| anyhow!("{} is not a valid address: {}", name, value))
+}
+
+/// Unit variants, written the same way as in the TOML file
+fn parse_value<T: for<'de> Deserialize<'de>>(name: &str, value: &str) -> Result<T> {
+    #[derive(Deserialize)]
+    struct Wrapper<T> {
+        value: T,
+    }
+
+    match toml::from_str::<Wrapper<T>>(&format!("value = {:?}", value)) {
+        Ok(wrapper) => Ok(wrapper.value),
+        Err(_) => bail!("Invalid {}: {}", name, value),
+    }
+}
+
+fn pubkey_from_str<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Pubkey, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    value.parse().map_err(serde::de::Error::custom)
+}
@@ -0,0 +1,246 @@
+//! Compute unit limit and priority fee for every transaction sent by `ProfeciaClient`.
+//!
+//! Before sending, the transaction is simulated with the maximum limit to measure how many compute
+//! units it uses. The limit is then set to that usage plus a margin, and the price comes from the
+//! configured `FeePolicy`.
+
+use anyhow::Result;
+use solana_client::rpc_config::RpcSimulateTransactionConfig;
+use solana_compute_budget_interface::ComputeBudgetInstruction;
+use solana_sdk::{
+    message::{Instruction, Message, VersionedMessage, v0},
+    pubkey::Pubkey,
+    signer::Signer,
+    transaction::{TransactionError, VersionedTransaction},
+};
+
+use crate::{ProfeciaClient, TransactionOutcome};
+
+/// Highest limit a transaction can request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Base fee paid for each signature, independent of the priority fee
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u64 = 1_000_000;
+
+/// How the compute unit price (in micro-lamports) is chosen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeePolicy {
+    /// No priority fee
+    None,
+    /// Always the same price
+    Fixed(u64),
+    /// Percentile (0 to 100) of the prices recently paid by transactions that write to the same
+    /// accounts, capped at `max`
+    Percentile { percentile: u8, max: u64 },
+}
+
+/// What a transaction asked for and paid, reported back in `TransactionOutcome`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeBudget {
+    /// Units used when the transaction was simulated
+    pub units_consumed: u64,
+    pub compute_unit_limit: u32,
+    /// In micro-lamports per compute unit
+    pub compute_unit_price: u64,
+    /// Base fee plus priority fee
+    pub fee_lamports: u64,
+}
+
+impl ComputeBudget {
+    /// Instructions that must be placed before the ones of the transaction
+    pub fn instructions(&self) -> Vec<Instruction> {
+        let mut instructions = vec![ComputeBudgetInstruction::set_compute_unit_limit(
+            self.compute_unit_limit,
+        )];
+        if self.compute_unit_price > 0 {
+            instructions.push(ComputeBudgetInstruction::set_compute_unit_price(
+                self.compute_unit_price,
+            ));
+        }
+        instructions
+    }
+}
+
+/// Limit to request for a transaction that used `units_consumed` in simulation
+pub fn compute_unit_limit(units_consumed: u64, margin_percent: u32) -> u32 {
+    let with_margin = units_consumed.saturating_mul(100 + margin_percent as u64) / 100;
+    with_margin.min(MAX_COMPUTE_UNIT_LIMIT as u64) as u32
+}
+
+/// Lamports paid by a transaction with `num_signatures` signatures and the given limit and price
+pub fn fee_lamports(num_signatures: u64, compute_unit_limit: u32, compute_unit_price: u64) -> u64 {
+    let priority_fee = (compute_unit_limit as u128 * compute_unit_price as u128)
+        .div_ceil(MICRO_LAMPORTS_PER_LAMPORT as u128);
+
+    num_signatures
+        .saturating_mul(LAMPORTS_PER_SIGNATURE)
+        .saturating_add(u64::try_from(priority_fee).unwrap_or(u64::MAX))
+}
+
+/// Nearest-rank percentile. 0 if there are no fees
+pub fn fee_percentile(mut fees: Vec<u64>, percentile: u8) -> u64 {
+    if fees.is_empty() {
+        return 0;
+    }
+    fees.sort_unstable();
+    let rank = (fees.len() - 1) * percentile.min(100) as usize / 100;
+    fees[rank]
+}
+
+impl ProfeciaClient {
+    /// Simulates the transaction and sizes its budget from the result.
+    /// If the simulation fails, the failure is returned instead, since sending it would fail too
+    pub async fn estimate_compute_budget(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &[&(dyn Signer + Sync)],
+    ) -> Result<Result<ComputeBudget, TransactionOutcome>> {
+        let config = &self.submit_config;
+        let compute_unit_price = self.compute_unit_price(instructions, payer).await?;
+
+        let mut simulated = ComputeBudget {
+            units_consumed: 0,
+            compute_unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+            compute_unit_price,
+            fee_lamports: 0,
+        }
+        .instructions();
+        simulated.extend_from_slice(instructions);
+
+        let recent_blockhash = self
+            .rpc_client
+            .get_latest_blockhash_with_commitment(config.commitment)
+            .await?
+            .0;
+        let message =
+            v0::Message::try_compile(payer, &simulated, &self.lookup_tables, recent_blockhash)?;
+        let num_signatures = message.header.num_required_signatures as u64;
+        let transaction = VersionedTransaction::try_new(VersionedMessage::V0(message), signers)?;
+
+        let result = self
+            .rpc_client
+            .simulate_transaction_with_config(
+                &transaction,
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    commitment: Some(config.commitment),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .value;
+
+        if let Some(error) = result.err {
+            return Ok(Err(TransactionOutcome::Failed {
+                signature: transaction.signatures[0],
+                error: TransactionError::from(error),
+                budget: None,
+            }));
+        }
+
+        let units_consumed = result
+            .units_consumed
+            .unwrap_or(MAX_COMPUTE_UNIT_LIMIT as u64);
+        let compute_unit_limit =
+            compute_unit_limit(units_consumed, config.compute_unit_margin_percent);
+
+        Ok(Ok(ComputeBudget {
+            units_consumed,
+            compute_unit_limit,
+            compute_unit_price,
+            fee_lamports: fee_lamports(num_signatures, compute_unit_limit, compute_unit_price),
+        }))
+    }
+
+    pub(crate) async fn compute_unit_price(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+    ) -> Result<u64> {
+        match self.submit_config.fee_policy {
+            FeePolicy::None => Ok(0),
+            FeePolicy::Fixed(price) => Ok(price),
+            FeePolicy::Percentile { percentile, max } => {
+                // fees are only competitive between transactions that lock the same accounts
+                let message = Message::new(instructions, Some(payer));
+                let writable: Vec<Pubkey> = message
+                    .account_keys
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| message.is_maybe_writable(*i, None))
+                    .map(|(_, key)| *key)
+                    .collect();
+
+                let fees = self
+                    .rpc_client
+                    .get_recent_prioritization_fees(&writable)
+                    .await?
+                    .into_iter()
+                    .map(|fee| fee.prioritization_fee)
+                    .collect();
+
+                Ok(fee_percentile(fees, percentile).min(max))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_adds_the_margin() {
+        assert_eq!(compute_unit_limit(10_000, 10), 11_000);
+        assert_eq!(compute_unit_limit(10_000, 0), 10_000);
+        // rounds down
+        assert_eq!(compute_unit_limit(999, 10), 1_098);
+    }
+
+    #[test]
+    fn limit_is_capped() {
+        assert_eq!(
+            compute_unit_limit(MAX_COMPUTE_UNIT_LIMIT as u64, 10),
+            MAX_COMPUTE_UNIT_LIMIT
+        );
+        assert_eq!(compute_unit_limit(u64::MAX, 10), MAX_COMPUTE_UNIT_LIMIT);
+    }
+
+    #[test]
+    fn fee_without_priority_is_the_base_fee() {
+        assert_eq!(fee_lamports(1, 200_000, 0), LAMPORTS_PER_SIGNATURE);
+        assert_eq!(fee_lamports(3, 200_000, 0), 3 * LAMPORTS_PER_SIGNATURE);
+    }
+
+    #[test]
+    fn priority_fee_rounds_up() {
+        // 200_000 * 5 = 1_000_000 micro-lamports, exactly 1 lamport
+        assert_eq!(fee_lamports(1, 200_000, 5), LAMPORTS_PER_SIGNATURE + 1);
+        // 1 micro-lamport still costs a full lamport
+        assert_eq!(fee_lamports(1, 1, 1), LAMPORTS_PER_SIGNATURE + 1);
+        assert_eq!(fee_lamports(1, 200_001, 5), LAMPORTS_PER_SIGNATURE + 2);
+    }
+
+    #[test]
+    fn fee_saturates_instead_of_overflowing() {
+        assert_eq!(fee_lamports(1, u32::MAX, u64::MAX), u64::MAX);
+        assert_eq!(fee_lamports(u64::MAX, 0, 0), u64::MAX);
+    }
+
+    #[test]
+    fn percentile_of_no_fees_is_zero() {
+        assert_eq!(fee_percentile(Vec::new(), 50), 0);
+    }
+
+    #[test]
+    fn percentile_sorts_the_fees() {
+        let fees = vec![50, 10, 40, 20, 30];
+        assert_eq!(fee_percentile(fees.clone(), 0), 10);
+        assert_eq!(fee_percentile(fees.clone(), 50), 30);
+        assert_eq!(fee_percentile(fees.clone(), 75), 40);
+        assert_eq!(fee_percentile(fees.clone(), 100), 50);
+        // above 100 is treated as 100
+        assert_eq!(fee_percentile(fees, 200), 50);
+    }
+}
@@ -0,0 +1,291 @@
+//! Test USDC for wallets on local clusters.
+//!
+//! A `Faucet` credits wallets through a `FaucetBackend`, refuses credits that would take a wallet
+//! over its cap, and records every credit in a `CreditLedger`. Credits always add to the current
+//! balance.
+//!
+//! The two backends match the two ways the cluster can be run:
+//! - `SurfpoolFaucet` uses the `surfnet_setTokenAccount` cheatcode, so it works with the real USDC
+//!   mint that surfpool clones from mainnet.
+//! - `MintFaucet` mints with the admin wallet on a plain `solana-test-validator`. The mint must
+//!   have the admin as mint authority; `mint_account_json` writes one that the validator can load
+//!   with `--account`.
+
+use std::{sync::Mutex, time::SystemTime};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use blockchain_core::money::MicroUsdc;
+use serde_json::{Value, json};
+use solana_sdk::{
+    program_option::COption, program_pack::Pack, pubkey::Pubkey, rent::Rent, signature::Signature,
+    signer::Signer,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, instruction::create_associated_token_account_idempotent,
+};
+use spl_token::state::{Account as TokenAccount, Mint};
+
+use crate::ProfeciaClient;
+
+/// USDC has 6 decimals
+const USDC_DECIMALS: u8 = 6;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credit {
+    pub wallet: Pubkey,
+    pub amount: MicroUsdc,
+    /// None when the backend does not use transactions
+    pub signature: Option<Signature>,
+    pub credited_at: SystemTime,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum FaucetError {
+    #[error("Wallet {wallet} already received {credited:?} of a cap of {cap:?}")]
+    CapExceeded {
+        wallet: Pubkey,
+        credited: MicroUsdc,
+        cap: MicroUsdc,
+    },
+    #[error("Amount overflow")]
+    Overflow,
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+#[async_trait]
+pub trait FaucetBackend: Send + Sync {
+    /// Adds `amount` to the USDC balance of `wallet`, creating its token account if needed
+    async fn deposit(
+        &self,
+        client: &ProfeciaClient,
+        wallet: &Pubkey,
+        amount: MicroUsdc,
+    ) -> Result<Option<Signature>>;
+}
+
+/// Where credits are recorded, and read back to enforce the cap
+#[async_trait]
+pub trait CreditLedger: Send + Sync {
+    /// Sum of every credit of `wallet`
+    async fn credited(&self, wallet: &Pubkey) -> Result<MicroUsdc>;
+
+    async fn record(&self, credit: &Credit) -> Result<()>;
+}
+
+/// Forgets everything when dropped. Enough for the CLI and tests
+#[derive(Debug, Default)]
+pub struct MemoryLedger {
+    credits: Mutex<Vec<Credit>>,
+}
+
+impl MemoryLedger {
+    pub fn credits(&self) -> Vec<Credit> {
+        self.credits.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl CreditLedger for MemoryLedger {
+    async fn credited(&self, wallet: &Pubkey) -> Result<MicroUsdc> {
+        Ok(MicroUsdc(
+            self.credits
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|credit| credit.wallet == *wallet)
+                .map(|credit| credit.amount.0)
+                .sum(),
+        ))
+    }
+
+    async fn record(&self, credit: &Credit) -> Result<()> {
+        self.credits.lock().unwrap().push(*credit);
+        Ok(())
+    }
+}
+
+pub struct Faucet {
+    backend: Box<dyn FaucetBackend>,
+    ledger: Box<dyn CreditLedger>,
+    cap: Option<MicroUsdc>,
+    /// Credits are serialized, so that two of them cannot both pass the cap check
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl Faucet {
+    /// Without a cap, recording into a `MemoryLedger`
+    pub fn new(backend: Box<dyn FaucetBackend>) -> Self {
+        Self {
+            backend,
+            ledger: Box::new(MemoryLedger::default()),
+            cap: None,
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    pub fn with_ledger(mut self, ledger: Box<dyn CreditLedger>) -> Self {
+        self.ledger = ledger;
+        self
+    }
+
+    /// Most a single wallet can receive in total
+    pub fn with_cap(mut self, cap: MicroUsdc) -> Self {
+        self.cap = Some(cap);
+        self
+    }
+
+    pub async fn credit(
+        &self,
+        client: &ProfeciaClient,
+        wallet: &Pubkey,
+        amount: MicroUsdc,
+    ) -> Result<Credit, FaucetError> {
+        let _guard = self.lock.lock().await;
+
+        if let Some(cap) = self.cap {
+            let credited = self.ledger.credited(wallet).await?;
+            let total = credited.checked_add(amount).ok_or(FaucetError::Overflow)?;
+            if total > cap {
+                return Err(FaucetError::CapExceeded {
+                    wallet: *wallet,
+                    credited,
+                    cap,
+                });
+            }
+        }
+
+        let signature = self.backend.deposit(client, wallet, amount).await?;
+        let credit = Credit {
+            wallet: *wallet,
+            amount,
+            signature,
+            credited_at: SystemTime::now(),
+        };
+        self.ledger.record(&credit).await?;
+
+        Ok(credit)
+    }
+}
+
+/// Balance of the collateral token account of `wallet`, 0 if it does not exist
+pub async fn usdc_balance(client: &ProfeciaClient, wallet: &Pubkey) -> Result<MicroUsdc> {
+    let ata = get_associated_token_address(wallet, &client.profile.deployment.collateral_mint);
+    let account = client
+        .rpc_client
+        .get_account_with_commitment(&ata, client.submit_config.commitment)
+        .await?
+        .value;
+
+    match account {
+        Some(account) => Ok(MicroUsdc(TokenAccount::unpack(&account.data)?.amount)),
+        None => Ok(MicroUsdc(0)),
+    }
+}
+
+/// Uses the surfpool cheatcode. Only works against surfpool
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SurfpoolFaucet;
+
+#[async_trait]
+impl FaucetBackend for SurfpoolFaucet {
+    async fn deposit(
+        &self,
+        client: &ProfeciaClient,
+        wallet: &Pubkey,
+        amount: MicroUsdc,
+    ) -> Result<Option<Signature>> {
+        // the cheatcode overwrites the balance
+        let balance = usdc_balance(client, wallet)
+            .await?
+            .checked_add(amount)
+            .ok_or_else(|| anyhow::anyhow!("Amount overflow"))?;
+
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "surfnet_setTokenAccount",
+            "params": [
+                wallet.to_string(),
+                client.profile.deployment.collateral_mint.to_string(),
+                {
+                    "amount": balance,
+                    "state": "initialized",
+                },
+            ],
+        });
+        let json: Value = reqwest::Client::new()
+            .post(&client.profile.rpc_url)
+            .json(&body)
+            .send()
+            .await?
+            .json()
+            .await?;
+        if let Some(error) = json.get("error") {
+            anyhow::bail!("Error funding: {}", error);
+        }
+
+        Ok(None)
+    }
+}
+
+/// Mints with the admin wallet of the client, which must be the mint authority of the
+/// collateral mint of its cluster profile
+#[derive(Debug, Default, Clone, Copy)]
+pub struct MintFaucet;
+
+#[async_trait]
+impl FaucetBackend for MintFaucet {
+    async fn deposit(
+        &self,
+        client: &ProfeciaClient,
+        wallet: &Pubkey,
+        amount: MicroUsdc,
+    ) -> Result<Option<Signature>> {
+        let admin = client.admin_wallet.pubkey();
+        let mint = client.profile.deployment.collateral_mint;
+        let ata = get_associated_token_address(wallet, &mint);
+
+        let instructions = [
+            create_associated_token_account_idempotent(&admin, wallet, &mint, &spl_token::id()),
+            spl_token::instruction::mint_to(&spl_token::id(), &mint, &ata, &admin, &[], amount.0)?,
+        ];
+
+        let signature = client
+            .submit(&instructions, &admin, &[client.admin_wallet.as_ref()])
+            .await?
+            .confirmed()?;
+        Ok(Some(signature))
+    }
+}
+
+/// A USDC-like mint controlled by `authority`, in the JSON format `solana-test-validator` loads
+/// with `--account <mint> <file>`
+pub fn mint_account_json(mint: &Pubkey, authority: &Pubkey) -> Value {
+    let mut data = vec![0u8; Mint::LEN];
+    Mint::pack(
+        Mint {
+            mint_authority: COption::Some(*authority),
+            supply: 0,
+            decimals: USDC_DECIMALS,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        },
+        &mut data,
+    )
+    .expect("Buffer has the size of a mint");
+
+    json!({
+        "pubkey": mint.to_string(),
+        "account": {
+            "lamports": Rent::default().minimum_balance(Mint::LEN),
+            "data": [BASE64.encode(&data), "base64"],
+            "owner": spl_token::id().to_string(),
+            "executable": false,
+            "rentEpoch": 0,
+            "space": Mint::LEN,
+        },
+    })
+}
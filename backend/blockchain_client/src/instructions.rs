@@ -0,0 +1,305 @@
+//! Builders for every instruction of the program.
+//! These only derive the accounts and serialize the arguments, they never touch the network,
+//! so they can be used both by `ProfeciaClient` and by tests that run the program in-process.
+//! `deployment` is normally `Deployment::DEFAULT`, unless the program was deployed elsewhere.
+
+use anyhow::Result;
+use blockchain_core::{
+    accounts::event::Event,
+    instructions::{
+        AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, MarketInstruction,
+        TransferSharesArgs,
+    },
+};
+use solana_sdk::{
+    message::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address;
+
+use crate::{SYSTEM_PROGRAM, cluster::Deployment};
+
+fn market_instruction(
+    program_id: &Pubkey,
+    instruction: MarketInstruction,
+    accounts: Vec<AccountMeta>,
+) -> Result<Instruction> {
+    let instruction_bytes = wincode::serialize(&instruction)?;
+
+    Ok(Instruction::new_with_bytes(
+        *program_id,
+        instruction_bytes.as_ref(),
+        accounts,
+    ))
+}
+
+/// Every option needs its yes and no mints to sign, so they are marked as signers
+pub fn create_event(
+    deployment: &Deployment,
+    payer: &Pubkey,
+    args: &CreateEventArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.uuid, &deployment.program_id);
+
+    let treasury = get_associated_token_address(&event_pda, &deployment.collateral_mint);
+
+    let mut accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    for option in args.options.values() {
+        accounts.push(AccountMeta::new(option.yes_mint, true));
+        accounts.push(AccountMeta::new(option.no_mint, true));
+    }
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::CreateEvent(args.clone()),
+        accounts,
+    )
+}
+
+pub fn create_empty_event(
+    deployment: &Deployment,
+    payer: &Pubkey,
+    args: &CreateEmptyEventArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.uuid, &deployment.program_id);
+
+    let treasury = get_associated_token_address(&event_pda, &deployment.collateral_mint);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::CreateEmptyEvent(args.clone()),
+        accounts,
+    )
+}
+
+pub fn close_event(
+    deployment: &Deployment,
+    payer: &Pubkey,
+    args: &CloseEventArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.uuid, &deployment.program_id);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(event_pda, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::CloseEvent(*args),
+        accounts,
+    )
+}
+
+/// Both mints must sign, since they are created by the program
+pub fn add_option(
+    deployment: &Deployment,
+    payer: &Pubkey,
+    yes_mint: &Pubkey,
+    no_mint: &Pubkey,
+    args: &AddOptionArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*payer, true),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+        AccountMeta::new(*yes_mint, true),
+        AccountMeta::new(*no_mint, true),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::AddOption(args.clone()),
+        accounts,
+    )
+}
+
+pub fn fake_create_order(
+    deployment: &Deployment,
+    user: &Pubkey,
+    args: &FakeCreateOrderArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let treasury = get_associated_token_address(&event_pda, &deployment.collateral_mint);
+
+    let user_usdc_ata = get_associated_token_address(user, &deployment.collateral_mint);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_usdc_ata, false),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::FakeCreateOrder(*args),
+        accounts,
+    )
+}
+
+/// The admin signs, the user only receives the refund
+pub fn fake_cancel_order(
+    deployment: &Deployment,
+    admin: &Pubkey,
+    user: &Pubkey,
+    args: &FakeCancelOrderArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let treasury = get_associated_token_address(&event_pda, &deployment.collateral_mint);
+
+    let user_usdc_ata = get_associated_token_address(user, &deployment.collateral_mint);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*admin, true),
+        AccountMeta::new_readonly(*user, false),
+        AccountMeta::new(user_usdc_ata, false),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::FakeCancelOrder(*args),
+        accounts,
+    )
+}
+
+pub fn fake_match_order(
+    deployment: &Deployment,
+    user_yes: &Pubkey,
+    user_no: &Pubkey,
+    token_yes: &Pubkey,
+    token_no: &Pubkey,
+    args: &FakeMatchOrderArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let user_yes_token_ata = get_associated_token_address(user_yes, token_yes);
+
+    let user_no_token_ata = get_associated_token_address(user_no, token_no);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*user_yes, true),
+        AccountMeta::new(user_yes_token_ata, false),
+        AccountMeta::new(*user_no, true),
+        AccountMeta::new(user_no_token_ata, false),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new(*token_yes, false),
+        AccountMeta::new(*token_no, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::FakeMatchOrder(*args),
+        accounts,
+    )
+}
+
+pub fn fake_get_reward(
+    deployment: &Deployment,
+    user: &Pubkey,
+    token: &Pubkey,
+    args: &FakeGetRewardArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let treasury = get_associated_token_address(&event_pda, &deployment.collateral_mint);
+
+    let user_usdc_ata = get_associated_token_address(user, &deployment.collateral_mint);
+    let user_token_ata = get_associated_token_address(user, token);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*user, true),
+        AccountMeta::new(user_usdc_ata, false),
+        AccountMeta::new(user_token_ata, false),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new(treasury, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new(*token, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::FakeGetReward(*args),
+        accounts,
+    )
+}
+
+/// User A sends shares to B, user B sends usdc to A
+pub fn transfer_shares(
+    deployment: &Deployment,
+    user_a: &Pubkey,
+    user_b: &Pubkey,
+    token: &Pubkey,
+    args: &TransferSharesArgs,
+) -> Result<Instruction> {
+    let (event_pda, _) = Event::find_program_address(&args.event_uuid, &deployment.program_id);
+
+    let user_a_usdc_ata = get_associated_token_address(user_a, &deployment.collateral_mint);
+    let user_a_token_ata = get_associated_token_address(user_a, token);
+
+    let user_b_usdc_ata = get_associated_token_address(user_b, &deployment.collateral_mint);
+    let user_b_token_ata = get_associated_token_address(user_b, token);
+
+    let accounts: Vec<AccountMeta> = vec![
+        AccountMeta::new(*user_a, true),
+        AccountMeta::new(user_a_token_ata, false),
+        AccountMeta::new(user_a_usdc_ata, false),
+        AccountMeta::new(*user_b, true),
+        AccountMeta::new(user_b_token_ata, false),
+        AccountMeta::new(user_b_usdc_ata, false),
+        AccountMeta::new(event_pda, false),
+        AccountMeta::new_readonly(*token, false),
+        AccountMeta::new_readonly(deployment.collateral_mint, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_associated_token_account::ID, false),
+    ];
+
+    market_instruction(
+        &deployment.program_id,
+        MarketInstruction::TransferShares(args.clone()),
+        accounts,
+    )
+}
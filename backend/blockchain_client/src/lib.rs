@@ -1,21 +1,23 @@
+use std::sync::Arc;
+
 use anyhow::{Result, anyhow};
 use blockchain_core::{
     accounts::event::Event,
-    instructions::{AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs, FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, MarketInstruction, TransferSharesArgs},
+    instructions::{AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs, FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs},
 };
-use serde_json::{Value, json};
 use solana_client::{
+    client_error::{ClientError, ClientErrorKind},
     nonblocking::rpc_client::RpcClient,
-    rpc_config::{CommitmentConfig, RpcSendTransactionConfig},
+    rpc_config::RpcSendTransactionConfig,
+    rpc_request::RpcError,
 };
 use solana_sdk::{
-    message::{AccountMeta, Instruction, Message},
+    message::AddressLookupTableAccount,
     native_token::LAMPORTS_PER_SOL,
     program_pack::Pack,
     pubkey::Pubkey,
     signature::{Keypair, Signature},
     signer::{EncodableKey, Signer},
-    transaction::Transaction,
 };
 use spl_associated_token_account::{
     get_associated_token_address, instruction::create_associated_token_account,
@@ -23,6 +25,28 @@ use spl_associated_token_account::{
 use spl_token::state::Account as TokenAccount;
 use uuid::Uuid;
 
+pub mod accounts;
+pub mod cluster;
+pub mod compute_budget;
+pub mod faucet;
+pub mod instructions;
+pub mod lookup_table;
+pub mod packing;
+pub mod signer;
+pub mod simulate;
+pub mod submit;
+pub mod subscription;
+
+pub use accounts::OptionBalance;
+pub use cluster::{ClusterProfile, Commitment, Deployment, Explorer};
+pub use compute_budget::{ComputeBudget, FeePolicy};
+pub use faucet::{Credit, Faucet, FaucetError};
+pub use packing::{EventPacker, PackedEventOutcome};
+pub use signer::{DynSigner, InMemoryProvider, ProviderError, SignerProvider};
+pub use simulate::{AccountDiff, AccountSnapshot, Simulation};
+pub use submit::{SendHook, SentStatus, SubmitConfig, TransactionOutcome};
+pub use subscription::{DEFAULT_RPC_WS, Notification, Subscription, Subscriptions};
+
 pub const DEFAULT_RPC_HTTP: &str = "http://127.0.0.1:8899";
 pub const USDC_MINT: Pubkey = solana_sdk::pubkey!("EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v");
 pub const MARKETPLACE_PROGRAM: Pubkey =
@@ -32,16 +56,23 @@ pub const SKIP_PREFLIGHT: bool = false;
 
 pub struct ProfeciaClient {
     pub rpc_client: RpcClient,
-    pub rpc_url: String,
-    pub external_rpc_url: String,
+    pub profile: ClusterProfile,
     pub rpc_config: RpcSendTransactionConfig,
-    pub admin_wallet: Keypair,
+    pub admin_wallet: Box<DynSigner>,
+    pub signers: Arc<dyn SignerProvider>,
+    /// Used to shorten every transaction sent by `submit`
+    pub lookup_tables: Vec<AddressLookupTableAccount>,
+    pub submit_config: SubmitConfig,
 }
 
 impl ProfeciaClient {
-    pub fn new_with_wallet(admin_wallet: Keypair, rpc_url: &str, external_rpc_url: &str) -> Self {
+    pub fn new_with_wallet(admin_wallet: Box<DynSigner>, profile: ClusterProfile) -> Self {
         let rpc_client =
-            RpcClient::new_with_commitment(rpc_url.into(), CommitmentConfig::confirmed());
+            RpcClient::new_with_commitment(profile.rpc_url.clone(), profile.commitment_config());
+        let submit_config = SubmitConfig {
+            commitment: profile.commitment_config(),
+            ..SubmitConfig::default()
+        };
 
         let rpc_config = RpcSendTransactionConfig {
             skip_preflight: SKIP_PREFLIGHT,
@@ -53,65 +84,45 @@ impl ProfeciaClient {
 
         Self {
             rpc_client,
-            rpc_url: rpc_url.into(),
-            external_rpc_url: external_rpc_url.into(),
+            profile,
             rpc_config,
             admin_wallet,
+            signers: Arc::new(InMemoryProvider),
+            lookup_tables: Vec::new(),
+            submit_config,
         }
     }
 
     /// like new() but creates the wallet just for testing
-    pub async fn new_debug(rpc_url: &str, external_rpc_url: &str) -> Result<Self> {
-        let admin_wallet = Keypair::new();
+    pub async fn new_debug(profile: ClusterProfile) -> Result<Self> {
+        let client = Self::new_with_wallet(Box::new(Keypair::new()), profile);
 
-        let rpc_client =
-            RpcClient::new_with_commitment(rpc_url.into(), CommitmentConfig::confirmed());
-
-        let rpc_config = RpcSendTransactionConfig {
-            skip_preflight: SKIP_PREFLIGHT,
-            preflight_commitment: None,
-            encoding: None,
-            max_retries: None,
-            min_context_slot: None,
-        };
-
-        let _sig = rpc_client
-            .request_airdrop(&admin_wallet.pubkey(), LAMPORTS_PER_SOL * 10)
+        let _sig = client
+            .rpc_client
+            .request_airdrop(&client.admin_wallet.pubkey(), LAMPORTS_PER_SOL * 10)
             .await?;
 
-        Ok(Self {
-            rpc_client,
-            rpc_url: rpc_url.into(),
-            external_rpc_url: external_rpc_url.into(),
-            rpc_config,
-            admin_wallet,
-        })
+        Ok(client)
     }
 
-    pub fn new(rpc_url: &str, external_rpc_url: &str) -> Result<Self> {
+    pub fn new(profile: ClusterProfile) -> Result<Self> {
         let wallet_path = std::env::var("ADMIN_WALLET_PATH")
             .unwrap_or_else(|_| "../blockchain_program/PRIVATE_KEY/id.json".to_string());
 
-        let admin_wallet = Keypair::read_from_file(wallet_path).map_err(|e| anyhow!("Error creating admin wallet: {}", e.to_string()))?;
+        let admin_wallet = Keypair::read_from_file(wallet_path).map_err(|e| anyhow!("Error creating admin wallet: {}", e))?;
 
-        let rpc_client =
-            RpcClient::new_with_commitment(rpc_url.into(), CommitmentConfig::confirmed());
+        Ok(Self::new_with_wallet(Box::new(admin_wallet), profile))
+    }
 
-        let rpc_config = RpcSendTransactionConfig {
-            skip_preflight: SKIP_PREFLIGHT,
-            preflight_commitment: None,
-            encoding: None,
-            max_retries: None,
-            min_context_slot: None,
-        };
+    /// Where user wallets are looked up and created. `InMemoryProvider` by default
+    pub fn with_signer_provider(mut self, signers: Arc<dyn SignerProvider>) -> Self {
+        self.signers = signers;
+        self
+    }
 
-        Ok(Self {
-            rpc_client,
-            rpc_url: rpc_url.into(),
-            external_rpc_url: external_rpc_url.into(),
-            rpc_config,
-            admin_wallet,
-        })
+    pub fn with_lookup_tables(mut self, lookup_tables: Vec<AddressLookupTableAccount>) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
     }
 
     /// Create a new event.
@@ -120,155 +131,43 @@ impl ProfeciaClient {
         &self,
         token_keypairs: &[Keypair],
         args: &CreateEventArgs,
-    ) -> Result<Signature> {
-        let instruction_args = MarketInstruction::CreateEvent(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.uuid, &MARKETPLACE_PROGRAM);
-
-        let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        // println!("event PDA is {}", event_pda);
-        // println!("treasury PDA is {}\n\n", treasury);
-
-        let mut accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(self.admin_wallet.pubkey(), true),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new(treasury, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        for option in args.options.values() {
-            accounts.push(AccountMeta::new(option.yes_mint, true));
-            accounts.push(AccountMeta::new(option.no_mint, true));
-        }
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&self.admin_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
+    ) -> Result<TransactionOutcome> {
+        let instruction = instructions::create_event(&self.profile.deployment, &self.admin_wallet.pubkey(), args)?;
 
-        {
-            let mut all_signers: Vec<&dyn Signer> = vec![&self.admin_wallet];
-            all_signers.extend(token_keypairs.iter().map(|k| k as &dyn Signer));
-            transaction.sign(&all_signers[..], recent_blockhash);
-        }
-
-        let signature = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
+        let mut signers: Vec<&(dyn Signer + Sync)> = vec![self.admin_wallet.as_ref()];
+        signers.extend(token_keypairs.iter().map(|k| k as &(dyn Signer + Sync)));
 
-        Ok(signature)
+        self.submit(&[instruction], &self.admin_wallet.pubkey(), &signers).await
     }
 
-    pub async fn close_event(&self, args: &CloseEventArgs) -> Result<Signature> {
-        let instruction_args = MarketInstruction::CloseEvent(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.uuid, &MARKETPLACE_PROGRAM);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(self.admin_wallet.pubkey(), true),
-            AccountMeta::new(event_pda, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&self.admin_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
+    pub async fn close_event(&self, args: &CloseEventArgs) -> Result<TransactionOutcome> {
+        let instruction = instructions::close_event(&self.profile.deployment, &self.admin_wallet.pubkey(), args)?;
 
-        transaction.sign(&[&self.admin_wallet], recent_blockhash);
-
-        let signature = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(signature)
+        self.submit(&[instruction], &self.admin_wallet.pubkey(), &[self.admin_wallet.as_ref()]).await
     }
 
-    /// Creates a random keypair, and airdrops some funds into it
-    pub async fn init_new_wallet(&self) -> Result<Keypair> {
-        let wallet = Keypair::new();
+    /// Creates a wallet with `signers`, and airdrops some funds into it.
+    /// Returns the key to store for it and its address
+    pub async fn init_new_wallet(&self) -> Result<(String, Pubkey)> {
+        let (key, pubkey) = self.signers.create()?;
 
         let _sig = self
             .rpc_client
-            .request_airdrop(&wallet.pubkey(), LAMPORTS_PER_SOL * 10)
-            .await?;
-
-        Ok(wallet)
-    }
-
-    /// amount is in micro-usdc. Assumes the usdc ATA does not exist (basically, if this is the first time airdropping, call create_usdc_ata() first)
-    pub async fn airdrop_usdc(&self, wallet: &Pubkey, amount: u64) -> Result<()> {
-        let body = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "surfnet_setTokenAccount",
-            "params": [
-                wallet.to_string(),
-                USDC_MINT.to_string(),
-                {
-                    "amount": amount,
-                    "closeAuthority": "83astBRguLMdt2h5U1Tpdq5tjFoJ6noeGwaY3mDLVcri",
-                    "delegate": "83astBRguLMdt2h5U1Tpdq5tjFoJ6noeGwaY3mDLVcri",
-                    "delegatedAmount": amount,
-                    "state": "initialized",
-                },
-            ],
-        });
-        let client = reqwest::Client::new();
-        let res = client
-            .post(&self.rpc_url)
-            .header("Content-Type", "application/json")
-            .body(body.to_string())
-            .send()
+            .request_airdrop(&pubkey, LAMPORTS_PER_SOL * 10)
             .await?;
-        let json: Value = res.json().await?;
-        if let Some(error) = json.get("error") {
-            anyhow::bail!(
-                "Error funding: {}",
-                serde_json::to_string(error).unwrap_or_else(|_| "Unknown error".to_string())
-            );
-        }
 
-        Ok(())
+        Ok((key, pubkey))
     }
 
-    pub async fn create_usdc_ata(&self, wallet: &Keypair) -> Result<Signature> {
+    pub async fn create_usdc_ata(&self, wallet: &DynSigner) -> Result<TransactionOutcome> {
         let instruction = create_associated_token_account(
             &wallet.pubkey(),
             &wallet.pubkey(),
-            &USDC_MINT,
+            &self.profile.deployment.collateral_mint,
             &spl_token::id(),
         );
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let tx = Transaction::new_signed_with_payer(
-            &[instruction],
-            Some(&wallet.pubkey()),
-            &[&wallet],
-            recent_blockhash,
-        );
-
-        let sig = self.rpc_client.send_and_confirm_transaction(&tx).await?;
-
-        Ok(sig)
+        self.submit(&[instruction], &wallet.pubkey(), &[wallet]).await
     }
 
     pub async fn fetch_ata(&self, wallet: &Pubkey, token: &Pubkey) -> Result<TokenAccount> {
@@ -281,246 +180,76 @@ impl ProfeciaClient {
         Ok(token_account)
     }
 
+    /// Like `fetch_ata`, but None when the token account was not created yet
+    pub async fn fetch_ata_if_exists(&self, wallet: &Pubkey, token: &Pubkey) -> Result<Option<TokenAccount>> {
+        match self.fetch_ata(wallet, token).await {
+            Ok(token_account) => Ok(Some(token_account)),
+            Err(e) if is_account_not_found(&e, &get_associated_token_address(wallet, token)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     pub async fn fetch_usdc(&self, wallet: &Pubkey) -> Result<TokenAccount> {
-        self.fetch_ata(wallet, &USDC_MINT).await
+        self.fetch_ata(wallet, &self.profile.deployment.collateral_mint).await
     }
 
     pub async fn match_order(
         &self,
-        user_yes_wallet: &Keypair,
-        user_no_wallet: &Keypair,
+        user_yes_wallet: &DynSigner,
+        user_no_wallet: &DynSigner,
         token_yes: &Pubkey,
         token_no: &Pubkey,
         args: &FakeMatchOrderArgs,
-    ) -> Result<Signature> {
-        let instruction_args = MarketInstruction::FakeMatchOrder(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        // let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        // let user_yes_usdc_ata = get_associated_token_address(&user_yes_wallet.pubkey(), &USDC_MINT);
-        let user_yes_token_ata = get_associated_token_address(&user_yes_wallet.pubkey(), token_yes);
-
-        // let user_no_usdc_ata = get_associated_token_address(&user_no_wallet.pubkey(), &USDC_MINT);
-        let user_no_token_ata = get_associated_token_address(&user_no_wallet.pubkey(), token_no);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(user_yes_wallet.pubkey(), true),
-            // AccountMeta::new(user_yes_usdc_ata, false),
-            AccountMeta::new(user_yes_token_ata, false),
-            AccountMeta::new(user_no_wallet.pubkey(), true),
-            // AccountMeta::new(user_no_usdc_ata, false),
-            AccountMeta::new(user_no_token_ata, false),
-            AccountMeta::new(event_pda, false),
-            // AccountMeta::new(treasury, false),
-            AccountMeta::new(*token_yes, false),
-            AccountMeta::new(*token_no, false),
-            // AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&user_yes_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&user_yes_wallet, &user_no_wallet], recent_blockhash);
-
-        let sig = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(sig)
+    ) -> Result<TransactionOutcome> {
+        let instruction = instructions::fake_match_order(
+            &self.profile.deployment,
+            &user_yes_wallet.pubkey(),
+            &user_no_wallet.pubkey(),
+            token_yes,
+            token_no,
+            args,
+        )?;
+
+        self.submit(&[instruction], &user_yes_wallet.pubkey(), &[user_yes_wallet, user_no_wallet]).await
     }
 
-    pub async fn create_order(&self, user: &Keypair, args: &FakeCreateOrderArgs) -> Result<Signature> {
-        let instruction_args = MarketInstruction::FakeCreateOrder(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        let user_usdc_ata = get_associated_token_address(&user.pubkey(), &USDC_MINT);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(user.pubkey(), true),
-            AccountMeta::new(user_usdc_ata, false),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new(treasury, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&user.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&user], recent_blockhash);
+    pub async fn create_order(&self, user: &DynSigner, args: &FakeCreateOrderArgs) -> Result<TransactionOutcome> {
+        let instruction = instructions::fake_create_order(&self.profile.deployment, &user.pubkey(), args)?;
 
-        let sig = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(sig)
+        self.submit(&[instruction], &user.pubkey(), &[user]).await
     }
 
-    pub async fn cancel_order(&self, user: &Pubkey, args: &FakeCancelOrderArgs) -> Result<Signature> {
-        let instruction_args = MarketInstruction::FakeCancelOrder(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        let user_usdc_ata = get_associated_token_address(&user, &USDC_MINT);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(self.admin_wallet.pubkey(), true),
-            AccountMeta::new_readonly(*user, false),
-            AccountMeta::new(user_usdc_ata, false),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new(treasury, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
+    pub async fn cancel_order(&self, user: &Pubkey, args: &FakeCancelOrderArgs) -> Result<TransactionOutcome> {
+        let instruction = instructions::fake_cancel_order(&self.profile.deployment, &self.admin_wallet.pubkey(), user, args)?;
 
-        let message = Message::new(&[instruction], Some(&self.admin_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&self.admin_wallet], recent_blockhash);
-
-        let sig = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(sig)
+        self.submit(&[instruction], &self.admin_wallet.pubkey(), &[self.admin_wallet.as_ref()]).await
     }
 
-    pub async fn get_reward(&self, user: &Keypair, token: &Pubkey, args: &FakeGetRewardArgs) -> Result<Signature> {
-        let instruction_args = MarketInstruction::FakeGetReward(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        let user_usdc_ata = get_associated_token_address(&user.pubkey(), &USDC_MINT);
-        let user_token_ata = get_associated_token_address(&user.pubkey(), &token);
+    pub async fn get_reward(&self, user: &DynSigner, token: &Pubkey, args: &FakeGetRewardArgs) -> Result<TransactionOutcome> {
+        let instruction = instructions::fake_get_reward(&self.profile.deployment, &user.pubkey(), token, args)?;
 
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(user.pubkey(), true),
-            AccountMeta::new(user_usdc_ata, false),
-            AccountMeta::new(user_token_ata, false),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new(treasury, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new(*token, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&user.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&user], recent_blockhash);
-
-        let sig = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(sig)
+        self.submit(&[instruction], &user.pubkey(), &[user]).await
     }
 
-    pub fn derive_event_pubkey(event_id: &Uuid) -> Pubkey {
-        Event::find_program_address(event_id, &MARKETPLACE_PROGRAM).0
+    pub fn event_pubkey(&self, event_id: &Uuid) -> Pubkey {
+        Event::find_program_address(event_id, &self.profile.deployment.program_id).0
     }
 
     pub fn get_account_url(&self, pubkey: &Pubkey) -> String {
-        format!("https://solscan.io/account/{}?cluster=custom&customUrl={}", pubkey.to_string(), self.external_rpc_url)
+        self.profile.account_url(pubkey)
     }
 
     pub fn get_transaction_url(&self, sig: &Signature) -> String {
-        format!("https://solscan.io/tx/{}?cluster=custom&customUrl={}", sig.to_string(), self.external_rpc_url)
+        self.profile.transaction_url(sig)
     }
 
     pub async fn create_empty_event(
         &self,
         args: &CreateEmptyEventArgs,
-    ) -> Result<Signature> {
-        let instruction_args = MarketInstruction::CreateEmptyEvent(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
+    ) -> Result<TransactionOutcome> {
+        let instruction = instructions::create_empty_event(&self.profile.deployment, &self.admin_wallet.pubkey(), args)?;
 
-        let (event_pda, _) = Event::find_program_address(&args.uuid, &MARKETPLACE_PROGRAM);
-
-        let treasury = get_associated_token_address(&event_pda, &USDC_MINT);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(self.admin_wallet.pubkey(), true),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new(treasury, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&self.admin_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&self.admin_wallet], recent_blockhash);
-
-        let signature = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(signature)
+        self.submit(&[instruction], &self.admin_wallet.pubkey(), &[self.admin_wallet.as_ref()]).await
     }
 
     pub async fn add_option(
@@ -528,95 +257,38 @@ impl ProfeciaClient {
         yes_token: &Keypair,
         no_token: &Keypair,
         args: &AddOptionArgs,
-    ) -> Result<Signature> {
-        let instruction_args = MarketInstruction::AddOption(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(self.admin_wallet.pubkey(), true),
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-            AccountMeta::new(yes_token.pubkey(), true),
-            AccountMeta::new(no_token.pubkey(), true),
-        ];
-
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&self.admin_wallet.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[&self.admin_wallet, yes_token, no_token], recent_blockhash);
-
-        let signature = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(signature)
+    ) -> Result<TransactionOutcome> {
+        let instruction = instructions::add_option(
+            &self.profile.deployment,
+            &self.admin_wallet.pubkey(),
+            &yes_token.pubkey(),
+            &no_token.pubkey(),
+            args,
+        )?;
+
+        self.submit(&[instruction], &self.admin_wallet.pubkey(), &[self.admin_wallet.as_ref(), yes_token, no_token]).await
     }
 
     /// User A sends shares to B, user B sends usdc to A
     pub async fn trasfer_shares(
         &self,
-        user_a: &Keypair,
-        user_b: &Keypair,
+        user_a: &DynSigner,
+        user_b: &DynSigner,
         token: &Pubkey,
         args: &TransferSharesArgs,
-    ) -> Result<Signature> {
-        let instruction_args = MarketInstruction::TransferShares(args.clone());
-
-        let instruction_bytes = wincode::serialize(&instruction_args)?;
-
-        let (event_pda, _) = Event::find_program_address(&args.event_uuid, &MARKETPLACE_PROGRAM);
-
-        let user_a_usdc_ata = get_associated_token_address(&user_a.pubkey(), &USDC_MINT);
-        let user_a_token_ata = get_associated_token_address(&user_a.pubkey(), token);
-
-        let user_b_usdc_ata = get_associated_token_address(&user_b.pubkey(), &USDC_MINT);
-        let user_b_token_ata = get_associated_token_address(&user_b.pubkey(), token);
-
-        let accounts: Vec<AccountMeta> = vec![
-            AccountMeta::new(user_a.pubkey(), true),
-            AccountMeta::new(user_a_token_ata, false),
-            AccountMeta::new(user_a_usdc_ata, false),
-
-            AccountMeta::new(user_b.pubkey(), true),
-            AccountMeta::new(user_b_token_ata, false),
-            AccountMeta::new(user_b_usdc_ata, false),
-
-            AccountMeta::new(event_pda, false),
-            AccountMeta::new_readonly(*token, false),
-            AccountMeta::new_readonly(USDC_MINT, false),
-            AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
-            AccountMeta::new_readonly(spl_token::ID, false),
-            AccountMeta::new_readonly(spl_associated_token_account::ID, false),
-        ];
+    ) -> Result<TransactionOutcome> {
+        let instruction = instructions::transfer_shares(&self.profile.deployment, &user_a.pubkey(), &user_b.pubkey(), token, args)?;
 
-        let recent_blockhash = self.rpc_client.get_latest_blockhash().await?;
-
-        let instruction =
-            Instruction::new_with_bytes(MARKETPLACE_PROGRAM, instruction_bytes.as_ref(), accounts);
-
-        let message = Message::new(&[instruction], Some(&user_a.pubkey()));
-
-        let mut transaction = Transaction::new_unsigned(message);
-
-        transaction.sign(&[user_a, user_b], recent_blockhash);
-
-        let signature = self
-            .rpc_client
-            .send_transaction_with_config(&transaction, self.rpc_config)
-            .await?;
-
-        Ok(signature)
+        self.submit(&[instruction], &user_a.pubkey(), &[user_a, user_b]).await
     }
 }
+
+/// `get_account` reports a missing account with exactly this message, and failed requests with
+/// the same prefix followed by the error
+fn is_account_not_found(error: &anyhow::Error, pubkey: &Pubkey) -> bool {
+    matches!(
+        error.downcast_ref::<ClientError>().map(ClientError::kind),
+        Some(ClientErrorKind::RpcError(RpcError::ForUser(message)))
+            if *message == format!("AccountNotFound: pubkey={pubkey}")
+    )
+}
@@ -0,0 +1,43 @@
+//! Address lookup tables, which `submit` uses to shorten transactions (see `packing`).
+
+use anyhow::{Result, anyhow};
+use solana_address_lookup_table_interface::{
+    instruction::{create_lookup_table, extend_lookup_table},
+    state::AddressLookupTable,
+};
+use solana_client::rpc_config::CommitmentConfig;
+use solana_sdk::{message::AddressLookupTableAccount, pubkey::Pubkey, signer::Signer};
+
+use crate::ProfeciaClient;
+
+impl ProfeciaClient {
+    /// Creates a table holding `addresses`, with the admin wallet as authority.
+    /// It can only be used from the next slot on
+    pub async fn create_lookup_table(&self, addresses: Vec<Pubkey>) -> Result<Pubkey> {
+        let admin = self.admin_wallet.pubkey();
+        let recent_slot = self
+            .rpc_client
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await?;
+
+        let (create, table) = create_lookup_table(admin, admin, recent_slot);
+        let extend = extend_lookup_table(table, admin, Some(admin), addresses);
+
+        self.submit(&[create, extend], &admin, &[self.admin_wallet.as_ref()])
+            .await?
+            .confirmed()?;
+
+        Ok(table)
+    }
+
+    pub async fn fetch_lookup_table(&self, key: &Pubkey) -> Result<AddressLookupTableAccount> {
+        let account = self.rpc_client.get_account(key).await?;
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|e| anyhow!("Account {} is not a lookup table: {}", key, e))?;
+
+        Ok(AddressLookupTableAccount {
+            key: *key,
+            addresses: table.addresses.to_vec(),
+        })
+    }
+}
@@ -1,179 +1,818 @@
-use std::{collections::HashMap, env};
-
-use anyhow::Result;
-use blockchain_client::{DEFAULT_RPC_HTTP, ProfeciaClient, USDC_MINT};
+//! Admin CLI for the marketplace program.
+//!
+//! Every command prints a human readable summary, or a single JSON document with `--output json`
+//! so that it can be piped into other tools.
+
+use std::{collections::HashMap, path::PathBuf, str::FromStr, sync::Arc};
+
+use anyhow::{Context, Result, anyhow, bail};
+use blockchain_client::{
+    ClusterProfile, DynSigner, Faucet, ProfeciaClient,
+    faucet::{MintFaucet, SurfpoolFaucet, mint_account_json, usdc_balance},
+    packing::lookup_table_addresses,
+    signer::{Keystore, KeystoreProvider, remote},
+};
 use blockchain_core::{
-    accounts::event::EventOption,
-    instructions::{CloseEventArgs, CreateEventArgs, FakeCancelOrderArgs, FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs},
+    accounts::event::{Event, EventOption, EventState},
+    instructions::{AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, FakeGetRewardArgs},
+    money::{Cents, MicroUsdc},
 };
+use clap::{Parser, Subcommand, ValueEnum};
+use serde::Deserialize;
+use serde_json::{Value, json};
 use solana_sdk::{
-    signature::Keypair,
-    signer::Signer,
+    native_token::LAMPORTS_PER_SOL,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::{EncodableKey, Signer},
 };
-use spl_associated_token_account::
-    get_associated_token_address
-;
+use spl_associated_token_account::get_associated_token_address;
+use tokio::net::UnixListener;
 use uuid::Uuid;
 
-#[tokio::main]
-pub async fn main() -> Result<()> {
-    let rpc_url = env::var("RPC_HTTP").unwrap_or_else(|_| DEFAULT_RPC_HTTP.to_string());
-    let external_rpc_url = env::var("EXTERNAL_RPC_HTTP").unwrap_or_else(|_| DEFAULT_RPC_HTTP.to_string());
+#[derive(Parser)]
+#[command(about = "Manage the events of the marketplace program")]
+struct Cli {
+    /// Preset name (surfpool, localnet) or TOML file. The RPC_URL, WS_URL, EXTERNAL_RPC_URL,
+    /// PROGRAM_ID, COLLATERAL_MINT, COMMITMENT and EXPLORER variables override its fields
+    #[arg(long, env = "CLUSTER_PROFILE", global = true)]
+    cluster: Option<String>,
+    /// Overrides the RPC of the cluster profile
+    #[arg(long, global = true)]
+    rpc_url: Option<String>,
+    /// Used in explorer links. Overrides the one of the cluster profile
+    #[arg(long, global = true)]
+    external_rpc_url: Option<String>,
+    /// Keypair or keystore file of the admin, which pays for and signs every transaction
+    #[arg(
+        long = "wallet",
+        env = "ADMIN_WALLET_PATH",
+        default_value = "../blockchain_program/PRIVATE_KEY/id.json",
+        global = true
+    )]
+    admin_wallet: PathBuf,
+    /// Passphrase of the keystore files given as --wallet or --holder
+    #[arg(
+        long,
+        env = "KEYSTORE_PASSPHRASE",
+        hide_env_values = true,
+        global = true
+    )]
+    passphrase: Option<String>,
+    /// Overrides the program of the cluster profile
+    #[arg(long, global = true)]
+    program_id: Option<Pubkey>,
+    /// Overrides the collateral mint of the cluster profile
+    #[arg(long, global = true)]
+    collateral_mint: Option<Pubkey>,
+    /// Lookup tables to shorten transactions with, see `lookup-table create`
+    #[arg(
+        long = "lookup-table",
+        env = "LOOKUP_TABLES",
+        value_delimiter = ',',
+        global = true
+    )]
+    lookup_tables: Vec<Pubkey>,
+    #[arg(long, value_enum, default_value_t = Output::Human, global = true)]
+    output: Output,
+    #[command(subcommand)]
+    command: Command,
+}
 
-    let profecia_client = ProfeciaClient::new_debug(rpc_url.as_str(), external_rpc_url.as_str()).await?;
+#[derive(Clone, Copy, ValueEnum)]
+enum Output {
+    Human,
+    Json,
+}
 
-    println!("GENERATED WALLET {}", profecia_client.admin_wallet.pubkey());
-    println!(
-        "USDC ATA {}",
-        get_associated_token_address(&profecia_client.admin_wallet.pubkey(), &USDC_MINT)
-    );
+#[derive(Subcommand)]
+enum Command {
+    /// Creates, closes, resolves and inspects events
+    #[command(subcommand)]
+    Event(EventCommand),
+    /// Adds test USDC to a wallet
+    AirdropUsdc {
+        wallet: Pubkey,
+        /// In USDC, e.g. 12.50
+        #[arg(value_parser = parse_cents)]
+        amount: Cents,
+        #[arg(long, value_enum, default_value_t = FaucetKind::Surfpool)]
+        faucet: FaucetKind,
+    },
+    /// Writes a test USDC mint whose authority is the given wallet, to be loaded with
+    /// `solana-test-validator --account <mint> <file>` and used with `--faucet mint`
+    MintAccount {
+        authority: Pubkey,
+        /// Defaults to the collateral mint of the cluster profile
+        #[arg(long)]
+        mint: Option<Pubkey>,
+    },
+    /// Requests an airdrop of SOL. Only works on local validators and devnet
+    FundSol {
+        wallet: Pubkey,
+        /// In SOL, e.g. 1.5
+        #[arg(value_parser = parse_lamports)]
+        amount: u64,
+    },
+    /// Prints addresses derived from the program and the given arguments
+    #[command(subcommand)]
+    Address(AddressCommand),
+    /// Manages encrypted keystores and serves them to other processes
+    #[command(subcommand)]
+    Signer(SignerCommand),
+    /// Manages the address lookup tables used with --lookup-table
+    #[command(subcommand)]
+    LookupTable(LookupTableCommand),
+}
+
+#[derive(Subcommand)]
+enum EventCommand {
+    /// Creates every event of a file in the `events-examples.json` format, with one option per market
+    Create {
+        file: PathBuf,
+    },
+    Close {
+        uuid: Uuid,
+    },
+    /// Closes the event if still open, then pays out the winning shares of each holder
+    Resolve {
+        uuid: Uuid,
+        #[arg(long)]
+        option: Uuid,
+        #[arg(long, value_enum)]
+        winner: Winner,
+        /// Keypair or keystore files of the users holding shares. They sign the payout
+        #[arg(long = "holder")]
+        holders: Vec<PathBuf>,
+    },
+    /// Prints the event account and the USDC held by its treasury
+    Show {
+        uuid: Uuid,
+    },
+    List,
+}
+
+#[derive(Subcommand)]
+enum AddressCommand {
+    Event {
+        uuid: Uuid,
+    },
+    Treasury {
+        uuid: Uuid,
+    },
+    /// Associated token account of a wallet
+    Ata {
+        wallet: Pubkey,
+        /// Defaults to the collateral mint of the cluster profile
+        #[arg(long)]
+        mint: Option<Pubkey>,
+    },
+}
 
-    let mut options = HashMap::new();
-    let mut token_keypairs = Vec::new();
+#[derive(Subcommand)]
+enum SignerCommand {
+    /// Encrypts a keypair file into the keystore directory with --passphrase
+    Import {
+        keypair: PathBuf,
+        #[arg(long)]
+        keystore_dir: PathBuf,
+    },
+    /// Signs with the keystores of the directory for whoever connects to the socket
+    Serve {
+        #[arg(long)]
+        socket: PathBuf,
+        #[arg(long)]
+        keystore_dir: PathBuf,
+    },
+}
 
-    let some_option_uuid = Uuid::new_v4();
+#[derive(Subcommand)]
+enum LookupTableCommand {
+    /// Creates a table with the programs and collateral mint used by every event, plus the
+    /// given addresses. The admin wallet is its authority
+    Create {
+        #[arg(long = "address")]
+        addresses: Vec<Pubkey>,
+    },
+    Show {
+        table: Pubkey,
+    },
+}
 
-    {
-        let yes_mint = Keypair::new();
-        let no_mint = Keypair::new();
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum FaucetKind {
+    /// The surfpool cheatcode
+    Surfpool,
+    /// Mint with the admin wallet, which must be the mint authority
+    Mint,
+}
 
-        options.insert(
-            some_option_uuid,
-            EventOption {
-                option_desc: "Some option #1".into(),
-                yes_mint: (*yes_mint.pubkey().as_array()).into(),
-                no_mint: (*no_mint.pubkey().as_array()).into(),
-            },
-        );
+#[derive(Clone, Copy, ValueEnum)]
+enum Winner {
+    Yes,
+    No,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExampleEvent {
+    display_name: String,
+    markets: Vec<ExampleMarket>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ExampleMarket {
+    display_name: String,
+}
+
+/// What a command prints, in both formats
+struct Report {
+    human: String,
+    json: Value,
+}
 
-        token_keypairs.push(yes_mint);
-        token_keypairs.push(no_mint);
+impl Command {
+    /// Commands that only read or use cheat RPCs can run without the admin keypair
+    fn needs_wallet(&self) -> bool {
+        matches!(
+            self,
+            Command::Event(
+                EventCommand::Create { .. }
+                    | EventCommand::Close { .. }
+                    | EventCommand::Resolve { .. }
+            ) | Command::AirdropUsdc {
+                faucet: FaucetKind::Mint,
+                ..
+            } | Command::LookupTable(LookupTableCommand::Create { .. })
+        )
     }
-    {
-        let yes_mint = Keypair::new();
-        let no_mint = Keypair::new();
-
-        options.insert(
-            Uuid::new_v4(),
-            EventOption {
-                option_desc: "Some option #2".into(),
-                yes_mint: (*yes_mint.pubkey().as_array()).into(),
-                no_mint: (*no_mint.pubkey().as_array()).into(),
-            },
-        );
+}
+
+#[tokio::main]
+pub async fn main() -> Result<()> {
+    let cli = Cli::parse();
 
-        token_keypairs.push(yes_mint);
-        token_keypairs.push(no_mint);
+    let passphrase = cli.passphrase.as_deref();
+    let wallet = if cli.command.needs_wallet() {
+        read_signer(&cli.admin_wallet, passphrase)?
+    } else {
+        Box::new(Keypair::new())
+    };
+    let mut client = ProfeciaClient::new_with_wallet(wallet, cluster_profile(&cli)?);
+    let mut lookup_tables = Vec::with_capacity(cli.lookup_tables.len());
+    for table in &cli.lookup_tables {
+        lookup_tables.push(
+            client
+                .fetch_lookup_table(table)
+                .await
+                .with_context(|| format!("Fetching lookup table {}", table))?,
+        );
     }
-    let event_uuid = Uuid::new_v4();
-    let args = CreateEventArgs {
-        uuid: event_uuid,
-        description: "Some event".into(),
-        options: options.clone(),
+    client = client.with_lookup_tables(lookup_tables);
+
+    let report = match cli.command {
+        Command::Event(EventCommand::Create { file }) => create_events(&client, &file).await?,
+        Command::Event(EventCommand::Close { uuid }) => close_event(&client, uuid).await?,
+        Command::Event(EventCommand::Resolve {
+            uuid,
+            option,
+            winner,
+            holders,
+        }) => resolve_event(&client, uuid, option, winner, &holders, passphrase).await?,
+        Command::Event(EventCommand::Show { uuid }) => show_event(&client, uuid).await?,
+        Command::Event(EventCommand::List) => list_events(&client).await?,
+        Command::AirdropUsdc {
+            wallet,
+            amount,
+            faucet,
+        } => airdrop_usdc(&client, wallet, amount, faucet).await?,
+        Command::MintAccount { authority, mint } => {
+            let mint = mint.unwrap_or(client.profile.deployment.collateral_mint);
+            Report {
+                human: serde_json::to_string_pretty(&mint_account_json(&mint, &authority))?,
+                json: mint_account_json(&mint, &authority),
+            }
+        }
+        Command::FundSol { wallet, amount } => fund_sol(&client, wallet, amount).await?,
+        Command::Address(command) => address(&client, command),
+        Command::Signer(command) => signer(command, passphrase).await?,
+        Command::LookupTable(command) => lookup_table(&client, command).await?,
     };
 
-    let sig = profecia_client.create_event(&token_keypairs, &args).await?;
+    match cli.output {
+        Output::Human => println!("{}", report.human),
+        Output::Json => println!("{}", serde_json::to_string_pretty(&report.json)?),
+    }
+
+    Ok(())
+}
 
-    println!("Sig for creating marketplace: {}", sig);
+async fn create_events(client: &ProfeciaClient, file: &PathBuf) -> Result<Report> {
+    let content =
+        std::fs::read_to_string(file).with_context(|| format!("Reading {}", file.display()))?;
+    let events: Vec<ExampleEvent> =
+        serde_json::from_str(&content).with_context(|| format!("Parsing {}", file.display()))?;
+
+    let mut human = Vec::new();
+    let mut json_events = Vec::new();
+
+    for example in events {
+        let uuid = Uuid::new_v4();
+        let markets: Vec<(Uuid, String, Keypair, Keypair)> = example
+            .markets
+            .into_iter()
+            .map(|market| {
+                (
+                    Uuid::new_v4(),
+                    market.display_name,
+                    Keypair::new(),
+                    Keypair::new(),
+                )
+            })
+            .collect();
+
+        let mut packer = client.event_packer(CreateEmptyEventArgs {
+            uuid,
+            description: example.display_name.clone(),
+        });
+        for (option_uuid, display_name, yes_mint, no_mint) in &markets {
+            packer = packer.add_option(
+                yes_mint,
+                no_mint,
+                AddOptionArgs {
+                    event_uuid: uuid,
+                    option_uuid: *option_uuid,
+                    option_info: EventOption {
+                        option_desc: display_name.clone(),
+                        yes_mint: yes_mint.pubkey(),
+                        no_mint: no_mint.pubkey(),
+                    },
+                },
+            );
+        }
+
+        let outcome = client.create_packed_event(&packer).await?;
+        for chunk in &outcome.chunks {
+            if let Some(error) = &chunk.error {
+                bail!("Creating event \"{}\": {}", example.display_name, error);
+            }
+            if let Some(outcome) = &chunk.outcome {
+                outcome
+                    .clone()
+                    .confirmed()
+                    .with_context(|| format!("Creating event \"{}\"", example.display_name))?;
+            }
+        }
+        let signature = *outcome.chunks[0]
+            .outcome
+            .as_ref()
+            .expect("The first chunk is always sent")
+            .signature();
+        let landed: HashMap<Uuid, Signature> = outcome.landed().collect();
+
+        human.push(format!(
+            "Created event {} \"{}\" in {} transactions",
+            uuid,
+            example.display_name,
+            outcome.chunks.len()
+        ));
+        human.push(format!("  account: {}", client.event_pubkey(&uuid)));
+        human.push(format!("  tx: {}", client.get_transaction_url(&signature)));
+
+        let mut json_options = Vec::new();
+        for (option_uuid, display_name, yes_mint, no_mint) in &markets {
+            let signature = landed[option_uuid];
+
+            human.push(format!(
+                "  option {} \"{}\" (yes {}, no {})",
+                option_uuid,
+                display_name,
+                yes_mint.pubkey(),
+                no_mint.pubkey()
+            ));
+            json_options.push(json!({
+                "uuid": option_uuid,
+                "description": display_name,
+                "yesMint": yes_mint.pubkey().to_string(),
+                "noMint": no_mint.pubkey().to_string(),
+                "signature": signature.to_string(),
+            }));
+        }
+
+        json_events.push(json!({
+            "uuid": uuid,
+            "description": example.display_name,
+            "account": client.event_pubkey(&uuid).to_string(),
+            "signature": signature.to_string(),
+            "options": json_options,
+        }));
+    }
 
-    let args = CloseEventArgs { uuid: args.uuid };
+    Ok(Report {
+        human: human.join("\n"),
+        json: Value::Array(json_events),
+    })
+}
 
-    let sig = profecia_client.close_event(&args).await?;
+async fn close_event(client: &ProfeciaClient, uuid: Uuid) -> Result<Report> {
+    let signature = client
+        .close_event(&CloseEventArgs { uuid })
+        .await?
+        .confirmed()?;
+
+    Ok(Report {
+        human: format!(
+            "Closed event {}\n  tx: {}",
+            uuid,
+            client.get_transaction_url(&signature)
+        ),
+        json: json!({ "uuid": uuid, "signature": signature.to_string() }),
+    })
+}
 
-    println!("Sig for closing marketplace: {}", sig);
+async fn resolve_event(
+    client: &ProfeciaClient,
+    uuid: Uuid,
+    option_uuid: Uuid,
+    winner: Winner,
+    holders: &[PathBuf],
+    passphrase: Option<&str>,
+) -> Result<Report> {
+    let event = client.fetch_event(&uuid).await?;
+    let option = event
+        .options
+        .get(&option_uuid)
+        .ok_or_else(|| anyhow!("Event {} has no option {}", uuid, option_uuid))?;
+    let winning_mint = match winner {
+        Winner::Yes => option.yes_mint,
+        Winner::No => option.no_mint,
+    };
 
-    println!("Airdropping 1000 USDC");
-    let sig = profecia_client
-        .create_usdc_ata(&profecia_client.admin_wallet)
-        .await?;
-    println!("sig: {}", sig);
+    let mut human = Vec::new();
+    let mut json_payouts = Vec::new();
 
-    profecia_client
-        .airdrop_usdc(&profecia_client.admin_wallet.pubkey(), 1000000 * 1000)
-        .await?;
+    let close_signature = if matches!(event.state, EventState::NotFinished) {
+        let signature = client
+            .close_event(&CloseEventArgs { uuid })
+            .await?
+            .confirmed()?;
+        human.push(format!("Closed event {}", uuid));
+        Some(signature.to_string())
+    } else {
+        None
+    };
 
-    println!(
-        "info: {:#?}",
-        profecia_client
-            .fetch_ata(&profecia_client.admin_wallet.pubkey(), &USDC_MINT)
+    for path in holders {
+        let holder = read_signer(path, passphrase)?;
+        let balance = client
+            .fetch_option_balances(&holder.pubkey(), &uuid)
             .await?
-    );
+            .into_iter()
+            .find(|balance| balance.option_uuid == option_uuid)
+            .ok_or_else(|| anyhow!("Event {} has no option {}", uuid, option_uuid))?;
+        let num_shares = match winner {
+            Winner::Yes => balance.yes,
+            Winner::No => balance.no,
+        };
+
+        if num_shares.0 == 0 {
+            human.push(format!("{} holds no winning shares", holder.pubkey()));
+            continue;
+        }
+
+        let signature = client
+            .get_reward(
+                holder.as_ref(),
+                &winning_mint,
+                &FakeGetRewardArgs {
+                    event_uuid: uuid,
+                    option_uuid,
+                    num_shares,
+                },
+            )
+            .await?
+            .confirmed()
+            .with_context(|| format!("Paying out {}", holder.pubkey()))?;
+
+        human.push(format!(
+            "Paid {} shares to {}\n  tx: {}",
+            num_shares.0,
+            holder.pubkey(),
+            client.get_transaction_url(&signature)
+        ));
+        json_payouts.push(json!({
+            "holder": holder.pubkey().to_string(),
+            "shares": num_shares,
+            "signature": signature.to_string(),
+        }));
+    }
 
-    // create two clients
-    let client_yes = profecia_client.init_new_wallet().await?;
-    let client_no = profecia_client.init_new_wallet().await?;
-    profecia_client.create_usdc_ata(&client_yes).await?;
-    profecia_client.create_usdc_ata(&client_no).await?;
-    profecia_client
-        .airdrop_usdc(&client_yes.pubkey(), 100 * 1000000)
-        .await?;
-    profecia_client
-        .airdrop_usdc(&client_no.pubkey(), 100 * 1000000)
-        .await?;
-
-    println!("Finished airdrops");
-
-    // 60 cent yes, 40 cent no
-    let yes_price = 60 * 10000;
-    let no_price = 40 * 10000;
-
-    // create order (will just transfer some usdc)
-    let create_order_yes = FakeCreateOrderArgs {
-        event_uuid,
-        option_uuid: some_option_uuid,
-        num_shares: 5,
-        price_per_share: yes_price
+    Ok(Report {
+        human: human.join("\n"),
+        json: json!({
+            "uuid": uuid,
+            "option": option_uuid,
+            "winningMint": winning_mint.to_string(),
+            "closeSignature": close_signature,
+            "payouts": json_payouts,
+        }),
+    })
+}
+
+async fn show_event(client: &ProfeciaClient, uuid: Uuid) -> Result<Report> {
+    let pubkey = client.event_pubkey(&uuid);
+    let event = client.fetch_event_by_pubkey(&pubkey).await?;
+    let treasury =
+        get_associated_token_address(&pubkey, &client.profile.deployment.collateral_mint);
+    // the treasury is only created with the first order
+    let treasury_balance = match client.fetch_usdc(&pubkey).await {
+        Ok(account) => MicroUsdc(account.amount),
+        Err(_) => MicroUsdc(0),
     };
-    let sig = profecia_client.create_order(&client_yes, &create_order_yes).await?;
-    println!("buy order for yes {}", sig);
-
-    // cancel order, then buy again
-    let cancel_order_yes = FakeCancelOrderArgs {
-        event_uuid,
-        option_uuid: some_option_uuid,
-        num_shares: 5,
-        price_per_share: yes_price
+
+    let mut human = vec![
+        describe_event(&pubkey, &event),
+        format!("  account: {}", client.get_account_url(&pubkey)),
+        format!(
+            "  treasury: {} ({} USDC)",
+            treasury,
+            format_usdc(treasury_balance)
+        ),
+    ];
+    human.extend(event.options.iter().map(|(option_uuid, option)| {
+        format!(
+            "  option {} \"{}\" (yes {}, no {})",
+            option_uuid, option.option_desc, option.yes_mint, option.no_mint
+        )
+    }));
+
+    let mut json = event_json(&pubkey, &event);
+    json["treasury"] = json!({
+        "address": treasury.to_string(),
+        "microUsdc": treasury_balance,
+    });
+
+    Ok(Report {
+        human: human.join("\n"),
+        json,
+    })
+}
+
+async fn list_events(client: &ProfeciaClient) -> Result<Report> {
+    let events = client.list_events().await?;
+
+    Ok(Report {
+        human: events
+            .iter()
+            .map(|(pubkey, event)| describe_event(pubkey, event))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        json: events
+            .iter()
+            .map(|(pubkey, event)| event_json(pubkey, event))
+            .collect(),
+    })
+}
+
+async fn airdrop_usdc(
+    client: &ProfeciaClient,
+    wallet: Pubkey,
+    amount: Cents,
+    faucet: FaucetKind,
+) -> Result<Report> {
+    let amount = amount
+        .to_micro_usdc()
+        .ok_or_else(|| anyhow!("Amount is too large"))?;
+    let faucet = match faucet {
+        FaucetKind::Surfpool => Faucet::new(Box::new(SurfpoolFaucet)),
+        FaucetKind::Mint => Faucet::new(Box::new(MintFaucet)),
     };
-    let sig = profecia_client.cancel_order(&client_yes.pubkey(), &cancel_order_yes).await?;
-    println!("cancel order for yes {}", sig);
+    let credit = faucet.credit(client, &wallet, amount).await?;
+    let balance = usdc_balance(client, &wallet).await?;
+
+    let mut human = format!(
+        "Added {} USDC to {}, balance is now {}",
+        format_usdc(amount),
+        wallet,
+        format_usdc(balance)
+    );
+    if let Some(signature) = credit.signature {
+        human.push_str(&format!(
+            "\n  tx: {}",
+            client.get_transaction_url(&signature)
+        ));
+    }
 
-    let sig = profecia_client.create_order(&client_yes, &create_order_yes).await?;
-    println!("buy order for yes {}", sig);
+    Ok(Report {
+        human,
+        json: json!({
+            "wallet": wallet.to_string(),
+            "ata": get_associated_token_address(&wallet, &client.profile.deployment.collateral_mint).to_string(),
+            "microUsdc": amount,
+            "balance": balance,
+            "signature": credit.signature.map(|signature| signature.to_string()),
+        }),
+    })
+}
+
+async fn fund_sol(client: &ProfeciaClient, wallet: Pubkey, lamports: u64) -> Result<Report> {
+    let signature = client.rpc_client.request_airdrop(&wallet, lamports).await?;
+
+    Ok(Report {
+        human: format!(
+            "Requested {} SOL for {}\n  tx: {}",
+            lamports as f64 / LAMPORTS_PER_SOL as f64,
+            wallet,
+            client.get_transaction_url(&signature)
+        ),
+        json: json!({
+            "wallet": wallet.to_string(),
+            "lamports": lamports,
+            "signature": signature.to_string(),
+        }),
+    })
+}
 
-    let create_order_no = FakeCreateOrderArgs {
-        event_uuid,
-        option_uuid: some_option_uuid,
-        num_shares: 5,
-        price_per_share: no_price
+fn address(client: &ProfeciaClient, command: AddressCommand) -> Report {
+    let address = match command {
+        AddressCommand::Event { uuid } => client.event_pubkey(&uuid),
+        AddressCommand::Treasury { uuid } => get_associated_token_address(
+            &client.event_pubkey(&uuid),
+            &client.profile.deployment.collateral_mint,
+        ),
+        AddressCommand::Ata { wallet, mint } => get_associated_token_address(
+            &wallet,
+            &mint.unwrap_or(client.profile.deployment.collateral_mint),
+        ),
     };
-    let sig = profecia_client.create_order(&client_no, &create_order_no).await?;
-    println!("buy order for no {}", sig);
-
-    // get the first option in the map
-    // let option = options.get(&some_option_uuid).unwrap();
-    let yes = &token_keypairs[0];
-    let no = &token_keypairs[1];
-
-    let args = FakeMatchOrderArgs {
-        event_uuid,
-        option_uuid: some_option_uuid,
-        num_shares: 5,
+
+    Report {
+        human: address.to_string(),
+        json: json!({ "address": address.to_string() }),
+    }
+}
+
+async fn lookup_table(client: &ProfeciaClient, command: LookupTableCommand) -> Result<Report> {
+    let table = match command {
+        LookupTableCommand::Create { addresses } => {
+            let mut all = lookup_table_addresses(&client.profile.deployment);
+            for address in addresses {
+                if !all.contains(&address) {
+                    all.push(address);
+                }
+            }
+            client.create_lookup_table(all).await?
+        }
+        LookupTableCommand::Show { table } => table,
     };
+    let addresses = client.fetch_lookup_table(&table).await?.addresses;
+
+    let mut human = vec![table.to_string()];
+    human.extend(addresses.iter().map(|address| format!("  {}", address)));
+
+    Ok(Report {
+        human: human.join("\n"),
+        json: json!({
+            "table": table.to_string(),
+            "addresses": addresses.iter().map(ToString::to_string).collect::<Vec<_>>(),
+        }),
+    })
+}
 
-    let sig = profecia_client
-        .match_order(&client_yes, &client_no, &yes.pubkey(), &no.pubkey(), &args)
-        .await?;
+fn describe_event(pubkey: &Pubkey, event: &Event) -> String {
+    format!(
+        "{} \"{}\" [{:?}] {} options ({})",
+        event.uuid,
+        event.description,
+        event.state,
+        event.options.len(),
+        pubkey
+    )
+}
+
+fn event_json(pubkey: &Pubkey, event: &Event) -> Value {
+    json!({
+        "uuid": event.uuid,
+        "account": pubkey.to_string(),
+        "description": event.description,
+        "finished": matches!(event.state, EventState::Finished),
+        "options": event.options.iter().map(|(option_uuid, option)| json!({
+            "uuid": option_uuid,
+            "description": option.option_desc,
+            "yesMint": option.yes_mint.to_string(),
+            "noMint": option.no_mint.to_string(),
+        })).collect::<Vec<_>>(),
+    })
+}
+
+async fn signer(command: SignerCommand, passphrase: Option<&str>) -> Result<Report> {
+    let passphrase = passphrase.ok_or_else(|| anyhow!("A --passphrase is required"))?;
+
+    match command {
+        SignerCommand::Import {
+            keypair,
+            keystore_dir,
+        } => {
+            let keypair = read_keypair(&keypair)?;
+            let key = KeystoreProvider::new(&keystore_dir, passphrase).import(&keypair)?;
+
+            Ok(Report {
+                human: format!("Imported {} into {}", key, keystore_dir.display()),
+                json: json!({ "key": key }),
+            })
+        }
+        SignerCommand::Serve {
+            socket,
+            keystore_dir,
+        } => {
+            let provider = Arc::new(KeystoreProvider::new(&keystore_dir, passphrase));
+            // a socket left behind by a previous run would make bind fail
+            let _ = std::fs::remove_file(&socket);
+            let listener = UnixListener::bind(&socket)?;
+            eprintln!("Serving {} on {}", keystore_dir.display(), socket.display());
+            remote::serve(listener, provider).await?;
+            unreachable!("serve only returns on errors")
+        }
+    }
+}
 
-    println!("Match order sig: {}", sig);
+/// From --cluster and the environment, then the flags that override it
+fn cluster_profile(cli: &Cli) -> Result<ClusterProfile> {
+    let mut profile = ClusterProfile::from_vars(|name| match name {
+        "CLUSTER_PROFILE" => cli.cluster.clone(),
+        _ => std::env::var(name).ok(),
+    })?;
 
-    let args = FakeGetRewardArgs {
-        event_uuid,
-        option_uuid: some_option_uuid,
-        num_shares: 5
+    if let Some(rpc_url) = &cli.rpc_url {
+        profile.rpc_url = rpc_url.clone();
+    }
+    if let Some(external_rpc_url) = &cli.external_rpc_url {
+        profile.external_rpc_url = external_rpc_url.clone();
+    }
+    if let Some(program_id) = cli.program_id {
+        profile.deployment.program_id = program_id;
+    }
+    if let Some(collateral_mint) = cli.collateral_mint {
+        profile.deployment.collateral_mint = collateral_mint;
+    }
+
+    Ok(profile)
+}
+
+fn read_keypair(path: &PathBuf) -> Result<Keypair> {
+    Keypair::read_from_file(path)
+        .map_err(|e| anyhow!("Error reading keypair {}: {}", path.display(), e))
+}
+
+/// Plain keypair files are JSON arrays, keystores are JSON objects
+fn read_signer(path: &PathBuf, passphrase: Option<&str>) -> Result<Box<DynSigner>> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Error reading keypair {}", path.display()))?;
+    if !content.trim_start().starts_with('{') {
+        return Ok(Box::new(read_keypair(path)?));
+    }
+
+    let passphrase = passphrase.ok_or_else(|| {
+        anyhow!(
+            "{} is a keystore, a --passphrase is required",
+            path.display()
+        )
+    })?;
+    Ok(Box::new(Keystore::read(path)?.decrypt(passphrase, path)?))
+}
+
+fn format_usdc(amount: MicroUsdc) -> String {
+    format!("{}.{:06}", amount.0 / 1_000_000, amount.0 % 1_000_000)
+}
+
+/// Parses a non-negative decimal with at most `decimals` digits after the point into base units
+fn parse_decimal(value: &str, decimals: u32) -> Result<u64> {
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, ""));
+    if fraction.len() > decimals as usize {
+        bail!("At most {} decimal places are allowed", decimals);
+    }
+    let whole = if whole.is_empty() {
+        0
+    } else {
+        u64::from_str(whole)?
+    };
+    let fraction = if fraction.is_empty() {
+        0
+    } else {
+        u64::from_str(fraction)? * 10u64.pow(decimals - fraction.len() as u32)
     };
-    let sig = profecia_client.get_reward(&client_yes, &yes.pubkey(), &args).await?;
 
-    println!("Get reward sig: {}", sig);
+    whole
+        .checked_mul(10u64.pow(decimals))
+        .and_then(|whole| whole.checked_add(fraction))
+        .ok_or_else(|| anyhow!("Amount is too large"))
+}
 
-    Ok(())
+fn parse_cents(value: &str) -> Result<Cents> {
+    parse_decimal(value, 2).map(Cents)
+}
+
+fn parse_lamports(value: &str) -> Result<u64> {
+    parse_decimal(value, 9)
 }
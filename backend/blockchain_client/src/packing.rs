@@ -0,0 +1,375 @@
+//! Creating an event and its options in as few transactions as possible.
+//!
+//! `EventPacker` puts `CreateEmptyEvent` and then as many `AddOption` instructions as fit in each
+//! transaction. A transaction is full when its serialized size would go over `MAX_TRANSACTION_SIZE`
+//! or it would lock more than `MAX_ACCOUNT_LOCKS` accounts. Both mints of an option sign, so every
+//! option adds two signatures, which is what fills the transactions in practice. Lookup tables
+//! shorten the accounts that do not sign; the programs and the collateral mint are good candidates
+//! (see `lookup_table_addresses`).
+//!
+//! The transaction creating the event is sent first, since every option needs the event. The
+//! others only depend on it, so they are sent together once it is confirmed. Each chunk gets its
+//! own outcome, so an RPC error on one of them does not hide which ones landed. A creation that
+//! was interrupted is resumed with `EventPacker::resuming`, which leaves out what the event
+//! already holds on chain.
+
+use anyhow::{Result, bail};
+use blockchain_core::{
+    accounts::event::Event,
+    instructions::{AddOptionArgs, CreateEmptyEventArgs},
+};
+use futures::future::join_all;
+use solana_sdk::{
+    hash::Hash,
+    message::{AddressLookupTableAccount, Instruction, VersionedMessage, v0},
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+    signer::Signer,
+    transaction::VersionedTransaction,
+};
+use uuid::Uuid;
+
+use crate::{
+    ProfeciaClient, SYSTEM_PROGRAM, TransactionOutcome,
+    cluster::Deployment,
+    compute_budget::{ComputeBudget, MAX_COMPUTE_UNIT_LIMIT},
+    instructions,
+};
+
+/// Largest serialized transaction the cluster accepts
+pub const MAX_TRANSACTION_SIZE: usize = 1232;
+/// Most accounts a transaction can reference, lookup tables included
+pub const MAX_ACCOUNT_LOCKS: usize = 64;
+
+/// Accounts shared by the instructions of every event, which a lookup table can shorten
+pub fn lookup_table_addresses(deployment: &Deployment) -> Vec<Pubkey> {
+    vec![
+        SYSTEM_PROGRAM,
+        spl_token::ID,
+        spl_associated_token_account::ID,
+        deployment.collateral_mint,
+    ]
+}
+
+struct PackedOption<'a> {
+    args: AddOptionArgs,
+    yes_mint: &'a Keypair,
+    no_mint: &'a Keypair,
+}
+
+impl PackedOption<'_> {
+    fn instruction(&self, deployment: &Deployment, payer: &Pubkey) -> Result<Instruction> {
+        instructions::add_option(
+            deployment,
+            payer,
+            &self.yes_mint.pubkey(),
+            &self.no_mint.pubkey(),
+            &self.args,
+        )
+    }
+}
+
+/// One transaction of a packed event
+pub struct EventChunk<'a> {
+    pub instructions: Vec<Instruction>,
+    /// Mints of the options of the chunk, which sign along with the payer
+    pub mints: Vec<&'a Keypair>,
+    /// Whether it holds the `CreateEmptyEvent` instruction, which only the first one does, unless
+    /// the event already exists
+    pub creates_event: bool,
+    pub options: Vec<Uuid>,
+}
+
+pub struct EventPacker<'a> {
+    deployment: Deployment,
+    payer: Pubkey,
+    event: CreateEmptyEventArgs,
+    options: Vec<PackedOption<'a>>,
+    lookup_tables: Vec<AddressLookupTableAccount>,
+    /// The event is already on chain, so only the options are sent
+    event_exists: bool,
+}
+
+impl<'a> EventPacker<'a> {
+    pub fn new(deployment: Deployment, payer: Pubkey, event: CreateEmptyEventArgs) -> Self {
+        Self {
+            deployment,
+            payer,
+            event,
+            options: Vec::new(),
+            lookup_tables: Vec::new(),
+            event_exists: false,
+        }
+    }
+
+    /// The options keep the order they were added in
+    pub fn add_option(
+        mut self,
+        yes_mint: &'a Keypair,
+        no_mint: &'a Keypair,
+        args: AddOptionArgs,
+    ) -> Self {
+        self.options.push(PackedOption {
+            args,
+            yes_mint,
+            no_mint,
+        });
+        self
+    }
+
+    /// Must be the same tables the transactions are sent with
+    pub fn with_lookup_tables(mut self, lookup_tables: Vec<AddressLookupTableAccount>) -> Self {
+        self.lookup_tables = lookup_tables;
+        self
+    }
+
+    /// Picks up a creation that was interrupted, given the event as it is on chain. The event is
+    /// not created again, and the options it already holds are left out
+    pub fn resuming(mut self, event: &Event) -> Self {
+        self.event_exists = true;
+        self.options
+            .retain(|option| !event.options.contains_key(&option.args.option_uuid));
+        self
+    }
+
+    /// Fills each transaction before starting the next one. Fails if a single option does not fit
+    /// in a transaction of its own. When resuming, there is no chunk at all if every option is
+    /// already on chain
+    pub fn pack(&self) -> Result<Vec<EventChunk<'a>>> {
+        let mut chunks = Vec::new();
+        if !self.event_exists {
+            chunks.push(EventChunk {
+                instructions: vec![instructions::create_empty_event(
+                    &self.deployment,
+                    &self.payer,
+                    &self.event,
+                )?],
+                mints: Vec::new(),
+                creates_event: true,
+                options: Vec::new(),
+            });
+            if !self.fits(&chunks[0].instructions)? {
+                bail!("CreateEmptyEvent does not fit in a transaction");
+            }
+        }
+
+        for option in &self.options {
+            let instruction = option.instruction(&self.deployment, &self.payer)?;
+
+            let full = match chunks.last_mut() {
+                Some(chunk) => {
+                    chunk.instructions.push(instruction.clone());
+                    let full = !self.fits(&chunk.instructions)?;
+                    if full {
+                        chunk.instructions.pop();
+                    }
+                    full
+                }
+                None => true,
+            };
+            if full {
+                if !self.fits(std::slice::from_ref(&instruction))? {
+                    bail!(
+                        "Option {} does not fit in a transaction",
+                        option.args.option_uuid
+                    );
+                }
+                chunks.push(EventChunk {
+                    instructions: vec![instruction],
+                    mints: Vec::new(),
+                    creates_event: false,
+                    options: Vec::new(),
+                });
+            }
+
+            let chunk = chunks.last_mut().expect("There is always a chunk");
+            chunk.mints.extend([option.yes_mint, option.no_mint]);
+            chunk.options.push(option.args.option_uuid);
+        }
+
+        Ok(chunks)
+    }
+
+    /// Whether `instructions` fit in a transaction along with the compute budget `submit` adds
+    fn fits(&self, instructions: &[Instruction]) -> Result<bool> {
+        let size = transaction_size(&self.payer, instructions, &self.lookup_tables)?;
+        let accounts = account_count(&self.payer, instructions, &self.lookup_tables)?;
+
+        Ok(size <= MAX_TRANSACTION_SIZE && accounts <= MAX_ACCOUNT_LOCKS)
+    }
+}
+
+/// Size of the signed transaction `submit` would send for `instructions`, with the largest
+/// compute budget it could add
+pub fn transaction_size(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<usize> {
+    let message = compile(payer, instructions, lookup_tables)?;
+    let transaction = VersionedTransaction {
+        signatures: vec![Signature::default(); message.header.num_required_signatures as usize],
+        message: VersionedMessage::V0(message),
+    };
+
+    Ok(bincode::serialized_size(&transaction)? as usize)
+}
+
+fn account_count(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<usize> {
+    let message = compile(payer, instructions, lookup_tables)?;
+    let looked_up: usize = message
+        .address_table_lookups
+        .iter()
+        .map(|lookup| lookup.writable_indexes.len() + lookup.readonly_indexes.len())
+        .sum();
+
+    Ok(message.account_keys.len() + looked_up)
+}
+
+fn compile(
+    payer: &Pubkey,
+    instructions: &[Instruction],
+    lookup_tables: &[AddressLookupTableAccount],
+) -> Result<v0::Message> {
+    let mut budgeted = ComputeBudget {
+        units_consumed: 0,
+        compute_unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+        compute_unit_price: u64::MAX,
+        fee_lamports: 0,
+    }
+    .instructions();
+    budgeted.extend_from_slice(instructions);
+
+    Ok(v0::Message::try_compile(
+        payer,
+        &budgeted,
+        lookup_tables,
+        Hash::default(),
+    )?)
+}
+
+/// What happened to one chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkOutcome {
+    pub creates_event: bool,
+    pub options: Vec<Uuid>,
+    /// None when it was not sent, because the event could not be created, or when `error` is set
+    pub outcome: Option<TransactionOutcome>,
+    /// The RPC failed while the chunk was sent or awaited, so it may have landed anyway
+    pub error: Option<String>,
+}
+
+impl ChunkOutcome {
+    pub fn is_confirmed(&self) -> bool {
+        self.outcome
+            .as_ref()
+            .is_some_and(TransactionOutcome::is_confirmed)
+    }
+}
+
+/// Which options landed in which signature
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedEventOutcome {
+    pub chunks: Vec<ChunkOutcome>,
+}
+
+impl PackedEventOutcome {
+    pub fn is_confirmed(&self) -> bool {
+        self.chunks.iter().all(ChunkOutcome::is_confirmed)
+    }
+
+    /// Confirmed options, with the signature of the transaction that added them
+    pub fn landed(&self) -> impl Iterator<Item = (Uuid, Signature)> + '_ {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.is_confirmed())
+            .flat_map(|chunk| {
+                let signature = *chunk
+                    .outcome
+                    .as_ref()
+                    .expect("Confirmed chunks were sent")
+                    .signature();
+                chunk.options.iter().map(move |option| (*option, signature))
+            })
+    }
+
+    /// The chunk creating the event, unless the event already existed
+    pub fn event_chunk(&self) -> Option<&ChunkOutcome> {
+        self.chunks.iter().find(|chunk| chunk.creates_event)
+    }
+
+    /// Options that were not confirmed
+    pub fn missing(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.chunks
+            .iter()
+            .filter(|chunk| !chunk.is_confirmed())
+            .flat_map(|chunk| chunk.options.iter().copied())
+    }
+}
+
+impl ProfeciaClient {
+    /// Packer for an event created and paid by the admin wallet, using the lookup tables of the
+    /// client
+    pub fn event_packer<'a>(&self, event: CreateEmptyEventArgs) -> EventPacker<'a> {
+        EventPacker::new(self.profile.deployment, self.admin_wallet.pubkey(), event)
+            .with_lookup_tables(self.lookup_tables.clone())
+    }
+
+    /// Sends the chunks of `packer`, which must be paid by the admin wallet, as described in the
+    /// module docs. RPC errors are recorded on the chunk they happened on, so this only fails when
+    /// the event cannot be packed
+    pub async fn create_packed_event(
+        &self,
+        packer: &EventPacker<'_>,
+    ) -> Result<PackedEventOutcome> {
+        let mut chunks = packer.pack()?;
+        let mut outcomes = Vec::with_capacity(chunks.len());
+
+        if chunks.first().is_some_and(|chunk| chunk.creates_event) {
+            let first = chunks.remove(0);
+            let created = self.chunk_outcome(&first).await;
+            let event_exists = created.is_confirmed();
+            outcomes.push(created);
+
+            if !event_exists {
+                outcomes.extend(chunks.into_iter().map(|chunk| ChunkOutcome {
+                    creates_event: chunk.creates_event,
+                    options: chunk.options,
+                    outcome: None,
+                    error: None,
+                }));
+                return Ok(PackedEventOutcome { chunks: outcomes });
+            }
+        }
+
+        outcomes.extend(join_all(chunks.iter().map(|chunk| self.chunk_outcome(chunk))).await);
+
+        Ok(PackedEventOutcome { chunks: outcomes })
+    }
+
+    async fn chunk_outcome(&self, chunk: &EventChunk<'_>) -> ChunkOutcome {
+        let (outcome, error) = match self.submit_chunk(chunk).await {
+            Ok(outcome) => (Some(outcome), None),
+            Err(e) => (None, Some(e.to_string())),
+        };
+
+        ChunkOutcome {
+            creates_event: chunk.creates_event,
+            options: chunk.options.clone(),
+            outcome,
+            error,
+        }
+    }
+
+    async fn submit_chunk(&self, chunk: &EventChunk<'_>) -> Result<TransactionOutcome> {
+        let mut signers: Vec<&(dyn Signer + Sync)> = vec![self.admin_wallet.as_ref()];
+        signers.extend(chunk.mints.iter().map(|k| *k as &(dyn Signer + Sync)));
+
+        self.submit(&chunk.instructions, &self.admin_wallet.pubkey(), &signers)
+            .await
+    }
+}
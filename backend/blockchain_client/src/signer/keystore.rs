@@ -0,0 +1,219 @@
+//! Keypairs encrypted at rest with a passphrase.
+//!
+//! The passphrase is stretched with Argon2id into an AES-256-CTR key and an HMAC-SHA256 key.
+//! The MAC covers the IV and the ciphertext, so a wrong passphrase is detected before decrypting.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use aes::Aes256;
+use argon2::{
+    Algorithm, Argon2, Params, Version,
+    password_hash::rand_core::{OsRng, RngCore},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use ctr::cipher::{KeyIvInit, StreamCipher};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+use super::{DynSigner, ProviderError, SignerProvider};
+
+const VERSION: u32 = 1;
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct KdfParams {
+    pub m_cost: u32,
+    pub t_cost: u32,
+    pub p_cost: u32,
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Contents of a keystore file. Binary fields are base64 encoded
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    /// Stored in clear so the address is known without the passphrase
+    pub pubkey: String,
+    pub kdf: KdfParams,
+    pub salt: String,
+    pub iv: String,
+    pub ciphertext: String,
+    pub mac: String,
+}
+
+/// 32 bytes of encryption key followed by 32 bytes of MAC key
+fn derive_keys(passphrase: &str, salt: &[u8], kdf: &KdfParams) -> Result<[u8; 64], String> {
+    let params =
+        Params::new(kdf.m_cost, kdf.t_cost, kdf.p_cost, Some(64)).map_err(|e| e.to_string())?;
+    let mut keys = [0u8; 64];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut keys)
+        .map_err(|e| e.to_string())?;
+    Ok(keys)
+}
+
+fn mac(key: &[u8], iv: &[u8], ciphertext: &[u8]) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(iv);
+    mac.update(ciphertext);
+    mac
+}
+
+impl Keystore {
+    pub fn encrypt(keypair: &Keypair, passphrase: &str) -> Self {
+        Self::encrypt_with_params(keypair, passphrase, KdfParams::default())
+    }
+
+    pub fn encrypt_with_params(keypair: &Keypair, passphrase: &str, kdf: KdfParams) -> Self {
+        let mut salt = [0u8; 16];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+
+        let keys = derive_keys(passphrase, &salt, &kdf).expect("Invalid KDF parameters");
+        let mut ciphertext = keypair.to_bytes().to_vec();
+        Aes256Ctr::new(keys[..32].into(), &iv.into()).apply_keystream(&mut ciphertext);
+        let mac = mac(&keys[32..], &iv, &ciphertext).finalize().into_bytes();
+
+        Self {
+            version: VERSION,
+            pubkey: keypair.pubkey().to_string(),
+            kdf,
+            salt: BASE64.encode(salt),
+            iv: BASE64.encode(iv),
+            ciphertext: BASE64.encode(ciphertext),
+            mac: BASE64.encode(mac),
+        }
+    }
+
+    /// `path` is only used in errors
+    pub fn decrypt(&self, passphrase: &str, path: &Path) -> Result<Keypair, ProviderError> {
+        let invalid = |reason: String| ProviderError::InvalidKeystore {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let decode = |field: &str| BASE64.decode(field).map_err(|e| invalid(e.to_string()));
+
+        if self.version != VERSION {
+            return Err(invalid(format!("Unsupported version {}", self.version)));
+        }
+        let salt = decode(&self.salt)?;
+        let iv = decode(&self.iv)?;
+        let mut plaintext = decode(&self.ciphertext)?;
+        let expected_mac = decode(&self.mac)?;
+        if iv.len() != 16 {
+            return Err(invalid("IV must have 16 bytes".into()));
+        }
+
+        let keys = derive_keys(passphrase, &salt, &self.kdf).map_err(invalid)?;
+        mac(&keys[32..], &iv, &plaintext)
+            .verify_slice(&expected_mac)
+            .map_err(|_| ProviderError::Decryption(path.to_path_buf()))?;
+
+        Aes256Ctr::new(keys[..32].into(), iv.as_slice().into()).apply_keystream(&mut plaintext);
+        let keypair =
+            Keypair::try_from(plaintext.as_slice()).map_err(|e| invalid(e.to_string()))?;
+
+        if keypair.pubkey().to_string() != self.pubkey {
+            return Err(invalid("Public key does not match the secret key".into()));
+        }
+        Ok(keypair)
+    }
+
+    pub fn read(path: &Path) -> Result<Self, ProviderError> {
+        let content = fs::read_to_string(path)?;
+        serde_json::from_str(&content).map_err(|e| ProviderError::InvalidKeystore {
+            path: path.to_path_buf(),
+            reason: e.to_string(),
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), ProviderError> {
+        let content = serde_json::to_string_pretty(self).expect("Keystore is always serializable");
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// One keystore per wallet in `dir`, named `<pubkey>.json`, all with the same passphrase.
+/// Keys are the pubkeys. Every call to `signer` decrypts the file again, so the keypairs are only
+/// in memory while they are used
+pub struct KeystoreProvider {
+    dir: PathBuf,
+    passphrase: String,
+    kdf: KdfParams,
+}
+
+impl KeystoreProvider {
+    pub fn new(dir: impl Into<PathBuf>, passphrase: impl Into<String>) -> Self {
+        Self {
+            dir: dir.into(),
+            passphrase: passphrase.into(),
+            kdf: KdfParams::default(),
+        }
+    }
+
+    /// Cheaper parameters make new keystores faster to open, and weaker
+    pub fn with_kdf_params(mut self, kdf: KdfParams) -> Self {
+        self.kdf = kdf;
+        self
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf, ProviderError> {
+        // the key must be an address, so that it cannot point outside of `dir`
+        let pubkey: Pubkey = key
+            .parse()
+            .map_err(|_| ProviderError::InvalidKey(key.to_string()))?;
+        Ok(self.dir.join(format!("{}.json", pubkey)))
+    }
+
+    /// Encrypts an existing keypair into the directory and returns its key
+    pub fn import(&self, keypair: &Keypair) -> Result<String, ProviderError> {
+        let key = keypair.pubkey().to_string();
+        fs::create_dir_all(&self.dir)?;
+        Keystore::encrypt_with_params(keypair, &self.passphrase, self.kdf)
+            .write(&self.path(&key)?)?;
+        Ok(key)
+    }
+}
+
+impl SignerProvider for KeystoreProvider {
+    fn signer(&self, key: &str) -> Result<Box<DynSigner>, ProviderError> {
+        let path = self.path(key)?;
+        if !path.exists() {
+            return Err(ProviderError::UnknownKey(key.to_string()));
+        }
+        let keypair = Keystore::read(&path)?.decrypt(&self.passphrase, &path)?;
+        Ok(Box::new(keypair))
+    }
+
+    fn create(&self) -> Result<(String, Pubkey), ProviderError> {
+        let keypair = Keypair::new();
+        Ok((self.import(&keypair)?, keypair.pubkey()))
+    }
+
+    fn pubkey(&self, key: &str) -> Result<Pubkey, ProviderError> {
+        let path = self.path(key)?;
+        if !path.exists() {
+            return Err(ProviderError::UnknownKey(key.to_string()));
+        }
+        key.parse()
+            .map_err(|_| ProviderError::InvalidKey(key.to_string()))
+    }
+}
@@ -0,0 +1,77 @@
+//! Where the private keys live.
+//!
+//! `ProfeciaClient` never needs to own a `Keypair`: every method takes a `DynSigner`, and wallets
+//! are looked up through a `SignerProvider` by the key the API stores for each user. Depending on
+//! the provider that key is the secret itself (`InMemoryProvider`), the name of an encrypted file
+//! (`KeystoreProvider`) or an opaque id resolved by another process (`RemoteProvider`).
+
+use std::path::PathBuf;
+
+use solana_sdk::{pubkey::Pubkey, signature::Keypair, signer::Signer};
+
+pub mod keystore;
+pub mod remote;
+
+pub use keystore::{Keystore, KeystoreProvider};
+pub use remote::{RemoteProvider, RemoteSigner};
+
+/// Any signer that can be shared between tasks
+pub type DynSigner = dyn Signer + Send + Sync;
+
+#[derive(Debug, thiserror::Error)]
+pub enum ProviderError {
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Unknown key {0}")]
+    UnknownKey(String),
+    #[error("Wrong passphrase or corrupted keystore {0}")]
+    Decryption(PathBuf),
+    #[error("Invalid keystore {path}: {reason}")]
+    InvalidKeystore { path: PathBuf, reason: String },
+    #[error("Remote signer error: {0}")]
+    Remote(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub trait SignerProvider: Send + Sync {
+    /// Signer of the wallet stored as `key`
+    fn signer(&self, key: &str) -> Result<Box<DynSigner>, ProviderError>;
+
+    /// Creates a wallet. Returns the key to store and its address
+    fn create(&self) -> Result<(String, Pubkey), ProviderError>;
+
+    fn pubkey(&self, key: &str) -> Result<Pubkey, ProviderError> {
+        self.signer(key)?
+            .try_pubkey()
+            .map_err(|e| ProviderError::Remote(e.to_string()))
+    }
+}
+
+/// Same as `Keypair::from_base58_string`, without panicking on malformed input
+pub fn keypair_from_base58(key: &str) -> Result<Keypair, ProviderError> {
+    let bytes = solana_sdk::bs58::decode(key)
+        .into_vec()
+        .map_err(|e| ProviderError::InvalidKey(e.to_string()))?;
+
+    Keypair::try_from(bytes.as_slice()).map_err(|e| ProviderError::InvalidKey(e.to_string()))
+}
+
+/// Keys are the base58 encoded secret keys themselves, as the API has always stored them
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InMemoryProvider;
+
+impl SignerProvider for InMemoryProvider {
+    fn signer(&self, key: &str) -> Result<Box<DynSigner>, ProviderError> {
+        Ok(Box::new(keypair_from_base58(key)?))
+    }
+
+    fn create(&self) -> Result<(String, Pubkey), ProviderError> {
+        let keypair = Keypair::new();
+        Ok((keypair.to_base58_string(), keypair.pubkey()))
+    }
+
+    fn pubkey(&self, key: &str) -> Result<Pubkey, ProviderError> {
+        Ok(keypair_from_base58(key)?.pubkey())
+    }
+}
@@ -0,0 +1,249 @@
+//! Signing in another process, reached over a Unix socket.
+//!
+//! The protocol is one JSON object per line in each direction, one request per connection:
+//!
+//! - `{"op":"pubkey","key":"..."}` answered with `{"pubkey":"..."}`
+//! - `{"op":"sign","key":"...","message":"<base64>"}` answered with `{"signature":"..."}`
+//! - `{"op":"create"}` answered with `{"created":{"key":"...","pubkey":"..."}}`
+//!
+//! Any failure is answered with `{"error":"..."}`. `serve` implements the other side on top of
+//! any `SignerProvider`, e.g. a `KeystoreProvider` whose passphrase only that process knows.
+//!
+//! `solana_sdk::signer::Signer` is synchronous, so the round trip is done on tokio sockets and
+//! bridged: inside a multi-threaded runtime the worker hands its other tasks over with
+//! `block_in_place`, anywhere else the request runs on a helper thread with its own runtime.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use serde::{Deserialize, Serialize};
+use solana_sdk::{
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::{Signer, SignerError},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{UnixListener, UnixStream},
+    runtime::{Handle, RuntimeFlavor},
+};
+
+use super::{DynSigner, ProviderError, SignerProvider};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+enum Request {
+    Pubkey { key: String },
+    Sign { key: String, message: String },
+    Create,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Response {
+    Pubkey(String),
+    Signature(String),
+    Created { key: String, pubkey: String },
+    Error(String),
+}
+
+async fn round_trip(socket: &Path, request: &Request) -> io::Result<String> {
+    let mut stream = UnixStream::connect(socket).await?;
+
+    let mut line = serde_json::to_string(request).expect("Requests are always serializable");
+    line.push('\n');
+    stream.write_all(line.as_bytes()).await?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).await?;
+    Ok(line)
+}
+
+async fn request_async(socket: &Path, request: &Request) -> Result<Response, ProviderError> {
+    let line = tokio::time::timeout(TIMEOUT, round_trip(socket, request))
+        .await
+        .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "Remote signer timed out"))??;
+
+    match serde_json::from_str(&line) {
+        Ok(Response::Error(error)) => Err(ProviderError::Remote(error)),
+        Ok(response) => Ok(response),
+        Err(e) => Err(ProviderError::Remote(format!("Invalid response: {}", e))),
+    }
+}
+
+/// Runs `request_async` from synchronous code without stalling a tokio worker
+fn request(socket: &Path, request: &Request) -> Result<Response, ProviderError> {
+    if let Ok(handle) = Handle::try_current()
+        && handle.runtime_flavor() == RuntimeFlavor::MultiThread
+    {
+        return tokio::task::block_in_place(|| handle.block_on(request_async(socket, request)));
+    }
+
+    // a runtime cannot be blocked on from one of its own threads
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()?
+                    .block_on(request_async(socket, request))
+            })
+            .join()
+            .unwrap_or_else(|_| Err(ProviderError::Remote("Remote signer panicked".into())))
+    })
+}
+
+fn unexpected(response: Response) -> ProviderError {
+    ProviderError::Remote(format!("Unexpected response {:?}", response))
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, ProviderError> {
+    pubkey
+        .parse()
+        .map_err(|_| ProviderError::Remote(format!("Invalid pubkey {}", pubkey)))
+}
+
+/// A wallet of the remote process. Holds no secret, only the key and the address
+#[derive(Debug, Clone)]
+pub struct RemoteSigner {
+    socket: PathBuf,
+    key: String,
+    pubkey: Pubkey,
+}
+
+impl Signer for RemoteSigner {
+    fn try_pubkey(&self) -> Result<Pubkey, SignerError> {
+        Ok(self.pubkey)
+    }
+
+    fn try_sign_message(&self, message: &[u8]) -> Result<Signature, SignerError> {
+        let response = request(
+            &self.socket,
+            &Request::Sign {
+                key: self.key.clone(),
+                message: BASE64.encode(message),
+            },
+        )
+        .map_err(|e| SignerError::Connection(e.to_string()))?;
+
+        let Response::Signature(signature) = response else {
+            return Err(SignerError::Protocol(unexpected(response).to_string()));
+        };
+        let signature: Signature = signature
+            .parse()
+            .map_err(|_| SignerError::Protocol(format!("Invalid signature {}", signature)))?;
+
+        // a misbehaving signer must not make us send garbage
+        if !signature.verify(self.pubkey.as_ref(), message) {
+            return Err(SignerError::KeypairPubkeyMismatch);
+        }
+        Ok(signature)
+    }
+
+    fn is_interactive(&self) -> bool {
+        false
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RemoteProvider {
+    socket: PathBuf,
+}
+
+impl RemoteProvider {
+    pub fn new(socket: impl Into<PathBuf>) -> Self {
+        Self {
+            socket: socket.into(),
+        }
+    }
+}
+
+impl SignerProvider for RemoteProvider {
+    fn signer(&self, key: &str) -> Result<Box<DynSigner>, ProviderError> {
+        Ok(Box::new(RemoteSigner {
+            socket: self.socket.clone(),
+            key: key.to_string(),
+            pubkey: self.pubkey(key)?,
+        }))
+    }
+
+    fn create(&self) -> Result<(String, Pubkey), ProviderError> {
+        match request(&self.socket, &Request::Create)? {
+            Response::Created { key, pubkey } => Ok((key, parse_pubkey(&pubkey)?)),
+            response => Err(unexpected(response)),
+        }
+    }
+
+    fn pubkey(&self, key: &str) -> Result<Pubkey, ProviderError> {
+        let pubkey_request = Request::Pubkey {
+            key: key.to_string(),
+        };
+        match request(&self.socket, &pubkey_request)? {
+            Response::Pubkey(pubkey) => parse_pubkey(&pubkey),
+            response => Err(unexpected(response)),
+        }
+    }
+}
+
+fn handle(provider: &dyn SignerProvider, request: Request) -> Response {
+    let result = match request {
+        Request::Pubkey { key } => provider
+            .pubkey(&key)
+            .map(|pubkey| Response::Pubkey(pubkey.to_string())),
+        Request::Sign { key, message } => BASE64
+            .decode(message)
+            .map_err(|e| ProviderError::Remote(e.to_string()))
+            .and_then(|message| {
+                provider
+                    .signer(&key)?
+                    .try_sign_message(&message)
+                    .map_err(|e| ProviderError::Remote(e.to_string()))
+            })
+            .map(|signature| Response::Signature(signature.to_string())),
+        Request::Create => provider.create().map(|(key, pubkey)| Response::Created {
+            key,
+            pubkey: pubkey.to_string(),
+        }),
+    };
+
+    result.unwrap_or_else(|e| Response::Error(e.to_string()))
+}
+
+async fn serve_connection(provider: Arc<dyn SignerProvider>, stream: UnixStream) -> io::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut line = String::new();
+    BufReader::new(reader).read_line(&mut line).await?;
+
+    let response = match serde_json::from_str(&line) {
+        // the providers may block, e.g. while decrypting a keystore
+        Ok(request) => tokio::task::spawn_blocking(move || handle(provider.as_ref(), request))
+            .await
+            .unwrap_or_else(|e| Response::Error(e.to_string())),
+        Err(e) => Response::Error(format!("Invalid request: {}", e)),
+    };
+
+    let mut line = serde_json::to_string(&response).expect("Responses are always serializable");
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await
+}
+
+/// Answers requests with `provider` until the listener fails
+pub async fn serve(
+    listener: UnixListener,
+    provider: Arc<dyn SignerProvider>,
+) -> Result<(), ProviderError> {
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let provider = provider.clone();
+        tokio::spawn(async move {
+            let _ = serve_connection(provider, stream).await;
+        });
+    }
+}
@@ -0,0 +1,301 @@
+//! Running transactions against the current state of the cluster without sending them.
+//!
+//! Simulations skip signature verification and replace the blockhash, so only the addresses of
+//! the signers are needed and nothing is ever signed. Every `simulate_*` method mirrors the
+//! `ProfeciaClient` method of the same name, with the same compute budget `submit` would use.
+//! Each simulation runs on its own: one never sees the changes of another.
+
+use anyhow::{Result, anyhow};
+use blockchain_core::instructions::{
+    AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+    FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, TransferSharesArgs,
+};
+use solana_client::rpc_config::{
+    RpcSimulateTransactionAccountsConfig, RpcSimulateTransactionConfig, UiAccountEncoding,
+};
+use solana_sdk::{
+    account::Account,
+    message::{Instruction, Message},
+    pubkey::Pubkey,
+    transaction::{Transaction, TransactionError},
+};
+
+use crate::{
+    ProfeciaClient,
+    compute_budget::{ComputeBudget, MAX_COMPUTE_UNIT_LIMIT, compute_unit_limit, fee_lamports},
+    instructions,
+};
+
+/// The parts of an account a transaction can change, other than its data
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountSnapshot {
+    pub lamports: u64,
+    pub data_len: usize,
+    pub owner: Pubkey,
+}
+
+impl From<&Account> for AccountSnapshot {
+    fn from(account: &Account) -> Self {
+        Self {
+            lamports: account.lamports,
+            data_len: account.data.len(),
+            owner: account.owner,
+        }
+    }
+}
+
+/// A writable account of the transaction, None when it does not exist
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountDiff {
+    pub pubkey: Pubkey,
+    pub before: Option<AccountSnapshot>,
+    pub after: Option<AccountSnapshot>,
+}
+
+impl AccountDiff {
+    pub fn lamports_before(&self) -> u64 {
+        self.before.map_or(0, |account| account.lamports)
+    }
+
+    pub fn lamports_after(&self) -> u64 {
+        self.after.map_or(0, |account| account.lamports)
+    }
+
+    pub fn is_created(&self) -> bool {
+        self.before.is_none() && self.after.is_some()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Simulation {
+    /// None when the transaction would succeed
+    pub error: Option<TransactionError>,
+    pub logs: Vec<String>,
+    /// Limit, price and fee `submit` would use
+    pub budget: ComputeBudget,
+    /// Only filled in when the transaction would succeed, since a failed one changes nothing
+    pub accounts: Vec<AccountDiff>,
+}
+
+impl Simulation {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// Rent of the accounts the transaction creates
+    pub fn rent_lamports(&self) -> u64 {
+        self.accounts
+            .iter()
+            .filter(|account| account.is_created())
+            .map(|account| account.lamports_after())
+            .sum()
+    }
+
+    /// Fee plus rent, which is what the signers pay in SOL
+    pub fn lamport_cost(&self) -> u64 {
+        self.budget
+            .fee_lamports
+            .saturating_add(self.rent_lamports())
+    }
+}
+
+impl ProfeciaClient {
+    /// Simulates `instructions` as `submit` would send them, with `payer` paying the fee.
+    /// RPC errors are returned as `Err`, errors of the transaction itself in `Simulation::error`
+    pub async fn simulate(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+    ) -> Result<Simulation> {
+        let config = &self.submit_config;
+        let compute_unit_price = self.compute_unit_price(instructions, payer).await?;
+
+        let mut simulated = ComputeBudget {
+            units_consumed: 0,
+            compute_unit_limit: MAX_COMPUTE_UNIT_LIMIT,
+            compute_unit_price,
+            fee_lamports: 0,
+        }
+        .instructions();
+        simulated.extend_from_slice(instructions);
+
+        let message = Message::new(&simulated, Some(payer));
+        let num_signatures = message.header.num_required_signatures as u64;
+        let writable: Vec<Pubkey> = message
+            .account_keys
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| message.is_maybe_writable(*i, None))
+            .map(|(_, key)| *key)
+            .collect();
+
+        let before = self
+            .rpc_client
+            .get_multiple_accounts_with_commitment(&writable, config.commitment)
+            .await?
+            .value;
+
+        let result = self
+            .rpc_client
+            .simulate_transaction_with_config(
+                &Transaction::new_unsigned(message),
+                RpcSimulateTransactionConfig {
+                    sig_verify: false,
+                    replace_recent_blockhash: true,
+                    commitment: Some(config.commitment),
+                    accounts: Some(RpcSimulateTransactionAccountsConfig {
+                        encoding: Some(UiAccountEncoding::Base64),
+                        addresses: writable.iter().map(ToString::to_string).collect(),
+                    }),
+                    ..Default::default()
+                },
+            )
+            .await?
+            .value;
+
+        let units_consumed = result.units_consumed.unwrap_or(0);
+        let compute_unit_limit =
+            compute_unit_limit(units_consumed, config.compute_unit_margin_percent);
+        let budget = ComputeBudget {
+            units_consumed,
+            compute_unit_limit,
+            compute_unit_price,
+            fee_lamports: fee_lamports(num_signatures, compute_unit_limit, compute_unit_price),
+        };
+
+        let accounts = match (&result.err, result.accounts) {
+            (None, Some(after)) => writable
+                .iter()
+                .zip(before)
+                .zip(after)
+                .map(|((pubkey, before), after)| {
+                    let after = after
+                        .map(|account| {
+                            account.decode::<Account>().ok_or_else(|| {
+                                anyhow!("Could not decode simulated account {}", pubkey)
+                            })
+                        })
+                        .transpose()?;
+                    Ok(AccountDiff {
+                        pubkey: *pubkey,
+                        before: before.as_ref().map(AccountSnapshot::from),
+                        after: after.as_ref().map(AccountSnapshot::from),
+                    })
+                })
+                .collect::<Result<_>>()?,
+            _ => Vec::new(),
+        };
+
+        Ok(Simulation {
+            error: result.err.map(TransactionError::from),
+            logs: result.logs.unwrap_or_default(),
+            budget,
+            accounts,
+        })
+    }
+
+    /// Unlike `create_event`, only the addresses of the mints are needed
+    pub async fn simulate_create_event(&self, args: &CreateEventArgs) -> Result<Simulation> {
+        let admin = self.admin_wallet.pubkey();
+        let instruction = instructions::create_event(&self.profile.deployment, &admin, args)?;
+
+        self.simulate(&[instruction], &admin).await
+    }
+
+    pub async fn simulate_close_event(&self, args: &CloseEventArgs) -> Result<Simulation> {
+        let admin = self.admin_wallet.pubkey();
+        let instruction = instructions::close_event(&self.profile.deployment, &admin, args)?;
+
+        self.simulate(&[instruction], &admin).await
+    }
+
+    pub async fn simulate_create_empty_event(
+        &self,
+        args: &CreateEmptyEventArgs,
+    ) -> Result<Simulation> {
+        let admin = self.admin_wallet.pubkey();
+        let instruction = instructions::create_empty_event(&self.profile.deployment, &admin, args)?;
+
+        self.simulate(&[instruction], &admin).await
+    }
+
+    pub async fn simulate_add_option(
+        &self,
+        yes_token: &Pubkey,
+        no_token: &Pubkey,
+        args: &AddOptionArgs,
+    ) -> Result<Simulation> {
+        let admin = self.admin_wallet.pubkey();
+        let instruction =
+            instructions::add_option(&self.profile.deployment, &admin, yes_token, no_token, args)?;
+
+        self.simulate(&[instruction], &admin).await
+    }
+
+    pub async fn simulate_create_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCreateOrderArgs,
+    ) -> Result<Simulation> {
+        let instruction = instructions::fake_create_order(&self.profile.deployment, user, args)?;
+
+        self.simulate(&[instruction], user).await
+    }
+
+    pub async fn simulate_match_order(
+        &self,
+        user_yes_wallet: &Pubkey,
+        user_no_wallet: &Pubkey,
+        token_yes: &Pubkey,
+        token_no: &Pubkey,
+        args: &FakeMatchOrderArgs,
+    ) -> Result<Simulation> {
+        let instruction = instructions::fake_match_order(
+            &self.profile.deployment,
+            user_yes_wallet,
+            user_no_wallet,
+            token_yes,
+            token_no,
+            args,
+        )?;
+
+        self.simulate(&[instruction], user_yes_wallet).await
+    }
+
+    pub async fn simulate_cancel_order(
+        &self,
+        user: &Pubkey,
+        args: &FakeCancelOrderArgs,
+    ) -> Result<Simulation> {
+        let admin = self.admin_wallet.pubkey();
+        let instruction =
+            instructions::fake_cancel_order(&self.profile.deployment, &admin, user, args)?;
+
+        self.simulate(&[instruction], &admin).await
+    }
+
+    pub async fn simulate_get_reward(
+        &self,
+        user: &Pubkey,
+        token: &Pubkey,
+        args: &FakeGetRewardArgs,
+    ) -> Result<Simulation> {
+        let instruction =
+            instructions::fake_get_reward(&self.profile.deployment, user, token, args)?;
+
+        self.simulate(&[instruction], user).await
+    }
+
+    pub async fn simulate_transfer_shares(
+        &self,
+        user_a: &Pubkey,
+        user_b: &Pubkey,
+        token: &Pubkey,
+        args: &TransferSharesArgs,
+    ) -> Result<Simulation> {
+        let instruction =
+            instructions::transfer_shares(&self.profile.deployment, user_a, user_b, token, args)?;
+
+        self.simulate(&[instruction], user_a).await
+    }
+}
@@ -0,0 +1,303 @@
+//! Sending transactions and waiting for them to land.
+//!
+//! Every `ProfeciaClient` method goes through `ProfeciaClient::submit`, which sizes the compute
+//! budget (see `compute_budget`), signs a versioned transaction using the lookup tables of the
+//! client with a fresh blockhash, polls the signature until it reaches the configured commitment and re-signs when
+//! the blockhash expires, or when preflight rejects it as unknown. Re-signing is safe: once the block height passes the last valid height
+//! of a blockhash, a transaction using it can no longer be processed, so it can never land twice.
+//!
+//! Callers that must not send a transaction again after losing its answer run `submit` under
+//! `with_send_hook`, which hands them every signature right before it is sent, and look it up
+//! later with `ProfeciaClient::sent_status`.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use solana_client::rpc_config::CommitmentConfig;
+use solana_sdk::{
+    message::{Instruction, VersionedMessage, v0},
+    pubkey::Pubkey,
+    signature::Signature,
+    signer::Signer,
+    transaction::{TransactionError, VersionedTransaction},
+};
+
+use crate::{
+    ProfeciaClient,
+    compute_budget::{ComputeBudget, FeePolicy},
+};
+
+#[derive(Debug, Clone, Copy)]
+pub struct SubmitConfig {
+    /// Commitment the transaction must reach to count as confirmed
+    pub commitment: CommitmentConfig,
+    /// Time between two status polls
+    pub poll_interval: Duration,
+    /// How long to wait for one signature before giving up with an error, in case the RPC keeps
+    /// reporting a status below the configured commitment
+    pub confirm_timeout: Duration,
+    /// How many times the transaction is re-signed with a new blockhash after the previous one expired
+    /// or was not found
+    pub max_resigns: u32,
+    pub fee_policy: FeePolicy,
+    /// Added on top of the simulated compute units, in case the real execution uses a bit more
+    pub compute_unit_margin_percent: u32,
+}
+
+impl Default for SubmitConfig {
+    fn default() -> Self {
+        Self {
+            commitment: CommitmentConfig::confirmed(),
+            poll_interval: Duration::from_millis(500),
+            confirm_timeout: Duration::from_secs(90),
+            max_resigns: 2,
+            fee_policy: FeePolicy::None,
+            compute_unit_margin_percent: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TransactionOutcome {
+    /// Reached the configured commitment
+    Confirmed {
+        signature: Signature,
+        budget: ComputeBudget,
+    },
+    /// Landed (or failed simulation) with an error. Nothing was changed on chain, but the fee
+    /// was paid if it landed. `budget` is None when it failed before being sent
+    Failed {
+        signature: Signature,
+        error: TransactionError,
+        budget: Option<ComputeBudget>,
+    },
+    /// Every blockhash expired before the transaction was seen, it will never land
+    Dropped { last_signature: Signature },
+}
+
+impl TransactionOutcome {
+    pub fn signature(&self) -> &Signature {
+        match self {
+            Self::Confirmed { signature, .. } => signature,
+            Self::Failed { signature, .. } => signature,
+            Self::Dropped { last_signature } => last_signature,
+        }
+    }
+
+    /// Compute units and fees of the transaction, if it was sent
+    pub fn budget(&self) -> Option<&ComputeBudget> {
+        match self {
+            Self::Confirmed { budget, .. } => Some(budget),
+            Self::Failed { budget, .. } => budget.as_ref(),
+            Self::Dropped { .. } => None,
+        }
+    }
+
+    pub fn is_confirmed(&self) -> bool {
+        matches!(self, Self::Confirmed { .. })
+    }
+
+    /// Turns anything other than a confirmation into an error
+    pub fn confirmed(self) -> Result<Signature> {
+        match self {
+            Self::Confirmed { signature, .. } => Ok(signature),
+            Self::Failed {
+                signature, error, ..
+            } => Err(anyhow!("Transaction {} failed: {}", signature, error)),
+            Self::Dropped { last_signature } => Err(anyhow!(
+                "Transaction {} was dropped before being confirmed",
+                last_signature
+            )),
+        }
+    }
+}
+
+/// Where a transaction sent earlier stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SentStatus {
+    /// Reached the configured commitment
+    Confirmed,
+    Failed(TransactionError),
+    /// Not confirmed yet, and its blockhash is still valid
+    InFlight,
+    /// Its blockhash expired before it was confirmed, it will never land
+    Expired,
+}
+
+/// Told about every transaction right before it is sent, see `with_send_hook`
+#[async_trait]
+pub trait SendHook: Send + Sync {
+    /// An error keeps the transaction from being sent, and is returned by `submit`
+    async fn before_send(&self, signature: &Signature, last_valid_block_height: u64) -> Result<()>;
+}
+
+tokio::task_local! {
+    static SEND_HOOK: Arc<dyn SendHook>;
+}
+
+/// Runs `future` with `hook` called before each transaction it sends
+pub async fn with_send_hook<F: Future>(hook: Arc<dyn SendHook>, future: F) -> F::Output {
+    SEND_HOOK.scope(hook, future).await
+}
+
+/// Calls the hook of the current task, if it runs under `with_send_hook`
+pub async fn before_send(signature: &Signature, last_valid_block_height: u64) -> Result<()> {
+    match SEND_HOOK.try_with(Arc::clone) {
+        Ok(hook) => hook.before_send(signature, last_valid_block_height).await,
+        Err(_) => Ok(()),
+    }
+}
+
+impl ProfeciaClient {
+    /// Signs, sends and waits for `instructions` as described in the module docs.
+    /// RPC errors are returned as `Err`, errors of the transaction itself as `TransactionOutcome::Failed`
+    pub async fn submit(
+        &self,
+        instructions: &[Instruction],
+        payer: &Pubkey,
+        signers: &[&(dyn Signer + Sync)],
+    ) -> Result<TransactionOutcome> {
+        let commitment = self.submit_config.commitment;
+
+        let budget = match self
+            .estimate_compute_budget(instructions, payer, signers)
+            .await?
+        {
+            Ok(budget) => budget,
+            Err(failed) => return Ok(failed),
+        };
+        let mut budgeted = budget.instructions();
+        budgeted.extend_from_slice(instructions);
+
+        let mut last_signature = Signature::default();
+
+        for _ in 0..=self.submit_config.max_resigns {
+            let (recent_blockhash, last_valid_block_height) = self
+                .rpc_client
+                .get_latest_blockhash_with_commitment(commitment)
+                .await?;
+
+            let message =
+                v0::Message::try_compile(payer, &budgeted, &self.lookup_tables, recent_blockhash)?;
+            let transaction =
+                VersionedTransaction::try_new(VersionedMessage::V0(message), signers)?;
+            last_signature = transaction.signatures[0];
+            before_send(&last_signature, last_valid_block_height).await?;
+
+            if let Err(e) = self
+                .rpc_client
+                .send_transaction_with_config(&transaction, self.rpc_config)
+                .await
+            {
+                // preflight simulation failed
+                return match e.get_transaction_error() {
+                    // the node doesn't know the blockhash yet or anymore, nothing was sent
+                    Some(TransactionError::BlockhashNotFound) => continue,
+                    Some(error) => Ok(TransactionOutcome::Failed {
+                        signature: last_signature,
+                        error,
+                        budget: None,
+                    }),
+                    None => Err(e.into()),
+                };
+            }
+
+            if let Some(outcome) = self
+                .wait_for_signature(&last_signature, last_valid_block_height, budget)
+                .await?
+            {
+                return Ok(outcome);
+            }
+        }
+
+        Ok(TransactionOutcome::Dropped { last_signature })
+    }
+
+    /// Polls until the transaction reaches the configured commitment, fails,
+    /// or its blockhash expires (returns None). Gives up with an error after `confirm_timeout`
+    async fn wait_for_signature(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+        budget: ComputeBudget,
+    ) -> Result<Option<TransactionOutcome>> {
+        let started = Instant::now();
+
+        loop {
+            match self.sent_status(signature, last_valid_block_height).await? {
+                SentStatus::Confirmed => {
+                    return Ok(Some(TransactionOutcome::Confirmed {
+                        signature: *signature,
+                        budget,
+                    }));
+                }
+                SentStatus::Failed(error) => {
+                    return Ok(Some(TransactionOutcome::Failed {
+                        signature: *signature,
+                        error,
+                        budget: Some(budget),
+                    }));
+                }
+                SentStatus::Expired => return Ok(None),
+                SentStatus::InFlight => {}
+            }
+
+            if started.elapsed() > self.submit_config.confirm_timeout {
+                return Err(anyhow!(
+                    "Transaction {} did not reach {:?} commitment within {:?}",
+                    signature,
+                    self.submit_config.commitment.commitment,
+                    self.submit_config.confirm_timeout
+                ));
+            }
+
+            tokio::time::sleep(self.submit_config.poll_interval).await;
+        }
+    }
+
+    /// Looks up a transaction sent with a blockhash valid up to `last_valid_block_height`. One
+    /// that was seen but is still below the commitment once the blockhash expired at that
+    /// commitment sits on an abandoned fork, so it is treated as expired too
+    pub async fn sent_status(
+        &self,
+        signature: &Signature,
+        last_valid_block_height: u64,
+    ) -> Result<SentStatus> {
+        let commitment = self.submit_config.commitment;
+
+        // read before the status, so that the status is at least as recent when deciding that
+        // the blockhash expired
+        let block_height = self
+            .rpc_client
+            .get_block_height_with_commitment(commitment)
+            .await?;
+        // with history, for transactions sent long ago by a runner that died
+        let status = self
+            .rpc_client
+            .get_signature_statuses_with_history(&[*signature])
+            .await?
+            .value
+            .into_iter()
+            .next()
+            .flatten();
+
+        if let Some(status) = status {
+            if let Some(error) = status.err {
+                return Ok(SentStatus::Failed(error));
+            }
+            if status.satisfies_commitment(commitment) {
+                return Ok(SentStatus::Confirmed);
+            }
+        }
+
+        Ok(if block_height > last_valid_block_height {
+            SentStatus::Expired
+        } else {
+            SentStatus::InFlight
+        })
+    }
+}
@@ -0,0 +1,448 @@
+//! Real-time notifications from the pubsub websocket.
+//!
+//! `subscribe` spawns a task that keeps one websocket connection open, decodes every notification
+//! into `blockchain_core` / SPL types and forwards it through a channel. When the connection drops
+//! it reconnects with exponential backoff, subscribes to everything again and sends
+//! `Notification::Reconnected`, since updates may have been missed in between.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use blockchain_core::accounts::event::Event;
+use futures::{
+    StreamExt,
+    stream::{BoxStream, select_all},
+};
+use solana_client::{
+    nonblocking::pubsub_client::PubsubClient,
+    rpc_config::{
+        CommitmentConfig, RpcAccountInfoConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter,
+        UiAccountEncoding,
+    },
+    rpc_response::{Response, RpcLogsResponse, UiAccount},
+};
+use solana_sdk::{
+    program_pack::Pack, pubkey::Pubkey, signature::Signature, transaction::TransactionError,
+};
+use spl_token::state::Account as TokenAccount;
+use tokio::{sync::mpsc, task::JoinHandle};
+use uuid::Uuid;
+
+use crate::ProfeciaClient;
+
+/// Surfpool serves the websocket on the port after the RPC one
+pub const DEFAULT_RPC_WS: &str = "ws://127.0.0.1:8900";
+
+const MIN_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+const CHANNEL_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Subscription {
+    /// Logs of every transaction that mentions the program
+    ProgramLogs,
+    /// The PDA of an event
+    Event(Uuid),
+    /// Any SPL token account, e.g. a user ATA or an event treasury
+    TokenAccount(Pubkey),
+}
+
+#[derive(Debug, Clone)]
+pub enum Notification {
+    Logs {
+        slot: u64,
+        signature: Signature,
+        err: Option<TransactionError>,
+        logs: Vec<String>,
+    },
+    Event {
+        slot: u64,
+        pubkey: Pubkey,
+        event: Event,
+    },
+    TokenAccount {
+        slot: u64,
+        pubkey: Pubkey,
+        account: TokenAccount,
+    },
+    /// The account no longer exists
+    Closed { slot: u64, pubkey: Pubkey },
+    /// The connection was lost and established again. Anything may have changed meanwhile
+    Reconnected,
+}
+
+/// Receives the notifications. Dropping it closes the connection
+pub struct Subscriptions {
+    receiver: mpsc::Receiver<Notification>,
+    task: JoinHandle<()>,
+}
+
+impl Subscriptions {
+    /// None only if the task stopped, which does not happen while this is alive
+    pub async fn recv(&mut self) -> Option<Notification> {
+        self.receiver.recv().await
+    }
+}
+
+impl Drop for Subscriptions {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+/// Must be called inside a tokio runtime
+pub fn subscribe(
+    ws_url: &str,
+    program_id: Pubkey,
+    commitment: CommitmentConfig,
+    subscriptions: Vec<Subscription>,
+) -> Subscriptions {
+    let (sender, receiver) = mpsc::channel(CHANNEL_CAPACITY);
+    let ws_url = ws_url.to_string();
+
+    let task = tokio::spawn(async move {
+        if subscriptions.is_empty() {
+            return;
+        }
+
+        let mut backoff = MIN_BACKOFF;
+        let mut first = true;
+
+        loop {
+            if let Ok(client) = PubsubClient::new(ws_url.as_str()).await {
+                backoff = MIN_BACKOFF;
+                if !first && sender.send(Notification::Reconnected).await.is_err() {
+                    return;
+                }
+                first = false;
+
+                // returns when the connection drops or when nobody is listening anymore
+                let _ = forward(&client, program_id, commitment, &subscriptions, &sender).await;
+                let _ = client.shutdown().await;
+                if sender.is_closed() {
+                    return;
+                }
+            }
+
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+        }
+    });
+
+    Subscriptions { receiver, task }
+}
+
+impl ProfeciaClient {
+    /// Same as `subscribe`, using the websocket, program and commitment of the cluster profile
+    pub fn subscribe(&self, subscriptions: Vec<Subscription>) -> Subscriptions {
+        subscribe(
+            &self.profile.ws_url,
+            self.profile.deployment.program_id,
+            self.submit_config.commitment,
+            subscriptions,
+        )
+    }
+}
+
+async fn forward(
+    client: &PubsubClient,
+    program_id: Pubkey,
+    commitment: CommitmentConfig,
+    subscriptions: &[Subscription],
+    sender: &mpsc::Sender<Notification>,
+) -> Result<()> {
+    let account_config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(commitment),
+        ..Default::default()
+    };
+
+    let mut streams: Vec<BoxStream<'_, Option<Notification>>> = Vec::new();
+    for subscription in subscriptions {
+        match *subscription {
+            Subscription::ProgramLogs => {
+                let (stream, _unsubscribe) = client
+                    .logs_subscribe(
+                        RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]),
+                        RpcTransactionLogsConfig {
+                            commitment: Some(commitment),
+                        },
+                    )
+                    .await?;
+                streams.push(stream.map(decode_logs).boxed());
+            }
+            Subscription::Event(uuid) => {
+                let pubkey = Event::find_program_address(&uuid, &program_id).0;
+                let (stream, _unsubscribe) = client
+                    .account_subscribe(&pubkey, Some(account_config.clone()))
+                    .await?;
+                streams.push(
+                    stream
+                        .map(move |response| decode_event(pubkey, response))
+                        .boxed(),
+                );
+            }
+            Subscription::TokenAccount(pubkey) => {
+                let (stream, _unsubscribe) = client
+                    .account_subscribe(&pubkey, Some(account_config.clone()))
+                    .await?;
+                streams.push(
+                    stream
+                        .map(move |response| decode_token_account(pubkey, response))
+                        .boxed(),
+                );
+            }
+        }
+    }
+
+    // every stream ends when the connection drops
+    let mut notifications = select_all(streams);
+    while let Some(notification) = notifications.next().await {
+        // notifications that cannot be decoded are skipped
+        let Some(notification) = notification else {
+            continue;
+        };
+        if sender.send(notification).await.is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+fn decode_logs(response: Response<RpcLogsResponse>) -> Option<Notification> {
+    Some(Notification::Logs {
+        slot: response.context.slot,
+        signature: response.value.signature.parse().ok()?,
+        err: response.value.err.map(TransactionError::from),
+        logs: response.value.logs,
+    })
+}
+
+/// None if the data cannot be read. Accounts without lamports were closed
+fn account_data(
+    pubkey: Pubkey,
+    response: &Response<UiAccount>,
+) -> Option<Result<Vec<u8>, Notification>> {
+    if response.value.lamports == 0 {
+        return Some(Err(Notification::Closed {
+            slot: response.context.slot,
+            pubkey,
+        }));
+    }
+    response.value.data.decode().map(Ok)
+}
+
+fn decode_event(pubkey: Pubkey, response: Response<UiAccount>) -> Option<Notification> {
+    let data = match account_data(pubkey, &response)? {
+        Ok(data) => data,
+        Err(closed) => return Some(closed),
+    };
+
+    Some(Notification::Event {
+        slot: response.context.slot,
+        pubkey,
+        event: Event::from_bytes(&data).ok()?,
+    })
+}
+
+fn decode_token_account(pubkey: Pubkey, response: Response<UiAccount>) -> Option<Notification> {
+    let data = match account_data(pubkey, &response)? {
+        Ok(data) => data,
+        Err(closed) => return Some(closed),
+    };
+
+    Some(Notification::TokenAccount {
+        slot: response.context.slot,
+        pubkey,
+        account: TokenAccount::unpack(&data).ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use blockchain_core::accounts::event::EventState;
+    use serde_json::json;
+    use spl_token::state::AccountState;
+
+    use super::*;
+
+    const SLOT: u64 = 42;
+
+    fn account_response(lamports: u64, data: &[u8]) -> Response<UiAccount> {
+        serde_json::from_value(json!({
+            "context": { "slot": SLOT },
+            "value": {
+                "lamports": lamports,
+                "data": [BASE64_STANDARD.encode(data), "base64"],
+                "owner": Pubkey::new_unique().to_string(),
+                "executable": false,
+                "rentEpoch": 0,
+                "space": data.len(),
+            },
+        }))
+        .unwrap()
+    }
+
+    fn logs_response(signature: &str, err: serde_json::Value) -> Response<RpcLogsResponse> {
+        serde_json::from_value(json!({
+            "context": { "slot": SLOT },
+            "value": {
+                "signature": signature,
+                "err": err,
+                "logs": ["Program log: hello"],
+            },
+        }))
+        .unwrap()
+    }
+
+    fn event_bytes() -> (Event, Vec<u8>) {
+        let event = Event {
+            uuid: Uuid::new_v4(),
+            description: "event".into(),
+            state: EventState::NotFinished,
+            options: HashMap::new(),
+            bump: 255,
+        };
+        let mut bytes = vec![0; event.len().unwrap() as usize];
+        event.write_into_bytes(&mut bytes).unwrap();
+        (event, bytes)
+    }
+
+    fn token_account_bytes(owner: Pubkey) -> Vec<u8> {
+        let mut bytes = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint: Pubkey::new_unique(),
+            owner,
+            amount: 7,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        }
+        .pack_into_slice(&mut bytes);
+        bytes
+    }
+
+    #[test]
+    fn decodes_logs() {
+        let signature = Signature::new_unique();
+        let notification = decode_logs(logs_response(&signature.to_string(), json!(null)));
+
+        let Some(Notification::Logs {
+            slot,
+            signature: decoded,
+            err,
+            logs,
+        }) = notification
+        else {
+            panic!("unexpected {notification:?}");
+        };
+        assert_eq!(slot, SLOT);
+        assert_eq!(decoded, signature);
+        assert_eq!(err, None);
+        assert_eq!(logs, vec!["Program log: hello".to_string()]);
+    }
+
+    #[test]
+    fn decodes_logs_of_failed_transaction() {
+        let signature = Signature::new_unique().to_string();
+        let notification = decode_logs(logs_response(&signature, json!("AccountNotFound")));
+
+        assert!(matches!(
+            notification,
+            Some(Notification::Logs {
+                err: Some(TransactionError::AccountNotFound),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_logs_with_invalid_signature() {
+        assert!(decode_logs(logs_response("not a signature", json!(null))).is_none());
+    }
+
+    #[test]
+    fn decodes_event() {
+        let pubkey = Pubkey::new_unique();
+        let (event, bytes) = event_bytes();
+
+        let Some(Notification::Event {
+            slot,
+            pubkey: decoded_pubkey,
+            event: decoded,
+        }) = decode_event(pubkey, account_response(1, &bytes))
+        else {
+            panic!("event not decoded");
+        };
+        assert_eq!(slot, SLOT);
+        assert_eq!(decoded_pubkey, pubkey);
+        assert_eq!(decoded.uuid, event.uuid);
+        assert_eq!(decoded.bump, event.bump);
+    }
+
+    #[test]
+    fn rejects_event_with_wrong_discriminator() {
+        let (_, mut bytes) = event_bytes();
+        bytes[0] = Event::DISCRIMINATOR + 1;
+
+        assert!(decode_event(Pubkey::new_unique(), account_response(1, &bytes)).is_none());
+    }
+
+    #[test]
+    fn rejects_truncated_event() {
+        let (_, bytes) = event_bytes();
+
+        let response = account_response(1, &bytes[..bytes.len() / 2]);
+        assert!(decode_event(Pubkey::new_unique(), response).is_none());
+        assert!(decode_event(Pubkey::new_unique(), account_response(1, &[])).is_none());
+    }
+
+    #[test]
+    fn event_without_lamports_is_closed() {
+        let pubkey = Pubkey::new_unique();
+
+        assert!(matches!(
+            decode_event(pubkey, account_response(0, &[])),
+            Some(Notification::Closed { slot: SLOT, pubkey: closed }) if closed == pubkey
+        ));
+    }
+
+    #[test]
+    fn decodes_token_account() {
+        let (pubkey, owner) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let bytes = token_account_bytes(owner);
+
+        let Some(Notification::TokenAccount {
+            slot,
+            pubkey: decoded_pubkey,
+            account,
+        }) = decode_token_account(pubkey, account_response(1, &bytes))
+        else {
+            panic!("token account not decoded");
+        };
+        assert_eq!(slot, SLOT);
+        assert_eq!(decoded_pubkey, pubkey);
+        assert_eq!(account.owner, owner);
+        assert_eq!(account.amount, 7);
+    }
+
+    #[test]
+    fn rejects_token_account_with_wrong_length() {
+        let mut bytes = token_account_bytes(Pubkey::new_unique());
+        bytes.push(0);
+        assert!(decode_token_account(Pubkey::new_unique(), account_response(1, &bytes)).is_none());
+
+        let response = account_response(1, &bytes[..TokenAccount::LEN - 1]);
+        assert!(decode_token_account(Pubkey::new_unique(), response).is_none());
+    }
+
+    #[test]
+    fn rejects_uninitialized_token_account() {
+        let bytes = vec![0; TokenAccount::LEN];
+
+        assert!(decode_token_account(Pubkey::new_unique(), account_response(1, &bytes)).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Cluster profiles read from TOML and the environment.
+
+use std::collections::HashMap;
+
+use blockchain_client::{ClusterProfile, Commitment, Deployment, Explorer, USDC_MINT};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+
+fn vars(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+    let vars: HashMap<String, String> = pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    move |name| vars.get(name).cloned()
+}
+
+#[test]
+fn toml_fields_are_optional() {
+    let program_id = Pubkey::new_unique();
+    let profile = ClusterProfile::from_toml(&format!(
+        r#"
+        name = "staging"
+        rpc_url = "https://rpc.staging.example"
+        program_id = "{}"
+        commitment = "finalized"
+        explorer = "none"
+        "#,
+        program_id
+    ))
+    .unwrap();
+
+    assert_eq!(profile.name, "staging");
+    assert_eq!(profile.rpc_url, "https://rpc.staging.example");
+    assert_eq!(profile.ws_url, ClusterProfile::surfpool().ws_url);
+    assert_eq!(
+        profile.deployment,
+        Deployment {
+            program_id,
+            collateral_mint: USDC_MINT,
+        }
+    );
+    assert_eq!(profile.commitment, Commitment::Finalized);
+    assert_eq!(profile.explorer, Explorer::None);
+
+    assert!(ClusterProfile::from_toml(r#"program_id = "not an address""#).is_err());
+}
+
+#[test]
+fn env_overrides_the_preset() {
+    let mint = Pubkey::new_unique();
+    let profile = ClusterProfile::from_vars(vars(&[
+        ("CLUSTER_PROFILE", "localnet"),
+        ("RPC_URL", "http://validator:8899"),
+        ("COLLATERAL_MINT", &mint.to_string()),
+        ("COMMITMENT", "processed"),
+    ]))
+    .unwrap();
+
+    assert_eq!(profile.name, "localnet");
+    assert_eq!(profile.rpc_url, "http://validator:8899");
+    assert_eq!(profile.deployment.collateral_mint, mint);
+    assert_eq!(profile.commitment, Commitment::Processed);
+    assert_eq!(profile.explorer, Explorer::SolanaExplorer);
+
+    assert!(ClusterProfile::from_vars(vars(&[("COMMITMENT", "eventually")])).is_err());
+    assert!(ClusterProfile::from_vars(vars(&[("CLUSTER_PROFILE", "/missing.toml")])).is_err());
+}
+
+#[test]
+fn explorer_links() {
+    let pubkey = Pubkey::new_unique();
+    let signature = Signature::default();
+    let mut profile = ClusterProfile {
+        external_rpc_url: "https://rpc.example".into(),
+        ..ClusterProfile::surfpool()
+    };
+
+    assert_eq!(
+        profile.account_url(&pubkey),
+        format!(
+            "https://solscan.io/account/{}?cluster=custom&customUrl=https://rpc.example",
+            pubkey
+        )
+    );
+
+    profile.explorer = Explorer::SolanaExplorer;
+    assert_eq!(
+        profile.transaction_url(&signature),
+        format!(
+            "https://explorer.solana.com/tx/{}?cluster=custom&customUrl=https://rpc.example",
+            signature
+        )
+    );
+
+    profile.explorer = Explorer::Custom {
+        account: "https://scan.example/a/{address}".into(),
+        transaction: "https://scan.example/t/{signature}?rpc={rpc_url}".into(),
+    };
+    assert_eq!(
+        profile.account_url(&pubkey),
+        format!("https://scan.example/a/{}", pubkey)
+    );
+    assert_eq!(
+        profile.transaction_url(&signature),
+        format!(
+            "https://scan.example/t/{}?rpc=https://rpc.example",
+            signature
+        )
+    );
+
+    profile.explorer = Explorer::None;
+    assert_eq!(profile.account_url(&pubkey), pubkey.to_string());
+}
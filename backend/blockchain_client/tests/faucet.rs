@@ -0,0 +1,143 @@
+//! Faucet caps and records, with a backend that never touches the network.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use base64::{Engine, engine::general_purpose::STANDARD as BASE64};
+use blockchain_client::{
+    ClusterProfile, Faucet, FaucetError, ProfeciaClient, USDC_MINT,
+    faucet::{CreditLedger, FaucetBackend, MemoryLedger, mint_account_json},
+};
+use blockchain_core::money::MicroUsdc;
+use solana_sdk::{
+    program_option::COption,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::{Keypair, Signature},
+};
+use spl_token::state::Mint;
+
+/// Balances kept in memory, so that credits can be checked to add up
+#[derive(Default, Clone)]
+struct FakeBackend {
+    balances: Arc<Mutex<HashMap<Pubkey, u64>>>,
+}
+
+impl FakeBackend {
+    fn balance(&self, wallet: &Pubkey) -> u64 {
+        self.balances
+            .lock()
+            .unwrap()
+            .get(wallet)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+#[async_trait]
+impl FaucetBackend for FakeBackend {
+    async fn deposit(
+        &self,
+        _client: &ProfeciaClient,
+        wallet: &Pubkey,
+        amount: MicroUsdc,
+    ) -> Result<Option<Signature>> {
+        *self.balances.lock().unwrap().entry(*wallet).or_default() += amount.0;
+        Ok(None)
+    }
+}
+
+/// Shares its records with the test
+#[derive(Default, Clone)]
+struct SharedLedger(Arc<MemoryLedger>);
+
+#[async_trait]
+impl CreditLedger for SharedLedger {
+    async fn credited(&self, wallet: &Pubkey) -> Result<MicroUsdc> {
+        self.0.credited(wallet).await
+    }
+
+    async fn record(&self, credit: &blockchain_client::Credit) -> Result<()> {
+        self.0.record(credit).await
+    }
+}
+
+fn client() -> ProfeciaClient {
+    ProfeciaClient::new_with_wallet(Box::new(Keypair::new()), ClusterProfile::surfpool())
+}
+
+#[tokio::test]
+async fn credits_add_up_and_are_recorded() {
+    let backend = FakeBackend::default();
+    let ledger = SharedLedger::default();
+    let faucet = Faucet::new(Box::new(backend.clone())).with_ledger(Box::new(ledger.clone()));
+    let client = client();
+    let wallet = Pubkey::new_unique();
+
+    faucet
+        .credit(&client, &wallet, MicroUsdc(10))
+        .await
+        .unwrap();
+    faucet.credit(&client, &wallet, MicroUsdc(5)).await.unwrap();
+
+    assert_eq!(backend.balance(&wallet), 15);
+    let credits = ledger.0.credits();
+    assert_eq!(credits.len(), 2);
+    assert!(credits.iter().all(|credit| credit.wallet == wallet));
+    assert_eq!(ledger.credited(&wallet).await.unwrap(), MicroUsdc(15));
+}
+
+#[tokio::test]
+async fn cap_is_per_wallet() {
+    let backend = FakeBackend::default();
+    let faucet = Faucet::new(Box::new(backend.clone())).with_cap(MicroUsdc(100));
+    let client = client();
+    let wallet = Pubkey::new_unique();
+    let other = Pubkey::new_unique();
+
+    faucet
+        .credit(&client, &wallet, MicroUsdc(60))
+        .await
+        .unwrap();
+    let result = faucet.credit(&client, &wallet, MicroUsdc(41)).await;
+    assert!(matches!(
+        result,
+        Err(FaucetError::CapExceeded {
+            credited: MicroUsdc(60),
+            cap: MicroUsdc(100),
+            ..
+        })
+    ));
+    assert_eq!(backend.balance(&wallet), 60);
+
+    // reaching the cap exactly is fine, and other wallets are unaffected
+    faucet
+        .credit(&client, &wallet, MicroUsdc(40))
+        .await
+        .unwrap();
+    faucet
+        .credit(&client, &other, MicroUsdc(100))
+        .await
+        .unwrap();
+    assert_eq!(backend.balance(&wallet), 100);
+    assert_eq!(backend.balance(&other), 100);
+}
+
+#[test]
+fn mint_account_has_the_authority() {
+    let authority = Pubkey::new_unique();
+    let json = mint_account_json(&USDC_MINT, &authority);
+
+    assert_eq!(json["pubkey"], USDC_MINT.to_string());
+    assert_eq!(json["account"]["owner"], spl_token::id().to_string());
+    let data = BASE64
+        .decode(json["account"]["data"][0].as_str().unwrap())
+        .unwrap();
+    let mint = Mint::unpack(&data).unwrap();
+    assert_eq!(mint.mint_authority, COption::Some(authority));
+    assert_eq!(mint.decimals, 6);
+}
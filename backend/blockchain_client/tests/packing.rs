@@ -0,0 +1,156 @@
+//! Packing event creation into transactions.
+
+use blockchain_client::{
+    Deployment, EventPacker,
+    packing::{MAX_TRANSACTION_SIZE, lookup_table_addresses, transaction_size},
+};
+use blockchain_core::{
+    accounts::event::{Event, EventOption, EventState},
+    instructions::{AddOptionArgs, CreateEmptyEventArgs},
+};
+use solana_sdk::{
+    message::AddressLookupTableAccount, pubkey::Pubkey, signature::Keypair, signer::Signer,
+};
+use uuid::Uuid;
+
+fn mints(num_options: usize) -> Vec<(Keypair, Keypair)> {
+    (0..num_options)
+        .map(|_| (Keypair::new(), Keypair::new()))
+        .collect()
+}
+
+fn packer<'a>(payer: Pubkey, mints: &'a [(Keypair, Keypair)]) -> (EventPacker<'a>, Vec<Uuid>) {
+    let event_uuid = Uuid::new_v4();
+    let mut packer = EventPacker::new(
+        Deployment::DEFAULT,
+        payer,
+        CreateEmptyEventArgs {
+            uuid: event_uuid,
+            description: "event".into(),
+        },
+    );
+
+    let mut options = Vec::new();
+    for (i, (yes_mint, no_mint)) in mints.iter().enumerate() {
+        let option_uuid = Uuid::new_v4();
+        packer = packer.add_option(
+            yes_mint,
+            no_mint,
+            AddOptionArgs {
+                event_uuid,
+                option_uuid,
+                option_info: EventOption {
+                    option_desc: format!("option {i}"),
+                    yes_mint: yes_mint.pubkey(),
+                    no_mint: no_mint.pubkey(),
+                },
+            },
+        );
+        options.push(option_uuid);
+    }
+
+    (packer, options)
+}
+
+#[test]
+fn chunks_fit_and_keep_option_order() {
+    let payer = Pubkey::new_unique();
+    let mints = mints(10);
+    let (packer, options) = packer(payer, &mints);
+
+    let chunks = packer.pack().unwrap();
+    assert!(chunks.len() > 1);
+    assert!(chunks[0].creates_event);
+    assert!(chunks[1..].iter().all(|chunk| !chunk.creates_event));
+
+    for chunk in &chunks {
+        assert!(
+            transaction_size(&payer, &chunk.instructions, &[]).unwrap() <= MAX_TRANSACTION_SIZE
+        );
+        assert_eq!(chunk.mints.len(), chunk.options.len() * 2);
+    }
+
+    let packed: Vec<Uuid> = chunks
+        .iter()
+        .flat_map(|chunk| chunk.options.iter().copied())
+        .collect();
+    assert_eq!(packed, options);
+}
+
+#[test]
+fn event_without_options_is_one_chunk() {
+    let (packer, _) = packer(Pubkey::new_unique(), &[]);
+
+    let chunks = packer.pack().unwrap();
+    assert_eq!(chunks.len(), 1);
+    assert_eq!(chunks[0].instructions.len(), 1);
+    assert!(chunks[0].options.is_empty());
+}
+
+#[test]
+fn lookup_tables_shrink_transactions() {
+    let payer = Pubkey::new_unique();
+    let mints = mints(10);
+    let (packer, _) = packer(payer, &mints);
+    let table = AddressLookupTableAccount {
+        key: Pubkey::new_unique(),
+        addresses: lookup_table_addresses(&Deployment::DEFAULT),
+    };
+
+    let chunks = packer.pack().unwrap();
+    let plain = transaction_size(&payer, &chunks[0].instructions, &[]).unwrap();
+    let looked_up = transaction_size(
+        &payer,
+        &chunks[0].instructions,
+        std::slice::from_ref(&table),
+    )
+    .unwrap();
+    assert!(looked_up < plain);
+
+    let packed = packer.with_lookup_tables(vec![table]).pack().unwrap();
+    assert!(packed.len() <= chunks.len());
+}
+
+/// The event as it is on chain after its first option was added
+fn event_holding(option_uuid: Uuid, (yes_mint, no_mint): &(Keypair, Keypair)) -> Event {
+    Event {
+        uuid: Uuid::new_v4(),
+        description: "event".into(),
+        state: EventState::NotFinished,
+        options: [(
+            option_uuid,
+            EventOption {
+                option_desc: "option 0".into(),
+                yes_mint: yes_mint.pubkey(),
+                no_mint: no_mint.pubkey(),
+            },
+        )]
+        .into(),
+        bump: 255,
+    }
+}
+
+#[test]
+fn resuming_skips_the_event_and_its_options() {
+    let payer = Pubkey::new_unique();
+    let mints = mints(3);
+    let (packer, options) = packer(payer, &mints);
+    let event = event_holding(options[0], &mints[0]);
+
+    let chunks = packer.resuming(&event).pack().unwrap();
+    assert!(chunks.iter().all(|chunk| !chunk.creates_event));
+    let packed: Vec<Uuid> = chunks
+        .iter()
+        .flat_map(|chunk| chunk.options.iter().copied())
+        .collect();
+    assert_eq!(packed, options[1..]);
+}
+
+#[test]
+fn resuming_a_complete_event_sends_nothing() {
+    let mints = mints(1);
+    let (packer, options) = packer(Pubkey::new_unique(), &mints);
+    let event = event_holding(options[0], &mints[0]);
+
+    assert!(packer.resuming(&event).pack().unwrap().is_empty());
+}
@@ -0,0 +1,1438 @@
+//! Runs the compiled program inside LiteSVM and exercises every instruction.
+//!
+//! The tests need the Solana SBF toolchain, so they are ignored by default. Run them with
+//! `cargo test -p blockchain_client --test program -- --ignored`. Before the first test, the
+//! program is rebuilt with `cargo build-sbf` when `blockchain_program/target/deploy/blockchain_program.so`
+//! is missing or older than the sources of the program or of `blockchain_core`, since the account
+//! layouts would not match. Set `PROFECIA_PROGRAM_SO` to test a prebuilt binary instead. CI runs
+//! them in the `program` job of `.github/workflows/backend.yml`.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::OnceLock,
+    time::SystemTime,
+};
+
+use blockchain_client::{Deployment, MARKETPLACE_PROGRAM, USDC_MINT, instructions};
+use blockchain_core::{
+    accounts::event::{Event, EventOption, EventState},
+    accounts::order::TokenOption,
+    error::MarketError,
+    instructions::{
+        AddOptionArgs, CloseEventArgs, CreateEmptyEventArgs, CreateEventArgs, FakeCancelOrderArgs,
+        FakeCreateOrderArgs, FakeGetRewardArgs, FakeMatchOrderArgs, GetRewardArgs,
+        MarketInstruction, TransferSharesArgs,
+    },
+    money::{MicroUsdc, Price, Shares},
+};
+use litesvm::{LiteSVM, types::TransactionResult};
+use solana_sdk::{
+    account::Account,
+    instruction::InstructionError,
+    message::{AccountMeta, Instruction},
+    native_token::LAMPORTS_PER_SOL,
+    program_pack::Pack,
+    pubkey::Pubkey,
+    signature::Keypair,
+    signer::Signer,
+    transaction::{Transaction, TransactionError},
+};
+use spl_associated_token_account::get_associated_token_address;
+use spl_token::{
+    solana_program::program_option::COption,
+    state::{Account as TokenAccount, AccountState, Mint},
+};
+use uuid::Uuid;
+
+struct Harness {
+    svm: LiteSVM,
+    admin: Keypair,
+}
+
+/// An event with a single option, as created by the API
+struct TestEvent {
+    uuid: Uuid,
+    option_uuid: Uuid,
+    yes_mint: Pubkey,
+    no_mint: Pubkey,
+}
+
+fn default_program_path() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("../blockchain_program/target/deploy/blockchain_program.so")
+}
+
+/// Most recent modification of any file under `dir`
+fn newest_modification(dir: &Path) -> Option<SystemTime> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.is_dir() {
+                newest_modification(&path)
+            } else {
+                entry.metadata().and_then(|m| m.modified()).ok()
+            }
+        })
+        .max()
+}
+
+fn is_stale(binary: &Path) -> bool {
+    let Ok(built) = fs::metadata(binary).and_then(|m| m.modified()) else {
+        return true;
+    };
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+
+    ["../blockchain_program/src", "../blockchain_core/src"]
+        .iter()
+        .filter_map(|dir| newest_modification(&root.join(dir)))
+        .any(|modified| modified > built)
+}
+
+/// Builds the program from the current sources unless it is up to date. Panics if it cannot be
+/// built, so that a missing toolchain fails the suite instead of passing it
+fn build_program() -> PathBuf {
+    let path = default_program_path();
+    if !is_stale(&path) {
+        return path;
+    }
+
+    let manifest =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../blockchain_program/Cargo.toml");
+    let status = Command::new("cargo")
+        .arg("build-sbf")
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .status()
+        .expect("failed to run `cargo build-sbf`, is the Solana toolchain installed?");
+    assert!(status.success(), "`cargo build-sbf` failed: {status}");
+    assert!(
+        path.exists(),
+        "`cargo build-sbf` did not produce {}",
+        path.display()
+    );
+
+    path
+}
+
+fn program_path() -> &'static Path {
+    static PATH: OnceLock<PathBuf> = OnceLock::new();
+    PATH.get_or_init(|| match std::env::var("PROFECIA_PROGRAM_SO") {
+        Ok(path) => {
+            let path = PathBuf::from(path);
+            assert!(path.exists(), "{} not found", path.display());
+            path
+        }
+        Err(_) => build_program(),
+    })
+}
+
+impl Harness {
+    fn new() -> Self {
+        let path = program_path();
+
+        let mut svm = LiteSVM::new();
+        svm.add_program_from_file(MARKETPLACE_PROGRAM, path)
+            .expect("failed to load program");
+
+        // mock USDC mint, at the same address as the real one since the program checks it
+        let mut mint_data = vec![0; Mint::LEN];
+        Mint {
+            mint_authority: COption::None,
+            supply: 0,
+            decimals: 6,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut mint_data);
+        svm.set_account(
+            USDC_MINT,
+            Account {
+                lamports: svm.minimum_balance_for_rent_exemption(Mint::LEN),
+                data: mint_data,
+                owner: spl_token::ID,
+                executable: false,
+                rent_epoch: 0,
+            },
+        )
+        .unwrap();
+
+        let admin = Keypair::new();
+        svm.airdrop(&admin.pubkey(), 100 * LAMPORTS_PER_SOL)
+            .unwrap();
+
+        Self { svm, admin }
+    }
+
+    /// Sends a transaction paid for by the admin. A new blockhash is used every time so that
+    /// identical transactions are not rejected as duplicates
+    #[allow(clippy::result_large_err)]
+    fn send(&mut self, instruction: Instruction, signers: &[&Keypair]) -> TransactionResult {
+        self.svm.expire_blockhash();
+
+        let mut all_signers: Vec<&Keypair> = vec![&self.admin];
+        all_signers.extend(signers);
+
+        let transaction = Transaction::new_signed_with_payer(
+            &[instruction],
+            Some(&self.admin.pubkey()),
+            &all_signers,
+            self.svm.latest_blockhash(),
+        );
+
+        self.svm.send_transaction(transaction)
+    }
+
+    fn new_wallet(&mut self) -> Keypair {
+        let wallet = Keypair::new();
+        self.svm
+            .airdrop(&wallet.pubkey(), 10 * LAMPORTS_PER_SOL)
+            .unwrap();
+        wallet
+    }
+
+    /// Overwrites the USDC ATA of `owner`, creating it if needed
+    fn set_usdc(&mut self, owner: &Pubkey, amount: MicroUsdc) {
+        self.set_token_account(owner, &USDC_MINT, amount.0);
+    }
+
+    fn set_token_account(&mut self, owner: &Pubkey, mint: &Pubkey, amount: u64) {
+        let mut data = vec![0; TokenAccount::LEN];
+        TokenAccount {
+            mint: *mint,
+            owner: *owner,
+            amount,
+            delegate: COption::None,
+            state: AccountState::Initialized,
+            is_native: COption::None,
+            delegated_amount: 0,
+            close_authority: COption::None,
+        }
+        .pack_into_slice(&mut data);
+
+        self.svm
+            .set_account(
+                get_associated_token_address(owner, mint),
+                Account {
+                    lamports: self
+                        .svm
+                        .minimum_balance_for_rent_exemption(TokenAccount::LEN),
+                    data,
+                    owner: spl_token::ID,
+                    executable: false,
+                    rent_epoch: 0,
+                },
+            )
+            .unwrap();
+    }
+
+    /// 0 if the ATA does not exist
+    fn token_balance(&self, owner: &Pubkey, mint: &Pubkey) -> u64 {
+        self.svm
+            .get_account(&get_associated_token_address(owner, mint))
+            .filter(|account| !account.data.is_empty())
+            .map(|account| TokenAccount::unpack(&account.data).unwrap().amount)
+            .unwrap_or(0)
+    }
+
+    fn usdc_balance(&self, owner: &Pubkey) -> MicroUsdc {
+        MicroUsdc(self.token_balance(owner, &USDC_MINT))
+    }
+
+    fn fetch_event(&self, uuid: &Uuid) -> Event {
+        let account = self
+            .svm
+            .get_account(&event_pda(uuid))
+            .expect("event does not exist");
+        assert_eq!(account.owner, MARKETPLACE_PROGRAM);
+        Event::from_bytes(&account.data).unwrap()
+    }
+
+    fn create_empty_event(&mut self) -> Uuid {
+        let uuid = Uuid::new_v4();
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &self.admin.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid,
+                description: "event".into(),
+            },
+        )
+        .unwrap();
+        self.send(instruction, &[]).unwrap();
+        uuid
+    }
+
+    fn add_option(&mut self, event_uuid: Uuid) -> TestEvent {
+        let option_uuid = Uuid::new_v4();
+        let yes_mint = Keypair::new();
+        let no_mint = Keypair::new();
+        let instruction = add_option_instruction(
+            &self.admin.pubkey(),
+            event_uuid,
+            option_uuid,
+            &yes_mint.pubkey(),
+            &no_mint.pubkey(),
+        );
+        self.send(instruction, &[&yes_mint, &no_mint]).unwrap();
+
+        TestEvent {
+            uuid: event_uuid,
+            option_uuid,
+            yes_mint: yes_mint.pubkey(),
+            no_mint: no_mint.pubkey(),
+        }
+    }
+
+    fn create_event_with_option(&mut self) -> TestEvent {
+        let uuid = self.create_empty_event();
+        self.add_option(uuid)
+    }
+
+    /// Places a YES and a NO order for `shares` at complementary prices and matches them
+    fn buy_and_match(
+        &mut self,
+        event: &TestEvent,
+        user_yes: &Keypair,
+        user_no: &Keypair,
+        shares: Shares,
+        yes_price: Price,
+    ) {
+        for (user, price) in [(user_yes, yes_price), (user_no, yes_price.complement())] {
+            let instruction = instructions::fake_create_order(
+                &Deployment::DEFAULT,
+                &user.pubkey(),
+                &FakeCreateOrderArgs {
+                    event_uuid: event.uuid,
+                    option_uuid: event.option_uuid,
+                    num_shares: shares,
+                    price_per_share: price.per_share(),
+                },
+            )
+            .unwrap();
+            self.send(instruction, &[user]).unwrap();
+        }
+
+        let instruction = instructions::fake_match_order(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &user_no.pubkey(),
+            &event.yes_mint,
+            &event.no_mint,
+            &FakeMatchOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: shares,
+            },
+        )
+        .unwrap();
+        self.send(instruction, &[user_yes, user_no]).unwrap();
+    }
+}
+
+fn event_pda(uuid: &Uuid) -> Pubkey {
+    Event::find_program_address(uuid, &MARKETPLACE_PROGRAM).0
+}
+
+fn treasury(uuid: &Uuid) -> Pubkey {
+    get_associated_token_address(&event_pda(uuid), &USDC_MINT)
+}
+
+fn add_option_instruction(
+    payer: &Pubkey,
+    event_uuid: Uuid,
+    option_uuid: Uuid,
+    yes_mint: &Pubkey,
+    no_mint: &Pubkey,
+) -> Instruction {
+    instructions::add_option(
+        &Deployment::DEFAULT,
+        payer,
+        yes_mint,
+        no_mint,
+        &AddOptionArgs {
+            event_uuid,
+            option_uuid,
+            option_info: EventOption {
+                option_desc: "option".into(),
+                yes_mint: *yes_mint,
+                no_mint: *no_mint,
+            },
+        },
+    )
+    .unwrap()
+}
+
+fn raw_instruction(instruction: &MarketInstruction, accounts: Vec<AccountMeta>) -> Instruction {
+    Instruction::new_with_bytes(
+        MARKETPLACE_PROGRAM,
+        &wincode::serialize(instruction).unwrap(),
+        accounts,
+    )
+}
+
+/// Replaces the account at `index`, keeping its writable and signer flags
+fn replace_account(mut instruction: Instruction, index: usize, pubkey: Pubkey) -> Instruction {
+    instruction.accounts[index].pubkey = pubkey;
+    instruction
+}
+
+/// Keeps the account but stops requiring it to sign.
+/// The program does not check signers itself, it relies on the runtime rejecting the CPIs that
+/// need them, which fails with `PrivilegeEscalation`
+fn drop_signer(mut instruction: Instruction, index: usize) -> Instruction {
+    instruction.accounts[index].is_signer = false;
+    instruction
+}
+
+fn assert_instruction_error(result: TransactionResult, expected: InstructionError) {
+    match result {
+        Ok(meta) => panic!("transaction succeeded, logs: {:#?}", meta.logs),
+        Err(failed) => assert_eq!(
+            failed.err,
+            TransactionError::InstructionError(0, expected),
+            "logs: {:#?}",
+            failed.meta.logs
+        ),
+    }
+}
+
+/// For failures where the runtime reports a transaction level error instead of the instruction
+/// error, e.g. when the event was already resized before the failing check
+fn assert_failed_with_log(result: TransactionResult, expected: &str) {
+    match result {
+        Ok(meta) => panic!("transaction succeeded, logs: {:#?}", meta.logs),
+        Err(failed) => assert!(
+            failed.meta.logs.iter().any(|log| log.contains(expected)),
+            "expected {expected:?} in logs: {:#?}",
+            failed.meta.logs
+        ),
+    }
+}
+
+fn assert_market_error(result: TransactionResult, expected: MarketError) {
+    assert_instruction_error(result, InstructionError::Custom(expected as u32));
+}
+
+const PRICE: Price = match Price::new(60) {
+    Some(price) => price,
+    None => panic!(),
+};
+
+mod create_empty_event {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn creates_event_and_treasury() {
+        let mut harness = Harness::new();
+
+        let uuid = harness.create_empty_event();
+
+        let event = harness.fetch_event(&uuid);
+        assert_eq!(event.uuid, uuid);
+        assert!(matches!(event.state, EventState::NotFinished));
+        assert!(event.options.is_empty());
+
+        let event_account = harness.svm.get_account(&event_pda(&uuid)).unwrap();
+        assert_eq!(event_account.data[0], Event::DISCRIMINATOR);
+
+        let treasury_account = harness.svm.get_account(&treasury(&uuid)).unwrap();
+        let treasury_data = TokenAccount::unpack(&treasury_account.data).unwrap();
+        assert_eq!(treasury_data.mint, USDC_MINT);
+        assert_eq!(treasury_data.owner, event_pda(&uuid));
+        assert_eq!(treasury_data.amount, 0);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_pda() {
+        let mut harness = Harness::new();
+
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid: Uuid::new_v4(),
+                description: "event".into(),
+            },
+        )
+        .unwrap();
+        // PDA of another event
+        let instruction = replace_account(instruction, 1, event_pda(&Uuid::new_v4()));
+
+        assert_market_error(harness.send(instruction, &[]), MarketError::MarketPDA);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_mint() {
+        let mut harness = Harness::new();
+
+        let uuid = Uuid::new_v4();
+        let fake_usdc = Pubkey::new_unique();
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid,
+                description: "event".into(),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(instruction, 2, fake_usdc);
+        let instruction = replace_account(
+            instruction,
+            3,
+            get_associated_token_address(&event_pda(&uuid), &fake_usdc),
+        );
+
+        assert_market_error(harness.send(instruction, &[]), MarketError::UsdcMint);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_existing_event() {
+        let mut harness = Harness::new();
+
+        let uuid = harness.create_empty_event();
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid,
+                description: "again".into(),
+            },
+        )
+        .unwrap();
+
+        assert_instruction_error(
+            harness.send(instruction, &[]),
+            InstructionError::AccountAlreadyInitialized,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_signer() {
+        let mut harness = Harness::new();
+
+        let payer = harness.new_wallet();
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &payer.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid: Uuid::new_v4(),
+                description: "event".into(),
+            },
+        )
+        .unwrap();
+        let instruction = drop_signer(instruction, 0);
+
+        assert_instruction_error(
+            harness.send(instruction, &[]),
+            InstructionError::PrivilegeEscalation,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_token_program() {
+        let mut harness = Harness::new();
+
+        let instruction = instructions::create_empty_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CreateEmptyEventArgs {
+                uuid: Uuid::new_v4(),
+                description: "event".into(),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(instruction, 5, spl_associated_token_account::ID);
+
+        assert_instruction_error(
+            harness.send(instruction, &[]),
+            InstructionError::IncorrectProgramId,
+        );
+    }
+}
+
+mod create_event {
+    use super::*;
+
+    fn create_event_args(num_options: usize) -> (CreateEventArgs, Vec<Keypair>) {
+        let mut options = HashMap::new();
+        let mut mints = Vec::new();
+        for i in 0..num_options {
+            let yes_mint = Keypair::new();
+            let no_mint = Keypair::new();
+            options.insert(
+                Uuid::new_v4(),
+                EventOption {
+                    option_desc: format!("option {i}"),
+                    yes_mint: yes_mint.pubkey(),
+                    no_mint: no_mint.pubkey(),
+                },
+            );
+            mints.push(yes_mint);
+            mints.push(no_mint);
+        }
+
+        let args = CreateEventArgs {
+            uuid: Uuid::new_v4(),
+            description: "event".into(),
+            options,
+        };
+
+        (args, mints)
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn creates_event_with_mints() {
+        let mut harness = Harness::new();
+
+        let (args, mints) = create_event_args(2);
+        let instruction =
+            instructions::create_event(&Deployment::DEFAULT, &harness.admin.pubkey(), &args)
+                .unwrap();
+        let signers: Vec<&Keypair> = mints.iter().collect();
+        harness.send(instruction, &signers).unwrap();
+
+        let event = harness.fetch_event(&args.uuid);
+        assert_eq!(event.options.len(), 2);
+        for option in event.options.values() {
+            for mint in [option.yes_mint, option.no_mint] {
+                let account = harness.svm.get_account(&mint).unwrap();
+                let mint_data = Mint::unpack(&account.data).unwrap();
+                assert_eq!(
+                    mint_data.mint_authority,
+                    COption::Some(event_pda(&args.uuid))
+                );
+                assert_eq!(mint_data.decimals, 6);
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_mint_accounts() {
+        let mut harness = Harness::new();
+
+        let (args, mints) = create_event_args(1);
+        let mut instruction =
+            instructions::create_event(&Deployment::DEFAULT, &harness.admin.pubkey(), &args)
+                .unwrap();
+        // drop the NO mint
+        instruction.accounts.pop();
+
+        assert_market_error(
+            harness.send(instruction, &[&mints[0]]),
+            MarketError::InvalidAccounts,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_reused_mints() {
+        let mut harness = Harness::new();
+
+        let existing = harness.create_event_with_option();
+
+        let (mut args, mints) = create_event_args(1);
+        let option = args.options.values_mut().next().unwrap();
+        option.yes_mint = existing.yes_mint;
+        let instruction =
+            instructions::create_event(&Deployment::DEFAULT, &harness.admin.pubkey(), &args)
+                .unwrap();
+        // the existing mint cannot sign anymore, but it is already initialized anyway
+        let instruction = drop_signer(instruction, 7);
+
+        assert_instruction_error(
+            harness.send(instruction, &[&mints[1]]),
+            InstructionError::AccountAlreadyInitialized,
+        );
+    }
+}
+
+mod add_option {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn adds_option_and_mints() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let second = harness.add_option(event.uuid);
+
+        let event_data = harness.fetch_event(&event.uuid);
+        assert_eq!(event_data.options.len(), 2);
+        let option = &event_data.options[&second.option_uuid];
+        assert_eq!(option.yes_mint, second.yes_mint);
+        assert_eq!(option.no_mint, second.no_mint);
+
+        let account = harness.svm.get_account(&second.yes_mint).unwrap();
+        assert_eq!(account.owner, spl_token::ID);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_reused_mints() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let instruction = add_option_instruction(
+            &harness.admin.pubkey(),
+            event.uuid,
+            Uuid::new_v4(),
+            &event.yes_mint,
+            &event.no_mint,
+        );
+        let instruction = drop_signer(drop_signer(instruction, 5), 6);
+
+        assert_failed_with_log(
+            harness.send(instruction, &[]),
+            "instruction requires an uninitialized account",
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_mint_signer() {
+        let mut harness = Harness::new();
+
+        let uuid = harness.create_empty_event();
+        let yes_mint = Keypair::new();
+        let no_mint = Keypair::new();
+        let instruction = add_option_instruction(
+            &harness.admin.pubkey(),
+            uuid,
+            Uuid::new_v4(),
+            &yes_mint.pubkey(),
+            &no_mint.pubkey(),
+        );
+        let instruction = drop_signer(instruction, 6);
+
+        assert_failed_with_log(
+            harness.send(instruction, &[&yes_mint]),
+            "Cross-program invocation with unauthorized signer or writable account",
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_unknown_event() {
+        let mut harness = Harness::new();
+
+        let yes_mint = Keypair::new();
+        let no_mint = Keypair::new();
+        let instruction = add_option_instruction(
+            &harness.admin.pubkey(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            &yes_mint.pubkey(),
+            &no_mint.pubkey(),
+        );
+
+        assert_market_error(
+            harness.send(instruction, &[&yes_mint, &no_mint]),
+            MarketError::EventDeser,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_event_of_another_uuid() {
+        let mut harness = Harness::new();
+
+        let uuid = harness.create_empty_event();
+        let other = harness.create_empty_event();
+        let yes_mint = Keypair::new();
+        let no_mint = Keypair::new();
+        let instruction = add_option_instruction(
+            &harness.admin.pubkey(),
+            uuid,
+            Uuid::new_v4(),
+            &yes_mint.pubkey(),
+            &no_mint.pubkey(),
+        );
+        let instruction = replace_account(instruction, 1, event_pda(&other));
+
+        assert_market_error(
+            harness.send(instruction, &[&yes_mint, &no_mint]),
+            MarketError::InvalidEvent,
+        );
+    }
+}
+
+mod close_event {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn marks_event_finished() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let instruction = instructions::close_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CloseEventArgs { uuid: event.uuid },
+        )
+        .unwrap();
+        harness.send(instruction, &[]).unwrap();
+
+        let event_data = harness.fetch_event(&event.uuid);
+        assert!(matches!(event_data.state, EventState::Finished));
+        // options are kept
+        assert_eq!(event_data.options.len(), 1);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_unknown_event() {
+        let mut harness = Harness::new();
+
+        let uuid = harness.create_empty_event();
+        let instruction = instructions::close_event(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &CloseEventArgs {
+                uuid: Uuid::new_v4(),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(instruction, 1, event_pda(&uuid));
+
+        assert_market_error(harness.send(instruction, &[]), MarketError::InvalidEvent);
+    }
+}
+
+mod fake_create_order {
+    use super::*;
+
+    fn args(event: &TestEvent, shares: u64) -> FakeCreateOrderArgs {
+        FakeCreateOrderArgs {
+            event_uuid: event.uuid,
+            option_uuid: event.option_uuid,
+            num_shares: Shares(shares),
+            price_per_share: PRICE.per_share(),
+        }
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn moves_usdc_to_treasury() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction =
+            instructions::fake_create_order(&Deployment::DEFAULT, &user.pubkey(), &args(&event, 5))
+                .unwrap();
+        harness.send(instruction, &[&user]).unwrap();
+
+        let cost = PRICE.total(Shares(5)).unwrap();
+        assert_eq!(
+            harness.usdc_balance(&user.pubkey()),
+            MicroUsdc(10_000_000).checked_sub(cost).unwrap()
+        );
+        assert_eq!(
+            MicroUsdc(harness.token_balance(&event_pda(&event.uuid), &USDC_MINT)),
+            cost
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_signer() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction =
+            instructions::fake_create_order(&Deployment::DEFAULT, &user.pubkey(), &args(&event, 5))
+                .unwrap();
+        let instruction = drop_signer(instruction, 0);
+
+        assert_instruction_error(
+            harness.send(instruction, &[]),
+            InstructionError::PrivilegeEscalation,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_mint() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction =
+            instructions::fake_create_order(&Deployment::DEFAULT, &user.pubkey(), &args(&event, 5))
+                .unwrap();
+        let instruction = replace_account(instruction, 4, Pubkey::new_unique());
+
+        assert_market_error(harness.send(instruction, &[&user]), MarketError::UsdcMint);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_uninitialized_treasury() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction =
+            instructions::fake_create_order(&Deployment::DEFAULT, &user.pubkey(), &args(&event, 5))
+                .unwrap();
+        let instruction = replace_account(instruction, 3, Pubkey::new_unique());
+
+        assert_market_error(harness.send(instruction, &[&user]), MarketError::InvalidAta);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_treasury_of_another_event() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let other = harness.create_empty_event();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction =
+            instructions::fake_create_order(&Deployment::DEFAULT, &user.pubkey(), &args(&event, 5))
+                .unwrap();
+        let instruction = replace_account(instruction, 3, treasury(&other));
+
+        assert_market_error(harness.send(instruction, &[&user]), MarketError::InvalidAta);
+    }
+}
+
+mod fake_cancel_order {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn refunds_user() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction = instructions::fake_create_order(
+            &Deployment::DEFAULT,
+            &user.pubkey(),
+            &FakeCreateOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+        harness.send(instruction, &[&user]).unwrap();
+
+        let instruction = instructions::fake_cancel_order(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &user.pubkey(),
+            &FakeCancelOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+        harness.send(instruction, &[]).unwrap();
+
+        assert_eq!(harness.usdc_balance(&user.pubkey()), MicroUsdc(10_000_000));
+        assert_eq!(
+            harness.token_balance(&event_pda(&event.uuid), &USDC_MINT),
+            0
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_ata_of_another_user() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        let other = harness.new_wallet();
+        harness.set_usdc(&other.pubkey(), MicroUsdc(0));
+
+        let instruction = instructions::fake_cancel_order(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &user.pubkey(),
+            &FakeCancelOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(
+            instruction,
+            2,
+            get_associated_token_address(&other.pubkey(), &USDC_MINT),
+        );
+
+        assert_market_error(harness.send(instruction, &[]), MarketError::InvalidAta);
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_refund_larger_than_treasury() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+        harness.set_usdc(&user.pubkey(), MicroUsdc(0));
+
+        let instruction = instructions::fake_cancel_order(
+            &Deployment::DEFAULT,
+            &harness.admin.pubkey(),
+            &user.pubkey(),
+            &FakeCancelOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+
+        // spl token's InsufficientFunds
+        assert_instruction_error(harness.send(instruction, &[]), InstructionError::Custom(1));
+    }
+}
+
+mod fake_match_order {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn mints_both_sides() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+        harness.set_usdc(&user_yes.pubkey(), MicroUsdc(10_000_000));
+        harness.set_usdc(&user_no.pubkey(), MicroUsdc(10_000_000));
+
+        harness.buy_and_match(&event, &user_yes, &user_no, Shares(5), PRICE);
+
+        let tokens = Shares(5).to_token_amount().unwrap();
+        assert_eq!(
+            harness.token_balance(&user_yes.pubkey(), &event.yes_mint),
+            tokens
+        );
+        assert_eq!(
+            harness.token_balance(&user_no.pubkey(), &event.no_mint),
+            tokens
+        );
+        // the treasury holds exactly the payout of the minted pair
+        assert_eq!(
+            MicroUsdc(harness.token_balance(&event_pda(&event.uuid), &USDC_MINT)),
+            Shares(5).payout().unwrap()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_unknown_option() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+
+        let instruction = instructions::fake_match_order(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &user_no.pubkey(),
+            &event.yes_mint,
+            &event.no_mint,
+            &FakeMatchOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: Uuid::new_v4(),
+                num_shares: Shares(1),
+            },
+        )
+        .unwrap();
+
+        assert_market_error(
+            harness.send(instruction, &[&user_yes, &user_no]),
+            MarketError::OptionMissmatch,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_token() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let other = harness.add_option(event.uuid);
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+
+        // yes mint of another option
+        let instruction = instructions::fake_match_order(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &user_no.pubkey(),
+            &other.yes_mint,
+            &event.no_mint,
+            &FakeMatchOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(1),
+            },
+        )
+        .unwrap();
+
+        assert_market_error(
+            harness.send(instruction, &[&user_yes, &user_no]),
+            MarketError::TokenMissmatch,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_signer() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+
+        let instruction = instructions::fake_match_order(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &user_no.pubkey(),
+            &event.yes_mint,
+            &event.no_mint,
+            &FakeMatchOrderArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(1),
+            },
+        )
+        .unwrap();
+        let instruction = drop_signer(instruction, 2);
+
+        assert_instruction_error(
+            harness.send(instruction, &[&user_yes]),
+            InstructionError::PrivilegeEscalation,
+        );
+    }
+}
+
+mod fake_get_reward {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn pays_out_and_burns() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+        harness.set_usdc(&user_yes.pubkey(), MicroUsdc(10_000_000));
+        harness.set_usdc(&user_no.pubkey(), MicroUsdc(10_000_000));
+        harness.buy_and_match(&event, &user_yes, &user_no, Shares(5), PRICE);
+
+        let before = harness.usdc_balance(&user_yes.pubkey());
+        let instruction = instructions::fake_get_reward(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &event.yes_mint,
+            &FakeGetRewardArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+            },
+        )
+        .unwrap();
+        harness.send(instruction, &[&user_yes]).unwrap();
+
+        assert_eq!(
+            harness.usdc_balance(&user_yes.pubkey()),
+            before.checked_add(Shares(5).payout().unwrap()).unwrap()
+        );
+        assert_eq!(
+            harness.token_balance(&user_yes.pubkey(), &event.yes_mint),
+            0
+        );
+        assert_eq!(
+            harness.token_balance(&event_pda(&event.uuid), &USDC_MINT),
+            0
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_token_of_another_user() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+        harness.set_usdc(&user_yes.pubkey(), MicroUsdc(10_000_000));
+        harness.set_usdc(&user_no.pubkey(), MicroUsdc(10_000_000));
+        harness.buy_and_match(&event, &user_yes, &user_no, Shares(5), PRICE);
+
+        // the NO holder tries to redeem using the YES holder's tokens
+        let instruction = instructions::fake_get_reward(
+            &Deployment::DEFAULT,
+            &user_no.pubkey(),
+            &event.yes_mint,
+            &FakeGetRewardArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(
+            instruction,
+            2,
+            get_associated_token_address(&user_yes.pubkey(), &event.yes_mint),
+        );
+
+        assert_market_error(
+            harness.send(instruction, &[&user_no]),
+            MarketError::InvalidAta,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_mint() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user = harness.new_wallet();
+
+        let instruction = instructions::fake_get_reward(
+            &Deployment::DEFAULT,
+            &user.pubkey(),
+            &event.yes_mint,
+            &FakeGetRewardArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                num_shares: Shares(5),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(instruction, 5, Pubkey::new_unique());
+
+        assert_market_error(harness.send(instruction, &[&user]), MarketError::UsdcMint);
+    }
+}
+
+mod transfer_shares {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn swaps_tokens_for_usdc() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let user_yes = harness.new_wallet();
+        let user_no = harness.new_wallet();
+        let buyer = harness.new_wallet();
+        harness.set_usdc(&user_yes.pubkey(), MicroUsdc(10_000_000));
+        harness.set_usdc(&user_no.pubkey(), MicroUsdc(10_000_000));
+        harness.set_usdc(&buyer.pubkey(), MicroUsdc(10_000_000));
+        harness.buy_and_match(&event, &user_yes, &user_no, Shares(5), PRICE);
+
+        let seller_usdc = harness.usdc_balance(&user_yes.pubkey());
+        let price = Price::new(70).unwrap();
+        let instruction = instructions::transfer_shares(
+            &Deployment::DEFAULT,
+            &user_yes.pubkey(),
+            &buyer.pubkey(),
+            &event.yes_mint,
+            &TransferSharesArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                token_option: TokenOption::Yes,
+                num_shares: Shares(2),
+                price_per_share: price.per_share(),
+            },
+        )
+        .unwrap();
+        harness.send(instruction, &[&user_yes, &buyer]).unwrap();
+
+        let cost = price.total(Shares(2)).unwrap();
+        assert_eq!(
+            harness.token_balance(&user_yes.pubkey(), &event.yes_mint),
+            Shares(3).to_token_amount().unwrap()
+        );
+        assert_eq!(
+            harness.token_balance(&buyer.pubkey(), &event.yes_mint),
+            Shares(2).to_token_amount().unwrap()
+        );
+        assert_eq!(
+            harness.usdc_balance(&user_yes.pubkey()),
+            seller_usdc.checked_add(cost).unwrap()
+        );
+        assert_eq!(
+            harness.usdc_balance(&buyer.pubkey()),
+            MicroUsdc(10_000_000).checked_sub(cost).unwrap()
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_wrong_mint() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let seller = harness.new_wallet();
+        let buyer = harness.new_wallet();
+
+        let instruction = instructions::transfer_shares(
+            &Deployment::DEFAULT,
+            &seller.pubkey(),
+            &buyer.pubkey(),
+            &event.yes_mint,
+            &TransferSharesArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                token_option: TokenOption::Yes,
+                num_shares: Shares(1),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+        let instruction = replace_account(instruction, 8, Pubkey::new_unique());
+
+        assert_market_error(
+            harness.send(instruction, &[&seller, &buyer]),
+            MarketError::UsdcMint,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_buyer_signature() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let seller = harness.new_wallet();
+        let buyer = harness.new_wallet();
+        harness.set_token_account(
+            &seller.pubkey(),
+            &event.yes_mint,
+            Shares(1).to_token_amount().unwrap(),
+        );
+        harness.set_usdc(&seller.pubkey(), MicroUsdc(0));
+        harness.set_usdc(&buyer.pubkey(), MicroUsdc(10_000_000));
+
+        let instruction = instructions::transfer_shares(
+            &Deployment::DEFAULT,
+            &seller.pubkey(),
+            &buyer.pubkey(),
+            &event.yes_mint,
+            &TransferSharesArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                token_option: TokenOption::Yes,
+                num_shares: Shares(1),
+                price_per_share: PRICE.per_share(),
+            },
+        )
+        .unwrap();
+        let instruction = drop_signer(instruction, 3);
+
+        assert_instruction_error(
+            harness.send(instruction, &[&seller]),
+            InstructionError::PrivilegeEscalation,
+        );
+    }
+}
+
+mod dispatch {
+    use super::*;
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_garbage_instruction_data() {
+        let mut harness = Harness::new();
+
+        let instruction = Instruction::new_with_bytes(
+            MARKETPLACE_PROGRAM,
+            &[0xff, 0xff, 0xff, 0xff],
+            vec![AccountMeta::new(harness.admin.pubkey(), true)],
+        );
+
+        assert_market_error(
+            harness.send(instruction, &[]),
+            MarketError::InstructionDeser,
+        );
+    }
+
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn rejects_missing_accounts() {
+        let mut harness = Harness::new();
+
+        let instruction = raw_instruction(
+            &MarketInstruction::CloseEvent(CloseEventArgs {
+                uuid: Uuid::new_v4(),
+            }),
+            vec![AccountMeta::new(harness.admin.pubkey(), true)],
+        );
+
+        assert_market_error(harness.send(instruction, &[]), MarketError::InvalidAccounts);
+    }
+
+    /// `GetReward` is not implemented by the program yet and falls through as a no-op
+    #[test]
+    #[ignore = "needs `cargo build-sbf`, run with --ignored"]
+    fn unimplemented_get_reward_is_a_noop() {
+        let mut harness = Harness::new();
+
+        let event = harness.create_event_with_option();
+        let before = harness.fetch_event(&event.uuid);
+        let instruction = raw_instruction(
+            &MarketInstruction::GetReward(GetRewardArgs {
+                event_uuid: event.uuid,
+                option_uuid: event.option_uuid,
+                seed: 0,
+            }),
+            vec![AccountMeta::new(harness.admin.pubkey(), true)],
+        );
+        harness.send(instruction, &[]).unwrap();
+
+        let after = harness.fetch_event(&event.uuid);
+        assert_eq!(before.options.len(), after.options.len());
+    }
+}
@@ -0,0 +1,156 @@
+//! Signer providers, without a validator.
+
+use std::{path::PathBuf, sync::Arc};
+
+use blockchain_client::{
+    InMemoryProvider, ProviderError, SignerProvider,
+    signer::{Keystore, KeystoreProvider, RemoteProvider, keystore::KdfParams, remote},
+};
+use solana_sdk::{signature::Keypair, signer::Signer};
+use tokio::net::UnixListener;
+use uuid::Uuid;
+
+/// Fast enough for tests, far too weak for anything else
+const TEST_KDF: KdfParams = KdfParams {
+    m_cost: 64,
+    t_cost: 1,
+    p_cost: 1,
+};
+
+fn temp_dir() -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("profecia-signer-{}", Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn keystore_provider(dir: &PathBuf) -> KeystoreProvider {
+    KeystoreProvider::new(dir, "correct horse").with_kdf_params(TEST_KDF)
+}
+
+#[test]
+fn in_memory_rejects_malformed_keys() {
+    let result = InMemoryProvider.signer("not a key");
+    assert!(matches!(result, Err(ProviderError::InvalidKey(_))));
+
+    let (key, pubkey) = InMemoryProvider.create().unwrap();
+    assert_eq!(InMemoryProvider.pubkey(&key).unwrap(), pubkey);
+    assert_eq!(InMemoryProvider.signer(&key).unwrap().pubkey(), pubkey);
+}
+
+#[test]
+fn keystore_round_trip() {
+    let keypair = Keypair::new();
+    let path = temp_dir().join("wallet.json");
+    Keystore::encrypt_with_params(&keypair, "correct horse", TEST_KDF)
+        .write(&path)
+        .unwrap();
+
+    let decrypted = Keystore::read(&path)
+        .unwrap()
+        .decrypt("correct horse", &path)
+        .unwrap();
+    assert_eq!(decrypted.to_bytes(), keypair.to_bytes());
+}
+
+#[test]
+fn keystore_wrong_passphrase() {
+    let keypair = Keypair::new();
+    let keystore = Keystore::encrypt_with_params(&keypair, "correct horse", TEST_KDF);
+
+    let result = keystore.decrypt("battery staple", &PathBuf::from("wallet.json"));
+    assert!(matches!(result, Err(ProviderError::Decryption(_))));
+}
+
+#[test]
+fn keystore_provider_creates_and_signs() {
+    let dir = temp_dir();
+    let provider = keystore_provider(&dir);
+
+    let (key, pubkey) = provider.create().unwrap();
+    assert_eq!(key, pubkey.to_string());
+    assert!(dir.join(format!("{}.json", key)).exists());
+
+    let signer = provider.signer(&key).unwrap();
+    let signature = signer.sign_message(b"message");
+    assert!(signature.verify(pubkey.as_ref(), b"message"));
+
+    let unknown = Keypair::new().pubkey().to_string();
+    assert!(matches!(
+        provider.signer(&unknown),
+        Err(ProviderError::UnknownKey(_))
+    ));
+    // keys are addresses, never paths
+    assert!(matches!(
+        provider.signer("../wallet"),
+        Err(ProviderError::InvalidKey(_))
+    ));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remote_provider_signs_through_the_socket() {
+    let dir = temp_dir();
+    let socket = dir.join("signer.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    tokio::spawn(remote::serve(
+        listener,
+        Arc::new(keystore_provider(&dir.join("keys"))),
+    ));
+
+    // from the blocking pool, e.g. while building a transaction in `spawn_blocking`
+    tokio::task::spawn_blocking(move || {
+        let provider = RemoteProvider::new(&socket);
+
+        let (key, pubkey) = provider.create().unwrap();
+        assert_eq!(provider.pubkey(&key).unwrap(), pubkey);
+
+        let signer = provider.signer(&key).unwrap();
+        assert_eq!(signer.pubkey(), pubkey);
+        let signature = signer.try_sign_message(b"message").unwrap();
+        assert!(signature.verify(pubkey.as_ref(), b"message"));
+
+        let unknown = Keypair::new().pubkey().to_string();
+        assert!(matches!(
+            provider.signer(&unknown),
+            Err(ProviderError::Remote(_))
+        ));
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn remote_provider_can_be_used_from_async_tasks() {
+    let dir = temp_dir();
+    let socket = dir.join("signer.sock");
+    let listener = UnixListener::bind(&socket).unwrap();
+    tokio::spawn(remote::serve(
+        listener,
+        Arc::new(keystore_provider(&dir.join("keys"))),
+    ));
+
+    // as the API does, straight from a worker of the runtime
+    let provider = RemoteProvider::new(&socket);
+    let (key, pubkey) = provider.create().unwrap();
+    let signature = provider
+        .signer(&key)
+        .unwrap()
+        .try_sign_message(b"message")
+        .unwrap();
+    assert!(signature.verify(pubkey.as_ref(), b"message"));
+}
+
+#[test]
+fn remote_provider_works_outside_a_runtime() {
+    let dir = temp_dir();
+    let socket = dir.join("signer.sock");
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let listener = runtime.block_on(async { UnixListener::bind(&socket).unwrap() });
+    runtime.spawn(remote::serve(
+        listener,
+        Arc::new(keystore_provider(&dir.join("keys"))),
+    ));
+
+    let provider = RemoteProvider::new(&socket);
+    let (key, pubkey) = provider.create().unwrap();
+    assert_eq!(provider.pubkey(&key).unwrap(), pubkey);
+}
@@ -0,0 +1,65 @@
+//! What simulations report as cost, from hand-built results.
+
+use blockchain_client::{AccountDiff, AccountSnapshot, ComputeBudget, Simulation};
+use solana_sdk::{pubkey::Pubkey, transaction::TransactionError};
+
+fn snapshot(lamports: u64, data_len: usize) -> Option<AccountSnapshot> {
+    Some(AccountSnapshot {
+        lamports,
+        data_len,
+        owner: Pubkey::new_unique(),
+    })
+}
+
+fn simulation(accounts: Vec<AccountDiff>) -> Simulation {
+    Simulation {
+        error: None,
+        logs: Vec::new(),
+        budget: ComputeBudget {
+            units_consumed: 20_000,
+            compute_unit_limit: 22_000,
+            compute_unit_price: 0,
+            fee_lamports: 5_000,
+        },
+        accounts,
+    }
+}
+
+#[test]
+fn cost_is_fee_plus_rent_of_created_accounts() {
+    let simulation = simulation(vec![
+        // the payer, which pays for the new account
+        AccountDiff {
+            pubkey: Pubkey::new_unique(),
+            before: snapshot(10_000_000, 0),
+            after: snapshot(7_960_720, 0),
+        },
+        // created
+        AccountDiff {
+            pubkey: Pubkey::new_unique(),
+            before: None,
+            after: snapshot(2_039_280, 165),
+        },
+        // already existed, only its data changed
+        AccountDiff {
+            pubkey: Pubkey::new_unique(),
+            before: snapshot(1_000_000, 100),
+            after: snapshot(1_000_000, 100),
+        },
+    ]);
+
+    assert!(simulation.succeeded());
+    assert!(simulation.accounts[1].is_created());
+    assert!(!simulation.accounts[2].is_created());
+    assert_eq!(simulation.rent_lamports(), 2_039_280);
+    assert_eq!(simulation.lamport_cost(), 2_044_280);
+}
+
+#[test]
+fn failed_simulation_only_costs_the_fee() {
+    let mut simulation = simulation(Vec::new());
+    simulation.error = Some(TransactionError::AccountNotFound);
+
+    assert!(!simulation.succeeded());
+    assert_eq!(simulation.lamport_cost(), 5_000);
+}
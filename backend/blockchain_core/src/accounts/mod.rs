@@ -1,2 +1,6 @@
+//! Every account owned by the program starts with a one byte discriminator (`Event::DISCRIMINATOR`,
+//! `Order::DISCRIMINATOR`), so clients can tell them apart, e.g. with a memcmp filter in
+//! getProgramAccounts.
+
 pub mod event;
 pub mod order;
@@ -24,17 +24,34 @@ pub enum TokenOption {
 }
 
 impl Order {
+    /// First byte of the account data
+    pub const DISCRIMINATOR: u8 = 2;
+
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, MarketError> {
-        wincode::deserialize(bytes).map_err(|_| MarketError::OrderDeser)
+        match bytes.split_first() {
+            Some((&Self::DISCRIMINATOR, data)) => {
+                wincode::deserialize(data).map_err(|_| MarketError::OrderDeser)
+            }
+            _ => Err(MarketError::OrderDeser),
+        }
     }
 
+    /// Size of the account data, including the discriminator
+    #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> Result<u64, MarketError> {
-        wincode::serialized_size(self).map_err(|_| MarketError::WincodeSize)
+        wincode::serialized_size(self)
+            .map(|size| size + 1)
+            .map_err(|_| MarketError::WincodeSize)
     }
 
     pub fn write_into_bytes(&self, bytes: &mut [u8]) -> MarketResult {
+        let Some((discriminator, data)) = bytes.split_first_mut() else {
+            return Err(MarketError::OrderSer);
+        };
+        *discriminator = Self::DISCRIMINATOR;
+
         // if you don't use a variable this no longer works, wtf??
-        let mut buffer = &mut bytes[..];
+        let mut buffer = &mut data[..];
         wincode::serialize_into(&mut buffer, self).map_err(|_| MarketError::OrderSer)
     }
 
@@ -19,6 +19,7 @@ pub enum MarketError {
     OptionMissmatch = 12,
     TokenMissmatch = 13,
     InvalidAta = 14,
+    Overflow = 15,
 }
 
 pub type MarketResult = Result<(), MarketError>;
@@ -50,6 +51,7 @@ impl ToStr for MarketError {
             Self::OptionMissmatch => "Option missmatch",
             Self::TokenMissmatch => "Token missmatch",
             Self::InvalidAta => "Invalida ATA",
+            Self::Overflow => "Arithmetic overflow",
         }
     }
 }
@@ -73,6 +75,7 @@ impl TryFrom<u32> for MarketError {
             12 => Ok(Self::OptionMissmatch),
             13 => Ok(Self::TokenMissmatch),
             14 => Ok(Self::InvalidAta),
+            15 => Ok(Self::Overflow),
             _ => Err(ProgramError::Custom(u32::MAX)),
         }
     }
@@ -3,7 +3,10 @@ use std::collections::HashMap;
 use uuid::Uuid;
 use wincode::{SchemaRead, SchemaWrite};
 
-use crate::accounts::{event::EventOption, order::TokenOption};
+use crate::{
+    accounts::{event::EventOption, order::TokenOption},
+    money::{MicroUsdc, Price, Shares},
+};
 
 #[derive(SchemaWrite, SchemaRead, Debug, Clone)]
 pub enum MarketInstruction {
@@ -44,43 +47,40 @@ pub struct CloseEventArgs {
 pub struct CreateOrderArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
+    pub num_shares: Shares,
     pub token: TokenOption,
     pub seed: Uuid,
-    pub token_per_share: u64,
+    pub price_per_share: Price,
 }
 
-/// PRICE IN MICRO USDC!!!
 #[derive(SchemaWrite, SchemaRead, Debug, Copy, Clone)]
 pub struct FakeMatchOrderArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
+    pub num_shares: Shares,
 }
 
-/// PRICE IN MICRO USDC!!!
 #[derive(SchemaWrite, SchemaRead, Debug, Copy, Clone)]
 pub struct FakeCreateOrderArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
-    pub price_per_share: u64,
+    pub num_shares: Shares,
+    pub price_per_share: MicroUsdc,
 }
 
-/// PRICE IN MICRO USDC!!!
 #[derive(SchemaWrite, SchemaRead, Debug, Copy, Clone)]
 pub struct FakeCancelOrderArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
-    pub price_per_share: u64,
+    pub num_shares: Shares,
+    pub price_per_share: MicroUsdc,
 }
 
 #[derive(SchemaWrite, SchemaRead, Debug, Copy, Clone)]
 pub struct MatchOrderArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
+    pub num_shares: Shares,
 }
 
 #[derive(SchemaWrite, SchemaRead, Debug, Copy, Clone)]
@@ -101,7 +101,7 @@ pub struct GetRewardArgs {
 pub struct FakeGetRewardArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
-    pub num_shares: u64,
+    pub num_shares: Shares,
 }
 
 #[derive(SchemaWrite, SchemaRead, Debug, Clone)]
@@ -116,6 +116,6 @@ pub struct TransferSharesArgs {
     pub event_uuid: Uuid,
     pub option_uuid: Uuid,
     pub token_option: TokenOption,
-    pub num_shares: u64,
-    pub price_per_share: u64,
+    pub num_shares: Shares,
+    pub price_per_share: MicroUsdc,
 }
@@ -1,6 +1,7 @@
 pub mod accounts;
 pub mod error;
 pub mod instructions;
+pub mod money;
 
 // WARN: I don't know if wincode will shit itself because of this but it should be fine
 #[cfg(feature = "client")]
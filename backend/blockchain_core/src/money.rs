@@ -0,0 +1,351 @@
+//! Fixed-point money types shared between the program, the client and the API.
+//!
+//! Every amount that crosses a crate boundary should use one of these instead of a bare integer,
+//! so that mixing up cents, micro-USDC and shares becomes a compile error.
+
+use wincode::{SchemaRead, SchemaWrite};
+
+/// USDC has 6 decimals, so one cent is 10^4 base units
+pub const MICRO_USDC_PER_CENT: u64 = 10_000;
+/// Outcome tokens have 6 decimals, so 1 share is 10^6 base units
+pub const TOKENS_PER_SHARE: u64 = 1_000_000;
+/// A winning share pays out 1 USDC
+pub const PAYOUT_CENTS: u64 = 100;
+
+/// Amount of USDC in its base unit (6 decimals). This is what the token program sees.
+#[derive(
+    SchemaWrite, SchemaRead, Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct MicroUsdc(pub u64);
+
+/// Amount of USDC in cents. This is what the API and the database use.
+#[derive(
+    SchemaWrite, SchemaRead, Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Cents(pub u64);
+
+/// Number of whole shares of an outcome
+#[derive(
+    SchemaWrite, SchemaRead, Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
+pub struct Shares(pub u64);
+
+/// Price of one share in cents. Always within `0..=PAYOUT_CENTS`, since a share never pays more than that
+#[derive(
+    SchemaWrite, SchemaRead, Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(try_from = "u64", into = "u64"))]
+pub struct Price(u64);
+
+impl MicroUsdc {
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Total amount for `shares` shares when each one costs `self`
+    pub fn checked_mul_shares(self, shares: Shares) -> Option<Self> {
+        self.0.checked_mul(shares.0).map(Self)
+    }
+
+    /// Rounds down to whole cents
+    pub fn to_cents(self) -> Cents {
+        Cents(self.0 / MICRO_USDC_PER_CENT)
+    }
+}
+
+impl Cents {
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    pub fn to_micro_usdc(self) -> Option<MicroUsdc> {
+        self.0.checked_mul(MICRO_USDC_PER_CENT).map(MicroUsdc)
+    }
+}
+
+impl Shares {
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Self)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        self.0.checked_sub(other.0).map(Self)
+    }
+
+    /// Amount of outcome tokens (in their base unit) that represent these shares
+    pub fn to_token_amount(self) -> Option<u64> {
+        self.0.checked_mul(TOKENS_PER_SHARE)
+    }
+
+    /// Whole shares held by a token account. Fractional tokens are ignored
+    pub fn from_token_amount(amount: u64) -> Self {
+        Self(amount / TOKENS_PER_SHARE)
+    }
+
+    /// USDC paid out by the treasury when these shares win
+    pub fn payout(self) -> Option<MicroUsdc> {
+        Cents(PAYOUT_CENTS)
+            .to_micro_usdc()?
+            .checked_mul_shares(self)
+    }
+}
+
+impl Price {
+    pub const MIN: Self = Self(0);
+    pub const MAX: Self = Self(PAYOUT_CENTS);
+
+    /// Returns None if the price is above the payout of a share
+    pub const fn new(cents: u64) -> Option<Self> {
+        if cents <= PAYOUT_CENTS {
+            Some(Self(cents))
+        } else {
+            None
+        }
+    }
+
+    pub const fn cents(self) -> u64 {
+        self.0
+    }
+
+    /// Price of the opposite outcome, such that both together pay for one full share
+    pub const fn complement(self) -> Self {
+        Self(PAYOUT_CENTS - self.0)
+    }
+
+    /// Price of one share in micro USDC. Cannot overflow since the price is bounded
+    pub const fn per_share(self) -> MicroUsdc {
+        MicroUsdc(self.0 * MICRO_USDC_PER_CENT)
+    }
+
+    /// Total cost of buying `shares` shares at this price
+    pub fn total(self, shares: Shares) -> Option<MicroUsdc> {
+        self.per_share().checked_mul_shares(shares)
+    }
+}
+
+impl TryFrom<u64> for Price {
+    type Error = MoneyError;
+
+    fn try_from(cents: u64) -> Result<Self, Self::Error> {
+        Self::new(cents).ok_or(MoneyError::InvalidPrice)
+    }
+}
+
+impl From<Price> for u64 {
+    fn from(price: Price) -> Self {
+        price.0
+    }
+}
+
+impl From<Price> for Cents {
+    fn from(price: Price) -> Self {
+        Cents(price.0)
+    }
+}
+
+/// The database stores amounts as signed integers
+macro_rules! impl_i64_conversions {
+    ($($ty:ty),*) => {
+        $(
+            impl TryFrom<i64> for $ty {
+                type Error = MoneyError;
+
+                fn try_from(value: i64) -> Result<Self, Self::Error> {
+                    let value = u64::try_from(value).map_err(|_| MoneyError::Negative)?;
+                    value.try_into().map_err(Into::into)
+                }
+            }
+
+            impl TryFrom<$ty> for i64 {
+                type Error = MoneyError;
+
+                fn try_from(value: $ty) -> Result<Self, Self::Error> {
+                    i64::try_from(u64::from(value)).map_err(|_| MoneyError::Overflow)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_u64_conversions {
+    ($($ty:ident),*) => {
+        $(
+            impl From<u64> for $ty {
+                fn from(value: u64) -> Self {
+                    Self(value)
+                }
+            }
+
+            impl From<$ty> for u64 {
+                fn from(value: $ty) -> Self {
+                    value.0
+                }
+            }
+        )*
+    };
+}
+
+impl_u64_conversions!(MicroUsdc, Cents, Shares);
+impl_i64_conversions!(MicroUsdc, Cents, Shares, Price);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum MoneyError {
+    Negative,
+    Overflow,
+    InvalidPrice,
+}
+
+impl From<core::convert::Infallible> for MoneyError {
+    fn from(e: core::convert::Infallible) -> Self {
+        match e {}
+    }
+}
+
+impl core::fmt::Display for MoneyError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::Negative => write!(f, "amount cannot be negative"),
+            Self::Overflow => write!(f, "amount overflowed"),
+            Self::InvalidPrice => write!(f, "price must be between 0 and {} cents", PAYOUT_CENTS),
+        }
+    }
+}
+
+impl std::error::Error for MoneyError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cents_and_micro_usdc_convert_both_ways() {
+        assert_eq!(
+            Cents(1).to_micro_usdc(),
+            Some(MicroUsdc(MICRO_USDC_PER_CENT))
+        );
+        assert_eq!(Cents(1_234).to_micro_usdc(), Some(MicroUsdc(12_340_000)));
+        assert_eq!(MicroUsdc(12_340_000).to_cents(), Cents(1_234));
+        assert_eq!(Cents(0).to_micro_usdc(), Some(MicroUsdc(0)));
+    }
+
+    #[test]
+    fn micro_usdc_rounds_down_to_cents() {
+        assert_eq!(MicroUsdc(9_999).to_cents(), Cents(0));
+        assert_eq!(MicroUsdc(19_999).to_cents(), Cents(1));
+    }
+
+    #[test]
+    fn cents_overflow_as_micro_usdc() {
+        assert_eq!(Cents(u64::MAX).to_micro_usdc(), None);
+        assert_eq!(
+            Cents(u64::MAX / MICRO_USDC_PER_CENT).to_micro_usdc(),
+            Some(MicroUsdc(
+                u64::MAX / MICRO_USDC_PER_CENT * MICRO_USDC_PER_CENT
+            ))
+        );
+    }
+
+    #[test]
+    fn arithmetic_overflows_to_none() {
+        assert_eq!(MicroUsdc(u64::MAX).checked_add(MicroUsdc(1)), None);
+        assert_eq!(MicroUsdc(0).checked_sub(MicroUsdc(1)), None);
+        assert_eq!(MicroUsdc(u64::MAX).checked_mul_shares(Shares(2)), None);
+        assert_eq!(Cents(u64::MAX).checked_add(Cents(1)), None);
+        assert_eq!(Cents(0).checked_sub(Cents(1)), None);
+        assert_eq!(Shares(u64::MAX).checked_add(Shares(1)), None);
+        assert_eq!(Shares(0).checked_sub(Shares(1)), None);
+        assert_eq!(Shares(3).checked_sub(Shares(1)), Some(Shares(2)));
+    }
+
+    #[test]
+    fn shares_convert_to_tokens() {
+        assert_eq!(Shares(5).to_token_amount(), Some(5 * TOKENS_PER_SHARE));
+        assert_eq!(Shares(u64::MAX).to_token_amount(), None);
+        assert_eq!(Shares::from_token_amount(5 * TOKENS_PER_SHARE), Shares(5));
+        // fractional tokens are not a share
+        assert_eq!(Shares::from_token_amount(TOKENS_PER_SHARE - 1), Shares(0));
+        assert_eq!(
+            Shares::from_token_amount(2 * TOKENS_PER_SHARE + 1),
+            Shares(2)
+        );
+    }
+
+    #[test]
+    fn shares_pay_one_usdc_each() {
+        assert_eq!(Shares(0).payout(), Some(MicroUsdc(0)));
+        assert_eq!(Shares(5).payout(), Some(MicroUsdc(5_000_000)));
+        assert_eq!(Shares(u64::MAX).payout(), None);
+    }
+
+    #[test]
+    fn price_is_bounded_by_the_payout() {
+        assert_eq!(Price::new(0), Some(Price::MIN));
+        assert_eq!(Price::new(PAYOUT_CENTS), Some(Price::MAX));
+        assert_eq!(Price::new(PAYOUT_CENTS + 1), None);
+        assert_eq!(Price::try_from(101u64), Err(MoneyError::InvalidPrice));
+        assert_eq!(Price::try_from(60i64).map(Price::cents), Ok(60));
+        assert_eq!(Price::try_from(-1i64), Err(MoneyError::Negative));
+    }
+
+    #[test]
+    fn prices_of_both_outcomes_add_up_to_the_payout() {
+        let price = Price::new(60).unwrap();
+        assert_eq!(price.complement().cents(), 40);
+        assert_eq!(Price::MIN.complement(), Price::MAX);
+        assert_eq!(price.complement().complement(), price);
+    }
+
+    #[test]
+    fn price_total_is_exact() {
+        let price = Price::new(60).unwrap();
+        assert_eq!(price.per_share(), MicroUsdc(600_000));
+        assert_eq!(price.total(Shares(5)), Some(MicroUsdc(3_000_000)));
+        assert_eq!(price.total(Shares(0)), Some(MicroUsdc(0)));
+        // a cent a share adds up to whole cents, nothing is rounded away
+        let cent = Price::new(1).unwrap();
+        assert_eq!(
+            cent.total(Shares(3)).map(MicroUsdc::to_cents),
+            Some(Cents(3))
+        );
+        assert_eq!(Price::MIN.total(Shares(u64::MAX)), Some(MicroUsdc(0)));
+    }
+
+    #[test]
+    fn price_total_overflows_to_none() {
+        assert_eq!(Price::MAX.total(Shares(u64::MAX)), None);
+        assert_eq!(
+            Price::new(1)
+                .unwrap()
+                .total(Shares(u64::MAX / MICRO_USDC_PER_CENT + 1)),
+            None
+        );
+    }
+
+    #[test]
+    fn database_amounts_must_fit_an_i64() {
+        assert_eq!(MicroUsdc::try_from(5i64), Ok(MicroUsdc(5)));
+        assert_eq!(Cents::try_from(-5i64), Err(MoneyError::Negative));
+        assert_eq!(Shares::try_from(-1i64), Err(MoneyError::Negative));
+        assert_eq!(i64::try_from(Shares(7)), Ok(7));
+        assert_eq!(
+            i64::try_from(MicroUsdc(u64::MAX)),
+            Err(MoneyError::Overflow)
+        );
+        assert_eq!(i64::try_from(Cents(i64::MAX as u64)), Ok(i64::MAX));
+    }
+}
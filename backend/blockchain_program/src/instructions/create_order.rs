@@ -95,8 +95,10 @@ pub fn create_order(accounts: &[AccountInfo], args: &CreateOrderArgs) -> Program
     // check vault
     must_be_uninit(order_ata)?;
 
-    // TODO: CHECK OVERFLOW
-    let total_usdc = args.num_shares * args.token_per_share;
+    let total_usdc = args
+        .price_per_share
+        .total(args.num_shares)
+        .ok_or(MarketError::Overflow)?;
 
     // deposit USDC into vault, from user's ATA
     // TODO: does not need to be checked
@@ -105,7 +107,7 @@ pub fn create_order(accounts: &[AccountInfo], args: &CreateOrderArgs) -> Program
         mint: usdc,
         to: order_ata,
         authority: order,
-        amount: total_usdc,
+        amount: total_usdc.into(),
         decimals: 6
     }.invoke()?;
 
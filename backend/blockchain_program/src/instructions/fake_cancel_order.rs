@@ -44,7 +44,11 @@ pub fn fake_cancel_order(accounts: &[AccountInfo], args: &FakeCancelOrderArgs) -
         mint: usdc,
         to: user_usdc_ata,
         authority: event,
-        amount: args.num_shares * args.price_per_share, // TODO: overflow
+        amount: args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(MarketError::Overflow)?
+            .into(),
         decimals: 6,
     }
     .invoke_signed(&[Signer::from(&event_seeds)])?;
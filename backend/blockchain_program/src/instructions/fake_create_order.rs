@@ -33,7 +33,11 @@ pub fn fake_create_order(accounts: &[AccountInfo], args: &FakeCreateOrderArgs) -
         mint: usdc,
         to: treasury,
         authority: user,
-        amount: args.num_shares * args.price_per_share, // TODO: overflow
+        amount: args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(MarketError::Overflow)?
+            .into(),
         decimals: 6,
     }
     .invoke()?;
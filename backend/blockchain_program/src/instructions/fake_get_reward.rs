@@ -47,7 +47,7 @@ pub fn fake_get_reward(accounts: &[AccountInfo], args: &FakeGetRewardArgs) -> Pr
         mint: usdc,
         to: user_usdc_ata,
         authority: event,
-        amount: args.num_shares * 1000000, // TODO: overflow
+        amount: args.num_shares.payout().ok_or(MarketError::Overflow)?.into(),
         decimals: 6,
     }
     .invoke_signed(&[Signer::from(&event_seeds)])?;
@@ -56,7 +56,10 @@ pub fn fake_get_reward(accounts: &[AccountInfo], args: &FakeGetRewardArgs) -> Pr
         account: user_token_ata,
         mint: token,
         authority: user,
-        amount: args.num_shares * 1000000, // TODO: overflow
+        amount: args
+            .num_shares
+            .to_token_amount()
+            .ok_or(MarketError::Overflow)?,
         decimals: 6,
     }
     .invoke()?;
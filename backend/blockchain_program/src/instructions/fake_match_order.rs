@@ -74,7 +74,10 @@ pub fn fake_match_order(accounts: &[AccountInfo], args: &FakeMatchOrderArgs) ->
         mint: token_yes,
         account: user_yes_token_ata,
         mint_authority: event,
-        amount: args.num_shares * 1000000, // 1 share will result in 1000000 tokens, as they have 6 decimals
+        amount: args
+            .num_shares
+            .to_token_amount()
+            .ok_or(MarketError::Overflow)?, // 1 share will result in 1000000 tokens, as they have 6 decimals
         decimals: 6,
     }
     .invoke_signed(&[Signer::from(&event_seeds)])?;
@@ -83,7 +86,10 @@ pub fn fake_match_order(accounts: &[AccountInfo], args: &FakeMatchOrderArgs) ->
         mint: token_no,
         account: user_no_token_ata,
         mint_authority: event,
-        amount: args.num_shares * 1000000, // 1 share will result in 1000000 tokens, as they have 6 decimals
+        amount: args
+            .num_shares
+            .to_token_amount()
+            .ok_or(MarketError::Overflow)?, // 1 share will result in 1000000 tokens, as they have 6 decimals
         decimals: 6,
     }
     .invoke_signed(&[Signer::from(&event_seeds)])?;
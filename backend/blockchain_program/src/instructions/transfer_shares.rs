@@ -83,7 +83,10 @@ pub fn transfer_shares(accounts: &[AccountInfo], args: &TransferSharesArgs) -> P
         to: user_b_token_ata,
         mint: token,
         authority: user_a,
-        amount: args.num_shares * 1000000,
+        amount: args
+            .num_shares
+            .to_token_amount()
+            .ok_or(MarketError::Overflow)?,
         decimals: 6
     }.invoke()?;
 
@@ -93,7 +96,11 @@ pub fn transfer_shares(accounts: &[AccountInfo], args: &TransferSharesArgs) -> P
         to: user_a_usdc_ata,
         mint: usdc,
         authority: user_b,
-        amount: args.num_shares * args.price_per_share,
+        amount: args
+            .price_per_share
+            .checked_mul_shares(args.num_shares)
+            .ok_or(MarketError::Overflow)?
+            .into(),
         decimals: 6
     }.invoke()?;
 
@@ -0,0 +1,22 @@
+use sea_orm::entity::prelude::*;
+
+/// The share tokens of a wallet before and after a transaction, to put positions back when a
+/// fork drops it
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_balance_change")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub signature: String,
+    pub slot: i64,
+    /// Of the transaction
+    pub seq: i64,
+    pub wallet: String,
+    pub mint: String,
+    /// Token amounts
+    pub before: i64,
+    pub after: i64,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,16 @@
+use sea_orm::entity::prelude::*;
+
+/// The last transaction indexed for a program, where the indexer resumes after a restart
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "indexer_checkpoint")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub program_id: String,
+    /// None before the first transaction, or after a fork dropped all of them
+    pub signature: Option<String>,
+    pub slot: i64,
+    pub updated_at: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
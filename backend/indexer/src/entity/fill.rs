@@ -0,0 +1,43 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::entity::market::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum FillKind {
+    /// New shares minted for both sides, the buyer of YES against the buyer of NO
+    #[sea_orm(string_value = "match")]
+    Match,
+    /// Existing shares sold for USDC
+    #[sea_orm(string_value = "transfer")]
+    Transfer,
+}
+
+/// A trade that landed on chain
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_fill")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub signature: String,
+    pub slot: i64,
+    /// Of the instruction in its transaction
+    pub instruction: i32,
+    pub event_id: Uuid,
+    pub market_id: Uuid,
+    pub kind: FillKind,
+    /// The token the buyer got, always YES for a match
+    pub token: Token,
+    /// Wallet that got the shares
+    pub buyer: String,
+    /// Wallet that gave them up, or took the NO side of a match
+    pub seller: String,
+    pub shares: i64,
+    /// In micro USDC, None for a match where each side paid its own price into the event
+    pub price_per_share: Option<i64>,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,40 @@
+use sea_orm::entity::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, Deserialize, Serialize)]
+#[sea_orm(rs_type = "String", db_type = "String(StringLen::None)")]
+#[serde(rename_all = "lowercase")]
+pub enum Token {
+    #[sea_orm(string_value = "yes")]
+    Yes,
+    #[sea_orm(string_value = "no")]
+    No,
+}
+
+impl From<blockchain_core::accounts::order::TokenOption> for Token {
+    fn from(value: blockchain_core::accounts::order::TokenOption) -> Self {
+        match value {
+            blockchain_core::accounts::order::TokenOption::Yes => Token::Yes,
+            blockchain_core::accounts::order::TokenOption::No => Token::No,
+        }
+    }
+}
+
+/// An option of an event, with the mints of its shares. Its id is the option's uuid on chain,
+/// which is the id of the market in the API
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_market")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub event_id: Uuid,
+    #[sea_orm(unique)]
+    pub yes_mint: String,
+    #[sea_orm(unique)]
+    pub no_mint: String,
+    pub signature: String,
+    pub slot: i64,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,7 @@
+pub mod balance_change;
+pub mod checkpoint;
+pub mod fill;
+pub mod market;
+pub mod position;
+pub mod redemption;
+pub mod transaction;
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+
+use crate::entity::market::Token;
+
+/// Shares of a market held by a wallet, as of `slot`
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_position")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    #[sea_orm(unique_key = "holding")]
+    pub wallet: String,
+    #[sea_orm(unique_key = "holding")]
+    pub mint: String,
+    pub event_id: Uuid,
+    pub market_id: Uuid,
+    pub token: Token,
+    /// Whole shares, fractional tokens are ignored
+    pub shares: i64,
+    pub slot: i64,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,24 @@
+use sea_orm::entity::prelude::*;
+
+use crate::entity::market::Token;
+
+/// Winning shares burned for their payout
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_redemption")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: Uuid,
+    pub signature: String,
+    pub slot: i64,
+    pub instruction: i32,
+    pub event_id: Uuid,
+    pub market_id: Uuid,
+    pub wallet: String,
+    pub token: Token,
+    pub shares: i64,
+    /// In micro USDC
+    pub payout: i64,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
@@ -0,0 +1,19 @@
+use sea_orm::entity::prelude::*;
+
+/// A transaction of the program that was indexed. Everything derived from it carries its
+/// signature and slot, so it can be rolled back if a fork drops it
+#[sea_orm::model]
+#[derive(Clone, Debug, PartialEq, Eq, DeriveEntityModel)]
+#[sea_orm(table_name = "chain_transaction")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub signature: String,
+    pub slot: i64,
+    /// Increases with every transaction indexed, in the order of the chain
+    #[sea_orm(unique)]
+    pub seq: i64,
+    pub block_time: Option<DateTimeWithTimeZone>,
+    pub indexed_at: DateTimeWithTimeZone,
+}
+
+impl ActiveModelBehavior for ActiveModel {}
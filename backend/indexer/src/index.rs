@@ -0,0 +1,501 @@
+use std::{collections::BTreeSet, time::Duration};
+
+use anyhow::{Context, Result, anyhow};
+use blockchain_core::{
+    accounts::event::EventOption, instructions::MarketInstruction, money::Shares,
+};
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue::Set, ColumnTrait, Condition, ConnectionTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use uuid::Uuid;
+
+use crate::{
+    entity::{self, fill::FillKind, market::Token},
+    source::{ProgramTransaction, TransactionSource},
+};
+
+pub struct Indexer<S> {
+    database: DatabaseConnection,
+    source: S,
+    program_id: Pubkey,
+    from_slot: u64,
+}
+
+impl<S: TransactionSource> Indexer<S> {
+    pub fn new(database: DatabaseConnection, source: S, program_id: Pubkey) -> Self {
+        Self {
+            database,
+            source,
+            program_id,
+            from_slot: 0,
+        }
+    }
+
+    /// Where to start when there is no checkpoint yet. Markets created before it are unknown,
+    /// so their shares are not followed
+    pub fn from_slot(mut self, slot: u64) -> Self {
+        self.from_slot = slot;
+        self
+    }
+
+    /// Polls every `interval` until the process stops
+    pub async fn run(&self, interval: Duration) {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match self.poll().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Indexed {} transactions", count),
+                Err(e) => tracing::error!("Failed to index the program: {:#}", e),
+            }
+        }
+    }
+
+    /// Rolls back what forks dropped, then indexes every new transaction. Returns how many were
+    /// indexed
+    pub async fn poll(&self) -> Result<usize> {
+        self.rollback_forks().await?;
+
+        let after = entity::checkpoint::Entity::find_by_id(self.program_id.to_string())
+            .one(&self.database)
+            .await?
+            .and_then(|checkpoint| checkpoint.signature)
+            .map(|signature| signature.parse::<Signature>())
+            .transpose()?;
+        let signatures = self
+            .source
+            .signatures(&self.program_id, after, self.from_slot)
+            .await?;
+
+        for signature in &signatures {
+            let transaction = self.source.transaction(&self.program_id, signature).await?;
+            self.index(&transaction)
+                .await
+                .with_context(|| format!("Failed to index transaction {}", signature))?;
+        }
+
+        Ok(signatures.len())
+    }
+
+    async fn index(&self, transaction: &ProgramTransaction) -> Result<()> {
+        let txn = self.database.begin().await?;
+        let signature = transaction.signature.to_string();
+
+        if entity::transaction::Entity::find_by_id(signature.clone())
+            .one(&txn)
+            .await?
+            .is_some()
+        {
+            return Ok(());
+        }
+
+        let seq = entity::transaction::Entity::find()
+            .order_by_desc(entity::transaction::Column::Seq)
+            .one(&txn)
+            .await?
+            .map_or(0, |last| last.seq + 1);
+        let block_time = transaction
+            .block_time
+            .and_then(|time| DateTime::from_timestamp(time, 0));
+        entity::transaction::ActiveModel {
+            signature: Set(signature.clone()),
+            slot: Set(i64::try_from(transaction.slot)?),
+            seq: Set(seq),
+            block_time: Set(block_time.map(Into::into)),
+            indexed_at: Set(Utc::now().into()),
+        }
+        .insert(&txn)
+        .await?;
+
+        for (index, instruction) in transaction.instructions.iter().enumerate() {
+            match wincode::deserialize::<MarketInstruction>(&instruction.data) {
+                Ok(decoded) => {
+                    apply_instruction(&txn, transaction, index, decoded, &instruction.accounts)
+                        .await?
+                }
+                Err(e) => tracing::warn!(
+                    "Skipping instruction {} of {}, which cannot be decoded: {:?}",
+                    index,
+                    signature,
+                    e
+                ),
+            }
+        }
+        apply_balances(&txn, transaction, seq).await?;
+
+        save_checkpoint(
+            &txn,
+            &self.program_id,
+            Some(signature),
+            i64::try_from(transaction.slot)?,
+        )
+        .await?;
+        txn.commit().await?;
+
+        Ok(())
+    }
+
+    /// Rolls back from the first transaction that is not finalized and is no longer in the slot
+    /// it was indexed in
+    async fn rollback_forks(&self) -> Result<()> {
+        let finalized = i64::try_from(self.source.finalized_slot().await?)?;
+        let unfinalized = entity::transaction::Entity::find()
+            .filter(entity::transaction::Column::Slot.gt(finalized))
+            .order_by_asc(entity::transaction::Column::Seq)
+            .all(&self.database)
+            .await?;
+        if unfinalized.is_empty() {
+            return Ok(());
+        }
+
+        let signatures = unfinalized
+            .iter()
+            .map(|transaction| transaction.signature.parse())
+            .collect::<Result<Vec<Signature>, _>>()?;
+        let slots = self.source.slots(&signatures).await?;
+
+        let dropped = unfinalized
+            .iter()
+            .zip(slots)
+            .find(|(transaction, slot)| *slot != u64::try_from(transaction.slot).ok());
+        if let Some((transaction, slot)) = dropped {
+            tracing::warn!(
+                "Transaction {} of slot {} is now in {:?}, rolling back from it",
+                transaction.signature,
+                transaction.slot,
+                slot
+            );
+            self.rollback(transaction.seq).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes every transaction from `seq` on, and everything derived from them
+    async fn rollback(&self, seq: i64) -> Result<()> {
+        let txn = self.database.begin().await?;
+
+        let signatures: Vec<String> = entity::transaction::Entity::find()
+            .filter(entity::transaction::Column::Seq.gte(seq))
+            .all(&txn)
+            .await?
+            .into_iter()
+            .map(|transaction| transaction.signature)
+            .collect();
+
+        let last = entity::transaction::Entity::find()
+            .filter(entity::transaction::Column::Seq.lt(seq))
+            .order_by_desc(entity::transaction::Column::Seq)
+            .one(&txn)
+            .await?;
+        let last_slot = last.as_ref().map_or(0, |last| last.slot);
+
+        // Each position goes back to what it was before its first dropped change
+        let changes = entity::balance_change::Entity::find()
+            .filter(entity::balance_change::Column::Seq.gte(seq))
+            .order_by_asc(entity::balance_change::Column::Seq)
+            .all(&txn)
+            .await?;
+        let mut restored = BTreeSet::new();
+        for change in &changes {
+            if !restored.insert((change.wallet.clone(), change.mint.clone())) {
+                continue;
+            }
+            entity::position::Entity::update_many()
+                .set(entity::position::ActiveModel {
+                    shares: Set(whole_shares(u64::try_from(change.before)?)?),
+                    slot: Set(last_slot),
+                    ..Default::default()
+                })
+                .filter(entity::position::Column::Wallet.eq(&change.wallet))
+                .filter(entity::position::Column::Mint.eq(&change.mint))
+                .exec(&txn)
+                .await?;
+        }
+
+        entity::balance_change::Entity::delete_many()
+            .filter(entity::balance_change::Column::Seq.gte(seq))
+            .exec(&txn)
+            .await?;
+        entity::fill::Entity::delete_many()
+            .filter(entity::fill::Column::Signature.is_in(signatures.clone()))
+            .exec(&txn)
+            .await?;
+        entity::redemption::Entity::delete_many()
+            .filter(entity::redemption::Column::Signature.is_in(signatures.clone()))
+            .exec(&txn)
+            .await?;
+        entity::market::Entity::delete_many()
+            .filter(entity::market::Column::Signature.is_in(signatures))
+            .exec(&txn)
+            .await?;
+        entity::transaction::Entity::delete_many()
+            .filter(entity::transaction::Column::Seq.gte(seq))
+            .exec(&txn)
+            .await?;
+
+        save_checkpoint(
+            &txn,
+            &self.program_id,
+            last.map(|last| last.signature),
+            last_slot,
+        )
+        .await?;
+
+        txn.commit().await?;
+        Ok(())
+    }
+}
+
+async fn save_checkpoint(
+    txn: &impl ConnectionTrait,
+    program_id: &Pubkey,
+    signature: Option<String>,
+    slot: i64,
+) -> Result<()> {
+    let checkpoint = entity::checkpoint::ActiveModel {
+        program_id: Set(program_id.to_string()),
+        signature: Set(signature),
+        slot: Set(slot),
+        updated_at: Set(Utc::now().into()),
+    };
+
+    if entity::checkpoint::Entity::find_by_id(program_id.to_string())
+        .one(txn)
+        .await?
+        .is_some()
+    {
+        checkpoint.update(txn).await?;
+    } else {
+        checkpoint.insert(txn).await?;
+    }
+    Ok(())
+}
+
+fn account(accounts: &[Pubkey], index: usize) -> Result<String> {
+    accounts
+        .get(index)
+        .map(ToString::to_string)
+        .ok_or_else(|| anyhow!("Instruction has no account {}", index))
+}
+
+/// Fractional tokens are ignored
+fn whole_shares(tokens: u64) -> Result<i64> {
+    Ok(i64::try_from(Shares::from_token_amount(tokens))?)
+}
+
+async fn apply_instruction(
+    txn: &impl ConnectionTrait,
+    transaction: &ProgramTransaction,
+    index: usize,
+    instruction: MarketInstruction,
+    accounts: &[Pubkey],
+) -> Result<()> {
+    let signature = transaction.signature.to_string();
+    let slot = i64::try_from(transaction.slot)?;
+    let index = i32::try_from(index)?;
+
+    match instruction {
+        MarketInstruction::CreateEvent(args) => {
+            for (option_id, option) in &args.options {
+                insert_market(txn, transaction, args.uuid, *option_id, option).await?;
+            }
+        }
+        MarketInstruction::AddOption(args) => {
+            insert_market(
+                txn,
+                transaction,
+                args.event_uuid,
+                args.option_uuid,
+                &args.option_info,
+            )
+            .await?;
+        }
+        MarketInstruction::FakeMatchOrder(args) => {
+            entity::fill::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                signature: Set(signature),
+                slot: Set(slot),
+                instruction: Set(index),
+                event_id: Set(args.event_uuid),
+                market_id: Set(args.option_uuid),
+                kind: Set(FillKind::Match),
+                token: Set(Token::Yes),
+                buyer: Set(account(accounts, 0)?),
+                seller: Set(account(accounts, 2)?),
+                shares: Set(i64::try_from(args.num_shares)?),
+                price_per_share: Set(None),
+            }
+            .insert(txn)
+            .await?;
+        }
+        MarketInstruction::TransferShares(args) => {
+            entity::fill::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                signature: Set(signature),
+                slot: Set(slot),
+                instruction: Set(index),
+                event_id: Set(args.event_uuid),
+                market_id: Set(args.option_uuid),
+                kind: Set(FillKind::Transfer),
+                token: Set(args.token_option.into()),
+                buyer: Set(account(accounts, 3)?),
+                seller: Set(account(accounts, 0)?),
+                shares: Set(i64::try_from(args.num_shares)?),
+                price_per_share: Set(Some(i64::try_from(args.price_per_share)?)),
+            }
+            .insert(txn)
+            .await?;
+        }
+        MarketInstruction::FakeGetReward(args) => {
+            let mint = account(accounts, 6)?;
+            let Some(market) = entity::market::Entity::find_by_id(args.option_uuid)
+                .one(txn)
+                .await?
+            else {
+                tracing::warn!(
+                    "Skipping the redemption in {} of market {}, created before the first indexed slot",
+                    signature,
+                    args.option_uuid
+                );
+                return Ok(());
+            };
+            let token = if market.yes_mint == mint {
+                Token::Yes
+            } else {
+                Token::No
+            };
+            let payout = args
+                .num_shares
+                .payout()
+                .ok_or_else(|| anyhow!("Payout of {} overflows", signature))?;
+
+            entity::redemption::ActiveModel {
+                id: Set(Uuid::new_v4()),
+                signature: Set(signature),
+                slot: Set(slot),
+                instruction: Set(index),
+                event_id: Set(args.event_uuid),
+                market_id: Set(args.option_uuid),
+                wallet: Set(account(accounts, 0)?),
+                token: Set(token),
+                shares: Set(i64::try_from(args.num_shares)?),
+                payout: Set(i64::try_from(payout)?),
+            }
+            .insert(txn)
+            .await?;
+        }
+        // Orders only move USDC, which is not indexed
+        _ => {}
+    }
+
+    Ok(())
+}
+
+async fn insert_market(
+    txn: &impl ConnectionTrait,
+    transaction: &ProgramTransaction,
+    event_id: Uuid,
+    market_id: Uuid,
+    option: &EventOption,
+) -> Result<()> {
+    entity::market::ActiveModel {
+        id: Set(market_id),
+        event_id: Set(event_id),
+        yes_mint: Set(option.yes_mint.to_string()),
+        no_mint: Set(option.no_mint.to_string()),
+        signature: Set(transaction.signature.to_string()),
+        slot: Set(i64::try_from(transaction.slot)?),
+    }
+    .insert(txn)
+    .await?;
+    Ok(())
+}
+
+/// Follows the share tokens of known markets, USDC and other tokens are left out
+async fn apply_balances(
+    txn: &impl ConnectionTrait,
+    transaction: &ProgramTransaction,
+    seq: i64,
+) -> Result<()> {
+    let mints: Vec<String> = transaction
+        .token_balances
+        .iter()
+        .map(|balance| balance.mint.to_string())
+        .collect();
+    let markets = entity::market::Entity::find()
+        .filter(
+            Condition::any()
+                .add(entity::market::Column::YesMint.is_in(mints.clone()))
+                .add(entity::market::Column::NoMint.is_in(mints)),
+        )
+        .all(txn)
+        .await?;
+
+    let slot = i64::try_from(transaction.slot)?;
+    for balance in &transaction.token_balances {
+        if balance.pre == balance.post {
+            continue;
+        }
+        let mint = balance.mint.to_string();
+        let wallet = balance.owner.to_string();
+        let Some((market, token)) = markets.iter().find_map(|market| {
+            if market.yes_mint == mint {
+                Some((market, Token::Yes))
+            } else if market.no_mint == mint {
+                Some((market, Token::No))
+            } else {
+                None
+            }
+        }) else {
+            continue;
+        };
+
+        entity::balance_change::ActiveModel {
+            id: Set(Uuid::new_v4()),
+            signature: Set(transaction.signature.to_string()),
+            slot: Set(slot),
+            seq: Set(seq),
+            wallet: Set(wallet.clone()),
+            mint: Set(mint.clone()),
+            before: Set(i64::try_from(balance.pre)?),
+            after: Set(i64::try_from(balance.post)?),
+        }
+        .insert(txn)
+        .await?;
+
+        let shares = whole_shares(balance.post)?;
+        let position = entity::position::Entity::find()
+            .filter(entity::position::Column::Wallet.eq(&wallet))
+            .filter(entity::position::Column::Mint.eq(&mint))
+            .one(txn)
+            .await?;
+        match position {
+            Some(position) => {
+                let mut active: entity::position::ActiveModel = position.into();
+                active.shares = Set(shares);
+                active.slot = Set(slot);
+                active.update(txn).await?;
+            }
+            None => {
+                entity::position::ActiveModel {
+                    id: Set(Uuid::new_v4()),
+                    wallet: Set(wallet),
+                    mint: Set(mint),
+                    event_id: Set(market.event_id),
+                    market_id: Set(market.id),
+                    token: Set(token),
+                    shares: Set(shares),
+                    slot: Set(slot),
+                }
+                .insert(txn)
+                .await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,26 @@
+//! Rebuilds trades, positions and redemptions from the program's transactions.
+//!
+//! `Indexer` follows the program from a checkpoint at confirmed commitment. Every transaction is
+//! indexed in one database transaction together with the checkpoint, so a restart resumes right
+//! after the last one. Confirmed blocks can still be dropped by a fork: before each pass the
+//! transactions that are not finalized yet are checked again, and everything from the first one
+//! that moved or disappeared is rolled back and indexed again.
+
+use sea_orm::{DatabaseConnection, DbErr};
+
+pub mod entity;
+pub mod index;
+pub mod source;
+
+pub use index::Indexer;
+pub use source::{
+    ProgramInstruction, ProgramTransaction, RpcSource, TokenBalance, TransactionSource,
+};
+
+/// Creates or updates every table of the entities
+pub async fn sync_schema(database: &DatabaseConnection) -> Result<(), DbErr> {
+    database
+        .get_schema_registry(module_path!().split("::").next().unwrap())
+        .sync(database)
+        .await
+}
@@ -0,0 +1,256 @@
+//! Where the indexer reads the program's transactions from.
+//!
+//! `RpcSource` asks a node for them at confirmed commitment. Tests put their own transactions
+//! behind `TransactionSource` instead.
+
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use solana_client::{
+    nonblocking::rpc_client::RpcClient,
+    rpc_client::GetConfirmedSignaturesForAddress2Config,
+    rpc_config::{CommitmentConfig, RpcTransactionConfig},
+};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use solana_transaction_status_client_types::{
+    UiTransactionEncoding, UiTransactionTokenBalance, option_serializer::OptionSerializer,
+};
+
+/// Most signatures a node returns at once
+const PAGE_SIZE: usize = 1000;
+/// Most signatures a node checks at once
+const STATUS_BATCH: usize = 256;
+
+/// An instruction of the program, with its accounts resolved
+#[derive(Debug, Clone)]
+pub struct ProgramInstruction {
+    pub data: Vec<u8>,
+    pub accounts: Vec<Pubkey>,
+}
+
+/// The tokens of one account before and after a transaction
+#[derive(Debug, Clone)]
+pub struct TokenBalance {
+    pub owner: Pubkey,
+    pub mint: Pubkey,
+    pub pre: u64,
+    pub post: u64,
+}
+
+/// A successful transaction that called the program
+#[derive(Debug, Clone)]
+pub struct ProgramTransaction {
+    pub signature: Signature,
+    pub slot: u64,
+    pub block_time: Option<i64>,
+    /// The top-level instructions of the program, in order
+    pub instructions: Vec<ProgramInstruction>,
+    pub token_balances: Vec<TokenBalance>,
+}
+
+#[async_trait]
+pub trait TransactionSource: Send + Sync {
+    /// Signatures of the program's successful transactions after `after`, or from `from_slot`
+    /// when there is none. Oldest first
+    async fn signatures(
+        &self,
+        program_id: &Pubkey,
+        after: Option<Signature>,
+        from_slot: u64,
+    ) -> Result<Vec<Signature>>;
+
+    async fn transaction(
+        &self,
+        program_id: &Pubkey,
+        signature: &Signature,
+    ) -> Result<ProgramTransaction>;
+
+    /// The slot each transaction is in now, None for those no longer on the chain
+    async fn slots(&self, signatures: &[Signature]) -> Result<Vec<Option<u64>>>;
+
+    /// The last slot that can no longer be rolled back
+    async fn finalized_slot(&self) -> Result<u64>;
+}
+
+/// A node, read at confirmed commitment
+pub struct RpcSource {
+    client: RpcClient,
+}
+
+impl RpcSource {
+    pub fn new(rpc_url: String) -> Self {
+        Self {
+            client: RpcClient::new_with_commitment(rpc_url, CommitmentConfig::confirmed()),
+        }
+    }
+}
+
+#[async_trait]
+impl TransactionSource for RpcSource {
+    async fn signatures(
+        &self,
+        program_id: &Pubkey,
+        after: Option<Signature>,
+        from_slot: u64,
+    ) -> Result<Vec<Signature>> {
+        let mut signatures = Vec::new();
+        let mut before = None;
+
+        // The node pages from the newest back
+        loop {
+            let page = self
+                .client
+                .get_signatures_for_address_with_config(
+                    program_id,
+                    GetConfirmedSignaturesForAddress2Config {
+                        before,
+                        until: after,
+                        limit: Some(PAGE_SIZE),
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await?;
+            let Some(last) = page.last() else {
+                break;
+            };
+            before = Some(last.signature.parse()?);
+            let done = page.len() < PAGE_SIZE || last.slot < from_slot;
+
+            for status in page {
+                // Failed transactions changed nothing
+                if status.err.is_none() && status.slot >= from_slot {
+                    signatures.push(status.signature.parse()?);
+                }
+            }
+            if done {
+                break;
+            }
+        }
+
+        signatures.reverse();
+        Ok(signatures)
+    }
+
+    async fn transaction(
+        &self,
+        program_id: &Pubkey,
+        signature: &Signature,
+    ) -> Result<ProgramTransaction> {
+        let confirmed = self
+            .client
+            .get_transaction_with_config(
+                signature,
+                RpcTransactionConfig {
+                    encoding: Some(UiTransactionEncoding::Base64),
+                    commitment: Some(CommitmentConfig::confirmed()),
+                    max_supported_transaction_version: Some(0),
+                },
+            )
+            .await?;
+        let meta = confirmed
+            .transaction
+            .meta
+            .with_context(|| format!("Transaction {} has no status", signature))?;
+        let transaction = confirmed
+            .transaction
+            .transaction
+            .decode()
+            .with_context(|| format!("Transaction {} cannot be decoded", signature))?;
+
+        // Accounts from lookup tables come after the static ones, writable first
+        let mut keys = transaction.message.static_account_keys().to_vec();
+        if let OptionSerializer::Some(loaded) = &meta.loaded_addresses {
+            for key in loaded.writable.iter().chain(&loaded.readonly) {
+                keys.push(key.parse()?);
+            }
+        }
+        let key = |index: usize| {
+            keys.get(index)
+                .copied()
+                .ok_or_else(|| anyhow!("Transaction {} has no account {}", signature, index))
+        };
+
+        let mut instructions = Vec::new();
+        for instruction in transaction.message.instructions() {
+            if key(instruction.program_id_index.into())? != *program_id {
+                continue;
+            }
+            instructions.push(ProgramInstruction {
+                data: instruction.data.clone(),
+                accounts: instruction
+                    .accounts
+                    .iter()
+                    .map(|index| key((*index).into()))
+                    .collect::<Result<_>>()?,
+            });
+        }
+
+        // Accounts created by the transaction have no balance before it, and closed ones none
+        // after
+        let mut balances: BTreeMap<u8, TokenBalance> = BTreeMap::new();
+        for (balance, post) in token_balances(&meta.pre_token_balances)
+            .map(|balance| (balance, false))
+            .chain(token_balances(&meta.post_token_balances).map(|balance| (balance, true)))
+        {
+            let OptionSerializer::Some(owner) = &balance.owner else {
+                continue;
+            };
+            let entry = balances
+                .entry(balance.account_index)
+                .or_insert(TokenBalance {
+                    owner: owner.parse()?,
+                    mint: balance.mint.parse()?,
+                    pre: 0,
+                    post: 0,
+                });
+            let amount = balance.ui_token_amount.amount.parse()?;
+            if post {
+                entry.post = amount;
+            } else {
+                entry.pre = amount;
+            }
+        }
+
+        Ok(ProgramTransaction {
+            signature: *signature,
+            slot: confirmed.slot,
+            block_time: confirmed.block_time,
+            instructions,
+            token_balances: balances.into_values().collect(),
+        })
+    }
+
+    async fn slots(&self, signatures: &[Signature]) -> Result<Vec<Option<u64>>> {
+        let mut slots = Vec::with_capacity(signatures.len());
+        for batch in signatures.chunks(STATUS_BATCH) {
+            let statuses = self
+                .client
+                .get_signature_statuses_with_history(batch)
+                .await?
+                .value;
+            slots.extend(
+                statuses
+                    .into_iter()
+                    .map(|status| status.map(|status| status.slot)),
+            );
+        }
+        Ok(slots)
+    }
+
+    async fn finalized_slot(&self) -> Result<u64> {
+        Ok(self
+            .client
+            .get_slot_with_commitment(CommitmentConfig::finalized())
+            .await?)
+    }
+}
+
+fn token_balances(
+    balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+) -> impl Iterator<Item = &UiTransactionTokenBalance> {
+    match balances {
+        OptionSerializer::Some(balances) => balances.iter(),
+        _ => [].iter(),
+    }
+}
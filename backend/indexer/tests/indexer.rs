@@ -0,0 +1,318 @@
+//! Runs the indexer against an in-memory SQLite database and transactions kept in memory.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use blockchain_core::{
+    accounts::{event::EventOption, order::TokenOption},
+    instructions::{
+        CreateEventArgs, FakeGetRewardArgs, FakeMatchOrderArgs, MarketInstruction,
+        TransferSharesArgs,
+    },
+    money::{MicroUsdc, Shares, TOKENS_PER_SHARE},
+};
+use indexer::{
+    Indexer, ProgramInstruction, ProgramTransaction, TokenBalance, TransactionSource,
+    entity::{self, fill::FillKind, market::Token},
+    sync_schema,
+};
+use sea_orm::{ColumnTrait, Database, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder};
+use solana_sdk::{pubkey::Pubkey, signature::Signature};
+use uuid::Uuid;
+
+const PROGRAM: Pubkey = Pubkey::new_from_array([7; 32]);
+
+/// The transactions on the chain, in order
+#[derive(Default)]
+struct MemorySource {
+    chain: Mutex<Vec<ProgramTransaction>>,
+    finalized: AtomicU64,
+}
+
+impl MemorySource {
+    fn push(&self, slot: u64, instruction: MarketInstruction, accounts: Vec<Pubkey>) {
+        self.push_with_balances(slot, instruction, accounts, Vec::new());
+    }
+
+    fn push_with_balances(
+        &self,
+        slot: u64,
+        instruction: MarketInstruction,
+        accounts: Vec<Pubkey>,
+        token_balances: Vec<TokenBalance>,
+    ) {
+        self.chain.lock().unwrap().push(ProgramTransaction {
+            signature: Signature::new_unique(),
+            slot,
+            block_time: None,
+            instructions: vec![ProgramInstruction {
+                data: wincode::serialize(&instruction).unwrap(),
+                accounts,
+            }],
+            token_balances,
+        });
+    }
+
+    /// Drops every transaction from `slot` on, as a fork would
+    fn fork(&self, slot: u64) {
+        self.chain.lock().unwrap().retain(|tx| tx.slot < slot);
+    }
+}
+
+#[async_trait]
+impl TransactionSource for &MemorySource {
+    async fn signatures(
+        &self,
+        _program_id: &Pubkey,
+        after: Option<Signature>,
+        from_slot: u64,
+    ) -> Result<Vec<Signature>> {
+        let chain = self.chain.lock().unwrap();
+        let start = after
+            .and_then(|after| chain.iter().position(|tx| tx.signature == after))
+            .map_or(0, |index| index + 1);
+        Ok(chain[start..]
+            .iter()
+            .filter(|tx| tx.slot >= from_slot)
+            .map(|tx| tx.signature)
+            .collect())
+    }
+
+    async fn transaction(
+        &self,
+        _program_id: &Pubkey,
+        signature: &Signature,
+    ) -> Result<ProgramTransaction> {
+        self.chain
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|tx| tx.signature == *signature)
+            .cloned()
+            .ok_or_else(|| anyhow!("Transaction {} not found", signature))
+    }
+
+    async fn slots(&self, signatures: &[Signature]) -> Result<Vec<Option<u64>>> {
+        let chain = self.chain.lock().unwrap();
+        Ok(signatures
+            .iter()
+            .map(|signature| {
+                chain
+                    .iter()
+                    .find(|tx| tx.signature == *signature)
+                    .map(|tx| tx.slot)
+            })
+            .collect())
+    }
+
+    async fn finalized_slot(&self) -> Result<u64> {
+        Ok(self.finalized.load(Ordering::SeqCst))
+    }
+}
+
+struct Market {
+    event_id: Uuid,
+    id: Uuid,
+    yes_mint: Pubkey,
+    no_mint: Pubkey,
+}
+
+fn create_market(source: &MemorySource, slot: u64) -> Market {
+    let market = Market {
+        event_id: Uuid::new_v4(),
+        id: Uuid::new_v4(),
+        yes_mint: Pubkey::new_unique(),
+        no_mint: Pubkey::new_unique(),
+    };
+    source.push(
+        slot,
+        MarketInstruction::CreateEvent(CreateEventArgs {
+            uuid: market.event_id,
+            description: "event".into(),
+            options: HashMap::from([(
+                market.id,
+                EventOption {
+                    option_desc: "option".into(),
+                    yes_mint: market.yes_mint,
+                    no_mint: market.no_mint,
+                },
+            )]),
+        }),
+        Vec::new(),
+    );
+    market
+}
+
+fn balance(owner: Pubkey, mint: Pubkey, pre: u64, post: u64) -> TokenBalance {
+    TokenBalance {
+        owner,
+        mint,
+        pre: pre * TOKENS_PER_SHARE,
+        post: post * TOKENS_PER_SHARE,
+    }
+}
+
+fn match_order(source: &MemorySource, slot: u64, market: &Market, yes: Pubkey, no: Pubkey) {
+    source.push_with_balances(
+        slot,
+        MarketInstruction::FakeMatchOrder(FakeMatchOrderArgs {
+            event_uuid: market.event_id,
+            option_uuid: market.id,
+            num_shares: Shares(5),
+        }),
+        vec![yes, Pubkey::new_unique(), no, Pubkey::new_unique()],
+        vec![
+            balance(yes, market.yes_mint, 0, 5),
+            balance(no, market.no_mint, 0, 5),
+        ],
+    );
+}
+
+async fn database() -> DatabaseConnection {
+    let database = Database::connect("sqlite::memory:").await.unwrap();
+    sync_schema(&database).await.unwrap();
+    database
+}
+
+async fn shares(database: &DatabaseConnection, wallet: Pubkey, mint: Pubkey) -> Option<i64> {
+    entity::position::Entity::find()
+        .filter(entity::position::Column::Wallet.eq(wallet.to_string()))
+        .filter(entity::position::Column::Mint.eq(mint.to_string()))
+        .one(database)
+        .await
+        .unwrap()
+        .map(|position| position.shares)
+}
+
+#[tokio::test]
+async fn rebuilds_fills_positions_and_redemptions() {
+    let database = database().await;
+    let source = MemorySource::default();
+    let (alice, bob, carol) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+
+    let market = create_market(&source, 1);
+    match_order(&source, 2, &market, alice, bob);
+    source.push_with_balances(
+        3,
+        MarketInstruction::TransferShares(TransferSharesArgs {
+            event_uuid: market.event_id,
+            option_uuid: market.id,
+            token_option: TokenOption::Yes,
+            num_shares: Shares(2),
+            price_per_share: MicroUsdc(700_000),
+        }),
+        vec![alice, Pubkey::new_unique(), Pubkey::new_unique(), carol],
+        vec![
+            balance(alice, market.yes_mint, 5, 3),
+            balance(carol, market.yes_mint, 0, 2),
+        ],
+    );
+    let mut accounts = vec![Pubkey::new_unique(); 7];
+    accounts[0] = carol;
+    accounts[6] = market.yes_mint;
+    source.push_with_balances(
+        4,
+        MarketInstruction::FakeGetReward(FakeGetRewardArgs {
+            event_uuid: market.event_id,
+            option_uuid: market.id,
+            num_shares: Shares(2),
+        }),
+        accounts,
+        vec![balance(carol, market.yes_mint, 2, 0)],
+    );
+
+    let indexer = Indexer::new(database.clone(), &source, PROGRAM);
+    assert_eq!(indexer.poll().await.unwrap(), 4);
+
+    let fills = entity::fill::Entity::find()
+        .order_by_asc(entity::fill::Column::Slot)
+        .all(&database)
+        .await
+        .unwrap();
+    assert_eq!(fills.len(), 2);
+    assert_eq!(fills[0].kind, FillKind::Match);
+    assert_eq!(fills[0].buyer, alice.to_string());
+    assert_eq!(fills[0].seller, bob.to_string());
+    assert_eq!(fills[1].kind, FillKind::Transfer);
+    assert_eq!(fills[1].buyer, carol.to_string());
+    assert_eq!(fills[1].price_per_share, Some(700_000));
+
+    assert_eq!(shares(&database, alice, market.yes_mint).await, Some(3));
+    assert_eq!(shares(&database, bob, market.no_mint).await, Some(5));
+    assert_eq!(shares(&database, carol, market.yes_mint).await, Some(0));
+
+    let redemptions = entity::redemption::Entity::find()
+        .all(&database)
+        .await
+        .unwrap();
+    assert_eq!(redemptions.len(), 1);
+    assert_eq!(redemptions[0].wallet, carol.to_string());
+    assert_eq!(redemptions[0].token, Token::Yes);
+    assert_eq!(redemptions[0].payout, 2_000_000);
+
+    // A new process resumes after the checkpoint
+    let restarted = Indexer::new(database.clone(), &source, PROGRAM);
+    assert_eq!(restarted.poll().await.unwrap(), 0);
+    match_order(&source, 5, &market, carol, alice);
+    assert_eq!(restarted.poll().await.unwrap(), 1);
+    assert_eq!(
+        entity::fill::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .len(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn rolls_back_transactions_dropped_by_a_fork() {
+    let database = database().await;
+    let source = MemorySource::default();
+    let (alice, bob, carol) = (
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+        Pubkey::new_unique(),
+    );
+    let indexer = Indexer::new(database.clone(), &source, PROGRAM);
+
+    let market = create_market(&source, 1);
+    match_order(&source, 2, &market, alice, bob);
+    assert_eq!(indexer.poll().await.unwrap(), 2);
+    source.finalized.store(1, Ordering::SeqCst);
+
+    // The block of the match is dropped, and another one lands instead
+    source.fork(2);
+    match_order(&source, 3, &market, carol, bob);
+    assert_eq!(indexer.poll().await.unwrap(), 1);
+
+    assert_eq!(shares(&database, alice, market.yes_mint).await, Some(0));
+    assert_eq!(shares(&database, carol, market.yes_mint).await, Some(5));
+    assert_eq!(shares(&database, bob, market.no_mint).await, Some(5));
+
+    let fills = entity::fill::Entity::find().all(&database).await.unwrap();
+    assert_eq!(fills.len(), 1);
+    assert_eq!(fills[0].buyer, carol.to_string());
+    assert_eq!(fills[0].slot, 3);
+
+    // The market was finalized before the fork and stays
+    assert_eq!(
+        entity::market::Entity::find()
+            .all(&database)
+            .await
+            .unwrap()
+            .len(),
+        1
+    );
+}
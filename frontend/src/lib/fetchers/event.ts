@@ -1,11 +1,11 @@
 import { error } from '@sveltejs/kit';
-import type { BuyOrderDto, EventChartDto, EventDto, EventPercentagesResponse, InfoResponse, MarketPercentagesDto, PositionDto } from '$lib/types';
+import type { BuyOrderDto, EventChartDto, EventDto, EventPercentagesResponse, InfoResponse, MarketPercentagesDto, OrderBookDto, PositionDto } from '$lib/types';
 
 export type EventPageData = {
 	event: EventDto;
 	positions: PositionDto[];
 	buyOrders: BuyOrderDto[];
-	allMarketOrders: Record<string, BuyOrderDto[]>;
+	marketBooks: Record<string, OrderBookDto>;
 	marketPercentages: Record<string, MarketPercentagesDto>;
 	chartData: EventChartDto;
 };
@@ -42,24 +42,24 @@ export async function fetchEventData(
 		marketPercentages = pctPayload.percentages;
 	}
 
-	// Fetch chart data (percentage history)
-	let chartData: EventChartDto = { points: [] };
+	// Fetch chart data (traded price history)
+	let chartData: EventChartDto = { interval: '1h', candles: {}, points: [] };
 	const chartResponse = await fetcher(`/api/event/chart/${eventId}`);
 	if (chartResponse.ok) {
 		chartData = (await chartResponse.json()) as EventChartDto;
 	}
 
-	// Fetch ALL buy orders for each market (for the order book)
-	const allOrderPromises = payload.event.markets.map(async (market) => {
-		const res = await fetcher(`/api/event/buyorder/${market.id}`);
-		if (!res.ok) return { marketId: market.id, orders: [] as BuyOrderDto[] };
-		const orders = (await res.json()) as BuyOrderDto[];
-		return { marketId: market.id, orders };
-	});
-	const allOrderResults = await Promise.all(allOrderPromises);
-	const allMarketOrders: Record<string, BuyOrderDto[]> = {};
-	for (const { marketId, orders } of allOrderResults) {
-		allMarketOrders[marketId] = orders;
+	// Fetch the aggregated order book of each market
+	const bookResults = await Promise.all(
+		payload.event.markets.map(async (market) => {
+			const res = await fetcher(`/api/event/market/${market.id}/book`);
+			if (!res.ok) return null;
+			return (await res.json()) as OrderBookDto;
+		})
+	);
+	const marketBooks: Record<string, OrderBookDto> = {};
+	for (const book of bookResults) {
+		if (book) marketBooks[book.marketId] = book;
 	}
 
 	if (userId) {
@@ -68,11 +68,16 @@ export async function fetchEventData(
 			positions = (await posResponse.json()) as PositionDto[];
 		}
 
-		// Filter user's buy orders from the already-fetched data
-		buyOrders = Object.values(allMarketOrders)
-			.flat()
-			.filter((o) => o.userId === userId);
+		// Only the user's own orders are returned
+		const orderResults = await Promise.all(
+			payload.event.markets.map(async (market) => {
+				const res = await fetcher(`/api/event/buyorder/${market.id}`);
+				if (!res.ok) return [] as BuyOrderDto[];
+				return (await res.json()) as BuyOrderDto[];
+			})
+		);
+		buyOrders = orderResults.flat();
 	}
 
-	return { event: payload.event, positions, buyOrders, allMarketOrders, marketPercentages, chartData };
+	return { event: payload.event, positions, buyOrders, marketBooks, marketPercentages, chartData };
 }
@@ -0,0 +1,40 @@
+import { invalidateAll } from '$app/navigation';
+import type { StreamMessage } from '$lib/types';
+
+/**
+ * Subscribes to the server-sent updates of the given channels and reloads the page data
+ * whenever one of them changes. The browser reconnects on its own after a drop.
+ * Automatically cleans up when the component is destroyed.
+ *
+ * Usage: call `useStream(() => ['market:<id>:book'])` at the top level of a component's `<script>`.
+ */
+export function useStream(channels: () => string[]) {
+	$effect(() => {
+		const list = channels();
+		if (list.length === 0) return;
+
+		const source = new EventSource(`/api/stream?channels=${encodeURIComponent(list.join(','))}`);
+		let opened = 0;
+		let pending: ReturnType<typeof setTimeout> | undefined;
+
+		// One change publishes on several channels, reload once for all of them
+		const reload = () => {
+			clearTimeout(pending);
+			pending = setTimeout(() => invalidateAll(), 250);
+		};
+
+		source.onopen = () => {
+			// Anything may have changed while reconnecting
+			if (opened++ > 0) reload();
+		};
+		source.onmessage = (event) => {
+			const message = JSON.parse(event.data) as StreamMessage;
+			if (message.kind === 'delta') reload();
+		};
+
+		return () => {
+			clearTimeout(pending);
+			source.close();
+		};
+	});
+}
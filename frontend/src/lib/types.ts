@@ -112,6 +112,23 @@ export interface BuyOrderDto {
 	option: MarketOption;
 }
 
+// Order book, in prices of option A
+
+export interface PriceLevelDto {
+	price: number;
+	shares: number;
+	orders: number;
+}
+
+export interface OrderBookDto {
+	marketId: string;
+	bids: PriceLevelDto[];
+	asks: PriceLevelDto[];
+	bestBid: number | null;
+	bestAsk: number | null;
+	spread: number | null;
+}
+
 // Market percentages
 
 export interface MarketPercentagesDto {
@@ -125,13 +142,35 @@ export interface EventPercentagesResponse {
 
 // Chart data
 
-export interface MarketSnapshotPointDto {
-	recordedAt: string;
+export type CandleInterval = '1m' | '5m' | '1h' | '1d';
+
+export interface CandleDto {
+	start: string;
+	open: number;
+	high: number;
+	low: number;
+	close: number;
+	volume: number;
+}
+
+export interface ChartPointDto {
+	time: string;
 	percentages: Record<string, number | null>;
 }
 
 export interface EventChartDto {
-	points: MarketSnapshotPointDto[];
+	interval: CandleInterval;
+	candles: Record<string, CandleDto[]>;
+	points: ChartPointDto[];
+}
+
+// Live updates
+
+export interface StreamMessage {
+	channel: string;
+	seq: number;
+	kind: 'snapshot' | 'delta';
+	data: unknown;
 }
 
 export interface AllPercentagesResponse {
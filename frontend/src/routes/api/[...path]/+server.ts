@@ -11,10 +11,11 @@ const BACKEND_URL = env.BACKEND_URL || 'http://localhost:3000';
 async function proxyRequest(
 	method: string,
 	path: string,
+	search: string,
 	request: Request,
 	fetcher: typeof globalThis.fetch
 ) {
-	const url = `${BACKEND_URL}/api/${path}`;
+	const url = `${BACKEND_URL}/api/${path}${search}`;
 
 	const options: RequestInit = {
 		method,
@@ -28,14 +29,14 @@ async function proxyRequest(
 	return await fetcher(url, options);
 }
 
-export const GET: RequestHandler = async ({ params, request, fetch }) => {
-	return await proxyRequest('GET', params.path, request, fetch);
+export const GET: RequestHandler = async ({ params, url, request, fetch }) => {
+	return await proxyRequest('GET', params.path, url.search, request, fetch);
 };
 
-export const POST: RequestHandler = async ({ params, request, fetch }) => {
-	return await proxyRequest('POST', params.path, request, fetch);
+export const POST: RequestHandler = async ({ params, url, request, fetch }) => {
+	return await proxyRequest('POST', params.path, url.search, request, fetch);
 };
 
-export const PATCH: RequestHandler = async ({ params, request, fetch }) => {
-	return await proxyRequest('PATCH', params.path, request, fetch);
+export const PATCH: RequestHandler = async ({ params, url, request, fetch }) => {
+	return await proxyRequest('PATCH', params.path, url.search, request, fetch);
 };